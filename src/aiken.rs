@@ -0,0 +1,266 @@
+//! Import and export of the [Aiken format](https://docs.moodle.org/404/en/Aiken_format).
+//!
+//! Aiken contains only single answer multiple choice questions: a question line, options from `A.` (or `A)`)
+//! onwards and the letter of the correct option on an `ANSWER:` line.
+//!
+//! ```
+//! use moodle_xml::aiken;
+//!
+//! let source = "What is the capital of France?
+//! A. Lyon
+//! B. Paris
+//! C. Marseille
+//! ANSWER: B
+//! ";
+//! let quiz = aiken::parse(source).unwrap();
+//! assert_eq!(quiz.len(), 1);
+//! assert_eq!(aiken::to_aiken(&quiz).unwrap(), source);
+//! ```
+use crate::{
+    answer::Answer,
+    question::{MultiChoiceQuestion, Question, QuestionType, TextFormat},
+    quiz::{EmptyError, Quiz, QuizError},
+};
+use std::fmt;
+
+/// Error when parsing Aiken, `line` is the 1-based line of the problem.
+#[derive(Debug)]
+pub struct AikenError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AikenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl std::error::Error for AikenError {}
+
+/// A question which is being parsed.
+struct Block {
+    line: usize,
+    text: Vec<String>,
+    options: Vec<(char, String)>,
+}
+
+/// Returns the letter and the text if the line is an option such as `A. text` or `A) text`.
+fn parse_option(line: &str) -> Option<(char, &str)> {
+    let mut chars = line.chars();
+    let letter = chars.next().filter(|c| c.is_ascii_uppercase())?;
+    let rest = chars.as_str();
+    let text = rest.strip_prefix('.').or_else(|| rest.strip_prefix(')'))?;
+    if !text.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some((letter, text.trim()))
+}
+
+/// Parses Aiken text into a quiz of multiple choice questions.
+///
+/// # Errors
+///
+/// Returns an [`AikenError`] with the line number of the first malformed question.
+pub fn parse(source: &str) -> Result<Quiz, AikenError> {
+    let mut questions: Vec<QuestionType> = Vec::new();
+    let mut block: Option<Block> = None;
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| AikenError {
+            line: line_number,
+            message,
+        };
+        if let Some(letter) = line.strip_prefix("ANSWER:") {
+            let current = block
+                .take()
+                .ok_or_else(|| error("ANSWER line without a question".to_string()))?;
+            questions.push(finish_block(current, letter.trim()).map_err(error)?.into());
+            continue;
+        }
+        // The first line of a question is always text, even if it looks like an option
+        let current = block.get_or_insert_with(|| Block {
+            line: line_number,
+            text: Vec::new(),
+            options: Vec::new(),
+        });
+        if let Some((letter, text)) = parse_option(line).filter(|_| !current.text.is_empty()) {
+            let expected = (b'A' + current.options.len() as u8) as char;
+            if letter != expected {
+                return Err(error(format!(
+                    "expected option {}, found option {}",
+                    expected, letter
+                )));
+            }
+            current.options.push((letter, text.to_string()));
+        } else if current.options.is_empty() {
+            current.text.push(line.to_string());
+        } else {
+            return Err(error(format!(
+                "expected an option or an ANSWER line, found `{}`",
+                line
+            )));
+        }
+    }
+    if let Some(block) = block {
+        return Err(AikenError {
+            line: block.line,
+            message: "question has no ANSWER line".to_string(),
+        });
+    }
+    Ok(Quiz::new(questions))
+}
+
+/// Creates the question from a parsed block and the letter of the `ANSWER:` line.
+fn finish_block(block: Block, letter: &str) -> Result<MultiChoiceQuestion, String> {
+    if block.options.len() < 2 {
+        return Err("question must have at least 2 options".to_string());
+    }
+    let correct = letter
+        .chars()
+        .next()
+        .filter(|_| letter.len() == 1)
+        .ok_or_else(|| format!("invalid answer `{}`, expected a single letter", letter))?;
+    if !block.options.iter().any(|(option, _)| *option == correct) {
+        return Err(format!("answer {} is not one of the options", correct));
+    }
+    let text = block.text.join(" ");
+    let name = text.chars().take(80).collect();
+    let mut question = MultiChoiceQuestion::new(
+        name,
+        text,
+        Some(true),
+        None,
+        None,
+        None,
+        None,
+        Some("ABCD".to_string()),
+    );
+    question.set_text_format(TextFormat::Moodle);
+    let answers = block
+        .options
+        .into_iter()
+        .map(|(option, text)| {
+            let mut answer = Answer::new(if option == correct { 100.0 } else { 0.0 }, text, None);
+            answer.set_text_format(TextFormat::Moodle);
+            answer
+        })
+        .collect();
+    question.add_answers(answers).map_err(|e| e.to_string())?;
+    Ok(question)
+}
+
+/// Writes the multiple choice questions of a quiz in Aiken format.
+///
+/// # Errors
+///
+/// Returns a [`QuizError`] if the quiz is empty or has a question which Aiken cannot express:
+/// other question types, multiple right answers, partial fractions or more than 26 options.
+pub fn to_aiken(quiz: &Quiz) -> Result<String, QuizError> {
+    if quiz.is_empty() {
+        return Err(EmptyError.into());
+    }
+    let mut output = String::new();
+    for (_, question) in quiz.questions() {
+        let unsupported = |reason: &str| {
+            QuizError::ValueError(format!(
+                "Question `{}` cannot be written in Aiken: {}",
+                question.as_question().get_name(),
+                reason
+            ))
+        };
+        let QuestionType::Multichoice(question) = question else {
+            return Err(unsupported("only multiple choice questions are supported"));
+        };
+        let answers = question.answers();
+        if !question.single
+            || answers.iter().filter(|a| a.fraction == 100.0).count() != 1
+            || answers
+                .iter()
+                .any(|a| a.fraction != 100.0 && a.fraction != 0.0)
+        {
+            return Err(unsupported("it must have exactly one right answer"));
+        }
+        if answers.len() > 26 {
+            return Err(unsupported("it has more than 26 options"));
+        }
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        output.push_str(&single_line(question.get_description()));
+        output.push('\n');
+        let mut correct = 'A';
+        for (letter, answer) in ('A'..='Z').zip(answers) {
+            output.push_str(&format!("{}. {}\n", letter, single_line(&answer.text)));
+            if answer.fraction == 100.0 {
+                correct = letter;
+            }
+        }
+        output.push_str(&format!("ANSWER: {}\n", correct));
+    }
+    Ok(output)
+}
+
+/// Aiken questions and options must be on a single line.
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let source = "What is the capital of France?
+A) Lyon
+B) Paris
+ANSWER: B
+
+Which of these is a prime number?
+A. 4
+B. 6
+C. 7
+D. 9
+ANSWER: C
+";
+        let quiz = parse(source).unwrap();
+        let questions: Vec<_> = quiz.questions().collect();
+        assert_eq!(questions.len(), 2);
+        match questions[1].1 {
+            QuestionType::Multichoice(q) => {
+                assert_eq!(q.get_description(), "Which of these is a prime number?");
+                let fractions: Vec<f64> = q.answers().iter().map(|a| a.fraction).collect();
+                assert_eq!(fractions, vec![0.0, 0.0, 100.0, 0.0]);
+            }
+            _ => panic!("expected multichoice"),
+        }
+        // Options are always written with a dot
+        assert!(to_aiken(&quiz)
+            .unwrap()
+            .starts_with("What is the capital of France?\nA. Lyon\n"));
+    }
+
+    #[test]
+    fn test_malformed_blocks() {
+        let cases = [
+            ("Question\nA. One\nB. Two\nANSWER: E\n", 4),
+            ("Question\nA. One\nC. Two\nANSWER: A\n", 3),
+            ("Question\nA. One\nB. Two\nNot an option\nANSWER: A\n", 4),
+            ("Question\nA. One\nANSWER: A\n", 3),
+            (
+                "First\nA. One\nB. Two\nANSWER: A\n\nSecond\nA. One\nB. Two\n",
+                6,
+            ),
+        ];
+        for (source, line) in cases {
+            match parse(source) {
+                Err(error) => assert_eq!(error.line, line, "{}", error),
+                Ok(_) => panic!("expected an error for {:?}", source),
+            }
+        }
+    }
+}
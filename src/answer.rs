@@ -1,15 +1,18 @@
-use std::fs::File;
-use xml::writer::{EventWriter, XmlEvent};
+use xml::writer::XmlEvent;
 
 use crate::question::TextFormat;
 use crate::quiz::QuizError;
-use crate::xml_util::{write_named_formatted_scope, write_text_tag};
+use crate::xml_util::{
+    end_formatted_element, start_formatted_element, write_named_formatted_scope, write_text_tag,
+    XmlWriter,
+};
 
 /// Answer type struct, which is common for all question types.
 #[derive(Debug, Clone)]
 pub struct Answer {
-    /// Fraction of the answer, 0-100
-    pub fraction: u8,
+    /// Fraction of the answer in percent, -100-100, such as 33.33333 for a third. Negative fractions are penalties
+    /// for wrong choices in multiple choice questions with several right answers.
+    pub fraction: f64,
     /// The answer text
     pub text: String,
     /// Optional feedback for the answer
@@ -22,10 +25,10 @@ impl Answer {
     /// Generates a new Answer type struct
     ///
     /// ### Arguments
-    /// * `new_fraction` - The amount of points answer gives from 0-100
+    /// * `new_fraction` - The amount of points answer gives from -100-100
     /// * `new_text` - Text displayed on the answer.
     /// * `new_feedback` - Feedback displayed on the answer can be left empty with None.
-    pub fn new(new_fraction: u8, new_text: String, new_feedback: Option<String>) -> Self {
+    pub fn new(new_fraction: f64, new_text: String, new_feedback: Option<String>) -> Self {
         Self {
             fraction: new_fraction,
             text: new_text,
@@ -38,16 +41,35 @@ impl Answer {
         self.text_format = text_format;
     }
     /// Writes answer part of xml for EventWriter
-    pub(crate) fn to_xml(&self, writer: &mut EventWriter<&File>) -> Result<(), QuizError> {
-        if self.fraction > 100 {
+    pub(crate) fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.to_xml_with(writer, |_| Ok(()))
+    }
+    /// Writes answer part of xml for EventWriter, `extra` writes question type specific elements
+    /// at the end of the answer element.
+    pub(crate) fn to_xml_with<F>(&self, writer: &mut XmlWriter, extra: F) -> Result<(), QuizError>
+    where
+        F: FnOnce(&mut XmlWriter) -> Result<(), QuizError>,
+    {
+        if self.fraction.is_nan() {
+            return Err(QuizError::AnswerFractionError(
+                "Answer fraction is not a number".to_string(),
+            ));
+        }
+        if self.fraction > 100.0 {
             return Err(QuizError::AnswerFractionError(
                 "Answer fraction is larger than 100".to_string(),
             ));
         }
-        writer.write(
-            XmlEvent::start_element("answer")
-                .attr("fraction", self.fraction.to_string().as_str())
-                .attr("format", self.text_format.name()),
+        if self.fraction < -100.0 {
+            return Err(QuizError::AnswerFractionError(
+                "Answer fraction is smaller than -100".to_string(),
+            ));
+        }
+        let fraction = format_fraction(self.fraction);
+        start_formatted_element(
+            writer,
+            XmlEvent::start_element("answer").attr("fraction", fraction.as_str()),
+            Some(self.text_format),
         )?;
         write_text_tag(writer, self.text.as_str(), false)?;
         if let Some(string) = self.feedback.as_ref() {
@@ -56,8 +78,206 @@ impl Answer {
                 Ok(())
             })?;
         }
-        writer.write(XmlEvent::end_element())?;
-        Ok(())
+        extra(writer)?;
+        end_formatted_element(writer)
+    }
+}
+
+/// Margin of the sums of fractions, so that the fractions Moodle rounds to 5 decimals, like three times 33.33333,
+/// still make 100.
+pub(crate) const FRACTION_TOLERANCE: f64 = 0.001;
+
+/// Formats a fraction with the precision Moodle writes, at most 5 decimals, such as `33.33333` or `100`.
+pub(crate) fn format_fraction(fraction: f64) -> String {
+    // Adding zero turns -0 into 0
+    ((fraction * 1e5).round() / 1e5 + 0.0).to_string()
+}
+
+/// Returns the fraction of each of `count` right answers which share the grade equally, such as 33.33333… for
+/// each of three answers.
+#[cfg(any(feature = "markdown", feature = "csv"))]
+pub(crate) fn shared_fraction(count: usize) -> f64 {
+    100.0 / count as f64
+}
+
+/// Answer of a numerical question. The answer text holds the correct value and
+/// any response within `tolerance` of it is accepted.
+#[derive(Debug, Clone)]
+pub struct NumericalAnswer {
+    pub answer: Answer,
+    /// The accepted error margin, 0 requires an exact value.
+    pub tolerance: f64,
+}
+
+impl NumericalAnswer {
+    /// Generates a new NumericalAnswer
+    ///
+    /// ### Arguments
+    /// * `fraction` - The amount of points answer gives from -100-100
+    /// * `value` - The correct value
+    /// * `tolerance` - The accepted error margin
+    /// * `feedback` - Feedback displayed on the answer can be left empty with None.
+    pub fn new(fraction: f64, value: f64, tolerance: f64, feedback: Option<String>) -> Self {
+        Self {
+            answer: Answer::new(fraction, value.to_string(), feedback),
+            tolerance,
+        }
+    }
+    /// Returns the correct value, if the answer text is a number.
+    pub fn value(&self) -> Option<f64> {
+        self.answer.text.trim().parse().ok()
+    }
+    /// Writes answer part of xml for EventWriter
+    pub(crate) fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        if self.value().is_none() {
+            return Err(QuizError::ValueError(format!(
+                "Numerical answer `{}` is not a number",
+                self.answer.text
+            )));
+        }
+        self.answer.to_xml_with(writer, |writer| {
+            writer.write(XmlEvent::start_element("tolerance"))?;
+            writer.write(XmlEvent::characters(&self.tolerance.to_string()))?;
+            writer.write(XmlEvent::end_element())?;
+            Ok(())
+        })
+    }
+}
+
+impl From<Answer> for NumericalAnswer {
+    fn from(answer: Answer) -> Self {
+        Self {
+            answer,
+            tolerance: 0.0,
+        }
+    }
+}
+
+/// How the tolerance of a calculated answer is applied to the correct value.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ToleranceType {
+    /// The tolerance is a fraction of the correct value.
+    #[default]
+    Relative,
+    /// The tolerance is an absolute error margin.
+    Nominal,
+    /// The tolerance is a relative margin applied on a logarithmic scale.
+    Geometric,
+}
+
+impl ToleranceType {
+    /// Returns the number Moodle uses for the tolerance type.
+    pub fn code(&self) -> u8 {
+        match self {
+            ToleranceType::Relative => 1,
+            ToleranceType::Nominal => 2,
+            ToleranceType::Geometric => 3,
+        }
+    }
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(ToleranceType::Relative),
+            2 => Some(ToleranceType::Nominal),
+            3 => Some(ToleranceType::Geometric),
+            _ => None,
+        }
+    }
+}
+
+/// How the correct value of a calculated answer is rounded when it is shown.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum CorrectAnswerFormat {
+    #[default]
+    Decimals,
+    SignificantFigures,
+}
+
+impl CorrectAnswerFormat {
+    /// Returns the number Moodle uses for the format.
+    pub fn code(&self) -> u8 {
+        match self {
+            CorrectAnswerFormat::Decimals => 1,
+            CorrectAnswerFormat::SignificantFigures => 2,
+        }
+    }
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(CorrectAnswerFormat::Decimals),
+            2 => Some(CorrectAnswerFormat::SignificantFigures),
+            _ => None,
+        }
+    }
+}
+
+/// Answer of a calculated question. The answer text holds the formula, such as `{a} + {b}`,
+/// or for calculated multichoice questions text with `{=formula}` expressions.
+#[derive(Debug, Clone)]
+pub struct CalculatedAnswer {
+    pub answer: Answer,
+    /// The accepted error margin, applied according to `tolerance_type`.
+    pub tolerance: f64,
+    pub tolerance_type: ToleranceType,
+    /// Number of decimals or significant figures of the correct value shown to the student.
+    pub correct_answer_length: u8,
+    pub correct_answer_format: CorrectAnswerFormat,
+}
+
+impl CalculatedAnswer {
+    /// Generates a new CalculatedAnswer with a relative tolerance, showing the correct value with 2 decimals.
+    ///
+    /// ### Arguments
+    /// * `fraction` - The amount of points answer gives from -100-100
+    /// * `formula` - The formula of the correct value
+    /// * `tolerance` - The accepted error margin
+    /// * `feedback` - Feedback displayed on the answer can be left empty with None.
+    pub fn new(fraction: f64, formula: String, tolerance: f64, feedback: Option<String>) -> Self {
+        Self {
+            answer: Answer::new(fraction, formula, feedback),
+            tolerance,
+            tolerance_type: ToleranceType::default(),
+            correct_answer_length: 2,
+            correct_answer_format: CorrectAnswerFormat::default(),
+        }
+    }
+    /// Writes answer part of xml for EventWriter
+    pub(crate) fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        if !self.tolerance.is_finite() || self.tolerance < 0.0 {
+            return Err(QuizError::ValueError(format!(
+                "Tolerance of answer `{}` must be a positive number",
+                self.answer.text
+            )));
+        }
+        self.answer.to_xml_with(writer, |writer| {
+            for (name, value) in [
+                ("tolerance", self.tolerance.to_string()),
+                ("tolerancetype", self.tolerance_type.code().to_string()),
+                (
+                    "correctanswerformat",
+                    self.correct_answer_format.code().to_string(),
+                ),
+                (
+                    "correctanswerlength",
+                    self.correct_answer_length.to_string(),
+                ),
+            ] {
+                writer.write(XmlEvent::start_element(name))?;
+                writer.write(XmlEvent::characters(&value))?;
+                writer.write(XmlEvent::end_element())?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl From<Answer> for CalculatedAnswer {
+    fn from(answer: Answer) -> Self {
+        Self {
+            answer,
+            tolerance: 0.01,
+            tolerance_type: ToleranceType::default(),
+            correct_answer_length: 2,
+            correct_answer_format: CorrectAnswerFormat::default(),
+        }
     }
 }
 
@@ -70,24 +290,28 @@ impl From<Answer> for Vec<Answer> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::xml_util::WriteContext;
     use std::io::{Read, Seek};
     use xml::writer::EmitterConfig;
 
     #[test]
     fn test_single_answer() {
         let mut tmp_file = tempfile::tempfile().unwrap();
-        let mut writer = EmitterConfig::new()
-            .perform_indent(true)
-            .create_writer(&tmp_file);
+        let mut writer = XmlWriter::new(
+            &tmp_file,
+            EmitterConfig::new().perform_indent(true),
+            WriteContext::default(),
+        );
 
         let mut answer = Answer::new(
-            100,
+            100.0,
             "Answer text".to_string(),
             "Particularly well answered!".to_string().into(),
         );
         answer.set_text_format(TextFormat::Moodle);
         answer.to_xml(&mut writer).unwrap();
         let mut buf = String::new();
+        drop(writer);
         tmp_file.seek(std::io::SeekFrom::Start(0)).unwrap();
         tmp_file.read_to_string(&mut buf).unwrap();
         let expected = r#"<?xml version="1.0" encoding="utf-8"?>
@@ -99,4 +323,14 @@ mod tests {
 </answer>"#;
         assert_eq!(expected, buf);
     }
+
+    #[test]
+    fn test_fraction_precision() {
+        assert_eq!(format_fraction(100.0), "100");
+        assert_eq!(format_fraction(100.0 / 3.0), "33.33333");
+        assert_eq!(format_fraction(-100.0 / 6.0), "-16.66667");
+        assert_eq!(format_fraction(100.0 / 7.0), "14.28571");
+        assert_eq!(format_fraction(12.5), "12.5");
+        assert_eq!(format_fraction(-0.0), "0");
+    }
 }
@@ -0,0 +1,623 @@
+//! Human-friendly authoring format for question banks.
+//!
+//! Question banks can be written in YAML (feature `yaml`) or TOML (feature `toml`) and loaded into a [`Quiz`],
+//! which can then be exported as Moodle XML. Both formats share the same schema:
+//!
+//! ```yaml
+//! category: capitals          # optional, see `Quiz::set_categories`
+//! questions:
+//!   - type: shortanswer       # multichoice, truefalse, shortanswer or essay
+//!     name: Capital of France
+//!     text: What is the capital of France?
+//!     format: html            # optional: html (default), moodle, markdown or plain
+//!     case_sensitive: false   # optional, shortanswer only
+//!     answers:
+//!       - text: Paris
+//!         correct: true       # same as `fraction: 100`
+//!         feedback: Yes, correct!
+//!   - type: multichoice
+//!     name: Capital of Finland
+//!     text: What is the capital of Finland?
+//!     single: true            # optional, default true
+//!     shuffle: true           # optional, default true
+//!     numbering: abc          # optional, default abc
+//!     correct_feedback: Correct!
+//!     partially_correct_feedback: Partially correct!
+//!     incorrect_feedback: Incorrect!
+//!     answers:
+//!       - text: Helsinki
+//!         correct: true
+//!       - Turku               # a plain entry is a distractor with fraction 0
+//!       - Tampere
+//!   - type: truefalse
+//!     name: Stockholm
+//!     text: Stockholm is the capital of Sweden.
+//!     answer: true
+//!   - type: essay
+//!     name: Capitals essay
+//!     text: Describe the history of your capital.
+//! ```
+//!
+//! Errors point to the line and column of the offending entry in the source document.
+use crate::{
+    answer::Answer,
+    question::{
+        EssayQuestion, MultiChoiceQuestion, Question, QuestionType, ShortAnswerQuestion,
+        TextFormat, TrueFalseQuestion,
+    },
+    quiz::{Quiz, QuizError},
+};
+use serde::{de, Deserialize, Deserializer};
+use std::{fmt, path::Path};
+
+/// Error type for loading an authored question bank.
+///
+/// ### Errors
+///
+/// ```Parse``` - The document is not valid YAML/TOML or does not follow the schema
+///
+/// ```Io``` - Reading or writing a file failed
+///
+/// ```Quiz``` - The loaded quiz could not be written as XML
+///
+/// ```UnknownFormat``` - The file extension is not one of the enabled formats
+#[derive(Debug)]
+pub enum AuthoringError {
+    Parse {
+        /// 1-based line of the error, if known
+        line: Option<usize>,
+        /// 1-based column of the error, if known
+        column: Option<usize>,
+        message: String,
+    },
+    Io(std::io::Error),
+    Quiz(QuizError),
+    UnknownFormat(String),
+}
+
+impl fmt::Display for AuthoringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthoringError::Parse {
+                line: Some(line),
+                column: Some(column),
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            AuthoringError::Parse {
+                line: Some(line),
+                message,
+                ..
+            } => write!(f, "line {}: {}", line, message),
+            AuthoringError::Parse { message, .. } => f.write_str(message),
+            AuthoringError::Io(e) => write!(f, "{}", e),
+            AuthoringError::Quiz(e) => write!(f, "{}", e),
+            AuthoringError::UnknownFormat(s) => write!(f, "Unknown question bank format: {}", s),
+        }
+    }
+}
+impl std::error::Error for AuthoringError {}
+impl From<std::io::Error> for AuthoringError {
+    fn from(e: std::io::Error) -> Self {
+        AuthoringError::Io(e)
+    }
+}
+impl From<QuizError> for AuthoringError {
+    fn from(e: QuizError) -> Self {
+        AuthoringError::Quiz(e)
+    }
+}
+
+/// The top-level document of a question bank.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuizDocument {
+    /// Category of all questions in the document.
+    pub category: Option<String>,
+    pub questions: Vec<QuestionDocument>,
+}
+
+/// The question types supported by the authoring format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuestionKind {
+    Multichoice,
+    TrueFalse,
+    ShortAnswer,
+    Essay,
+}
+
+/// A single question of the document.
+/// Type specific fields are rejected when used with other question types.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuestionDocument {
+    #[serde(rename = "type")]
+    pub kind: QuestionKind,
+    pub name: String,
+    /// The question text
+    pub text: String,
+    /// Text format of the question text and the answers, defaults to HTML.
+    #[serde(default, deserialize_with = "deserialize_text_format")]
+    pub format: Option<TextFormat>,
+    #[serde(default, deserialize_with = "deserialize_answers")]
+    pub answers: Vec<AnswerDocument>,
+    /// Shorthand for the correct answer of a true/false question.
+    pub answer: Option<bool>,
+    pub single: Option<bool>,
+    pub shuffle: Option<bool>,
+    pub numbering: Option<String>,
+    pub correct_feedback: Option<String>,
+    pub partially_correct_feedback: Option<String>,
+    pub incorrect_feedback: Option<String>,
+    pub case_sensitive: Option<bool>,
+}
+
+/// An answer of a question. Can be written either as a plain text, which makes it a distractor with fraction 0,
+/// or as a mapping with the `text` and optional `correct`, `fraction` and `feedback` keys.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnswerDocument {
+    pub text: String,
+    /// `correct: true` is a shorthand for `fraction: 100`.
+    pub correct: Option<bool>,
+    pub fraction: Option<f64>,
+    pub feedback: Option<String>,
+}
+
+impl AnswerDocument {
+    fn distractor(text: String) -> Self {
+        Self {
+            text,
+            correct: None,
+            fraction: None,
+            feedback: None,
+        }
+    }
+    fn into_answer(self, format: TextFormat) -> Result<Answer, String> {
+        let fraction = match (self.correct, self.fraction) {
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "answer `{}` must use either `correct` or `fraction`, not both",
+                    self.text
+                ))
+            }
+            (Some(true), None) => 100.0,
+            (_, Some(fraction)) => fraction,
+            _ => 0.0,
+        };
+        let mut answer = Answer::new(fraction, self.text, self.feedback);
+        answer.set_text_format(format);
+        Ok(answer)
+    }
+}
+
+/// Accepts both the plain and the mapping form of an answer.
+fn deserialize_answer<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<AnswerDocument, D::Error> {
+    struct AnswerVisitor;
+
+    impl<'de> de::Visitor<'de> for AnswerVisitor {
+        type Value = AnswerDocument;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an answer text or a mapping with `text`")
+        }
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(AnswerDocument::distractor(v.to_string()))
+        }
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(AnswerDocument::distractor(v.to_string()))
+        }
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(AnswerDocument::distractor(v.to_string()))
+        }
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+            Ok(AnswerDocument::distractor(v.to_string()))
+        }
+        fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+            Ok(AnswerDocument::distractor(v.to_string()))
+        }
+        fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            AnswerDocument::deserialize(de::value::MapAccessDeserializer::new(map))
+        }
+    }
+    deserializer.deserialize_any(AnswerVisitor)
+}
+
+/// Wrapper for deserializing answers in both forms inside `Vec<AnswerDocument>`.
+struct AnswerEntry(AnswerDocument);
+
+impl<'de> Deserialize<'de> for AnswerEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_answer(deserializer).map(AnswerEntry)
+    }
+}
+
+fn deserialize_answers<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<AnswerDocument>, D::Error> {
+    let entries = Vec::<AnswerEntry>::deserialize(deserializer)?;
+    Ok(entries.into_iter().map(|entry| entry.0).collect())
+}
+
+fn deserialize_text_format<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<TextFormat>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    TextFormat::from_name(&name).map(Some).ok_or_else(|| {
+        de::Error::custom(format!(
+            "unknown format `{}`, expected html, moodle, markdown or plain",
+            name
+        ))
+    })
+}
+
+impl QuestionDocument {
+    /// Rejects fields which are set but not used by the question type.
+    fn check_fields(&self) -> Result<(), String> {
+        let kind = self.kind;
+        let fields: [(&str, bool, &[QuestionKind]); 8] = [
+            ("answer", self.answer.is_some(), &[QuestionKind::TrueFalse]),
+            (
+                "single",
+                self.single.is_some(),
+                &[QuestionKind::Multichoice],
+            ),
+            (
+                "shuffle",
+                self.shuffle.is_some(),
+                &[QuestionKind::Multichoice],
+            ),
+            (
+                "numbering",
+                self.numbering.is_some(),
+                &[QuestionKind::Multichoice],
+            ),
+            (
+                "correct_feedback",
+                self.correct_feedback.is_some(),
+                &[QuestionKind::Multichoice],
+            ),
+            (
+                "partially_correct_feedback",
+                self.partially_correct_feedback.is_some(),
+                &[QuestionKind::Multichoice],
+            ),
+            (
+                "incorrect_feedback",
+                self.incorrect_feedback.is_some(),
+                &[QuestionKind::Multichoice],
+            ),
+            (
+                "case_sensitive",
+                self.case_sensitive.is_some(),
+                &[QuestionKind::ShortAnswer],
+            ),
+        ];
+        for (field, is_set, kinds) in fields {
+            if is_set && !kinds.contains(&kind) {
+                return Err(format!(
+                    "`{}` is not supported by {:?} questions",
+                    field, kind
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<QuestionDocument> for QuestionType {
+    type Error = String;
+
+    fn try_from(doc: QuestionDocument) -> Result<Self, Self::Error> {
+        doc.check_fields()?;
+        let format = doc.format.unwrap_or_default();
+        let answers = doc
+            .answers
+            .into_iter()
+            .map(|answer| answer.into_answer(format))
+            .collect::<Result<Vec<_>, _>>()?;
+        let name = doc.name;
+        let add_answers = |question: &mut dyn Question, answers: Vec<Answer>| {
+            question.set_text_format(format);
+            question
+                .add_answers(answers)
+                .map_err(|e| format!("question `{}`: {}", question.get_name(), e))
+        };
+        let question = match doc.kind {
+            QuestionKind::Multichoice => {
+                let mut question = MultiChoiceQuestion::new(
+                    name,
+                    doc.text,
+                    doc.single,
+                    doc.shuffle,
+                    doc.correct_feedback,
+                    doc.partially_correct_feedback,
+                    doc.incorrect_feedback,
+                    Some(doc.numbering.unwrap_or_else(|| "abc".to_string())),
+                );
+                add_answers(&mut question, answers)?;
+                question.into()
+            }
+            QuestionKind::TrueFalse => {
+                let answers = match (doc.answer, answers.is_empty()) {
+                    (Some(_), false) => {
+                        return Err(format!(
+                            "question `{}` must use either `answer` or `answers`, not both",
+                            name
+                        ))
+                    }
+                    (Some(correct), true) => vec![
+                        Answer::new(if correct { 100.0 } else { 0.0 }, "true".into(), None),
+                        Answer::new(if correct { 0.0 } else { 100.0 }, "false".into(), None),
+                    ],
+                    (None, _) => answers,
+                };
+                let mut question = TrueFalseQuestion::new(name, doc.text);
+                add_answers(&mut question, answers)?;
+                question.into()
+            }
+            QuestionKind::ShortAnswer => {
+                let mut question = ShortAnswerQuestion::new(name, doc.text, doc.case_sensitive);
+                add_answers(&mut question, answers)?;
+                question.into()
+            }
+            QuestionKind::Essay => {
+                let mut question = EssayQuestion::new(name, doc.text);
+                add_answers(&mut question, answers)?;
+                question.into()
+            }
+        };
+        Ok(question)
+    }
+}
+
+/// Same as [`QuizDocument`], but the questions are converted while deserializing
+/// so that the errors can be tied to a location in the source.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LoadedDocument {
+    category: Option<String>,
+    questions: Vec<LoadedQuestion>,
+}
+
+struct LoadedQuestion(QuestionType);
+
+impl<'de> Deserialize<'de> for LoadedQuestion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct QuestionVisitor;
+
+        impl<'de> de::Visitor<'de> for QuestionVisitor {
+            type Value = LoadedQuestion;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a question")
+            }
+            // The conversion must happen inside the mapping, otherwise the error
+            // would be reported at the location of the enclosing list.
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let doc =
+                    QuestionDocument::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                QuestionType::try_from(doc)
+                    .map(LoadedQuestion)
+                    .map_err(de::Error::custom)
+            }
+        }
+        deserializer.deserialize_map(QuestionVisitor)
+    }
+}
+
+impl LoadedDocument {
+    fn into_quiz(self) -> Quiz {
+        let mut quiz = Quiz::new(self.questions.into_iter().map(|q| q.0).collect());
+        if let Some(category) = self.category {
+            quiz.set_categories(vec![category.into()]);
+        }
+        quiz
+    }
+}
+
+/// Loads a quiz from a YAML document.
+///
+/// # Errors
+///
+/// Returns [`AuthoringError::Parse`] with the location of the problem when the document is not valid.
+#[cfg(feature = "yaml")]
+pub fn from_yaml_str(source: &str) -> Result<Quiz, AuthoringError> {
+    let document: LoadedDocument = serde_yaml::from_str(source).map_err(|e| {
+        let location = e.location();
+        let message = e.to_string();
+        // The location is reported separately, strip it from the message.
+        let message = match message.rsplit_once(" at line ") {
+            Some((message, _)) if location.is_some() => message.to_string(),
+            _ => message,
+        };
+        AuthoringError::Parse {
+            line: location.as_ref().map(|l| l.line()),
+            column: location.as_ref().map(|l| l.column()),
+            message,
+        }
+    })?;
+    Ok(document.into_quiz())
+}
+
+/// Loads a quiz from a TOML document. The questions are given as an array of tables, `[[questions]]`.
+///
+/// # Errors
+///
+/// Returns [`AuthoringError::Parse`] with the location of the problem when the document is not valid.
+#[cfg(feature = "toml")]
+pub fn from_toml_str(source: &str) -> Result<Quiz, AuthoringError> {
+    // The TOML deserializer reports conversion errors at the start of the whole array of tables,
+    // so the questions are converted afterwards using the span of each table.
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct TomlDocument {
+        category: Option<String>,
+        questions: Vec<toml::Spanned<QuestionDocument>>,
+    }
+    let parse_error = |offset: Option<usize>, message: String| {
+        let (line, column) = match offset {
+            Some(offset) => {
+                let (line, column) = line_and_column(source, offset);
+                (Some(line), Some(column))
+            }
+            None => (None, None),
+        };
+        AuthoringError::Parse {
+            line,
+            column,
+            message,
+        }
+    };
+    let document: TomlDocument = toml::from_str(source)
+        .map_err(|e| parse_error(e.span().map(|span| span.start), e.message().to_string()))?;
+    let questions = document
+        .questions
+        .into_iter()
+        .map(|question| {
+            let start = question.span().start;
+            QuestionType::try_from(question.into_inner())
+                .map(LoadedQuestion)
+                .map_err(|message| parse_error(Some(start), message))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(LoadedDocument {
+        category: document.category,
+        questions,
+    }
+    .into_quiz())
+}
+
+/// Converts a byte offset into a 1-based line and column.
+#[cfg(feature = "toml")]
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+/// Loads a quiz from a file. The format is chosen by the extension: `.yaml`/`.yml` or `.toml`.
+pub fn load_file(path: &str) -> Result<Quiz, AuthoringError> {
+    let source = std::fs::read_to_string(path)?;
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    match extension {
+        #[cfg(feature = "yaml")]
+        "yaml" | "yml" => from_yaml_str(&source),
+        #[cfg(feature = "toml")]
+        "toml" => from_toml_str(&source),
+        _ => Err(AuthoringError::UnknownFormat(path.to_string())),
+    }
+}
+
+/// Converts an authored question bank file into a Moodle XML file.
+pub fn convert_file(input: &str, output: &str) -> Result<(), AuthoringError> {
+    let mut quiz = load_file(input)?;
+    quiz.to_xml(output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml_shorthands() {
+        let source = r#"
+category: capitals
+questions:
+  - type: multichoice
+    name: Capital of Finland
+    text: What is the capital of Finland?
+    answers:
+      - text: Helsinki
+        correct: true
+        feedback: Correct!
+      - Turku
+      - Tampere
+"#;
+        let mut quiz = from_yaml_str(source).unwrap();
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let buf = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(buf.contains("<text>$course$/capitals/</text>"));
+        assert!(buf.contains(r#"<answer fraction="100" format="html">"#));
+        assert_eq!(
+            buf.matches(r#"<answer fraction="0" format="html">"#)
+                .count(),
+            2
+        );
+        assert!(buf.contains("<answernumbering>abc</answernumbering>"));
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml_error_location() {
+        let source = r#"questions:
+  - type: truefalse
+    name: Stockholm
+    text: Stockholm is the capital of Sweden.
+    answer: true
+  - type: shortanswer
+    name: Capital of France
+    text: What is the capital of France?
+    answers:
+      - text: Paris
+        fraction: 50
+"#;
+        match from_yaml_str(source) {
+            Err(AuthoringError::Parse { line, message, .. }) => {
+                assert_eq!(line, Some(6));
+                assert!(message.contains("at least 100"), "{}", message);
+            }
+            _ => panic!("expected a parse error"),
+        }
+        let source =
+            "questions:\n  - type: essay\n    name: Essay\n    text: Write\n    single: true\n";
+        match from_yaml_str(source) {
+            Err(AuthoringError::Parse { line, message, .. }) => {
+                assert_eq!(line, Some(2));
+                assert!(message.contains("`single`"), "{}", message);
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_document() {
+        let source = r#"
+[[questions]]
+type = "truefalse"
+name = "Stockholm"
+text = "Stockholm is the capital of Sweden."
+answer = true
+
+[[questions]]
+type = "shortanswer"
+name = "Capital of France"
+text = "What is the capital of France?"
+answers = [{ text = "Paris", correct = true }, "Lyon"]
+"#;
+        assert!(from_toml_str(source).is_ok());
+        let source = "[[questions]]\ntype = \"truefalse\"\nname = \"x\"\ntext = \"y\"\nanswer = false\n\n[[questions]]\ntype = \"essay\"\nname = \"x\"\ntext = \"y\"\nanswer = true\n";
+        match from_toml_str(source) {
+            Err(AuthoringError::Parse { line, message, .. }) => {
+                assert_eq!(line, Some(7));
+                assert!(message.contains("`answer`"), "{}", message);
+            }
+            _ => panic!("expected a parse error"),
+        }
+        let source = "[[questions]]\ntype = \"numerical\"\nname = \"x\"\ntext = \"y\"\n";
+        match from_toml_str(source) {
+            Err(AuthoringError::Parse { line, .. }) => assert_eq!(line, Some(2)),
+            _ => panic!("expected a parse error"),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Conversion of texts between formats when a quiz is written.
+//!
+//! [`TextFormat`] is only a label telling Moodle how to render a text, and Moodle renders Markdown differently
+//! between versions. With [`Quiz::set_text_conversion`](crate::quiz::Quiz::set_text_conversion) the texts are
+//! converted to one format when the quiz is written, and the `format` attributes are written accordingly:
+//!
+//! - To HTML, Markdown is rendered to sanitized HTML and plain text is escaped, keeping its line breaks.
+//!   Rendering Markdown needs the `markdown` feature.
+//! - To plain text, the tags of HTML are removed, and Markdown is first rendered to HTML.
+//!
+//! Texts in the Moodle auto-format may contain both text and HTML, so only Moodle can render them and they are
+//! written as they are. Texts without a format, such as names and tags, are not converted. The markup of
+//! [multilang] blocks is kept, and the contents of every block are converted on their own.
+//!
+//! ```
+//! use moodle_xml::{convert::{convert, TextConversion}, question::TextFormat};
+//!
+//! let (text, format) = convert("1 < 2\n3 > 2", TextFormat::PlainText, TextConversion::Html).unwrap();
+//! assert_eq!(text, "1 &lt; 2<br />\n3 &gt; 2");
+//! assert_eq!(format, TextFormat::HTML);
+//! ```
+use crate::{html, multilang, question::TextFormat, quiz::QuizError};
+use std::borrow::Cow;
+
+/// Format the texts of a quiz are converted to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextConversion {
+    /// Converts Markdown and plain text to HTML.
+    Html,
+    /// Converts HTML and Markdown to plain text.
+    PlainText,
+}
+
+impl TextConversion {
+    /// Returns the format a text in `format` has after the conversion.
+    pub fn format(self, format: TextFormat) -> TextFormat {
+        match (self, format) {
+            (_, TextFormat::Moodle) => TextFormat::Moodle,
+            (TextConversion::Html, _) => TextFormat::HTML,
+            (TextConversion::PlainText, _) => TextFormat::PlainText,
+        }
+    }
+}
+
+/// Converts a text in `format` and returns it with its new format. Only the contents of multilang blocks are
+/// converted, so that their markup is neither escaped nor stripped.
+///
+/// # Errors
+///
+/// Returns a `ValueError` if the text is in Markdown and the `markdown` feature is not enabled.
+pub fn convert(
+    text: &str,
+    format: TextFormat,
+    conversion: TextConversion,
+) -> Result<(Cow<'_, str>, TextFormat), QuizError> {
+    let converted = match multilang::change_contents(text, |part| {
+        Ok(convert_part(part, format, conversion)?.into_owned())
+    })? {
+        Some(converted) => Cow::Owned(converted),
+        None => convert_part(text, format, conversion)?,
+    };
+    Ok((converted, conversion.format(format)))
+}
+
+/// Converts a text without multilang blocks.
+fn convert_part(
+    text: &str,
+    format: TextFormat,
+    conversion: TextConversion,
+) -> Result<Cow<'_, str>, QuizError> {
+    Ok(match (conversion, format) {
+        (_, TextFormat::Moodle)
+        | (TextConversion::Html, TextFormat::HTML)
+        | (TextConversion::PlainText, TextFormat::PlainText) => Cow::Borrowed(text),
+        (TextConversion::Html, TextFormat::Markdown) => Cow::Owned(render_markdown(text)?),
+        (TextConversion::Html, TextFormat::PlainText) => Cow::Owned(html::from_text(text)),
+        (TextConversion::PlainText, TextFormat::HTML) => Cow::Owned(html::to_text(text)),
+        (TextConversion::PlainText, TextFormat::Markdown) => {
+            Cow::Owned(html::to_text(&render_markdown(text)?))
+        }
+    })
+}
+
+/// Renders Markdown to sanitized HTML.
+#[cfg(feature = "markdown")]
+pub fn render_markdown(text: &str) -> Result<String, QuizError> {
+    use pulldown_cmark::{html::push_html, Options, Parser};
+    let mut rendered = String::new();
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    push_html(&mut rendered, Parser::new_ext(text, options));
+    Ok(html::sanitize(rendered.trim_end()))
+}
+
+/// Renders Markdown to sanitized HTML.
+#[cfg(not(feature = "markdown"))]
+pub fn render_markdown(_text: &str) -> Result<String, QuizError> {
+    Err(QuizError::ValueError(
+        "Markdown texts can be converted only with the `markdown` feature".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let html = "<p>Paris is the <b>capital</b>.</p>";
+        let (text, format) = convert(html, TextFormat::HTML, TextConversion::PlainText).unwrap();
+        assert_eq!(text, "Paris is the capital.");
+        assert_eq!(format, TextFormat::PlainText);
+        let (text, format) = convert(html, TextFormat::HTML, TextConversion::Html).unwrap();
+        assert!(matches!(text, Cow::Borrowed(_)));
+        assert_eq!(format, TextFormat::HTML);
+        let (text, format) = convert("*auto*", TextFormat::Moodle, TextConversion::Html).unwrap();
+        assert_eq!((text.as_ref(), format), ("*auto*", TextFormat::Moodle));
+    }
+
+    #[test]
+    fn test_convert_multilang() {
+        let text = r#"<span lang="fi" class="multilang">1 < 2</span><span lang="en" class="multilang">Hi</span>"#;
+        let (text, _) = convert(text, TextFormat::PlainText, TextConversion::Html).unwrap();
+        assert_eq!(
+            text,
+            r#"<span lang="fi" class="multilang">1 &lt; 2</span><span lang="en" class="multilang">Hi</span>"#
+        );
+        let (text, _) = convert(&text, TextFormat::HTML, TextConversion::PlainText).unwrap();
+        assert_eq!(
+            text,
+            r#"<span lang="fi" class="multilang">1 < 2</span><span lang="en" class="multilang">Hi</span>"#
+        );
+        let text = "{mlang fi}<b>Hei</b>{mlang}{mlang en}Hello{mlang}";
+        let (text, _) = convert(text, TextFormat::HTML, TextConversion::PlainText).unwrap();
+        assert_eq!(text, "{mlang fi}Hei{mlang}{mlang en}Hello{mlang}");
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn test_convert_markdown() {
+        let markdown = "# Title\n\nSome **bold** text <script>alert(1)</script>\n\n[link](javascript:alert(1))";
+        let (text, format) = convert(markdown, TextFormat::Markdown, TextConversion::Html).unwrap();
+        assert_eq!(
+            text,
+            "<h1>Title</h1>\n<p>Some <strong>bold</strong> text </p>\n<p><a>link</a></p>"
+        );
+        assert_eq!(format, TextFormat::HTML);
+        let (text, _) = convert(markdown, TextFormat::Markdown, TextConversion::PlainText).unwrap();
+        assert_eq!(text, "Title\n\nSome bold text\n\nlink");
+    }
+
+    #[cfg(not(feature = "markdown"))]
+    #[test]
+    fn test_convert_markdown() {
+        assert!(convert("*a*", TextFormat::Markdown, TextConversion::Html).is_err());
+        assert!(convert("*a*", TextFormat::Markdown, TextConversion::PlainText).is_err());
+    }
+}
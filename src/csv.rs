@@ -0,0 +1,491 @@
+//! Import and export of spreadsheets as CSV.
+//!
+//! Every row after the header is a question. The columns are found by their header, which can be changed with
+//! [`ColumnMapping`]. By default a spreadsheet looks like this:
+//!
+//! | category | name    | question                        | correct  | feedback | distractor1 | distractor2 |
+//! |----------|---------|---------------------------------|----------|----------|-------------|-------------|
+//! | Capitals | France  | What is the capital of France?  | Paris    | Correct! | Lyon        | Marseille   |
+//! | Capitals | Finland | What is the capital of Finland? | Helsinki |          |             |             |
+//!
+//! Rows with distractors become multiple choice questions and rows without them short answer questions,
+//! unless the optional `type` column says otherwise. The `correct` column can hold several answers separated
+//! by `|`, which makes a multiple choice question accept several answers and a short answer question accept
+//! each of them. The right answers of a multiple choice question share the grade equally, such as 33.33333% for
+//! each of three. The `category`, `name`, `feedback` and `type` columns are optional. The questions keep the
+//! order of the rows, and a row with an empty category is in the category of the row before it, as questions
+//! after a category marker are in Moodle XML.
+//!
+//! ```
+//! use moodle_xml::csv::{self, CsvOptions};
+//!
+//! let source = "question,correct,distractor1,distractor2
+//! What is the capital of France?,Paris,Lyon,Marseille
+//! ";
+//! let quiz = csv::parse(source, &CsvOptions::default()).unwrap();
+//! assert_eq!(quiz.len(), 1);
+//! ```
+use crate::{
+    answer::{shared_fraction, Answer, FRACTION_TOLERANCE},
+    question::{MultiChoiceQuestion, Question, QuestionType, ShortAnswerQuestion},
+    quiz::{Category, EmptyError, Quiz, QuizError},
+};
+use std::fmt;
+
+/// Headers of the columns used for each field. Headers are matched case-insensitively.
+#[derive(Debug, Clone)]
+pub struct ColumnMapping {
+    /// Either `multichoice` or `shortanswer`.
+    pub question_type: String,
+    pub category: String,
+    pub name: String,
+    pub question: String,
+    pub correct: String,
+    /// Feedback of the right answers.
+    pub feedback: String,
+    /// Prefix of the distractor columns, which is followed by a number such as `distractor1`.
+    pub distractor_prefix: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            question_type: "type".to_string(),
+            category: "category".to_string(),
+            name: "name".to_string(),
+            question: "question".to_string(),
+            correct: "correct".to_string(),
+            feedback: "feedback".to_string(),
+            distractor_prefix: "distractor".to_string(),
+        }
+    }
+}
+
+/// Options for reading and writing CSV.
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub columns: ColumnMapping,
+    /// Field delimiter, spreadsheets in many locales use `;`.
+    pub delimiter: u8,
+    /// Separator of several right answers in the `correct` column.
+    pub answer_separator: char,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            columns: ColumnMapping::default(),
+            delimiter: b',',
+            answer_separator: '|',
+        }
+    }
+}
+
+/// Error when reading CSV, `row` is the 1-based row in the spreadsheet where the header is row 1.
+#[derive(Debug)]
+pub struct CsvError {
+    pub row: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "row {}: {}", self.row, self.message)
+    }
+}
+impl std::error::Error for CsvError {}
+
+/// Positions of the mapped columns in the header.
+struct Columns {
+    question_type: Option<usize>,
+    category: Option<usize>,
+    name: Option<usize>,
+    question: usize,
+    correct: usize,
+    feedback: Option<usize>,
+    distractors: Vec<usize>,
+}
+
+impl Columns {
+    fn from_header(
+        header: &::csv::StringRecord,
+        mapping: &ColumnMapping,
+    ) -> Result<Self, CsvError> {
+        let find = |name: &str| {
+            header
+                .iter()
+                .position(|column| column.trim().eq_ignore_ascii_case(name))
+        };
+        let require = |name: &str| {
+            find(name).ok_or_else(|| CsvError {
+                row: 1,
+                message: format!("missing column `{}`", name),
+            })
+        };
+        let prefix = mapping.distractor_prefix.to_lowercase();
+        let mut distractors: Vec<(u32, usize)> = header
+            .iter()
+            .enumerate()
+            .filter_map(|(index, column)| {
+                let number = column
+                    .trim()
+                    .to_lowercase()
+                    .strip_prefix(&prefix)?
+                    .parse()
+                    .ok()?;
+                Some((number, index))
+            })
+            .collect();
+        distractors.sort();
+        Ok(Self {
+            question_type: find(&mapping.question_type),
+            category: find(&mapping.category),
+            name: find(&mapping.name),
+            question: require(&mapping.question)?,
+            correct: require(&mapping.correct)?,
+            feedback: find(&mapping.feedback),
+            distractors: distractors.into_iter().map(|(_, index)| index).collect(),
+        })
+    }
+}
+
+/// Parses CSV into a quiz. Questions are put in the category of their row, or of the row before it if the
+/// category is empty, in the order of the rows.
+///
+/// # Errors
+///
+/// Returns a [`CsvError`] with the row number of the first bad row.
+pub fn parse(source: &str, options: &CsvOptions) -> Result<Quiz, CsvError> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .flexible(true)
+        .from_reader(source.as_bytes());
+    let header = reader.headers().map_err(|e| CsvError {
+        row: 1,
+        message: e.to_string(),
+    })?;
+    let columns = Columns::from_header(header, &options.columns)?;
+
+    let mut uncategorized = Vec::new();
+    let mut sections: Vec<(Category, Vec<QuestionType>)> = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let row = index + 2;
+        let record = record.map_err(|e| CsvError {
+            row,
+            message: e.to_string(),
+        })?;
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let question =
+            parse_row(&record, &columns, options).map_err(|message| CsvError { row, message })?;
+        let category = columns
+            .category
+            .and_then(|column| record.get(column))
+            .map(str::trim)
+            .filter(|category| !category.is_empty());
+        match (sections.last_mut(), category) {
+            (Some((current, questions)), Some(category)) if **current == category => {
+                questions.push(question)
+            }
+            (_, Some(category)) => sections.push((category.into(), vec![question])),
+            (Some((_, questions)), None) => questions.push(question),
+            (None, None) => uncategorized.push(question),
+        }
+    }
+    let mut quiz = Quiz::new(uncategorized);
+    for (category, questions) in sections {
+        quiz.add_category(category, questions);
+    }
+    Ok(quiz)
+}
+
+fn parse_row(
+    record: &::csv::StringRecord,
+    columns: &Columns,
+    options: &CsvOptions,
+) -> Result<QuestionType, String> {
+    let field = |column: Option<usize>| {
+        column
+            .and_then(|column| record.get(column))
+            .map(str::trim)
+            .unwrap_or_default()
+    };
+    let text = field(Some(columns.question));
+    if text.is_empty() {
+        return Err(format!("empty `{}` column", options.columns.question));
+    }
+    let correct: Vec<&str> = field(Some(columns.correct))
+        .split(options.answer_separator)
+        .map(str::trim)
+        .filter(|answer| !answer.is_empty())
+        .collect();
+    if correct.is_empty() {
+        return Err(format!("empty `{}` column", options.columns.correct));
+    }
+    let distractors: Vec<&str> = columns
+        .distractors
+        .iter()
+        .map(|column| field(Some(*column)))
+        .filter(|distractor| !distractor.is_empty())
+        .collect();
+    let feedback = Some(field(columns.feedback).to_string()).filter(|f| !f.is_empty());
+    let name = match field(columns.name) {
+        "" => text.chars().take(80).collect(),
+        name => name.to_string(),
+    };
+    let multichoice = match field(columns.question_type).to_lowercase().as_str() {
+        "" => !distractors.is_empty(),
+        "multichoice" => true,
+        "shortanswer" => false,
+        other => {
+            return Err(format!(
+                "unknown question type `{}`, expected `multichoice` or `shortanswer`",
+                other
+            ))
+        }
+    };
+    let right =
+        |fraction: f64, text: &str| Answer::new(fraction, text.to_string(), feedback.clone());
+    if multichoice {
+        if distractors.is_empty() && correct.len() < 2 {
+            return Err("multiple choice question needs at least 2 answers".to_string());
+        }
+        let mut question = MultiChoiceQuestion::new(
+            name,
+            text.to_string(),
+            Some(correct.len() == 1),
+            None,
+            None,
+            None,
+            None,
+            Some("abc".to_string()),
+        );
+        let fraction = shared_fraction(correct.len());
+        let mut answers: Vec<Answer> = correct.iter().map(|text| right(fraction, text)).collect();
+        answers.extend(
+            distractors
+                .iter()
+                .map(|text| Answer::new(0.0, text.to_string(), None)),
+        );
+        question.add_answers(answers).map_err(|e| e.to_string())?;
+        Ok(question.into())
+    } else {
+        if !distractors.is_empty() {
+            return Err("short answer question cannot have distractors".to_string());
+        }
+        let mut question = ShortAnswerQuestion::new(name, text.to_string(), None);
+        question
+            .add_answers(correct.iter().map(|text| right(100.0, text)).collect())
+            .map_err(|e| e.to_string())?;
+        Ok(question.into())
+    }
+}
+
+/// Writes the multiple choice and short answer questions of a quiz as CSV, in the format read by [`parse`].
+///
+/// # Errors
+///
+/// Returns a [`QuizError`] if the quiz is empty or has a question which cannot be written as a row:
+/// other question types, partial fractions or different feedback between the right answers.
+pub fn to_csv(quiz: &Quiz, options: &CsvOptions) -> Result<String, QuizError> {
+    if quiz.is_empty() {
+        return Err(EmptyError.into());
+    }
+    let mut rows = Vec::new();
+    let mut max_distractors = 0;
+    for (category, question) in quiz.questions() {
+        let unsupported = |reason: &str| {
+            QuizError::ValueError(format!(
+                "Question `{}` cannot be written as CSV: {}",
+                question.as_question().get_name(),
+                reason
+            ))
+        };
+        let (question_type, answers) = match question {
+            QuestionType::Multichoice(q) => ("multichoice", q.answers()),
+            QuestionType::ShortAnswer(q) => ("shortanswer", q.answers()),
+            _ => {
+                return Err(unsupported(
+                    "only multiple choice and short answer questions are supported",
+                ))
+            }
+        };
+        let (correct, distractors): (Vec<&Answer>, Vec<&Answer>) =
+            answers.iter().partition(|answer| answer.fraction > 0.0);
+        if distractors.iter().any(|answer| answer.fraction < 0.0) {
+            return Err(unsupported("negative fractions are not supported"));
+        }
+        if question_type == "shortanswer" && !distractors.is_empty() {
+            return Err(unsupported("answers with fraction 0 are not supported"));
+        }
+        // Every right answer gets the fraction `parse` gives it
+        let expected = if question_type == "shortanswer" {
+            100.0
+        } else {
+            shared_fraction(correct.len())
+        };
+        if correct
+            .iter()
+            .any(|a| (a.fraction - expected).abs() > FRACTION_TOLERANCE)
+        {
+            return Err(unsupported("partial fractions are not supported"));
+        }
+        let feedback = correct.first().and_then(|a| a.feedback.clone());
+        if correct.iter().any(|a| a.feedback != feedback) {
+            return Err(unsupported("the right answers have different feedback"));
+        }
+        max_distractors = max_distractors.max(distractors.len());
+        let mut row = vec![
+            question_type.to_string(),
+            category.map(|c| c.to_string()).unwrap_or_default(),
+            question.as_question().get_name().to_string(),
+            question.as_question().get_description().to_string(),
+            correct
+                .iter()
+                .map(|a| a.text.as_str())
+                .collect::<Vec<_>>()
+                .join(&options.answer_separator.to_string()),
+            feedback.unwrap_or_default(),
+        ];
+        row.extend(distractors.iter().map(|a| a.text.clone()));
+        rows.push(row);
+    }
+
+    let columns = &options.columns;
+    let mut header = vec![
+        columns.question_type.clone(),
+        columns.category.clone(),
+        columns.name.clone(),
+        columns.question.clone(),
+        columns.correct.clone(),
+        columns.feedback.clone(),
+    ];
+    header.extend((1..=max_distractors).map(|n| format!("{}{}", columns.distractor_prefix, n)));
+    let mut writer = ::csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .from_writer(Vec::new());
+    let write_error = |e: ::csv::Error| QuizError::ValueError(e.to_string());
+    writer.write_record(&header).map_err(write_error)?;
+    for mut row in rows {
+        row.resize(header.len(), String::new());
+        writer.write_record(&row).map_err(write_error)?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| QuizError::ValueError(e.to_string()))?;
+    String::from_utf8(bytes).map_err(|e| QuizError::ValueError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let source = "Category;Name;Question;Correct;Feedback;Distractor2;Distractor1
+;Finland;Which city is the capital of Finland?;Helsinki|Helsingfors;;;
+Capitals;France;What is the capital of France?;Paris;Correct!;Marseille;Lyon
+Nordic;Nordic;Which are Nordic capitals?;Oslo|Helsinki|Stockholm;;Berlin;
+Capitals;Sweden;What is the capital of Sweden?;Stockholm;;;
+;Norway;What is the capital of Norway?;Oslo;;;
+";
+        let options = CsvOptions {
+            delimiter: b';',
+            ..Default::default()
+        };
+        let quiz = parse(source, &options).unwrap();
+        let questions: Vec<_> = quiz.questions().collect();
+        assert_eq!(questions.len(), 5);
+        // The rows keep their order, and an empty category continues the category of the row before
+        let categories: Vec<Option<&str>> = questions
+            .iter()
+            .map(|(category, _)| category.map(|c| c.as_str()))
+            .collect();
+        assert_eq!(
+            categories,
+            vec![
+                None,
+                Some("Capitals"),
+                Some("Nordic"),
+                Some("Capitals"),
+                Some("Capitals")
+            ]
+        );
+        match questions[0].1 {
+            QuestionType::ShortAnswer(q) => {
+                let texts: Vec<&str> = q.answers().iter().map(|a| a.text.as_str()).collect();
+                assert_eq!(texts, vec!["Helsinki", "Helsingfors"]);
+            }
+            _ => panic!("expected shortanswer"),
+        }
+        assert_eq!(**questions[1].0.unwrap(), "Capitals");
+        match questions[1].1 {
+            QuestionType::Multichoice(q) => {
+                assert!(q.single);
+                let texts: Vec<&str> = q.answers().iter().map(|a| a.text.as_str()).collect();
+                assert_eq!(texts, vec!["Paris", "Lyon", "Marseille"]);
+                assert_eq!(q.answers()[0].feedback.as_deref(), Some("Correct!"));
+            }
+            _ => panic!("expected multichoice"),
+        }
+        match questions[2].1 {
+            QuestionType::Multichoice(q) => {
+                assert!(!q.single);
+                let fractions: Vec<f64> = q.answers().iter().map(|a| a.fraction).collect();
+                assert_eq!(fractions, vec![100.0 / 3.0, 100.0 / 3.0, 100.0 / 3.0, 0.0]);
+            }
+            _ => panic!("expected multichoice"),
+        }
+
+        let exported = to_csv(&quiz, &options).unwrap();
+        assert!(exported.starts_with(
+            "type;category;name;question;correct;feedback;distractor1;distractor2\n\
+             shortanswer;;Finland;Which city is the capital of Finland?;Helsinki|Helsingfors;;;\n"
+        ));
+        assert!(exported
+            .ends_with("shortanswer;Capitals;Norway;What is the capital of Norway?;Oslo;;;\n"));
+        let reimported = parse(&exported, &options).unwrap();
+        assert_eq!(to_csv(&reimported, &options).unwrap(), exported);
+    }
+
+    #[test]
+    fn test_column_mapping() {
+        let source = "Kysymys,Oikea,Väärä1\nMikä on Suomen pääkaupunki?,Helsinki,Turku\n";
+        let options = CsvOptions {
+            columns: ColumnMapping {
+                question: "kysymys".to_string(),
+                correct: "oikea".to_string(),
+                distractor_prefix: "väärä".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let quiz = parse(source, &options).unwrap();
+        let (_, question) = quiz.questions().next().unwrap();
+        assert!(matches!(question, QuestionType::Multichoice(_)));
+    }
+
+    #[test]
+    fn test_bad_rows() {
+        let cases = [
+            ("correct\nParis\n", 1),
+            (
+                "question,correct\nCapital of France?,Paris\nCapital of Finland?,\n",
+                3,
+            ),
+            ("question,correct,type\nCapital?,Paris,truefalse\n", 2),
+            ("question,correct,type\nCapital?,Paris,multichoice\n", 2),
+            (
+                "question,correct,distractor1\nCapital?,Paris,Lyon\n,,\n,Helsinki,Turku\n",
+                4,
+            ),
+        ];
+        for (source, row) in cases {
+            match parse(source, &CsvOptions::default()) {
+                Err(error) => assert_eq!(error.row, row, "{}", error),
+                Ok(_) => panic!("expected an error for {:?}", source),
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Datasets of calculated questions.
+//!
+//! Every wildcard of a calculated question, such as `{a}`, has a dataset definition with the range its
+//! values are sampled from. Moodle shows the question with one item set, the `n`th item of every dataset.
+//! The items are generated with [`DatasetDefinition::generate_items`], which uses a seeded [`Random`] so that
+//! the same seed always writes the same file.
+//!
+//! ```
+//! use moodle_xml::dataset::{DatasetDefinition, Random};
+//!
+//! let mut dataset = DatasetDefinition::new("a".to_string(), 1.0, 10.0, 1);
+//! dataset.generate_items(5, &mut Random::new(42)).unwrap();
+//! assert_eq!(dataset.items().len(), 5);
+//! assert!(dataset.items().iter().all(|item| (1.0..=10.0).contains(item)));
+//! ```
+use crate::{
+    quiz::QuizError,
+    xml_util::{write_named_formatted_scope, write_text_tag, XmlWriter},
+};
+use xml::writer::XmlEvent;
+
+/// Moodle accepts at most 100 items in a dataset.
+pub const MAX_ITEMS: usize = 100;
+
+/// How the values of a dataset are sampled from its range.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Distribution {
+    #[default]
+    Uniform,
+    /// Logarithmically uniform, small values are as likely as large ones. The range must be positive.
+    LogUniform,
+}
+
+impl Distribution {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Distribution::Uniform => "uniform",
+            Distribution::LogUniform => "loguniform",
+        }
+    }
+    /// Parses a distribution from its Moodle name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "uniform" => Some(Distribution::Uniform),
+            "loguniform" => Some(Distribution::LogUniform),
+            _ => None,
+        }
+    }
+}
+
+/// Deterministic pseudo-random number generator (SplitMix64) for generating dataset items.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+    /// Returns the next random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    /// Returns a random number in `0.0..1.0`.
+    pub fn next_f64(&mut self) -> f64 {
+        // The 53 high bits fill the mantissa of a double
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Definition of a wildcard of a calculated question and the items generated for it.
+#[derive(Debug, Clone)]
+pub struct DatasetDefinition {
+    /// Name of the wildcard, `a` for `{a}`.
+    pub name: String,
+    pub minimum: f64,
+    pub maximum: f64,
+    /// Number of decimals of the generated values.
+    pub decimals: u8,
+    pub distribution: Distribution,
+    /// Shared datasets are used by every calculated question of the category with the same wildcard.
+    pub shared: bool,
+    items: Vec<f64>,
+}
+
+impl DatasetDefinition {
+    pub fn new(name: String, minimum: f64, maximum: f64, decimals: u8) -> Self {
+        Self {
+            name,
+            minimum,
+            maximum,
+            decimals,
+            distribution: Distribution::default(),
+            shared: false,
+            items: Vec::new(),
+        }
+    }
+    /// Adds items to the dataset.
+    pub fn add_items(&mut self, items: Vec<f64>) {
+        self.items.extend(items);
+    }
+    /// Returns the items of the dataset.
+    pub fn items(&self) -> &[f64] {
+        &self.items
+    }
+    /// Replaces the items with `count` values sampled from the range and rounded to `decimals`.
+    /// Returns an error if the range is invalid for the distribution, has no value with `decimals` decimals or
+    /// `count` is more than Moodle accepts.
+    pub fn generate_items(&mut self, count: usize, random: &mut Random) -> Result<(), QuizError> {
+        self.check_range()?;
+        if count > MAX_ITEMS {
+            return Err(QuizError::ValueError(format!(
+                "Dataset `{}` can have at most {} items",
+                self.name, MAX_ITEMS
+            )));
+        }
+        // The items are whole multiples of the last decimal, rounded inwards at the ends so that they stay in
+        // the range
+        let scale = 10f64.powi(i32::from(self.decimals));
+        let (low, high) = (
+            inward(self.minimum * scale, f64::ceil),
+            inward(self.maximum * scale, f64::floor),
+        );
+        if low > high {
+            return Err(QuizError::ValueError(format!(
+                "Dataset `{}` has no value with {} decimals in its range {}-{}",
+                self.name, self.decimals, self.minimum, self.maximum
+            )));
+        }
+        self.items = (0..count)
+            .map(|_| {
+                let sample = random.next_f64();
+                let value = match self.distribution {
+                    Distribution::Uniform => self.minimum + sample * (self.maximum - self.minimum),
+                    Distribution::LogUniform => {
+                        let (low, high) = (self.minimum.ln(), self.maximum.ln());
+                        (low + sample * (high - low)).exp()
+                    }
+                };
+                let value = (value * scale).round().clamp(low, high) / scale;
+                self.format(value).parse().unwrap_or(value)
+            })
+            .collect();
+        Ok(())
+    }
+    /// Formats an item with the decimals of the dataset.
+    fn format(&self, value: f64) -> String {
+        format!("{:.*}", usize::from(self.decimals), value)
+    }
+    fn check_range(&self) -> Result<(), QuizError> {
+        if !self.minimum.is_finite() || !self.maximum.is_finite() || self.minimum > self.maximum {
+            return Err(QuizError::ValueError(format!(
+                "Dataset `{}` has an invalid range {}-{}",
+                self.name, self.minimum, self.maximum
+            )));
+        }
+        if self.distribution == Distribution::LogUniform && self.minimum <= 0.0 {
+            return Err(QuizError::ValueError(format!(
+                "Dataset `{}` must have a positive range for the loguniform distribution",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+    /// Writes the `<dataset_definition>` element.
+    pub(crate) fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.check_range()?;
+        if self.items.is_empty() {
+            return Err(QuizError::EmptyError(format!(
+                "Dataset `{}` has no items, generate them with `generate_items`",
+                self.name
+            )));
+        }
+        if self.items.len() > MAX_ITEMS {
+            return Err(QuizError::ValueError(format!(
+                "Dataset `{}` can have at most {} items",
+                self.name, MAX_ITEMS
+            )));
+        }
+        let text = |writer: &mut XmlWriter, name: &str, value: &str| {
+            write_named_formatted_scope(writer, name, None, |writer| {
+                write_text_tag(writer, value, false)
+            })
+        };
+        let value = |writer: &mut XmlWriter, name: &str, value: &str| {
+            writer.write(XmlEvent::start_element(name))?;
+            writer.write(XmlEvent::characters(value))?;
+            writer.write(XmlEvent::end_element())?;
+            Ok::<(), QuizError>(())
+        };
+        writer.write(XmlEvent::start_element("dataset_definition"))?;
+        text(
+            writer,
+            "status",
+            if self.shared { "shared" } else { "private" },
+        )?;
+        text(writer, "name", &self.name)?;
+        value(writer, "type", "calculated")?;
+        text(writer, "distribution", self.distribution.name())?;
+        text(writer, "minimum", &self.minimum.to_string())?;
+        text(writer, "maximum", &self.maximum.to_string())?;
+        text(writer, "decimals", &self.decimals.to_string())?;
+        value(writer, "itemcount", &self.items.len().to_string())?;
+        writer.write(XmlEvent::start_element("dataset_items"))?;
+        for (index, item) in self.items.iter().enumerate() {
+            writer.write(XmlEvent::start_element("dataset_item"))?;
+            value(writer, "number", &(index + 1).to_string())?;
+            value(writer, "value", &self.format(*item))?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        writer.write(XmlEvent::end_element())?;
+        value(writer, "number_of_items", &self.items.len().to_string())?;
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Rounds a scaled end of a range inwards with `round`, unless it is already whole but for the error of the
+/// multiplication, such as 0.3 * 10.
+fn inward(scaled: f64, round: fn(f64) -> f64) -> f64 {
+    let nearest = scaled.round();
+    if (scaled - nearest).abs() <= 1e-9 * nearest.abs().max(1.0) {
+        nearest
+    } else {
+        round(scaled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_is_deterministic() {
+        let first: Vec<u64> = {
+            let mut random = Random::new(7);
+            (0..3).map(|_| random.next_u64()).collect()
+        };
+        let mut random = Random::new(7);
+        assert_eq!(first, (0..3).map(|_| random.next_u64()).collect::<Vec<_>>());
+        assert!((0..1000).all(|_| (0.0..1.0).contains(&random.next_f64())));
+    }
+
+    #[test]
+    fn test_generate_items() {
+        let mut dataset = DatasetDefinition::new("x".to_string(), 0.5, 2.5, 2);
+        dataset.distribution = Distribution::LogUniform;
+        dataset.generate_items(50, &mut Random::new(1)).unwrap();
+        assert_eq!(dataset.items().len(), 50);
+        for item in dataset.items() {
+            assert!((0.5..=2.5).contains(item));
+            assert_eq!(*item, (item * 100.0).round() / 100.0);
+        }
+        assert!(dataset.generate_items(101, &mut Random::new(1)).is_err());
+        dataset.minimum = 0.0;
+        assert!(dataset.generate_items(5, &mut Random::new(1)).is_err());
+
+        // The ends are rounded inwards, so the items stay in the range
+        let mut dataset = DatasetDefinition::new("y".to_string(), 0.54, 0.66, 1);
+        dataset.generate_items(50, &mut Random::new(2)).unwrap();
+        assert!(dataset.items().iter().all(|item| *item == 0.6));
+        let mut dataset = DatasetDefinition::new("z".to_string(), 0.3, 0.7, 1);
+        dataset.generate_items(100, &mut Random::new(3)).unwrap();
+        assert!(dataset.items().contains(&0.3) && dataset.items().contains(&0.7));
+        let mut dataset = DatasetDefinition::new("w".to_string(), 0.55, 0.56, 1);
+        assert!(dataset.generate_items(5, &mut Random::new(1)).is_err());
+    }
+}
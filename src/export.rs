@@ -0,0 +1,247 @@
+//! Options for the XML written by [`Quiz::to_xml`](crate::quiz::Quiz::to_xml).
+//!
+//! The default options write the file as before: indented with two spaces, `\n` line endings, an XML declaration
+//! with the `utf-8` encoding, and the questions in the order they were added. The question types of this crate
+//! write their elements in the order Moodle's own exporter writes them, so that files exported from Moodle and
+//! written by this crate diff cleanly. Generated files which are committed to version control can be written
+//! compactly, with the elements of custom question types in Moodle's order as well, and with the questions of
+//! each category sorted by name or ID number.
+//!
+//! ```
+//! use moodle_xml::{export::{ElementOrder, ExportOptions, Indent, QuestionOrder}, prelude::*};
+//!
+//! let mut quiz = Quiz::new(vec![EssayQuestion::new("Essay".into(), "Describe Helsinki.".into()).into()]);
+//! quiz.set_export_options(ExportOptions {
+//!     indent: Indent::Compact,
+//!     element_order: ElementOrder::Canonical,
+//!     question_order: QuestionOrder::Name,
+//!     ..ExportOptions::default()
+//! });
+//! ```
+use crate::{question::QuestionType, quiz::QuizError, xml_util::XmlWriter};
+use xml::{
+    common::XmlVersion,
+    reader::{self, ParserConfig},
+    writer::{EmitterConfig, XmlEvent},
+};
+
+/// Indentation of the elements.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Indent {
+    /// Every level is indented with this many spaces.
+    Spaces(usize),
+    /// Every level is indented with a tab.
+    Tab,
+    /// No indentation or line breaks between the elements.
+    Compact,
+}
+
+/// Line endings of indented output.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+/// Order of the elements of a question.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ElementOrder {
+    /// The order each question type writes its elements in.
+    #[default]
+    Insertion,
+    /// The order of Moodle's exporter: the common elements of every question first, then the elements of the
+    /// question type in the order it writes them, and the hints and tags last. The question types of this crate
+    /// already write their elements in Moodle's order, so only custom question types are reordered.
+    Canonical,
+}
+
+impl ElementOrder {
+    /// Writes a question with its elements in this order.
+    pub(crate) fn write(
+        self,
+        question: &QuestionType,
+        writer: &mut XmlWriter,
+    ) -> Result<(), QuizError> {
+        match (self, question) {
+            (ElementOrder::Canonical, QuestionType::Custom(_)) => write_canonical(question, writer),
+            _ => question.to_xml(writer),
+        }
+    }
+}
+
+/// Order of the questions within each category.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum QuestionOrder {
+    /// The order the questions were added in.
+    #[default]
+    Insertion,
+    /// Sorted by name. Questions with the same name keep their order.
+    Name,
+    /// Sorted by ID number, with the questions without one last. Questions with the same ID number keep their
+    /// order.
+    IdNumber,
+}
+
+impl QuestionOrder {
+    /// Returns the questions of a category in this order.
+    pub(crate) fn sort(self, questions: &[QuestionType]) -> Vec<&QuestionType> {
+        let mut sorted: Vec<_> = questions.iter().collect();
+        match self {
+            QuestionOrder::Insertion => {}
+            QuestionOrder::Name => sorted.sort_by_key(|question| question.as_question().get_name()),
+            QuestionOrder::IdNumber => sorted.sort_by_key(|question| {
+                let idnumber = question.as_question().get_idnumber();
+                (idnumber.is_none(), idnumber)
+            }),
+        }
+        sorted
+    }
+}
+
+/// Options for writing the XML of a quiz.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportOptions {
+    pub indent: Indent,
+    /// Whether the file starts with an XML declaration.
+    pub declaration: bool,
+    /// Encoding named in the XML declaration. Files are always written in UTF-8, so this can only change how it
+    /// is spelled, such as `UTF-8` like Moodle writes it.
+    pub encoding: String,
+    pub line_ending: LineEnding,
+    pub element_order: ElementOrder,
+    pub question_order: QuestionOrder,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            indent: Indent::Spaces(2),
+            declaration: true,
+            encoding: "utf-8".to_string(),
+            line_ending: LineEnding::default(),
+            element_order: ElementOrder::default(),
+            question_order: QuestionOrder::default(),
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Checks that the options can be written.
+    pub(crate) fn check(&self) -> Result<(), QuizError> {
+        let encoding = self.encoding.to_ascii_lowercase();
+        if encoding != "utf-8" && encoding != "utf8" {
+            return Err(QuizError::ValueError(format!(
+                "Files are written in UTF-8, not in {}",
+                self.encoding
+            )));
+        }
+        Ok(())
+    }
+    /// Returns the configuration of the writer for these options. The declaration is written by
+    /// [`ExportOptions::start_document`].
+    pub(crate) fn emitter_config(&self) -> EmitterConfig {
+        let config = EmitterConfig::new()
+            .write_document_declaration(false)
+            .line_separator(match self.line_ending {
+                LineEnding::Lf => "\n",
+                LineEnding::CrLf => "\r\n",
+            });
+        match self.indent {
+            Indent::Spaces(width) => config.perform_indent(true).indent_string(" ".repeat(width)),
+            Indent::Tab => config.perform_indent(true).indent_string("\t"),
+            Indent::Compact => config.perform_indent(false),
+        }
+    }
+    /// Writes the XML declaration, if the options have one.
+    pub(crate) fn start_document(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        if self.declaration {
+            writer.write(XmlEvent::StartDocument {
+                version: XmlVersion::Version10,
+                encoding: Some(&self.encoding),
+                standalone: None,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Elements every question starts with, in Moodle's order.
+const HEAD: &[&str] = &[
+    "name",
+    "questiontext",
+    "generalfeedback",
+    "defaultgrade",
+    "penalty",
+    "hidden",
+    "idnumber",
+];
+
+/// Elements every question ends with, in Moodle's order.
+const TAIL: &[&str] = &["hint", "tags"];
+
+/// Returns the rank of an element of a question by its name. The elements of the question type get the rank of
+/// the hints, so they come after the common elements but keep their order before the hints and tags.
+fn rank(name: &str) -> usize {
+    let position = |name: &str| HEAD.iter().chain(TAIL).position(|element| *element == name);
+    match position(name) {
+        Some(position) => 2 * position + 1,
+        None => 2 * HEAD.len(),
+    }
+}
+
+/// Writes a question with its elements in Moodle's order. The question is written compactly into memory with the
+/// options of `writer`, and its elements are then sorted into `writer`.
+fn write_canonical(question: &QuestionType, writer: &mut XmlWriter) -> Result<(), QuizError> {
+    let mut written = Vec::new();
+    let config = EmitterConfig::new().write_document_declaration(false);
+    let mut buffer = XmlWriter::new(&mut written, config, writer.context().clone());
+    question.to_xml(&mut buffer)?;
+    buffer.flush()?;
+    drop(buffer);
+    let parser = ParserConfig::new()
+        .trim_whitespace(false)
+        .whitespace_to_characters(true)
+        .cdata_to_characters(false)
+        .ignore_comments(false)
+        .create_reader(written.as_slice());
+    let mut depth = 0usize;
+    // The elements of the question, each with its events
+    let mut children: Vec<Vec<reader::XmlEvent>> = Vec::new();
+    for event in parser {
+        let event = event.map_err(|e| QuizError::ValueError(e.to_string()))?;
+        match &event {
+            reader::XmlEvent::StartDocument { .. } | reader::XmlEvent::EndDocument => continue,
+            reader::XmlEvent::StartElement { .. } => depth += 1,
+            reader::XmlEvent::EndElement { .. } => depth -= 1,
+            _ => {}
+        }
+        match (depth, &event) {
+            // The start and the end of the question element itself
+            (1, reader::XmlEvent::StartElement { .. }) => write_event(writer, &event)?,
+            (0, reader::XmlEvent::EndElement { .. }) => {
+                children.sort_by_key(|child| match child.first() {
+                    Some(reader::XmlEvent::StartElement { name, .. }) => rank(&name.local_name),
+                    _ => rank(""),
+                });
+                for event in children.drain(..).flatten() {
+                    write_event(writer, &event)?;
+                }
+                write_event(writer, &event)?;
+            }
+            (2, reader::XmlEvent::StartElement { .. }) => children.push(vec![event]),
+            _ => match children.last_mut() {
+                Some(child) => child.push(event),
+                None => children.push(vec![event]),
+            },
+        }
+    }
+    Ok(())
+}
+
+fn write_event(writer: &mut XmlWriter, event: &reader::XmlEvent) -> Result<(), QuizError> {
+    if let Some(event) = event.as_writer_event() {
+        writer.write(event)?;
+    }
+    Ok(())
+}
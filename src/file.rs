@@ -0,0 +1,236 @@
+//! Files embedded in questions, such as images.
+//!
+//! Moodle XML stores the files base64 encoded next to the question text, which refers to them with
+//! `@@PLUGINFILE@@/name`:
+//!
+//! ```
+//! use moodle_xml::{file::EmbeddedFile, prelude::*};
+//!
+//! let image = EmbeddedFile::new("map.png".to_string(), vec![0x89, b'P', b'N', b'G']);
+//! let mut question = EssayQuestion::new(
+//!     "Map".to_string(),
+//!     format!(r#"Describe the map. <img src="{}">"#, image.url()),
+//! );
+//! question.add_files(vec![image]);
+//! ```
+use std::{io, path::Path};
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A file embedded in a question.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedFile {
+    /// File name, which the question text uses in `@@PLUGINFILE@@/name`.
+    pub name: String,
+    /// Contents of the file.
+    pub data: Vec<u8>,
+}
+
+impl EmbeddedFile {
+    pub fn new(name: String, data: Vec<u8>) -> Self {
+        Self { name, data }
+    }
+    /// Reads a file from disk, using its file name as the name of the embedded file.
+    pub fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"))?;
+        Ok(Self::new(name.to_string(), std::fs::read(path)?))
+    }
+    /// Creates a file from base64 encoded contents, as written in Moodle XML.
+    /// Returns `None` if the contents are not valid base64.
+    pub fn from_base64(name: String, encoded: &str) -> Option<Self> {
+        Some(Self::new(name, decode_base64(encoded)?))
+    }
+    /// Returns the contents encoded as base64.
+    pub fn to_base64(&self) -> String {
+        encode_base64(&self.data)
+    }
+    /// Returns the URL which refers to the file in question text, `@@PLUGINFILE@@/name`.
+    pub fn url(&self) -> String {
+        format!("@@PLUGINFILE@@/{}", self.name)
+    }
+    /// Returns the media type of the file based on its extension.
+    pub fn mime_type(&self) -> &'static str {
+        let extension = self
+            .name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "svg" => "image/svg+xml",
+            "webp" => "image/webp",
+            "mp3" => "audio/mpeg",
+            "mp4" => "video/mp4",
+            "pdf" => "application/pdf",
+            _ => "application/octet-stream",
+        }
+    }
+    /// Returns the width and height of a PNG or JPEG image in pixels, read from its header.
+    /// Returns `None` for other files and for images whose header is broken.
+    pub fn image_size(&self) -> Option<(u32, u32)> {
+        png_size(&self.data).or_else(|| jpeg_size(&self.data))
+    }
+    /// Returns the file as a `data:` URL, which embeds it directly in HTML.
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.mime_type(), self.to_base64())
+    }
+}
+
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// The IHDR chunk, which has the size, comes first after the signature.
+fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(PNG_SIGNATURE) || data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let number = |start: usize| {
+        Some(u32::from_be_bytes(
+            data.get(start..start + 4)?.try_into().ok()?,
+        ))
+    };
+    Some((number(16)?, number(20)?))
+}
+
+/// Walks the JPEG segments until a start of frame segment, which has the size.
+fn jpeg_size(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let number = |start: usize| {
+        Some(u16::from_be_bytes(
+            data.get(start..start + 2)?.try_into().ok()?,
+        ))
+    };
+    let mut position = 2;
+    loop {
+        if *data.get(position)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(position + 1)?;
+        match marker {
+            // Fill bytes before a marker
+            0xff => position += 1,
+            // Markers without a segment
+            0x01 | 0xd0..=0xd7 => position += 2,
+            // Start of frame, except the huffman table, arithmetic coding and JPEG extension markers
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                let (height, width) = (number(position + 5)?, number(position + 7)?);
+                return Some((u32::from(width), u32::from(height)));
+            }
+            // Start of scan or end of image before the frame
+            0xd9 | 0xda => return None,
+            _ => position += 2 + usize::from(number(position + 2)?),
+        }
+    }
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut output = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let sextet = (group >> (18 - 6 * index)) & 0x3f;
+                output.push(BASE64_ALPHABET[sextet as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    // Moodle wraps long base64 content on several lines
+    let sextets: Vec<u8> = encoded
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .take_while(|byte| *byte != b'=')
+        .map(|byte| {
+            BASE64_ALPHABET
+                .iter()
+                .position(|c| *c == byte)
+                .map(|p| p as u8)
+        })
+        .collect::<Option<_>>()?;
+    if sextets.len() % 4 == 1 {
+        return None;
+    }
+    let mut output = Vec::with_capacity(sextets.len() * 3 / 4);
+    for chunk in sextets.chunks(4) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (index, sextet)| {
+                group | (u32::from(*sextet) << (18 - 6 * index))
+            });
+        let bytes = group.to_be_bytes();
+        output.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        let cases: [(&[u8], &str); 4] = [
+            (b"", ""),
+            (b"M", "TQ=="),
+            (b"Mo", "TW8="),
+            (b"Moodle", "TW9vZGxl"),
+        ];
+        for (data, encoded) in cases {
+            assert_eq!(encode_base64(data), encoded);
+            assert_eq!(decode_base64(encoded).unwrap(), data);
+        }
+        assert_eq!(decode_base64("TW9v\nZGxl").unwrap(), b"Moodle");
+        assert!(decode_base64("T").is_none());
+        assert!(decode_base64("TW9*").is_none());
+    }
+
+    #[test]
+    fn test_data_url() {
+        let file = EmbeddedFile::new("dot.PNG".to_string(), b"Mo".to_vec());
+        assert_eq!(file.url(), "@@PLUGINFILE@@/dot.PNG");
+        assert_eq!(file.data_url(), "data:image/png;base64,TW8=");
+    }
+
+    #[test]
+    fn test_image_size() {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(&[0, 0, 0, 13]);
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        let file = EmbeddedFile::new("body.png".to_string(), png);
+        assert_eq!(file.image_size(), Some((640, 480)));
+
+        // SOI, an APP0 segment with 2 bytes of data and a baseline SOF0 segment
+        let jpeg = vec![
+            0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, 0xff, 0xc0, 0x00, 0x11, 0x08, 0x01,
+            0x2c, 0x00, 0xc8,
+        ];
+        let file = EmbeddedFile::new("body.jpg".to_string(), jpeg);
+        assert_eq!(file.image_size(), Some((200, 300)));
+
+        let file = EmbeddedFile::new("body.jpg".to_string(), vec![0xff, 0xd8, 0xff, 0xd9]);
+        assert_eq!(file.image_size(), None);
+        let file = EmbeddedFile::new("notes.txt".to_string(), b"PNG".to_vec());
+        assert_eq!(file.image_size(), None);
+    }
+}
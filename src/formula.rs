@@ -0,0 +1,556 @@
+//! Formulas of calculated questions, such as `{a} * sin({b})`.
+//!
+//! Moodle evaluates the formulas with PHP, so a mistake is only noticed when the question is imported or,
+//! worse, when a student sees a wrong answer. [`Formula::parse`] checks the syntax and [`Formula::evaluate`]
+//! computes the value for a set of wildcard values, which catches broken formulas before Moodle sees them.
+//!
+//! ```
+//! use moodle_xml::formula::Formula;
+//! use std::collections::HashMap;
+//!
+//! let formula = Formula::parse("{a} + pow({b}, 2)").unwrap();
+//! assert_eq!(formula.variables(), ["a", "b"]);
+//! let values = HashMap::from([("a".to_string(), 1.0), ("b".to_string(), 3.0)]);
+//! assert_eq!(formula.evaluate(&values).unwrap(), 10.0);
+//! assert!(Formula::parse("{a} ^ 2").is_err());
+//! ```
+use std::{collections::HashMap, fmt};
+
+/// Error in a formula. The position is the 1-based character where the problem was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "character {}: {}", self.position, self.message)
+    }
+}
+impl std::error::Error for FormulaError {}
+
+/// A parsed formula.
+#[derive(Debug, Clone)]
+pub struct Formula {
+    text: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    expression: Expression,
+    position: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Expression {
+    Number(f64),
+    Variable(String),
+    Negate(Box<Node>),
+    Binary(char, Box<Node>, Box<Node>),
+    Call(String, Vec<Node>),
+}
+
+/// Functions Moodle allows in formulas, with their minimum and maximum number of arguments.
+const FUNCTIONS: &[(&str, usize, usize)] = &[
+    ("abs", 1, 1),
+    ("acos", 1, 1),
+    ("acosh", 1, 1),
+    ("asin", 1, 1),
+    ("asinh", 1, 1),
+    ("atan", 1, 1),
+    ("atan2", 2, 2),
+    ("atanh", 1, 1),
+    ("ceil", 1, 1),
+    ("cos", 1, 1),
+    ("cosh", 1, 1),
+    ("deg2rad", 1, 1),
+    ("exp", 1, 1),
+    ("expm1", 1, 1),
+    ("floor", 1, 1),
+    ("fmod", 2, 2),
+    ("log", 1, 2),
+    ("log10", 1, 1),
+    ("log1p", 1, 1),
+    ("max", 1, usize::MAX),
+    ("min", 1, usize::MAX),
+    ("pi", 0, 0),
+    ("pow", 2, 2),
+    ("rad2deg", 1, 1),
+    ("round", 1, 2),
+    ("sin", 1, 1),
+    ("sinh", 1, 1),
+    ("sqrt", 1, 1),
+    ("tan", 1, 1),
+    ("tanh", 1, 1),
+];
+
+impl Formula {
+    /// Parses a formula. Wildcards are written as `{name}`, and the operators `+`, `-`, `*`, `/`, `%` and `**`
+    /// and the functions of Moodle, such as `sqrt` and `pow`, are supported.
+    pub fn parse(text: &str) -> Result<Self, FormulaError> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            index: 0,
+        };
+        let root = parser.parse_sum()?;
+        parser.skip_whitespace();
+        if let Some(c) = parser.peek() {
+            return Err(parser.error(format!("unexpected `{}`", c)));
+        }
+        Ok(Self {
+            text: text.to_string(),
+            root,
+        })
+    }
+    /// Returns the text of the formula.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    /// Returns the names of the wildcards in the order they first appear.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables = Vec::new();
+        collect_variables(&self.root, &mut variables);
+        variables
+    }
+    /// Evaluates the formula with the given wildcard values.
+    /// Returns an error if a wildcard has no value or the result is not a finite number.
+    pub fn evaluate(&self, values: &HashMap<String, f64>) -> Result<f64, FormulaError> {
+        let value = evaluate(&self.root, values)?;
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(FormulaError {
+                position: 1,
+                message: format!("`{}` is not a finite number", self.text),
+            })
+        }
+    }
+}
+
+fn collect_variables<'a>(node: &'a Node, variables: &mut Vec<&'a str>) {
+    match &node.expression {
+        Expression::Number(_) => {}
+        Expression::Variable(name) => {
+            if !variables.contains(&name.as_str()) {
+                variables.push(name);
+            }
+        }
+        Expression::Negate(operand) => collect_variables(operand, variables),
+        Expression::Binary(_, left, right) => {
+            collect_variables(left, variables);
+            collect_variables(right, variables);
+        }
+        Expression::Call(_, arguments) => {
+            for argument in arguments {
+                collect_variables(argument, variables);
+            }
+        }
+    }
+}
+
+fn evaluate(node: &Node, values: &HashMap<String, f64>) -> Result<f64, FormulaError> {
+    let error = |message: String| FormulaError {
+        position: node.position,
+        message,
+    };
+    Ok(match &node.expression {
+        Expression::Number(value) => *value,
+        Expression::Variable(name) => *values
+            .get(name)
+            .ok_or_else(|| error(format!("wildcard `{{{}}}` has no value", name)))?,
+        Expression::Negate(operand) => -evaluate(operand, values)?,
+        Expression::Binary(operator, left, right) => {
+            let left = evaluate(left, values)?;
+            let right = evaluate(right, values)?;
+            match operator {
+                '+' => left + right,
+                '-' => left - right,
+                '*' => left * right,
+                '/' | '%' if right == 0.0 => return Err(error("division by zero".to_string())),
+                '/' => left / right,
+                '%' => left % right,
+                _ => left.powf(right),
+            }
+        }
+        Expression::Call(name, arguments) => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| evaluate(argument, values))
+                .collect::<Result<Vec<f64>, FormulaError>>()?;
+            let first = arguments.first().copied().unwrap_or_default();
+            let second = arguments.get(1).copied();
+            match name.as_str() {
+                "abs" => first.abs(),
+                "acos" => first.acos(),
+                "acosh" => first.acosh(),
+                "asin" => first.asin(),
+                "asinh" => first.asinh(),
+                "atan" => first.atan(),
+                "atan2" => first.atan2(second.unwrap_or_default()),
+                "atanh" => first.atanh(),
+                "ceil" => first.ceil(),
+                "cos" => first.cos(),
+                "cosh" => first.cosh(),
+                "deg2rad" => first.to_radians(),
+                "exp" => first.exp(),
+                "expm1" => first.exp_m1(),
+                "floor" => first.floor(),
+                "fmod" => first % second.unwrap_or_default(),
+                "log" => match second {
+                    Some(base) => first.log(base),
+                    None => first.ln(),
+                },
+                "log10" => first.log10(),
+                "log1p" => first.ln_1p(),
+                "max" => arguments.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                "min" => arguments.iter().copied().fold(f64::INFINITY, f64::min),
+                "pi" => std::f64::consts::PI,
+                "pow" => first.powf(second.unwrap_or_default()),
+                "rad2deg" => first.to_degrees(),
+                "round" => {
+                    let scale = 10f64.powf(second.unwrap_or_default().trunc());
+                    (first * scale).round() / scale
+                }
+                "sin" => first.sin(),
+                "sinh" => first.sinh(),
+                "sqrt" => first.sqrt(),
+                "tan" => first.tan(),
+                _ => first.tanh(),
+            }
+        }
+    })
+}
+
+struct Parser {
+    chars: Vec<char>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.index += 1;
+        }
+    }
+    fn error(&self, message: String) -> FormulaError {
+        FormulaError {
+            position: self.index + 1,
+            message,
+        }
+    }
+    fn node(expression: Expression, position: usize) -> Node {
+        Node {
+            expression,
+            position,
+        }
+    }
+    /// sum := product (('+' | '-') product)*
+    fn parse_sum(&mut self) -> Result<Node, FormulaError> {
+        let mut left = self.parse_product()?;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(operator @ ('+' | '-')) => {
+                    let position = self.index + 1;
+                    self.index += 1;
+                    let right = self.parse_product()?;
+                    left = Self::node(
+                        Expression::Binary(operator, Box::new(left), Box::new(right)),
+                        position,
+                    );
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+    /// product := unary (('*' | '/' | '%') unary)*
+    fn parse_product(&mut self) -> Result<Node, FormulaError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('*') if self.chars.get(self.index + 1) != Some(&'*') => {}
+                Some('/' | '%') => {}
+                _ => return Ok(left),
+            }
+            let operator = self.chars[self.index];
+            let position = self.index + 1;
+            self.index += 1;
+            let right = self.parse_unary()?;
+            left = Self::node(
+                Expression::Binary(operator, Box::new(left), Box::new(right)),
+                position,
+            );
+        }
+    }
+    /// unary := ('-' | '+') unary | power
+    fn parse_unary(&mut self) -> Result<Node, FormulaError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('-') => {
+                let position = self.index + 1;
+                self.index += 1;
+                let operand = self.parse_unary()?;
+                Ok(Self::node(Expression::Negate(Box::new(operand)), position))
+            }
+            Some('+') => {
+                self.index += 1;
+                self.parse_unary()
+            }
+            _ => self.parse_power(),
+        }
+    }
+    /// power := primary ('**' unary)?, which is right associative and binds tighter than unary minus on its left
+    fn parse_power(&mut self) -> Result<Node, FormulaError> {
+        let base = self.parse_primary()?;
+        self.skip_whitespace();
+        match (self.peek(), self.chars.get(self.index + 1)) {
+            (Some('*'), Some('*')) => {
+                let position = self.index + 1;
+                self.index += 2;
+                let exponent = self.parse_unary()?;
+                Ok(Self::node(
+                    Expression::Binary('^', Box::new(base), Box::new(exponent)),
+                    position,
+                ))
+            }
+            (Some('^'), _) => Err(self.error(
+                "`^` is a bitwise operator in Moodle, use `**` or `pow()` for powers".to_string(),
+            )),
+            _ => Ok(base),
+        }
+    }
+    /// primary := number | '{' name '}' | name '(' arguments ')' | '(' sum ')'
+    fn parse_primary(&mut self) -> Result<Node, FormulaError> {
+        self.skip_whitespace();
+        let position = self.index + 1;
+        match self.peek() {
+            None => Err(self.error("unexpected end of formula".to_string())),
+            Some('(') => {
+                self.index += 1;
+                let inner = self.parse_sum()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Some('{') => {
+                self.index += 1;
+                let start = self.index;
+                while self
+                    .peek()
+                    .is_some_and(|c| !matches!(c, '}' | '{' | '>' | '<' | ' ' | '"' | '\'' | '`'))
+                {
+                    self.index += 1;
+                }
+                let name: String = self.chars[start..self.index].iter().collect();
+                if !name.starts_with(char::is_alphabetic) {
+                    return Err(FormulaError {
+                        position,
+                        message: "wildcard name must start with a letter".to_string(),
+                    });
+                }
+                self.expect('}')?;
+                Ok(Self::node(Expression::Variable(name), position))
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.parse_number(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let start = self.index;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    self.index += 1;
+                }
+                let name: String = self.chars[start..self.index].iter().collect();
+                let Some(&(_, min, max)) = FUNCTIONS.iter().find(|(known, ..)| *known == name)
+                else {
+                    return Err(FormulaError {
+                        position,
+                        message: format!(
+                            "unknown function `{}`, wildcards are written as `{{{}}}`",
+                            name, name
+                        ),
+                    });
+                };
+                self.skip_whitespace();
+                self.expect('(')?;
+                let mut arguments = Vec::new();
+                self.skip_whitespace();
+                if self.peek() != Some(')') {
+                    loop {
+                        arguments.push(self.parse_sum()?);
+                        self.skip_whitespace();
+                        if self.peek() != Some(',') {
+                            break;
+                        }
+                        self.index += 1;
+                    }
+                }
+                self.expect(')')?;
+                if arguments.len() < min || arguments.len() > max {
+                    return Err(FormulaError {
+                        position,
+                        message: format!(
+                            "function `{}` does not take {} arguments",
+                            name,
+                            arguments.len()
+                        ),
+                    });
+                }
+                Ok(Self::node(Expression::Call(name, arguments), position))
+            }
+            Some(c) => Err(self.error(format!("unexpected `{}`", c))),
+        }
+    }
+    fn parse_number(&mut self) -> Result<Node, FormulaError> {
+        let position = self.index + 1;
+        let start = self.index;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.index += 1;
+        }
+        // Exponent such as 1.5e-3
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let mut end = self.index + 1;
+            if matches!(self.chars.get(end), Some('+' | '-')) {
+                end += 1;
+            }
+            if self.chars.get(end).is_some_and(char::is_ascii_digit) {
+                self.index = end;
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.index += 1;
+                }
+            }
+        }
+        let text: String = self.chars[start..self.index].iter().collect();
+        let value = text.parse().map_err(|_| FormulaError {
+            position,
+            message: format!("`{}` is not a number", text),
+        })?;
+        Ok(Self::node(Expression::Number(value), position))
+    }
+    fn expect(&mut self, expected: char) -> Result<(), FormulaError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.index += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected `{}`, found `{}`", expected, c))),
+            None => Err(self.error(format!("expected `{}`", expected))),
+        }
+    }
+}
+
+/// Returns the names of the `{name}` wildcards in a text, such as a question text.
+pub(crate) fn wildcards(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        let end = rest
+            .find(['}', '{', '>', '<', ' ', '"', '\'', '`'])
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+        if rest[end..].starts_with('}') && name.starts_with(char::is_alphabetic) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+            rest = &rest[end + 1..];
+        }
+    }
+    names
+}
+
+/// Returns the `{=formula}` expressions in a text, such as an answer of a calculated multichoice question.
+pub(crate) fn embedded_formulas(text: &str) -> Vec<&str> {
+    let mut formulas = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{=") {
+        rest = &rest[start + 2..];
+        // The formula ends at the brace which closes it, wildcards inside have their own braces
+        let mut depth = 0;
+        let end = rest.char_indices().find_map(|(index, c)| match c {
+            '{' => {
+                depth += 1;
+                None
+            }
+            '}' if depth == 0 => Some(index),
+            '}' => {
+                depth -= 1;
+                None
+            }
+            _ => None,
+        });
+        match end {
+            Some(end) => {
+                formulas.push(&rest[..end]);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                formulas.push(rest);
+                break;
+            }
+        }
+    }
+    formulas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(formula: &str, values: &[(&str, f64)]) -> Result<f64, FormulaError> {
+        let values = values
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        Formula::parse(formula)?.evaluate(&values)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate("1 + 2 * 3", &[]).unwrap(), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3", &[]).unwrap(), 9.0);
+        assert_eq!(evaluate("-2 ** 2", &[]).unwrap(), -4.0);
+        assert_eq!(evaluate("2 ** 3 ** 2", &[]).unwrap(), 512.0);
+        assert_eq!(evaluate("10 - 4 - 3", &[]).unwrap(), 3.0);
+        assert_eq!(evaluate("7 % 4 + 1.5e1", &[]).unwrap(), 18.0);
+        assert_eq!(
+            evaluate("{a} * {b1}", &[("a", 2.0), ("b1", 4.0)]).unwrap(),
+            8.0
+        );
+        assert_eq!(evaluate("round(pi() * 100, -1)", &[]).unwrap(), 310.0);
+        assert_eq!(evaluate("max(1, {x}, 3)", &[("x", 5.0)]).unwrap(), 5.0);
+        assert_eq!(evaluate("log(8, 2)", &[]).unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |formula| evaluate(formula, &[("a", 0.0)]).unwrap_err();
+        assert_eq!(error("{a} +").position, 6);
+        assert_eq!(error("({a} + 1").message, "expected `)`");
+        assert!(error("{a} ^ 2").message.contains("bitwise"));
+        assert!(error("a + 1").message.contains("`{a}`"));
+        assert!(error("sqrt(1, 2)").message.contains("arguments"));
+        assert_eq!(error("1 / {a}").message, "division by zero");
+        assert_eq!(error("{b}").message, "wildcard `{b}` has no value");
+        assert!(error("sqrt(-1)").message.contains("not a finite number"));
+        assert_eq!(error("{1a}").position, 1);
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert_eq!(
+            wildcards("Add {a} and {b}, not {1} or { a}. {a}"),
+            ["a", "b"]
+        );
+        assert_eq!(
+            embedded_formulas("{={a} + 1} and {=sqrt({b})}"),
+            ["{a} + 1", "sqrt({b})"]
+        );
+    }
+}
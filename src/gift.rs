@@ -0,0 +1,801 @@
+//! Import and export of the [GIFT format](https://docs.moodle.org/404/en/GIFT_format).
+//!
+//! Supported are multiple choice, true/false, short answer, matching, numerical, essay and description questions,
+//! categories with `$CATEGORY:`, comments and escape sequences.
+//!
+//! ```
+//! use moodle_xml::gift;
+//!
+//! let source = r#"
+//! // The capital of France
+//! ::Capitals::What is the capital of France? {=Paris#Yes! ~Lyon ~Marseille}
+//!
+//! ::Pi::What is the value of pi to two decimals? {#3.14:0.005}
+//! "#;
+//! let quiz = gift::parse(source).unwrap();
+//! assert_eq!(quiz.len(), 2);
+//! let exported = gift::to_gift(&quiz).unwrap();
+//! assert!(exported.starts_with("::Capitals::What is the capital of France? {"));
+//! ```
+use crate::{
+    answer::{format_fraction, Answer, NumericalAnswer},
+    question::{
+        DescriptionQuestion, EssayQuestion, MatchingQuestion, MultiChoiceQuestion,
+        NumericalQuestion, Question, QuestionType, ShortAnswerQuestion, SubQuestion, TextFormat,
+        TrueFalseQuestion,
+    },
+    quiz::{Category, Quiz, QuizError},
+};
+use std::fmt;
+
+/// Error when parsing GIFT, `line` is the 1-based line where the failing question or category starts.
+#[derive(Debug)]
+pub struct GiftError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for GiftError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl std::error::Error for GiftError {}
+
+/// Characters which have a special meaning in GIFT and must be escaped with a backslash in text.
+const SPECIAL_CHARACTERS: &str = "~=#{}:";
+
+/// Parses GIFT text into a quiz. Questions after a `$CATEGORY:` line are imported into that category.
+///
+/// # Errors
+///
+/// Returns a [`GiftError`] with the line number of the question which could not be parsed.
+pub fn parse(source: &str) -> Result<Quiz, GiftError> {
+    let mut leading = Vec::new();
+    let mut sections: Vec<(Category, Vec<QuestionType>)> = Vec::new();
+    for (line, block) in blocks(source) {
+        let mut block = block.as_str();
+        let mut question_line = line;
+        if let Some(rest) = block.strip_prefix("$CATEGORY:") {
+            let (path, rest) = rest.split_once('\n').unwrap_or((rest, ""));
+            sections.push((Category::from_path(path), Vec::new()));
+            block = rest;
+            question_line += 1;
+            if block.trim().is_empty() {
+                continue;
+            }
+        }
+        let question = parse_question(block).map_err(|message| GiftError {
+            line: question_line,
+            message,
+        })?;
+        match sections.last_mut() {
+            Some((_, questions)) => questions.push(question),
+            None => leading.push(question),
+        }
+    }
+    let mut quiz = Quiz::new(leading);
+    for (category, questions) in sections {
+        quiz.add_category(category, questions);
+    }
+    Ok(quiz)
+}
+
+/// Splits the source into blank line separated blocks with their starting line numbers, dropping comment lines.
+fn blocks(source: &str) -> Vec<(usize, String)> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (index, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("//") {
+            continue;
+        }
+        if trimmed.is_empty() {
+            blocks.extend(current.take());
+            continue;
+        }
+        match current.as_mut() {
+            Some((_, block)) => {
+                block.push('\n');
+                block.push_str(line);
+            }
+            None => current = Some((index + 1, line.to_string())),
+        }
+    }
+    blocks.extend(current);
+    blocks
+}
+
+/// Finds the first occurrence of `pattern` which is not escaped with a backslash.
+fn find_unescaped(text: &str, pattern: &str) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            if let Some(&(_, next)) = chars.peek() {
+                if SPECIAL_CHARACTERS.contains(next) || next == 'n' {
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        if text[i..].starts_with(pattern) {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Splits `text` at the first unescaped `pattern`.
+fn split_unescaped<'a>(text: &'a str, pattern: &str) -> (&'a str, Option<&'a str>) {
+    match find_unescaped(text, pattern) {
+        Some(i) => (&text[..i], Some(&text[i + pattern.len()..])),
+        None => (text, None),
+    }
+}
+
+/// Replaces the escape sequences with the characters they stand for.
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.peek() {
+                Some(&next) if SPECIAL_CHARACTERS.contains(next) => {
+                    result.push(next);
+                    chars.next();
+                    continue;
+                }
+                Some('n') => {
+                    result.push('\n');
+                    chars.next();
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        result.push(c);
+    }
+    result.trim().to_string()
+}
+
+/// Escapes the special characters of the text for writing GIFT.
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => result.push_str("\\n"),
+            '\r' => {}
+            c if SPECIAL_CHARACTERS.contains(c) => {
+                result.push('\\');
+                result.push(c);
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn format_to_gift(format: TextFormat) -> &'static str {
+    match format {
+        TextFormat::HTML => "html",
+        TextFormat::Moodle => "moodle",
+        TextFormat::Markdown => "markdown",
+        TextFormat::PlainText => "plain",
+    }
+}
+
+/// Parses a single question block.
+fn parse_question(block: &str) -> Result<QuestionType, String> {
+    let mut rest = block.trim();
+    let mut name = None;
+    if let Some(after) = rest.strip_prefix("::") {
+        let end = find_unescaped(after, "::").ok_or("question title is not closed with `::`")?;
+        name = Some(unescape(&after[..end]));
+        rest = after[end + 2..].trim_start();
+    }
+    // GIFT questions are in the Moodle auto-format unless told otherwise
+    let mut format = TextFormat::Moodle;
+    if let Some(after) = rest.strip_prefix('[') {
+        if let Some((marker, after)) = after.split_once(']') {
+            if let Some(marker_format) = TextFormat::from_name(marker) {
+                format = marker_format;
+                rest = after;
+            }
+        }
+    }
+    let (text, answers) = match find_unescaped(rest, "{") {
+        None => (unescape(rest), None),
+        Some(start) => {
+            let end = find_unescaped(&rest[start..], "}")
+                .map(|end| start + end)
+                .ok_or("answer block is not closed with `}`")?;
+            let after = &rest[end + 1..];
+            // Text after the answers makes it a "missing word" question
+            let text = if after.trim().is_empty() {
+                unescape(&rest[..start])
+            } else {
+                unescape(&[&rest[..start], "_____", after].concat())
+            };
+            (text, Some(rest[start + 1..end].trim()))
+        }
+    };
+    let name = name
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| text.chars().take(80).collect());
+
+    let mut question: QuestionType = match answers {
+        None => DescriptionQuestion::new(name, text).into(),
+        Some(answers) => {
+            if find_unescaped(answers, "####").is_some() {
+                return Err("general feedback (`####`) is not supported".to_string());
+            }
+            parse_answers(name, text, answers, format)?
+        }
+    };
+    question.as_question_mut().set_text_format(format);
+    Ok(question)
+}
+
+/// A single answer of an answer block, `marker` is either `=` or `~`.
+struct RawAnswer<'a> {
+    marker: char,
+    weight: Option<f64>,
+    text: &'a str,
+    feedback: Option<&'a str>,
+}
+
+impl RawAnswer<'_> {
+    fn fraction(&self, default: f64) -> Result<f64, String> {
+        match self.weight {
+            None => Ok(default),
+            Some(weight) if (-100.0..=100.0).contains(&weight) => Ok(weight),
+            Some(weight) => Err(format!(
+                "answer weight {}% is not between -100 and 100",
+                weight
+            )),
+        }
+    }
+    fn feedback(&self) -> Option<String> {
+        self.feedback.map(unescape).filter(|f| !f.is_empty())
+    }
+    fn to_answer(&self, default: f64, format: TextFormat) -> Result<Answer, String> {
+        let mut answer = Answer::new(
+            self.fraction(default)?,
+            unescape(self.text),
+            self.feedback(),
+        );
+        answer.set_text_format(format);
+        Ok(answer)
+    }
+}
+
+/// Splits the answer block into answers starting with `=` or `~`.
+fn split_answers(answers: &str) -> Result<Vec<RawAnswer<'_>>, String> {
+    let mut starts = Vec::new();
+    let mut offset = 0;
+    while let Some(i) = [
+        find_unescaped(&answers[offset..], "="),
+        find_unescaped(&answers[offset..], "~"),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+    {
+        starts.push(offset + i);
+        offset += i + 1;
+    }
+    match starts.first() {
+        Some(&first) if answers[..first].trim().is_empty() => {}
+        _ => return Err(format!("cannot parse the answers `{}`", answers)),
+    }
+    starts.push(answers.len());
+    starts
+        .windows(2)
+        .map(|window| {
+            let marker = answers[window[0]..].chars().next().unwrap_or('=');
+            let mut body = answers[window[0] + 1..window[1]].trim();
+            let mut weight = None;
+            if let Some(after) = body.strip_prefix('%') {
+                let (value, after) = after
+                    .split_once('%')
+                    .ok_or_else(|| format!("answer weight is not closed with `%` in `{}`", body))?;
+                weight = Some(
+                    value
+                        .trim()
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid answer weight `{}`", value))?,
+                );
+                body = after;
+            }
+            let (text, feedback) = split_unescaped(body, "#");
+            Ok(RawAnswer {
+                marker,
+                weight,
+                text,
+                feedback,
+            })
+        })
+        .collect()
+}
+
+/// Parses the value of a numerical answer: `value`, `value:tolerance` or `min..max`.
+fn parse_numerical(text: &str) -> Result<(f64, f64), String> {
+    let number = |s: &str| {
+        s.trim()
+            .parse::<f64>()
+            .map_err(|_| format!("`{}` is not a number", s.trim()))
+    };
+    let text = unescape(text);
+    if let Some((min, max)) = text.split_once("..") {
+        let (min, max) = (number(min)?, number(max)?);
+        Ok(((min + max) / 2.0, (max - min).abs() / 2.0))
+    } else if let Some((value, tolerance)) = text.split_once(':') {
+        Ok((number(value)?, number(tolerance)?))
+    } else {
+        Ok((number(&text)?, 0.0))
+    }
+}
+
+/// Detects the question type from the answer block and creates the question.
+fn parse_answers(
+    name: String,
+    text: String,
+    answers: &str,
+    format: TextFormat,
+) -> Result<QuestionType, String> {
+    if answers.is_empty() {
+        return Ok(EssayQuestion::new(name, text).into());
+    }
+    if let Some(numerical) = answers.strip_prefix('#') {
+        let mut question = NumericalQuestion::new(name, text);
+        let numerical = numerical.trim();
+        let answers = if numerical.starts_with('=') || numerical.starts_with('~') {
+            split_answers(numerical)?
+                .iter()
+                .map(|raw| {
+                    let (value, tolerance) = parse_numerical(raw.text)?;
+                    let default = if raw.marker == '=' { 100.0 } else { 0.0 };
+                    let mut answer = NumericalAnswer::new(
+                        raw.fraction(default)?,
+                        value,
+                        tolerance,
+                        raw.feedback(),
+                    );
+                    answer.answer.set_text_format(format);
+                    Ok(answer)
+                })
+                .collect::<Result<Vec<_>, String>>()?
+        } else {
+            let (value, feedback) = split_unescaped(numerical, "#");
+            let (value, tolerance) = parse_numerical(value)?;
+            let mut answer = NumericalAnswer::new(
+                100.0,
+                value,
+                tolerance,
+                feedback.map(unescape).filter(|f| !f.is_empty()),
+            );
+            answer.answer.set_text_format(format);
+            vec![answer]
+        };
+        question
+            .add_numerical_answers(answers)
+            .map_err(|e| e.to_string())?;
+        return Ok(question.into());
+    }
+    let (head, feedbacks) = split_unescaped(answers, "#");
+    let correct = match head.trim() {
+        "T" | "TRUE" => Some(true),
+        "F" | "FALSE" => Some(false),
+        _ => None,
+    };
+    if let Some(correct) = correct {
+        // The first feedback is shown for a wrong answer, the second for the right one
+        let (wrong_feedback, right_feedback) = match feedbacks {
+            Some(feedbacks) => {
+                let (wrong, right) = split_unescaped(feedbacks, "#");
+                (
+                    Some(unescape(wrong)).filter(|f| !f.is_empty()),
+                    right.map(unescape).filter(|f| !f.is_empty()),
+                )
+            }
+            None => (None, None),
+        };
+        let (true_feedback, false_feedback) = if correct {
+            (right_feedback, wrong_feedback)
+        } else {
+            (wrong_feedback, right_feedback)
+        };
+        let mut true_answer = Answer::new(
+            if correct { 100.0 } else { 0.0 },
+            "true".into(),
+            true_feedback,
+        );
+        let mut false_answer = Answer::new(
+            if correct { 0.0 } else { 100.0 },
+            "false".into(),
+            false_feedback,
+        );
+        true_answer.set_text_format(format);
+        false_answer.set_text_format(format);
+        let mut question = TrueFalseQuestion::new(name, text);
+        question
+            .add_answers(vec![true_answer, false_answer])
+            .map_err(|e| e.to_string())?;
+        return Ok(question.into());
+    }
+    let raw_answers = split_answers(answers)?;
+    if find_unescaped(answers, "->").is_some() {
+        let mut question = MatchingQuestion::new(name, text, None);
+        let subquestions = raw_answers
+            .iter()
+            .map(|raw| {
+                let (text, answer) = split_unescaped(raw.text, "->");
+                let answer = answer
+                    .ok_or_else(|| format!("matching answer `{}` has no `->`", raw.text.trim()))?;
+                Ok(SubQuestion::new(unescape(text), unescape(answer)))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        question.add_subquestions(subquestions);
+        return Ok(question.into());
+    }
+    if raw_answers.iter().any(|raw| raw.marker == '~') {
+        // Without a `=` answer, several answers may be right with partial weights
+        let single = raw_answers.iter().any(|raw| raw.marker == '=');
+        let mut question = MultiChoiceQuestion::new(
+            name,
+            text,
+            Some(single),
+            None,
+            None,
+            None,
+            None,
+            Some("abc".to_string()),
+        );
+        let answers = raw_answers
+            .iter()
+            .map(|raw| raw.to_answer(if raw.marker == '=' { 100.0 } else { 0.0 }, format))
+            .collect::<Result<Vec<_>, String>>()?;
+        question.add_answers(answers).map_err(|e| e.to_string())?;
+        return Ok(question.into());
+    }
+    let mut question = ShortAnswerQuestion::new(name, text, None);
+    let answers = raw_answers
+        .iter()
+        .map(|raw| raw.to_answer(100.0, format))
+        .collect::<Result<Vec<_>, String>>()?;
+    question.add_answers(answers).map_err(|e| e.to_string())?;
+    Ok(question.into())
+}
+
+/// Writes a quiz in GIFT format. A `$CATEGORY:` line is written whenever the category changes.
+///
+/// # Errors
+///
+/// Returns a [`QuizError`] if the quiz has no questions.
+pub fn to_gift(quiz: &Quiz) -> Result<String, QuizError> {
+    if quiz.is_empty() {
+        return Err(crate::quiz::EmptyError.into());
+    }
+    let mut output = String::new();
+    let mut current_category = None;
+    for (category, question) in quiz.questions() {
+        if category != current_category {
+            if let Some(category) = category {
+                output.push_str("$CATEGORY: ");
+                output.push_str(category.path().trim_end_matches('/'));
+                output.push_str("\n\n");
+            }
+            current_category = category;
+        }
+        write_question(&mut output, question)?;
+        output.push_str("\n\n");
+    }
+    Ok(output)
+}
+
+/// Formats the feedback part of an answer.
+fn feedback(answer: &Answer) -> String {
+    match answer.feedback.as_deref() {
+        Some(feedback) if !feedback.is_empty() => ["#", &escape(feedback)].concat(),
+        _ => String::new(),
+    }
+}
+
+fn write_question(output: &mut String, question: &QuestionType) -> Result<(), QuizError> {
+    let common = question.as_question();
+    output.push_str("::");
+    output.push_str(&escape(common.get_name()));
+    output.push_str("::");
+    let format = common.get_text_format();
+    if !matches!(format, TextFormat::Moodle) {
+        output.push('[');
+        output.push_str(format_to_gift(format));
+        output.push(']');
+    }
+    output.push_str(&escape(common.get_description()));
+    match question {
+        QuestionType::Multichoice(q) => {
+            output.push_str(" {\n");
+            for answer in q.answers() {
+                let marker = if q.single && answer.fraction == 100.0 {
+                    "=".to_string()
+                } else if answer.fraction != 0.0 {
+                    format!("~%{}%", format_fraction(answer.fraction))
+                } else {
+                    "~".to_string()
+                };
+                output.push_str(&format!(
+                    "\t{}{}{}\n",
+                    marker,
+                    escape(&answer.text),
+                    feedback(answer)
+                ));
+            }
+            output.push('}');
+        }
+        QuestionType::TrueFalse(q) => {
+            let correct = q.answers().iter().find(|answer| answer.fraction == 100.0);
+            let wrong = q.answers().iter().find(|answer| answer.fraction != 100.0);
+            let is_true = correct
+                .map(|answer| answer.text.trim().to_lowercase().starts_with('t'))
+                .unwrap_or(true);
+            let right_feedback = correct.map(feedback).unwrap_or_default();
+            let wrong_feedback = wrong.map(feedback).unwrap_or_default();
+            output.push_str(if is_true { " {TRUE" } else { " {FALSE" });
+            if !right_feedback.is_empty() {
+                output.push_str(if wrong_feedback.is_empty() {
+                    "#"
+                } else {
+                    &wrong_feedback
+                });
+                output.push_str(&right_feedback);
+            } else {
+                output.push_str(&wrong_feedback);
+            }
+            output.push('}');
+        }
+        QuestionType::ShortAnswer(q) => {
+            output.push_str(" {\n");
+            for answer in q.answers() {
+                let weight = if answer.fraction == 100.0 {
+                    String::new()
+                } else {
+                    format!("%{}%", format_fraction(answer.fraction))
+                };
+                output.push_str(&format!(
+                    "\t={}{}{}\n",
+                    weight,
+                    escape(&answer.text),
+                    feedback(answer)
+                ));
+            }
+            output.push('}');
+        }
+        QuestionType::Matching(q) => {
+            output.push_str(" {\n");
+            for subquestion in q.subquestions() {
+                output.push_str(&format!(
+                    "\t={} -> {}\n",
+                    escape(&subquestion.text),
+                    escape(&subquestion.answer)
+                ));
+            }
+            output.push('}');
+        }
+        QuestionType::Numerical(q) => {
+            output.push_str(" {#\n");
+            for answer in q.answers() {
+                let weight = if answer.answer.fraction == 100.0 {
+                    String::new()
+                } else {
+                    format!("%{}%", format_fraction(answer.answer.fraction))
+                };
+                output.push_str(&format!(
+                    "\t={}{}:{}{}\n",
+                    weight,
+                    escape(&answer.answer.text),
+                    answer.tolerance,
+                    feedback(&answer.answer)
+                ));
+            }
+            output.push('}');
+        }
+        QuestionType::Essay(_) => output.push_str(" {}"),
+        QuestionType::Description(_) => {}
+        QuestionType::Calculated(_)
+        | QuestionType::CalculatedSimple(_)
+        | QuestionType::CalculatedMulti(_)
+        | QuestionType::DragDropText(_)
+        | QuestionType::GapSelect(_)
+        | QuestionType::DragDropImage(_)
+        | QuestionType::DragMarker(_)
+        | QuestionType::RandomShortAnswerMatch(_)
+        | QuestionType::Random(_)
+        | QuestionType::Ordering(_)
+        | QuestionType::CodeRunner(_)
+        | QuestionType::Custom(_) => {
+            return Err(QuizError::ValueError(format!(
+                "GIFT does not support {} questions",
+                question.type_name()
+            )))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+// A comment line is ignored
+$CATEGORY: $course$/Geography/Capitals
+
+::Capital of France::[html]What is the capital of <b>France</b>? {
+    =Paris#Correct!
+    ~Lyon#No, try again.
+    ~Marseille
+}
+
+::Stockholm::Stockholm is the capital of Sweden. {T#It is!#Yes.}
+
+::Finland::Two cities in Finland? {
+    ~%50%Helsinki
+    ~%50%Turku
+    ~Oslo
+}
+
+::Short::Which city is known as the city of light? {=Paris =%50%paris#Close enough}
+
+::Match::Match the countries with their capitals. {
+    =Finland -> Helsinki
+    =Sweden -> Stockholm
+    = -> Oslo
+}
+
+$CATEGORY: Numbers
+
+::Pi::What is pi? {#3.14:0.01}
+
+::Range::A number between 1 and 5 {#1..5}
+
+::Years::When was Moodle founded? {#
+    =2002:0#Exactly
+    =%50%2002:2
+}
+
+::Essay::Write about the escapes \{ \} \= \~ \# \: here. {}
+
+::Intro::This is only a description.
+
+Moodle costs {~lots of money =nothing ~a small amount} to download.
+"#;
+
+    #[test]
+    fn test_parse_sample() {
+        let quiz = parse(SAMPLE).unwrap();
+        let questions: Vec<_> = quiz.questions().collect();
+        assert_eq!(questions.len(), 11);
+        assert_eq!(**questions[0].0.unwrap(), "Geography/Capitals");
+        assert_eq!(**questions[5].0.unwrap(), "Numbers");
+        match questions[0].1 {
+            QuestionType::Multichoice(q) => {
+                assert!(q.single);
+                assert!(matches!(q.get_text_format(), TextFormat::HTML));
+                assert_eq!(q.answers().len(), 3);
+                assert_eq!(q.answers()[0].fraction, 100.0);
+                assert_eq!(q.answers()[0].feedback.as_deref(), Some("Correct!"));
+            }
+            _ => panic!("expected multichoice"),
+        }
+        match questions[1].1 {
+            QuestionType::TrueFalse(q) => {
+                assert_eq!(q.answers()[0].fraction, 100.0);
+                assert_eq!(q.answers()[0].feedback.as_deref(), Some("Yes."));
+                assert_eq!(q.answers()[1].feedback.as_deref(), Some("It is!"));
+            }
+            _ => panic!("expected true/false"),
+        }
+        match questions[2].1 {
+            QuestionType::Multichoice(q) => assert!(!q.single),
+            _ => panic!("expected multichoice"),
+        }
+        match questions[3].1 {
+            QuestionType::ShortAnswer(q) => assert_eq!(q.answers()[1].fraction, 50.0),
+            _ => panic!("expected short answer"),
+        }
+        match questions[4].1 {
+            QuestionType::Matching(q) => {
+                assert_eq!(q.subquestions().len(), 3);
+                assert_eq!(q.subquestions()[2].text, "");
+            }
+            _ => panic!("expected matching"),
+        }
+        match questions[6].1 {
+            QuestionType::Numerical(q) => {
+                assert_eq!(q.answers()[0].value(), Some(3.0));
+                assert_eq!(q.answers()[0].tolerance, 2.0);
+            }
+            _ => panic!("expected numerical"),
+        }
+        match questions[8].1 {
+            QuestionType::Essay(q) => {
+                assert_eq!(
+                    q.get_description(),
+                    "Write about the escapes { } = ~ # : here."
+                )
+            }
+            _ => panic!("expected essay"),
+        }
+        match questions[9].1 {
+            QuestionType::Description(q) => assert_eq!(q.get_name(), "Intro"),
+            _ => panic!("expected description"),
+        }
+        assert!(matches!(questions[10].1, QuestionType::Multichoice(_)));
+    }
+
+    #[test]
+    fn test_missing_word() {
+        let quiz = parse("Moodle costs {~lots of money =nothing} to download.").unwrap();
+        let (_, question) = quiz.questions().next().unwrap();
+        assert_eq!(
+            question.as_question().get_description(),
+            "Moodle costs _____ to download."
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let quiz = parse(SAMPLE).unwrap();
+        let exported = to_gift(&quiz).unwrap();
+        let reparsed = parse(&exported).unwrap();
+        assert_eq!(to_gift(&reparsed).unwrap(), exported);
+        assert!(exported.contains("$CATEGORY: $course$/Numbers\n"));
+        assert!(exported
+            .contains("::Stockholm::Stockholm is the capital of Sweden. {TRUE#It is!#Yes.}"));
+        assert!(exported.contains("\\{ \\} \\= \\~ \\# \\: here. {}"));
+    }
+
+    #[test]
+    fn test_non_ascii() {
+        let source = "::Pääkaupunki::Mikä on Suomen pääkaupunki? {=Helsinki ~Turku #Ei, Åbo.}";
+        let quiz = parse(source).unwrap();
+        let (_, question) = quiz.questions().next().unwrap();
+        assert_eq!(question.as_question().get_name(), "Pääkaupunki");
+        assert_eq!(
+            question.as_question().get_description(),
+            "Mikä on Suomen pääkaupunki?"
+        );
+        let exported = to_gift(&quiz).unwrap();
+        assert!(exported.contains("::Pääkaupunki::Mikä on Suomen pääkaupunki? {"));
+        assert_eq!(to_gift(&parse(&exported).unwrap()).unwrap(), exported);
+    }
+
+    #[test]
+    fn test_exact_weights() {
+        let source = "::Thirds::Which? {~%33.33333%a ~%33.33333%b ~%33.33333%c ~%-12.5%d}";
+        let quiz = parse(source).unwrap();
+        let (_, question) = quiz.questions().next().unwrap();
+        let QuestionType::Multichoice(q) = question else {
+            panic!("expected multichoice");
+        };
+        let fractions: Vec<f64> = q.answers().iter().map(|a| a.fraction).collect();
+        assert_eq!(fractions, vec![33.33333, 33.33333, 33.33333, -12.5]);
+        let exported = to_gift(&quiz).unwrap();
+        assert!(exported.contains("~%33.33333%a"), "{}", exported);
+        assert!(exported.contains("~%-12.5%d"), "{}", exported);
+    }
+
+    #[test]
+    fn test_error_line() {
+        let source = "::Good::Question {=Yes ~No}\n\n// comment\n::Bad::Question {=Yes ~No\n";
+        let Err(error) = parse(source) else {
+            panic!("expected an error");
+        };
+        assert_eq!(error.line, 4);
+        let Err(error) = parse("::Low::Question {=%30%Yes ~No}") else {
+            panic!("expected an error");
+        };
+        assert!(error.message.contains("at least 100"), "{}", error.message);
+    }
+}
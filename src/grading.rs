@@ -0,0 +1,526 @@
+//! Grading responses locally the way Moodle does, for checking a quiz before importing it.
+//!
+//! [`grade`] takes a question and a [`Response`] and returns the [`Grade`]: the fraction earned and the
+//! feedback shown to the student.
+//!
+//! ```
+//! use moodle_xml::{grading::{grade, Response}, prelude::*};
+//!
+//! let mut question = ShortAnswerQuestion::new(
+//!     "Capital".to_string(),
+//!     "What is the capital of France?".to_string(),
+//!     None,
+//! );
+//! question
+//!     .add_answers(vec![Answer::new(100.0, "Paris".to_string(), Some("Correct!".to_string()))])
+//!     .unwrap();
+//! let result = grade(&question.into(), &Response::Text("paris".to_string())).unwrap();
+//! assert_eq!(result.fraction, 1.0);
+//! assert_eq!(result.feedback, ["Correct!"]);
+//! ```
+use crate::{
+    answer::{Answer, FRACTION_TOLERANCE},
+    question::{
+        CombinedFeedback, MultiChoiceQuestion, NumericalQuestion, QuestionType, ShortAnswerQuestion,
+    },
+};
+use std::fmt;
+
+/// Response of a student to a question.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// Indexes of the selected answers of a multiple choice or true/false question, or the indexes of the
+    /// choices put into the gaps of a drag and drop into text or select missing words question, one per gap,
+    /// or of the items dropped into the zones of a drag and drop onto image question, one per zone.
+    Choices(Vec<usize>),
+    /// Typed text of a short answer question, or a number with an optional unit of a numerical question.
+    Text(String),
+    /// Number of a numerical question in the base unit.
+    Number(f64),
+    /// Answers chosen for the subquestions of a matching question, in the order of the subquestions.
+    /// Subquestions with empty text are extra answers and do not get a response.
+    Matches(Vec<String>),
+}
+
+/// Result of grading a response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grade {
+    /// Fraction of the question grade earned, at most 1. Like in Moodle, an answer with a negative fraction makes
+    /// it negative in the question types with one response, such as single choice questions.
+    pub fraction: f64,
+    /// Feedback shown for the response.
+    pub feedback: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum GradingError {
+    /// The response does not fit the question type.
+    ResponseTypeError(String),
+    /// The selected answers are not valid for the question.
+    ChoiceError(String),
+    /// The question type is graded manually by a teacher.
+    ManualGradingError(String),
+    /// Grading the question type is not supported.
+    UnsupportedError(String),
+}
+
+impl fmt::Display for GradingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GradingError::ResponseTypeError(e) => write!(f, "Response type error: {}", e),
+            GradingError::ChoiceError(e) => write!(f, "Choice error: {}", e),
+            GradingError::ManualGradingError(e) => write!(f, "Manual grading error: {}", e),
+            GradingError::UnsupportedError(e) => write!(f, "Unsupported error: {}", e),
+        }
+    }
+}
+impl std::error::Error for GradingError {}
+
+/// Grades a response to a question.
+/// Returns an error if the response does not fit the question or the question is graded manually.
+pub fn grade(question: &QuestionType, response: &Response) -> Result<Grade, GradingError> {
+    match (question, response) {
+        (QuestionType::Multichoice(q), Response::Choices(choices)) => grade_multichoice(q, choices),
+        (QuestionType::TrueFalse(q), Response::Choices(choices)) => {
+            if choices.len() != 1 {
+                return Err(GradingError::ChoiceError(
+                    "true/false question needs exactly one choice".to_string(),
+                ));
+            }
+            Ok(grade_answers(&select(q.answers(), choices)?))
+        }
+        (QuestionType::DragDropText(q), Response::Choices(choices)) => {
+            let groups: Vec<u8> = q.choices().iter().map(|choice| choice.group).collect();
+            grade_gaps(&q.gaps(), &groups, choices, &q.feedback)
+        }
+        (QuestionType::GapSelect(q), Response::Choices(choices)) => {
+            let groups: Vec<u8> = q.choices().iter().map(|choice| choice.group).collect();
+            grade_gaps(&q.gaps(), &groups, choices, &q.feedback)
+        }
+        (QuestionType::DragDropImage(q), Response::Choices(choices)) => {
+            let groups: Vec<u8> = q.drags().iter().map(|drag| drag.group).collect();
+            let drops: Vec<usize> = q.drops().iter().map(|drop| drop.choice).collect();
+            grade_gaps(&drops, &groups, choices, &q.feedback)
+        }
+        (QuestionType::ShortAnswer(q), Response::Text(text)) => Ok(grade_short_answer(q, text)),
+        (QuestionType::Numerical(q), Response::Text(text)) => grade_numerical_text(q, text),
+        (QuestionType::Numerical(q), Response::Number(value)) => {
+            Ok(grade_numerical(q, *value, 1.0))
+        }
+        (QuestionType::Matching(q), Response::Matches(matches)) => {
+            let pairs: Vec<_> = q
+                .subquestions()
+                .iter()
+                .filter(|subquestion| !subquestion.text.is_empty())
+                .collect();
+            if pairs.len() != matches.len() {
+                return Err(GradingError::ChoiceError(format!(
+                    "expected {} matches, got {}",
+                    pairs.len(),
+                    matches.len()
+                )));
+            }
+            let right = pairs
+                .iter()
+                .zip(matches)
+                .filter(|(subquestion, answer)| subquestion.answer == **answer)
+                .count();
+            Ok(grade_parts(right, pairs.len(), "subquestions", &q.feedback))
+        }
+        (QuestionType::Essay(_), _) | (QuestionType::Description(_), _) => {
+            Err(GradingError::ManualGradingError(format!(
+                "{} questions are not graded automatically",
+                question.type_name()
+            )))
+        }
+        (QuestionType::Calculated(_), _)
+        | (QuestionType::CalculatedSimple(_), _)
+        | (QuestionType::CalculatedMulti(_), _) => Err(GradingError::UnsupportedError(format!(
+            "{} questions depend on the item set shown, which is not known",
+            question.type_name()
+        ))),
+        (QuestionType::RandomShortAnswerMatch(_), _) | (QuestionType::Random(_), _) => {
+            Err(GradingError::UnsupportedError(format!(
+                "{} questions depend on the questions drawn from the category",
+                question.type_name()
+            )))
+        }
+        (QuestionType::CodeRunner(_), _) => Err(GradingError::UnsupportedError(
+            "coderunner questions are graded by running the tests in the sandbox of Moodle"
+                .to_string(),
+        )),
+        (QuestionType::Custom(_), _) => Err(GradingError::UnsupportedError(format!(
+            "{} questions are not graded locally",
+            question.type_name()
+        ))),
+        (QuestionType::Ordering(_), _) => Err(GradingError::UnsupportedError(
+            "ordering questions are not graded locally".to_string(),
+        )),
+        (QuestionType::DragMarker(_), _) => Err(GradingError::UnsupportedError(
+            "ddmarker questions are graded by the positions of the markers".to_string(),
+        )),
+        (_, response) => Err(GradingError::ResponseTypeError(format!(
+            "{:?} is not a response to a {} question",
+            response,
+            question.type_name()
+        ))),
+    }
+}
+
+/// Returns the selected answers, checking that every index is valid and selected once.
+fn select<'a>(answers: &'a [Answer], choices: &[usize]) -> Result<Vec<&'a Answer>, GradingError> {
+    let mut selected = Vec::with_capacity(choices.len());
+    for (position, choice) in choices.iter().enumerate() {
+        if choices[..position].contains(choice) {
+            return Err(GradingError::ChoiceError(format!(
+                "answer {} is selected twice",
+                choice
+            )));
+        }
+        let answer = answers.get(*choice).ok_or_else(|| {
+            GradingError::ChoiceError(format!(
+                "answer {} does not exist, the question has {} answers",
+                choice,
+                answers.len()
+            ))
+        })?;
+        selected.push(answer);
+    }
+    Ok(selected)
+}
+
+/// Sums the fractions of the selected answers and collects their feedback.
+fn grade_answers(selected: &[&Answer]) -> Grade {
+    let total: f64 = selected.iter().map(|answer| answer.fraction).sum();
+    Grade {
+        fraction: total / 100.0,
+        feedback: selected
+            .iter()
+            .filter_map(|answer| answer.feedback.clone())
+            .filter(|feedback| !feedback.is_empty())
+            .collect(),
+    }
+}
+
+fn grade_multichoice(q: &MultiChoiceQuestion, choices: &[usize]) -> Result<Grade, GradingError> {
+    if q.single && choices.len() > 1 {
+        return Err(GradingError::ChoiceError(
+            "only one answer can be chosen".to_string(),
+        ));
+    }
+    let mut grade = grade_answers(&select(q.answers(), choices)?);
+    // Moodle keeps the sum of several chosen answers between 0 and 1, but not the fraction of a single one
+    if !q.single {
+        grade.fraction = grade.fraction.clamp(0.0, 1.0);
+    }
+    // Combined feedback is shown after the feedback of the chosen answers. The fractions of answers sharing the
+    // grade, like three times 33.33333, are right although their sum is a bit less than 1.
+    let combined = if grade.fraction >= 1.0 - FRACTION_TOLERANCE / 100.0 {
+        &q.correctfeedback
+    } else if grade.fraction > 0.0 {
+        &q.partiallycorrectfeedback
+    } else {
+        &q.incorrectfeedback
+    };
+    if !combined.is_empty() {
+        grade.feedback.push(combined.clone());
+    }
+    Ok(grade)
+}
+
+/// Grades the choices put into the gaps. `gaps` are the 1-based numbers of the right choices and `groups`
+/// the groups of the choices.
+fn grade_gaps(
+    gaps: &[usize],
+    groups: &[u8],
+    choices: &[usize],
+    feedback: &CombinedFeedback,
+) -> Result<Grade, GradingError> {
+    if gaps.len() != choices.len() {
+        return Err(GradingError::ChoiceError(format!(
+            "expected {} choices, one for each gap, got {}",
+            gaps.len(),
+            choices.len()
+        )));
+    }
+    let mut right = 0;
+    for (gap, choice) in gaps.iter().zip(choices) {
+        let group = gap.checked_sub(1).and_then(|index| groups.get(index));
+        let chosen = groups.get(*choice);
+        if chosen.is_none() || chosen != group {
+            return Err(GradingError::ChoiceError(format!(
+                "choice {} can not be put into gap [[{}]]",
+                choice, gap
+            )));
+        }
+        if *choice + 1 == *gap {
+            right += 1;
+        }
+    }
+    Ok(grade_parts(right, gaps.len(), "gaps", feedback))
+}
+
+/// Grades a question with `right` of `total` parts right, such as gaps or subquestions, with the combined feedback
+/// and, if `shownumcorrect` is set, the number of right `parts` of a partially right response.
+fn grade_parts(right: usize, total: usize, parts: &str, feedback: &CombinedFeedback) -> Grade {
+    let fraction = if total == 0 {
+        0.0
+    } else {
+        right as f64 / total as f64
+    };
+    let combined = if fraction >= 1.0 {
+        &feedback.correctfeedback
+    } else if fraction > 0.0 {
+        &feedback.partiallycorrectfeedback
+    } else {
+        &feedback.incorrectfeedback
+    };
+    let mut shown = Vec::new();
+    if !combined.is_empty() {
+        shown.push(combined.clone());
+    }
+    if feedback.shownumcorrect && fraction > 0.0 && fraction < 1.0 {
+        shown.push(format!("{} of {} {} are right.", right, total, parts));
+    }
+    Grade {
+        fraction,
+        feedback: shown,
+    }
+}
+
+fn grade_short_answer(q: &ShortAnswerQuestion, text: &str) -> Grade {
+    grade_answers(&q.matches(text).into_iter().collect::<Vec<_>>())
+}
+
+/// Splits a numerical response into the number and the unit following it.
+fn split_unit(text: &str) -> Option<(f64, &str)> {
+    let text = text.trim();
+    let value_end = text
+        .char_indices()
+        .map(|(index, c)| index + c.len_utf8())
+        .rev()
+        .find(|end| {
+            text[..*end]
+                .trim()
+                .parse::<f64>()
+                .is_ok_and(|value| value.is_finite())
+        })?;
+    Some((
+        text[..value_end].trim().parse().ok()?,
+        text[value_end..].trim(),
+    ))
+}
+
+fn grade_numerical_text(q: &NumericalQuestion, text: &str) -> Result<Grade, GradingError> {
+    let (value, unit) = split_unit(text).ok_or_else(|| {
+        GradingError::ResponseTypeError(format!("`{}` is not a number", text.trim()))
+    })?;
+    if q.units().is_empty() {
+        if !unit.is_empty() {
+            return Err(GradingError::ResponseTypeError(format!(
+                "`{}` is not a number",
+                text.trim()
+            )));
+        }
+        return Ok(grade_numerical(q, value, 1.0));
+    }
+    // A missing or unknown unit is graded as the base unit with the unit penalty
+    match q.units().iter().find(|known| known.name == unit) {
+        Some(known) => Ok(grade_numerical(q, value / known.multiplier, 1.0)),
+        None => Ok(grade_numerical(q, value, 1.0 - q.unit_penalty)),
+    }
+}
+
+/// Grades a value in the base unit. The fraction of the first answer within its tolerance is multiplied by `scale`.
+fn grade_numerical(q: &NumericalQuestion, value: f64, scale: f64) -> Grade {
+    let answer = q.answers().iter().find(|answer| {
+        answer.answer.text.trim() == "*"
+            || answer
+                .value()
+                .is_some_and(|expected| (value - expected).abs() <= answer.tolerance)
+    });
+    let mut grade = grade_answers(&answer.map(|a| &a.answer).into_iter().collect::<Vec<_>>());
+    grade.fraction *= scale;
+    grade
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    fn text(text: &str) -> Response {
+        Response::Text(text.to_string())
+    }
+
+    #[test]
+    fn test_grade_multichoice() {
+        let mut question = MultiChoiceQuestion::new(
+            "Primes".to_string(),
+            "Which are primes?".to_string(),
+            Some(false),
+            None,
+            Some("Well done".to_string()),
+            Some("Almost".to_string()),
+            None,
+            None,
+        );
+        question
+            .add_answers(vec![
+                Answer::new(50.0, "2".to_string(), Some("Even prime".to_string())),
+                Answer::new(50.0, "3".to_string(), None),
+                Answer::new(-100.0, "4".to_string(), Some("4 = 2 * 2".to_string())),
+            ])
+            .unwrap();
+        let question: QuestionType = question.into();
+        let result = grade(&question, &Response::Choices(vec![0, 1])).unwrap();
+        assert_eq!(result.fraction, 1.0);
+        assert_eq!(result.feedback, ["Even prime", "Well done"]);
+        let result = grade(&question, &Response::Choices(vec![1])).unwrap();
+        assert_eq!(result.fraction, 0.5);
+        assert_eq!(result.feedback, ["Almost"]);
+        let result = grade(&question, &Response::Choices(vec![1, 2])).unwrap();
+        assert_eq!(result.fraction, 0.0);
+        assert_eq!(result.feedback, ["4 = 2 * 2"]);
+        assert!(grade(&question, &Response::Choices(vec![1, 1])).is_err());
+        assert!(grade(&question, &Response::Choices(vec![3])).is_err());
+        assert!(grade(&question, &text("2")).is_err());
+
+        let mut question = MultiChoiceQuestion::new(
+            "Prime".to_string(),
+            "Which is a prime?".to_string(),
+            Some(true),
+            None,
+            None,
+            None,
+            Some("Wrong".to_string()),
+            None,
+        );
+        question
+            .add_answers(vec![
+                Answer::new(100.0, "2".to_string(), None),
+                Answer::new(-50.0, "4".to_string(), None),
+            ])
+            .unwrap();
+        let result = grade(&question.into(), &Response::Choices(vec![1])).unwrap();
+        assert_eq!(result.fraction, -0.5);
+        assert_eq!(result.feedback, ["Wrong"]);
+    }
+
+    #[test]
+    fn test_grade_short_answer() {
+        let mut question = ShortAnswerQuestion::new(
+            "Capital".to_string(),
+            "What is the capital of France?".to_string(),
+            Some(true),
+        );
+        question
+            .add_answers(vec![
+                Answer::new(100.0, "Paris".to_string(), None),
+                Answer::new(
+                    50.0,
+                    "Par*".to_string(),
+                    Some("Check the spelling".to_string()),
+                ),
+                Answer::new(0.0, "*".to_string(), Some("No".to_string())),
+            ])
+            .unwrap();
+        let question: QuestionType = question.into();
+        assert_eq!(grade(&question, &text(" Paris ")).unwrap().fraction, 1.0);
+        let result = grade(&question, &text("Pariisi")).unwrap();
+        assert_eq!(result.fraction, 0.5);
+        assert_eq!(result.feedback, ["Check the spelling"]);
+        assert_eq!(grade(&question, &text("paris")).unwrap().feedback, ["No"]);
+    }
+
+    #[test]
+    fn test_grade_numerical() {
+        let mut question = NumericalQuestion::new("Length".to_string(), "How long?".to_string());
+        question
+            .add_numerical_answers(vec![
+                NumericalAnswer::new(100.0, 2.0, 0.1, None),
+                NumericalAnswer::new(50.0, 2.0, 0.5, None),
+            ])
+            .unwrap();
+        question.add_units(vec![
+            NumericalUnit::new("m".to_string(), 1.0),
+            NumericalUnit::new("cm".to_string(), 100.0),
+        ]);
+        question.unit_penalty = 0.2;
+        let question: QuestionType = question.into();
+        assert_eq!(grade(&question, &text("2.05 m")).unwrap().fraction, 1.0);
+        assert_eq!(grade(&question, &text("200cm")).unwrap().fraction, 1.0);
+        assert_eq!(grade(&question, &text("2.3 m")).unwrap().fraction, 0.5);
+        assert_eq!(grade(&question, &text("2")).unwrap().fraction, 0.8);
+        assert_eq!(grade(&question, &text("2 km")).unwrap().fraction, 0.8);
+        assert_eq!(grade(&question, &text("3 m")).unwrap().fraction, 0.0);
+        assert_eq!(
+            grade(&question, &Response::Number(2.0)).unwrap().fraction,
+            1.0
+        );
+        assert!(grade(&question, &text("two")).is_err());
+    }
+
+    #[test]
+    fn test_grade_matching_and_essay() {
+        let mut question = MatchingQuestion::new(
+            "Capitals".to_string(),
+            "Match the capitals".to_string(),
+            None,
+        );
+        question.add_subquestions(vec![
+            SubQuestion::new("France".to_string(), "Paris".to_string()),
+            SubQuestion::new("Finland".to_string(), "Helsinki".to_string()),
+            SubQuestion::new(String::new(), "Oslo".to_string()),
+        ]);
+        question.feedback.correctfeedback = "All right".to_string();
+        question.feedback.partiallycorrectfeedback = "Partly right".to_string();
+        question.feedback.shownumcorrect = true;
+        let question: QuestionType = question.into();
+        let response = Response::Matches(vec!["Paris".to_string(), "Oslo".to_string()]);
+        let result = grade(&question, &response).unwrap();
+        assert_eq!(result.fraction, 0.5);
+        assert_eq!(
+            result.feedback,
+            ["Partly right", "1 of 2 subquestions are right."]
+        );
+        let response = Response::Matches(vec!["Paris".to_string(), "Helsinki".to_string()]);
+        assert_eq!(grade(&question, &response).unwrap().feedback, ["All right"]);
+        // The incorrect feedback is empty, so nothing is shown
+        let response = Response::Matches(vec!["Oslo".to_string(), "Oslo".to_string()]);
+        assert!(grade(&question, &response).unwrap().feedback.is_empty());
+        assert!(grade(&question, &Response::Matches(vec![])).is_err());
+
+        let essay = EssayQuestion::new("Essay".to_string(), "Write".to_string());
+        assert!(matches!(
+            grade(&essay.into(), &text("Text")),
+            Err(GradingError::ManualGradingError(_))
+        ));
+    }
+
+    #[test]
+    fn test_grade_gaps() {
+        let mut question = GapSelectQuestion::new(
+            "Animals".to_string(),
+            "The [[1]] chases the [[2]].".to_string(),
+        );
+        question.add_choices(vec![
+            SelectOption::new("cat".to_string(), 1),
+            SelectOption::new("mouse".to_string(), 1),
+            SelectOption::new("runs".to_string(), 2),
+        ]);
+        question.feedback.shownumcorrect = true;
+        let question: QuestionType = question.into();
+        assert_eq!(
+            grade(&question, &Response::Choices(vec![0, 1]))
+                .unwrap()
+                .fraction,
+            1.0
+        );
+        let result = grade(&question, &Response::Choices(vec![0, 0])).unwrap();
+        assert_eq!(result.fraction, 0.5);
+        assert_eq!(result.feedback, ["1 of 2 gaps are right."]);
+        assert!(grade(&question, &Response::Choices(vec![0, 2])).is_err());
+        assert!(grade(&question, &Response::Choices(vec![0])).is_err());
+    }
+}
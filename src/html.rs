@@ -0,0 +1,660 @@
+//! Small HTML utilities for the texts of questions.
+//!
+//! These are not a full HTML parser. Tags are recognized as `<name attributes>`, and any other `<` is text.
+//!
+//! [`check`] reports HTML which Moodle may show wrongly or which breaks the export: unclosed tags, scripts and
+//! event handlers, which Moodle removes or which run in the browser of every student, and `]]>`, which ends the
+//! CDATA section the text is written in. [`sanitize`] removes the scripts, and
+//! [`Quiz::set_sanitize_html`](crate::quiz::Quiz::set_sanitize_html) sanitizes every HTML text when the quiz is
+//! written.
+//!
+//! ```
+//! use moodle_xml::html;
+//!
+//! let issues = html::check(r#"<p onclick="go()">Text"#);
+//! assert_eq!(issues[0].to_string(), "`<p>` has the event handler `onclick`");
+//! assert_eq!(issues[1].to_string(), "`<p>` is not closed");
+//! assert_eq!(html::sanitize(r#"<p onclick="go()">Text"#), "<p>Text");
+//! ```
+use crate::question::{QuestionType, TextFormat, TextLocation};
+use std::{fmt, fmt::Write};
+
+/// Elements which are kept by [`sanitize`].
+const ALLOWED_ELEMENTS: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "code",
+    "dd",
+    "del",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "s",
+    "samp",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+];
+
+/// Attributes which are kept by [`sanitize`] on the allowed elements.
+const ALLOWED_ATTRIBUTES: &[&str] = &[
+    "alt", "class", "colspan", "dir", "height", "href", "lang", "rowspan", "src", "title", "width",
+];
+
+/// Elements which are removed with their content.
+const REMOVED_ELEMENTS: &[&str] = &["script", "style", "iframe", "object", "embed", "template"];
+
+/// Elements which have no content and no end tag.
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements after which a line ends in plain text.
+const LINE_ELEMENTS: &[&str] = &["br", "div", "li", "tr", "dt", "dd", "hr", "figcaption"];
+
+/// Elements which are separated by an empty line in plain text.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "dl",
+    "pre",
+    "blockquote",
+    "table",
+    "figure",
+];
+
+/// A part of an HTML text.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Token<'a> {
+    Text(&'a str),
+    Tag(Tag<'a>),
+    Comment(&'a str),
+}
+
+/// A start or an end tag.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Tag<'a> {
+    /// Name of the element in lowercase.
+    pub name: String,
+    pub closing: bool,
+    pub self_closing: bool,
+    pub attributes: Vec<(String, Option<&'a str>)>,
+}
+
+/// Splits an HTML text into text, tags and comments.
+pub(crate) fn tokens(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut text_start = 0;
+    let mut position = 0;
+    while let Some(offset) = html[position..].find('<') {
+        let start = position + offset;
+        let parsed = if html[start..].starts_with("<!--") {
+            let end = html[start..]
+                .find("-->")
+                .map_or(html.len(), |end| start + end + 3);
+            Some((Token::Comment(&html[start..end]), end))
+        } else {
+            parse_tag(html, start).map(|(tag, end)| (Token::Tag(tag), end))
+        };
+        match parsed {
+            Some((token, end)) => {
+                if text_start < start {
+                    tokens.push(Token::Text(&html[text_start..start]));
+                }
+                tokens.push(token);
+                text_start = end;
+                position = end;
+            }
+            None => position = start + 1,
+        }
+    }
+    if text_start < html.len() {
+        tokens.push(Token::Text(&html[text_start..]));
+    }
+    tokens
+}
+
+fn parse_tag(html: &str, start: usize) -> Option<(Tag<'_>, usize)> {
+    let bytes = html.as_bytes();
+    let mut position = start + 1;
+    let closing = bytes.get(position) == Some(&b'/');
+    if closing {
+        position += 1;
+    }
+    let name_start = position;
+    while position < bytes.len() && bytes[position].is_ascii_alphanumeric() {
+        position += 1;
+    }
+    if position == name_start || !bytes[name_start].is_ascii_alphabetic() {
+        return None;
+    }
+    let name = html[name_start..position].to_ascii_lowercase();
+    let mut attributes = Vec::new();
+    let mut self_closing = false;
+    loop {
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        match bytes.get(position)? {
+            b'>' => break,
+            b'/' if bytes.get(position + 1) == Some(&b'>') => {
+                self_closing = true;
+                position += 1;
+                break;
+            }
+            _ => {}
+        }
+        let attribute_start = position;
+        while position < bytes.len() && !b" \t\r\n=>/".contains(&bytes[position]) {
+            position += 1;
+        }
+        if position == attribute_start {
+            // A stray `/` inside the tag
+            position += 1;
+            continue;
+        }
+        let attribute = html[attribute_start..position].to_ascii_lowercase();
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if bytes.get(position) != Some(&b'=') {
+            attributes.push((attribute, None));
+            continue;
+        }
+        position += 1;
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        let value = match bytes.get(position)? {
+            quote @ (b'"' | b'\'') => {
+                let length = html[position + 1..].find(*quote as char)?;
+                let value = &html[position + 1..position + 1 + length];
+                position += length + 2;
+                value
+            }
+            _ => {
+                let value_start = position;
+                while position < bytes.len() && !b" \t\r\n>".contains(&bytes[position]) {
+                    position += 1;
+                }
+                &html[value_start..position]
+            }
+        };
+        attributes.push((attribute, Some(value)));
+    }
+    let tag = Tag {
+        name,
+        closing,
+        self_closing,
+        attributes,
+    };
+    Some((tag, position + 1))
+}
+
+/// Escapes the characters which have a special meaning in HTML.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Converts plain text into HTML the way Moodle shows plain text: the text is escaped and line breaks are kept.
+pub fn from_text(text: &str) -> String {
+    escape(text).replace("\r\n", "\n").replace('\n', "<br />\n")
+}
+
+/// Returns true if a URL can not run scripts.
+fn is_safe_url(url: &str) -> bool {
+    let url: String = decode_entities(url)
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match url.find(':') {
+        // A relative URL, or a colon after the path starts
+        None => true,
+        Some(colon) if url[..colon].contains(['/', '?', '#']) => true,
+        Some(colon) => {
+            matches!(&url[..colon], "http" | "https" | "mailto" | "ftp")
+                || (url.starts_with("data:image/") && !url.starts_with("data:image/svg"))
+        }
+    }
+}
+
+/// Removes everything from HTML that could run scripts or change the page around the text.
+///
+/// Elements which are not allowed are removed but their content is kept, except for `<script>`, `<style>` and
+/// other embedded content, which are removed entirely. Event handlers, `style` and other attributes which are not
+/// allowed are removed, and so are links with URLs like `javascript:`. A `<` which does not start a tag is escaped,
+/// and so is the `>` of `]]>`.
+pub fn sanitize(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut removed: Option<String> = None;
+    for token in tokens(html) {
+        if let Some(name) = &removed {
+            if matches!(&token, Token::Tag(tag) if tag.closing && tag.name == *name) {
+                removed = None;
+            }
+            continue;
+        }
+        match token {
+            Token::Text(text) => {
+                output.push_str(&text.replace('<', "&lt;").replace("]]>", "]]&gt;"))
+            }
+            Token::Comment(_) => {}
+            Token::Tag(tag) if REMOVED_ELEMENTS.contains(&tag.name.as_str()) => {
+                if !tag.closing && !tag.self_closing {
+                    removed = Some(tag.name);
+                }
+            }
+            Token::Tag(tag) if ALLOWED_ELEMENTS.contains(&tag.name.as_str()) => {
+                if tag.closing {
+                    let _ = write!(output, "</{}>", tag.name);
+                    continue;
+                }
+                output.push('<');
+                output.push_str(&tag.name);
+                for (name, value) in &tag.attributes {
+                    if !ALLOWED_ATTRIBUTES.contains(&name.as_str()) {
+                        continue;
+                    }
+                    let value = value.unwrap_or_default();
+                    if matches!(name.as_str(), "href" | "src") && !is_safe_url(value) {
+                        continue;
+                    }
+                    let _ = write!(
+                        output,
+                        " {}=\"{}\"",
+                        name,
+                        value.replace('"', "&quot;").replace('<', "&lt;")
+                    );
+                }
+                output.push_str(if tag.self_closing { " />" } else { ">" });
+            }
+            Token::Tag(_) => {}
+        }
+    }
+    output
+}
+
+/// Replaces the character references of HTML with the characters.
+pub fn decode_entities(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => {
+                    let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => entity.strip_prefix('#').and_then(|n| n.parse().ok()),
+                    };
+                    code.and_then(char::from_u32)
+                }
+            };
+            c.map(|c| (c, end + 1))
+        });
+        match decoded {
+            Some((c, length)) => {
+                output.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Converts HTML into plain text.
+///
+/// Tags are removed and character references decoded. Whitespace is collapsed like a browser does, except
+/// inside `<pre>`. Line breaks, list items and other lines end with a newline, and paragraphs, headings and
+/// other blocks are separated by an empty line.
+pub fn to_text(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut removed: Option<String> = None;
+    let mut preformatted = 0usize;
+    let mut space = false;
+    for token in tokens(html) {
+        if let Some(name) = &removed {
+            if matches!(&token, Token::Tag(tag) if tag.closing && tag.name == *name) {
+                removed = None;
+            }
+            continue;
+        }
+        match token {
+            Token::Text(text) if preformatted > 0 => output.push_str(&decode_entities(text)),
+            Token::Text(text) => {
+                for c in decode_entities(text).chars() {
+                    if c.is_whitespace() && c != '\u{a0}' {
+                        space = true;
+                        continue;
+                    }
+                    if space && !output.is_empty() && !output.ends_with('\n') {
+                        output.push(' ');
+                    }
+                    space = false;
+                    output.push(c);
+                }
+            }
+            Token::Comment(_) => {}
+            Token::Tag(tag) => {
+                let name = tag.name.as_str();
+                if REMOVED_ELEMENTS.contains(&name) {
+                    if !tag.closing && !tag.self_closing {
+                        removed = Some(tag.name);
+                    }
+                    continue;
+                }
+                if name == "pre" {
+                    preformatted = if tag.closing {
+                        preformatted.saturating_sub(1)
+                    } else {
+                        preformatted + 1
+                    };
+                }
+                if BLOCK_ELEMENTS.contains(&name) {
+                    end_line(&mut output, 2);
+                    space = false;
+                } else if LINE_ELEMENTS.contains(&name) && (tag.closing || name == "br") {
+                    end_line(&mut output, 1);
+                    space = false;
+                }
+            }
+        }
+    }
+    output.trim().to_string()
+}
+
+/// Ends the output with at least `newlines` newlines, unless it is empty.
+fn end_line(output: &mut String, newlines: usize) {
+    while output.ends_with(' ') {
+        output.pop();
+    }
+    if output.is_empty() {
+        return;
+    }
+    let existing = output.len() - output.trim_end_matches('\n').len();
+    for _ in existing..newlines {
+        output.push('\n');
+    }
+}
+
+/// A problem with the HTML of a text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HtmlIssue {
+    /// The element is not closed.
+    Unclosed { element: String },
+    /// The end tag has no start tag.
+    Unopened { element: String },
+    /// The element can run scripts or change the page, such as `<script>` or `<iframe>`.
+    Disallowed { element: String },
+    /// The element has an event handler attribute, such as `onclick`.
+    EventHandler { element: String, attribute: String },
+    /// The element has a URL which can run scripts, such as `javascript:`.
+    UnsafeUrl { element: String, attribute: String },
+    /// The text contains `]]>`, which ends a CDATA section.
+    CdataEnd,
+}
+
+impl fmt::Display for HtmlIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HtmlIssue::Unclosed { element } => write!(f, "`<{}>` is not closed", element),
+            HtmlIssue::Unopened { element } => write!(f, "`</{}>` has no start tag", element),
+            HtmlIssue::Disallowed { element } => write!(f, "`<{}>` is not allowed", element),
+            HtmlIssue::EventHandler { element, attribute } => {
+                write!(f, "`<{}>` has the event handler `{}`", element, attribute)
+            }
+            HtmlIssue::UnsafeUrl { element, attribute } => {
+                write!(f, "`<{}>` has an unsafe URL in `{}`", element, attribute)
+            }
+            HtmlIssue::CdataEnd => f.write_str("`]]>` ends the CDATA section of the text"),
+        }
+    }
+}
+
+/// Checks HTML for unclosed tags, scripts, event handlers and `]]>`.
+pub fn check(html: &str) -> Vec<HtmlIssue> {
+    let mut issues = Vec::new();
+    let mut open: Vec<String> = Vec::new();
+    for token in tokens(html) {
+        let Token::Tag(tag) = token else {
+            continue;
+        };
+        if tag.closing {
+            match open.iter().rposition(|element| *element == tag.name) {
+                Some(index) => {
+                    issues.extend(
+                        open.drain(index..)
+                            .skip(1)
+                            .rev()
+                            .map(|element| HtmlIssue::Unclosed { element }),
+                    );
+                }
+                None if VOID_ELEMENTS.contains(&tag.name.as_str()) => {}
+                None => issues.push(HtmlIssue::Unopened { element: tag.name }),
+            }
+            continue;
+        }
+        if REMOVED_ELEMENTS.contains(&tag.name.as_str()) {
+            issues.push(HtmlIssue::Disallowed {
+                element: tag.name.clone(),
+            });
+        }
+        for (attribute, value) in &tag.attributes {
+            if attribute.starts_with("on") {
+                issues.push(HtmlIssue::EventHandler {
+                    element: tag.name.clone(),
+                    attribute: attribute.clone(),
+                });
+            } else if matches!(attribute.as_str(), "href" | "src" | "action" | "formaction")
+                && !is_safe_url(value.unwrap_or_default())
+            {
+                issues.push(HtmlIssue::UnsafeUrl {
+                    element: tag.name.clone(),
+                    attribute: attribute.clone(),
+                });
+            }
+        }
+        if !tag.self_closing && !VOID_ELEMENTS.contains(&tag.name.as_str()) {
+            open.push(tag.name);
+        }
+    }
+    issues.extend(
+        open.into_iter()
+            .rev()
+            .map(|element| HtmlIssue::Unclosed { element }),
+    );
+    if html.contains("]]>") {
+        issues.push(HtmlIssue::CdataEnd);
+    }
+    issues
+}
+
+/// A problem with the HTML of a text of a question.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlWarning {
+    pub location: TextLocation,
+    pub issue: HtmlIssue,
+}
+
+impl fmt::Display for HtmlWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.issue)
+    }
+}
+
+/// Checks the HTML in every text of a question, such as its question text, answers, choices and feedback.
+/// Texts in plain text or Markdown, and the code of CodeRunner questions, are only checked for `]]>`.
+pub fn check_question(question: &QuestionType) -> Vec<HtmlWarning> {
+    question
+        .texts()
+        .into_iter()
+        .flat_map(|(location, text, format)| {
+            let issues = match format {
+                TextFormat::HTML | TextFormat::Moodle => check(text),
+                TextFormat::PlainText | TextFormat::Markdown if text.contains("]]>") => {
+                    vec![HtmlIssue::CdataEnd]
+                }
+                TextFormat::PlainText | TextFormat::Markdown => Vec::new(),
+            };
+            issues
+                .into_iter()
+                .map(move |issue| HtmlWarning { location, issue })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(
+            sanitize(
+                r#"<p onclick="alert(1)" class='x'>A <b>bold</b> <font>move</font></p><script>alert("<p>")</script>"#
+            ),
+            r#"<p class="x">A <b>bold</b> move</p>"#
+        );
+        assert_eq!(
+            sanitize(
+                r#"<a href="javascript:alert(1)">x</a><a href="https://moodle.org">y</a><img src="@@PLUGINFILE@@/a.png"/>"#
+            ),
+            r#"<a>x</a><a href="https://moodle.org">y</a><img src="@@PLUGINFILE@@/a.png" />"#
+        );
+        assert_eq!(sanitize("1 < 2 <!-- note -->"), "1 &lt; 2 ");
+        assert_eq!(sanitize("a[b[1]]>0"), "a[b[1]]&gt;0");
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(
+            check(r#"<p>A<br><img src="a.png"><b>bold</p></i><iframe src="javascript:x()"></iframe> ]]>"#)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "`<b>` is not closed",
+                "`</i>` has no start tag",
+                "`<iframe>` is not allowed",
+                "`<iframe>` has an unsafe URL in `src`",
+                "`]]>` ends the CDATA section of the text",
+            ]
+        );
+        assert_eq!(check("<p>1 < 2 and <br/> &amp;</p>"), []);
+    }
+
+    #[test]
+    fn test_to_text() {
+        assert_eq!(
+            to_text("<h1>Title</h1>\n<p>Some   <em>text</em>&nbsp;&amp;\nmore<br>Line</p><ul><li>One</li><li>Two</li></ul>"),
+            "Title\n\nSome text\u{a0}& more\nLine\n\nOne\nTwo"
+        );
+        assert_eq!(
+            to_text("<pre><code>fn main() {\n    x &lt; 1\n}</code></pre><p>&#65;&#x42;</p>"),
+            "fn main() {\n    x < 1\n}\n\nAB"
+        );
+        assert_eq!(from_text("a < b\nc"), "a &lt; b<br />\nc");
+    }
+
+    #[test]
+    fn test_check_question() {
+        use crate::question::{CodeRunnerQuestion, DragBox, DragDropTextQuestion};
+
+        let mut question =
+            DragDropTextQuestion::new("Gaps".to_string(), "Fill [[1]] and [[2]]".to_string());
+        question.add_choices(vec![
+            DragBox::new("<b>x".to_string(), 1),
+            DragBox::new(r#"<img src="a.png" onerror="steal()">"#.to_string(), 1),
+        ]);
+        question.feedback.correctfeedback = "<script>steal()</script>".to_string();
+        let warnings: Vec<String> = check_question(&question.into())
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "choice 1: `<b>` is not closed",
+                "choice 2: `<img>` has the event handler `onerror`",
+                "correct feedback: `<script>` is not allowed",
+            ]
+        );
+
+        let mut coderunner = CodeRunnerQuestion::new(
+            "Code".to_string(),
+            "Index".to_string(),
+            "python3".to_string(),
+        );
+        coderunner.answer = "print(a[b[0]]>1)".to_string();
+        let warnings = check_question(&coderunner.into());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].location, TextLocation::CodeRunnerAnswer);
+        assert_eq!(warnings[0].issue, HtmlIssue::CdataEnd);
+    }
+}
@@ -1,17 +1,46 @@
 #![doc = include_str!("../README.md")]
+pub mod aiken;
 pub mod answer;
+#[cfg(any(feature = "yaml", feature = "toml"))]
+pub mod authoring;
+pub mod convert;
+#[cfg(feature = "csv")]
+pub mod csv;
+pub mod dataset;
+pub mod export;
+pub mod file;
+pub mod formula;
+pub mod gift;
+pub mod grading;
+pub mod html;
+#[cfg(feature = "markdown")]
+pub mod markdown;
+pub mod math;
+pub mod multilang;
+pub mod preview;
+#[cfg(feature = "qti")]
+pub mod qti;
 pub mod question;
 pub mod quiz;
-mod xml_util;
+pub mod reader;
+pub mod xml_util;
 
 /// A prelude containing the esstential types
 pub mod prelude {
     pub use crate::{
-        answer::Answer,
+        answer::{Answer, CalculatedAnswer, NumericalAnswer},
+        dataset::DatasetDefinition,
+        file::EmbeddedFile,
         question::{
-            EssayQuestion, MultiChoiceQuestion, Question, QuestionType, ShortAnswerQuestion,
-            TextFormat, TrueFalseQuestion,
+            AnswerWarning, CalculatedMultiQuestion, CalculatedQuestion, CalculatedSimpleQuestion,
+            CodeRunnerQuestion, DescriptionQuestion, DragBox, DragDropImageQuestion,
+            DragDropTextQuestion, DragItem, DragMarkerQuestion, DropZone, EssayQuestion,
+            GapSelectQuestion, Hint, Marker, MarkerShape, MarkerZone, MatchingQuestion,
+            MultiChoiceQuestion, NumericalQuestion, NumericalUnit, OrderingGrading, OrderingLayout,
+            OrderingQuestion, OrderingSelect, Question, QuestionBase, QuestionType, RandomQuestion,
+            RandomShortAnswerMatchQuestion, SelectOption, ShortAnswerQuestion, SubQuestion,
+            TestCase, TextFormat, TrueFalseQuestion,
         },
-        quiz::{Category, Quiz, QuizError},
+        quiz::{Category, CdataPolicy, Quiz, QuizError, QuizItem, QuizWriter},
     };
 }
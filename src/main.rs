@@ -0,0 +1,536 @@
+//! The `moodle-xml` command-line tool for converting, validating and inspecting quizzes.
+//!
+//! Exit codes: 0 on success, 1 if a quiz is invalid or cannot be converted, 2 for wrong usage
+//! and 3 if a file cannot be read or written.
+use clap::{Parser, Subcommand, ValueEnum};
+use moodle_xml::{
+    aiken, authoring,
+    csv::{self, CsvOptions},
+    gift, html,
+    markdown::{self, MarkdownOptions},
+    math,
+    prelude::*,
+    preview, reader,
+    xml_util::{WriteContext, XmlWriter},
+};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use xml::writer::EmitterConfig;
+
+#[derive(Parser)]
+#[command(
+    name = "moodle-xml",
+    version,
+    about = "Convert, validate and inspect Moodle quizzes"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Checks that every question of a quiz can be written as Moodle XML
+    Validate {
+        file: PathBuf,
+        /// Format of the file, detected from the extension by default
+        #[arg(long)]
+        from: Option<InputFormat>,
+    },
+    /// Converts a quiz to another format
+    Convert {
+        input: PathBuf,
+        /// Format of the input, detected from the extension by default
+        #[arg(long)]
+        from: Option<InputFormat>,
+        #[arg(long)]
+        to: OutputFormat,
+        /// Output file, standard output by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Shows the number of questions per type and category
+    Stats {
+        file: PathBuf,
+        /// Format of the file, detected from the extension by default
+        #[arg(long)]
+        from: Option<InputFormat>,
+    },
+    /// Renders a quiz as an HTML page for proofreading
+    Preview {
+        file: PathBuf,
+        /// Format of the file, detected from the extension by default
+        #[arg(long)]
+        from: Option<InputFormat>,
+        /// Output file, standard output by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Merges quizzes into one Moodle XML file, in the order of the files
+    Merge {
+        #[arg(required = true, num_args = 2..)]
+        files: Vec<PathBuf>,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum InputFormat {
+    MoodleXml,
+    Gift,
+    Aiken,
+    Yaml,
+    Toml,
+    Csv,
+    Markdown,
+}
+
+impl InputFormat {
+    fn detect(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "xml" => Some(InputFormat::MoodleXml),
+            "gift" => Some(InputFormat::Gift),
+            "aiken" => Some(InputFormat::Aiken),
+            "yaml" | "yml" => Some(InputFormat::Yaml),
+            "toml" => Some(InputFormat::Toml),
+            "csv" => Some(InputFormat::Csv),
+            "md" | "markdown" => Some(InputFormat::Markdown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    MoodleXml,
+    Gift,
+    Aiken,
+    Csv,
+    Markdown,
+    Json,
+}
+
+/// Errors of the tool, each with its own exit code.
+enum CliError {
+    Invalid(String),
+    Usage(String),
+    Io(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Invalid(_) => 1,
+            CliError::Usage(_) => 2,
+            CliError::Io(_) => 3,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Invalid(message) | CliError::Usage(message) | CliError::Io(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+fn invalid(path: &Path, error: impl fmt::Display) -> CliError {
+    CliError::Invalid(format!("{}: {}", path.display(), error))
+}
+
+fn io_error(path: &Path, error: impl fmt::Display) -> CliError {
+    CliError::Io(format!("{}: {}", path.display(), error))
+}
+
+/// Converts errors of writing a quiz, separating file errors from invalid quizzes.
+fn write_error(path: &Path, error: QuizError) -> CliError {
+    match error {
+        QuizError::IoError(e) => io_error(path, e),
+        e => invalid(path, e),
+    }
+}
+
+fn load(path: &Path, from: Option<InputFormat>) -> Result<Quiz, CliError> {
+    let format = from.or_else(|| InputFormat::detect(path)).ok_or_else(|| {
+        CliError::Usage(format!(
+            "{}: cannot detect the format from the extension, use --from",
+            path.display()
+        ))
+    })?;
+    let source = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+    let quiz = match format {
+        InputFormat::MoodleXml => reader::parse(&source).map_err(|e| invalid(path, e)),
+        InputFormat::Gift => gift::parse(&source).map_err(|e| invalid(path, e)),
+        InputFormat::Aiken => aiken::parse(&source).map_err(|e| invalid(path, e)),
+        InputFormat::Yaml => authoring::from_yaml_str(&source).map_err(|e| invalid(path, e)),
+        InputFormat::Toml => authoring::from_toml_str(&source).map_err(|e| invalid(path, e)),
+        InputFormat::Csv => {
+            csv::parse(&source, &CsvOptions::default()).map_err(|e| invalid(path, e))
+        }
+        InputFormat::Markdown => {
+            markdown::parse(&source, MarkdownOptions::default()).map_err(|e| invalid(path, e))
+        }
+    }?;
+    if quiz.is_empty() {
+        return Err(invalid(path, "the quiz has no questions"));
+    }
+    Ok(quiz)
+}
+
+fn validate(path: &Path, from: Option<InputFormat>) -> Result<(), CliError> {
+    let quiz = load(path, from)?;
+    let mut problems = Vec::new();
+    for (_, question) in quiz.questions() {
+        // A new writer for every question, so that a failed question does not affect the next one
+        let mut writer = XmlWriter::new(io::sink(), EmitterConfig::new(), WriteContext::default());
+        if let Err(e) = question.to_xml(&mut writer) {
+            problems.push(format!(
+                "question `{}`: {}",
+                question.as_question().get_name(),
+                e
+            ));
+        }
+        if let QuestionType::ShortAnswer(q) = question {
+            for warning in q.answer_warnings() {
+                eprintln!("warning: question `{}`: {}", q.get_name(), warning);
+            }
+        }
+        let name = question.as_question().get_name();
+        for warning in math::check_question(question) {
+            eprintln!("warning: question `{}`: {}", name, warning);
+        }
+        for warning in html::check_question(question) {
+            eprintln!("warning: question `{}`: {}", name, warning);
+        }
+    }
+    if !problems.is_empty() {
+        return Err(invalid(
+            path,
+            format!(
+                "{} of {} questions are invalid\n  {}",
+                problems.len(),
+                quiz.len(),
+                problems.join("\n  ")
+            ),
+        ));
+    }
+    println!("{}: {} questions are valid", path.display(), quiz.len());
+    Ok(())
+}
+
+fn stats(path: &Path, from: Option<InputFormat>) -> Result<(), CliError> {
+    let quiz = load(path, from)?;
+    let mut types: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut categories: BTreeMap<String, usize> = BTreeMap::new();
+    for (category, question) in quiz.questions() {
+        *types.entry(question.type_name()).or_default() += 1;
+        let category = category.map_or_else(|| "(no category)".to_string(), |c| c.to_string());
+        *categories.entry(category).or_default() += 1;
+    }
+    println!("{}: {} questions", path.display(), quiz.len());
+    for (title, counts) in [
+        (
+            "By type",
+            types.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+        ),
+        ("By category", categories),
+    ] {
+        println!("{}:", title);
+        let width = counts.keys().map(|key| key.len()).max().unwrap_or_default();
+        for (key, count) in counts {
+            println!("  {:width$}  {}", key, count, width = width);
+        }
+    }
+    Ok(())
+}
+
+fn write_moodle_xml(mut quiz: Quiz, output: &Path) -> Result<(), CliError> {
+    let filename = output
+        .to_str()
+        .ok_or_else(|| CliError::Usage(format!("{}: invalid file name", output.display())))?;
+    quiz.to_xml(filename).map_err(|e| write_error(output, e))
+}
+
+/// Streams a quiz as Moodle XML to the standard output.
+fn write_moodle_xml_stdout(quiz: Quiz) -> Result<(), CliError> {
+    quiz.into_writer(io::stdout().lock())
+        .and_then(QuizWriter::finish)
+        .map(|_| ())
+        .map_err(|e| write_error(Path::new("standard output"), e))
+}
+
+fn convert(
+    input: &Path,
+    from: Option<InputFormat>,
+    to: OutputFormat,
+    output: Option<&Path>,
+) -> Result<(), CliError> {
+    let quiz = load(input, from)?;
+    let text = match to {
+        OutputFormat::MoodleXml => {
+            return match output {
+                Some(output) => write_moodle_xml(quiz, output),
+                None => write_moodle_xml_stdout(quiz),
+            };
+        }
+        OutputFormat::Gift => gift::to_gift(&quiz),
+        OutputFormat::Aiken => aiken::to_aiken(&quiz),
+        OutputFormat::Csv => csv::to_csv(&quiz, &CsvOptions::default()),
+        OutputFormat::Markdown => markdown::to_markdown(&quiz),
+        OutputFormat::Json => Ok(quiz_json(&quiz)),
+    }
+    .map_err(|e| invalid(input, e))?;
+    match output {
+        Some(output) => fs::write(output, text).map_err(|e| io_error(output, e)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn preview(path: &Path, from: Option<InputFormat>, output: Option<&Path>) -> Result<(), CliError> {
+    let quiz = load(path, from)?;
+    match output {
+        Some(output) => preview::write_file(&quiz, output).map_err(|e| write_error(output, e)),
+        None => {
+            print!("{}", preview::to_html(&quiz));
+            Ok(())
+        }
+    }
+}
+
+fn merge(files: &[PathBuf], output: &Path) -> Result<(), CliError> {
+    let mut merged = Quiz::new(Vec::new());
+    for file in files {
+        merged
+            .append(load(file, None)?)
+            .map_err(|e| invalid(file, e))?;
+    }
+    let count = merged.len();
+    write_moodle_xml(merged, output)?;
+    println!("{}: {} questions", output.display(), count);
+    Ok(())
+}
+
+fn answer_json(answer: &Answer) -> Value {
+    json!({
+        "text": answer.text,
+        "format": answer.text_format.name(),
+        "fraction": answer.fraction,
+        "feedback": answer.feedback,
+    })
+}
+
+fn calculated_json(answers: &[CalculatedAnswer], datasets: &[DatasetDefinition]) -> Value {
+    json!({
+        "answers": answers
+            .iter()
+            .map(|a| {
+                let mut answer = answer_json(&a.answer);
+                answer["tolerance"] = json!(a.tolerance);
+                answer["tolerance_type"] = json!(a.tolerance_type.code());
+                answer
+            })
+            .collect::<Vec<_>>(),
+        "datasets": datasets
+            .iter()
+            .map(|d| json!({
+                "name": d.name,
+                "minimum": d.minimum,
+                "maximum": d.maximum,
+                "decimals": d.decimals,
+                "items": d.items(),
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn question_json(category: Option<&Category>, question: &QuestionType) -> Value {
+    let common = question.as_question();
+    let mut value = json!({
+        "type": question.type_name(),
+        "category": category.map(|c| c.as_str()),
+        "name": common.get_name(),
+        "text": common.get_description(),
+        "format": common.get_text_format().name(),
+        "tags": common.get_tags(),
+    });
+    let extra = match question {
+        QuestionType::Multichoice(q) => json!({
+            "single": q.single,
+            "shuffle": q.shuffleanswers,
+            "numbering": q.answernumbering,
+            "correct_feedback": q.correctfeedback,
+            "partially_correct_feedback": q.partiallycorrectfeedback,
+            "incorrect_feedback": q.incorrectfeedback,
+            "answers": q.answers().iter().map(answer_json).collect::<Vec<_>>(),
+        }),
+        QuestionType::TrueFalse(q) => json!({
+            "answers": q.answers().iter().map(answer_json).collect::<Vec<_>>(),
+        }),
+        QuestionType::ShortAnswer(q) => json!({
+            "case_sensitive": q.usecase,
+            "answers": q.answers().iter().map(answer_json).collect::<Vec<_>>(),
+        }),
+        QuestionType::Matching(q) => json!({
+            "shuffle": q.shuffleanswers,
+            "subquestions": q
+                .subquestions()
+                .iter()
+                .map(|s| json!({ "text": s.text, "answer": s.answer }))
+                .collect::<Vec<_>>(),
+        }),
+        QuestionType::Numerical(q) => json!({
+            "answers": q
+                .answers()
+                .iter()
+                .map(|a| {
+                    let mut answer = answer_json(&a.answer);
+                    answer["tolerance"] = json!(a.tolerance);
+                    answer
+                })
+                .collect::<Vec<_>>(),
+        }),
+        QuestionType::Essay(_) | QuestionType::Description(_) | QuestionType::Custom(_) => {
+            json!({})
+        }
+        QuestionType::Calculated(q) => calculated_json(q.answers(), q.datasets()),
+        QuestionType::CalculatedSimple(q) => calculated_json(q.answers(), q.datasets()),
+        QuestionType::CalculatedMulti(q) => {
+            let mut value = calculated_json(q.answers(), q.datasets());
+            value["single"] = json!(q.single);
+            value
+        }
+        QuestionType::DragDropText(q) => json!({
+            "shuffle": q.shuffleanswers,
+            "choices": q
+                .choices()
+                .iter()
+                .map(|c| json!({ "text": c.text, "group": c.group, "infinite": c.infinite }))
+                .collect::<Vec<_>>(),
+        }),
+        QuestionType::GapSelect(q) => json!({
+            "shuffle": q.shuffleanswers,
+            "choices": q
+                .choices()
+                .iter()
+                .map(|c| json!({ "text": c.text, "group": c.group }))
+                .collect::<Vec<_>>(),
+        }),
+        QuestionType::DragDropImage(q) => json!({
+            "background": q.background().map(|file| &file.name),
+            "drags": q
+                .drags()
+                .iter()
+                .map(|d| {
+                    json!({
+                        "text": d.text,
+                        "group": d.group,
+                        "infinite": d.infinite,
+                        "image": d.image.as_ref().map(|file| &file.name),
+                    })
+                })
+                .collect::<Vec<_>>(),
+            "drops": q
+                .drops()
+                .iter()
+                .map(|d| json!({ "choice": d.choice, "x": d.x, "y": d.y }))
+                .collect::<Vec<_>>(),
+        }),
+        QuestionType::RandomShortAnswerMatch(q) => json!({
+            "choose": q.choose,
+            "subcats": q.subcats,
+        }),
+        QuestionType::CodeRunner(q) => json!({
+            "coderunnertype": q.coderunnertype,
+            "language": q.language,
+            "precheck": q.precheck.code(),
+            "allornothing": q.allornothing,
+            "testcases": q
+                .testcases()
+                .iter()
+                .map(|t| {
+                    json!({
+                        "testcode": t.testcode,
+                        "stdin": t.stdin,
+                        "expected": t.expected,
+                        "mark": t.mark,
+                        "display": t.display.name(),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        }),
+        QuestionType::Ordering(q) => json!({
+            "layout": q.layout.name(),
+            "select": q.select.name(),
+            "select_count": q.select_count,
+            "grading": q.grading.name(),
+            "items": q.items(),
+        }),
+        QuestionType::Random(q) => json!({ "include_subcategories": q.include_subcategories }),
+        QuestionType::DragMarker(q) => json!({
+            "background": q.background().map(|file| &file.name),
+            "markers": q
+                .markers()
+                .iter()
+                .map(|m| json!({ "text": m.text, "infinite": m.infinite, "count": m.count }))
+                .collect::<Vec<_>>(),
+            "zones": q
+                .zones()
+                .iter()
+                .map(|z| json!({ "shape": z.shape.to_string(), "choice": z.choice }))
+                .collect::<Vec<_>>(),
+        }),
+    };
+    if let (Value::Object(value), Value::Object(extra)) = (&mut value, extra) {
+        value.extend(extra);
+    }
+    value
+}
+
+fn quiz_json(quiz: &Quiz) -> String {
+    let questions: Vec<Value> = quiz
+        .questions()
+        .map(|(category, question)| question_json(category, question))
+        .collect();
+    let mut text = serde_json::to_string_pretty(&json!({ "questions": questions }))
+        .unwrap_or_else(|e| unreachable!("a JSON value is always serializable: {}", e));
+    text.push('\n');
+    text
+}
+
+fn run(cli: Cli) -> Result<(), CliError> {
+    match cli.command {
+        Command::Validate { file, from } => validate(&file, from),
+        Command::Convert {
+            input,
+            from,
+            to,
+            output,
+        } => convert(&input, from, to, output.as_deref()),
+        Command::Stats { file, from } => stats(&file, from),
+        Command::Preview { file, from, output } => preview(&file, from, output.as_deref()),
+        Command::Merge { files, output } => merge(&files, &output),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::from(error.exit_code())
+        }
+    }
+}
@@ -0,0 +1,738 @@
+//! Markdown question banks.
+//!
+//! Every question starts with a heading which is used as the name of the question. The text below it is the
+//! question text, and a task list gives the answers of a multiple choice question, where `- [x]` marks the
+//! right answers. A blockquote right after an answer is the feedback for it. Questions without a task list
+//! are essay questions. The category and the tags of all questions are given in the front matter:
+//!
+//! ```markdown
+//! ---
+//! category: Geography/Capitals
+//! tags: [geography, europe]
+//! ---
+//!
+//! # Capital of France
+//!
+//! What is the **capital** of France?
+//!
+//! - [x] Paris
+//!   > Correct!
+//! - [ ] Lyon
+//!   > No, Lyon is the third largest city.
+//! - [ ] Marseille
+//!
+//! # Capitals essay
+//!
+//! Describe the history of your capital.
+//! ```
+//!
+//! With a single `- [x]` the question has one right answer. With several, any number of answers can be chosen
+//! and the grade is divided equally between the right answers, such as 33.33333% for each of three. A
+//! `<!-- single -->` or `<!-- multiple -->` line right before the answers gives the mode instead, and then every
+//! marked answer of a single choice question is fully right. [`to_markdown`] always writes this line. Partial
+//! credit and penalties are given as a percentage before the answer text, `- [ ] %50% Pariisi` or
+//! `- [ ] %-25% Berlin`. Then the answers without a percentage give 100 if they are marked and 0 otherwise.
+use crate::{
+    answer::{format_fraction, shared_fraction, Answer, FRACTION_TOLERANCE},
+    convert,
+    question::{EssayQuestion, MultiChoiceQuestion, Question, QuestionType, TextFormat},
+    quiz::{EmptyError, Quiz, QuizError},
+};
+use std::fmt;
+
+/// Options for parsing a Markdown question bank.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarkdownOptions {
+    /// Converts the question text, answers and feedback to HTML.
+    /// Otherwise they are kept as Markdown and rendered by Moodle with [`TextFormat::Markdown`].
+    pub render_html: bool,
+}
+
+/// Error when parsing a Markdown question bank, `line` is the 1-based line of the problem.
+#[derive(Debug)]
+pub struct MarkdownError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MarkdownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl std::error::Error for MarkdownError {}
+
+fn error(line: usize, message: impl Into<String>) -> MarkdownError {
+    MarkdownError {
+        line,
+        message: message.into(),
+    }
+}
+
+#[derive(Default)]
+struct FrontMatter {
+    category: Option<String>,
+    tags: Vec<String>,
+}
+
+/// An answer of the task list.
+struct DraftAnswer {
+    correct: bool,
+    /// Fraction given as `%50%` before the text.
+    weight: Option<f64>,
+    text: String,
+    feedback: Vec<String>,
+}
+
+/// A question which is being parsed.
+struct Draft {
+    line: usize,
+    name: String,
+    body: Vec<String>,
+    answers: Vec<DraftAnswer>,
+    /// Whether the question is single choice, if given with a [`SINGLE`] or [`MULTIPLE`] marker.
+    single: Option<bool>,
+}
+
+/// Marker line before the answers of a single choice question.
+const SINGLE: &str = "<!-- single -->";
+/// Marker line before the answers of a multiple response question.
+const MULTIPLE: &str = "<!-- multiple -->";
+
+/// Returns the level and the text if the line is an ATX heading such as `## Title`.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim()))
+}
+
+/// Returns whether the item is checked and its text if the line is a task list item such as `- [x] text`.
+fn task_item(line: &str) -> Option<(bool, &str)> {
+    let rest = line.trim_start();
+    let rest = rest
+        .strip_prefix("- ")
+        .or_else(|| rest.strip_prefix("* "))
+        .or_else(|| rest.strip_prefix("+ "))?;
+    let checked = match rest.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    let text = &rest[3..];
+    if !text.is_empty() && !text.starts_with(' ') {
+        return None;
+    }
+    Some((checked, text.trim()))
+}
+
+/// Splits the percentage such as `%50%` from the start of an answer text.
+fn weight(text: &str) -> Result<(Option<f64>, &str), String> {
+    let Some(rest) = text.strip_prefix('%') else {
+        return Ok((None, text));
+    };
+    let (value, rest) = rest
+        .split_once('%')
+        .ok_or_else(|| format!("answer percentage is not closed with `%` in `{}`", text))?;
+    let fraction = value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|fraction| fraction.is_finite())
+        .ok_or_else(|| format!("invalid answer percentage `{}`", value))?;
+    Ok((Some(fraction), rest.trim()))
+}
+
+/// Returns the fractions of answers without percentages. Every marked answer of a single choice question is
+/// right, and the marked answers of a multiple response question divide 100 equally, see [`shared_fraction`].
+fn divided_fractions(marked: &[bool], single: bool) -> Vec<f64> {
+    let fraction = if single {
+        100.0
+    } else {
+        shared_fraction(marked.iter().filter(|marked| **marked).count())
+    };
+    marked
+        .iter()
+        .map(|marked| if *marked { fraction } else { 0.0 })
+        .collect()
+}
+
+/// Parses the inline list of the front matter, `[a, b]` or `a, b`.
+fn parse_list(value: &str) -> Vec<String> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .unwrap_or(value);
+    value
+        .split(',')
+        .map(unquote)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return inner.to_string();
+        }
+    }
+    value.to_string()
+}
+
+/// Parses the front matter starting at `lines[0]`, returns it with the number of lines it spans.
+fn parse_front_matter(
+    lines: &[&str],
+    offset: usize,
+) -> Result<(FrontMatter, usize), MarkdownError> {
+    let mut front_matter = FrontMatter::default();
+    for (index, line) in lines.iter().enumerate().skip(1) {
+        let line_number = offset + index + 1;
+        let line = line.trim();
+        if line == "---" {
+            return Ok((front_matter, index + 1));
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| error(line_number, "expected `key: value` in the front matter"))?;
+        match key.trim() {
+            "category" => front_matter.category = Some(unquote(value)),
+            "tags" => front_matter.tags = parse_list(value),
+            key => {
+                return Err(error(
+                    line_number,
+                    format!("unknown front matter key `{}`", key),
+                ))
+            }
+        }
+    }
+    Err(error(offset + 1, "front matter is not closed with `---`"))
+}
+
+/// Parses a Markdown question bank into a quiz.
+///
+/// # Errors
+///
+/// Returns a [`MarkdownError`] with the line number of the problem.
+pub fn parse(source: &str, options: MarkdownOptions) -> Result<Quiz, MarkdownError> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut start = lines
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .unwrap_or(lines.len());
+    let mut front_matter = FrontMatter::default();
+    if lines.get(start).map(|line| line.trim()) == Some("---") {
+        let (parsed, length) = parse_front_matter(&lines[start..], start)?;
+        front_matter = parsed;
+        start += length;
+    }
+
+    let mut drafts: Vec<Draft> = Vec::new();
+    let mut question_level = None;
+    let mut in_code_block = false;
+    for (index, line) in lines.iter().enumerate().skip(start) {
+        let line_number = index + 1;
+        if !in_code_block {
+            if let Some((level, title)) = heading(line) {
+                // The level of the first heading is used for questions, deeper headings belong to the text
+                if question_level.is_none() || question_level == Some(level) {
+                    question_level = Some(level);
+                    drafts.push(Draft {
+                        line: line_number,
+                        name: title.to_string(),
+                        body: Vec::new(),
+                        answers: Vec::new(),
+                        single: None,
+                    });
+                    continue;
+                }
+            }
+        }
+        let Some(draft) = drafts.last_mut() else {
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Err(error(line_number, "text before the first question heading"));
+        };
+        let fence = line.trim_start().starts_with("```");
+        let Some(answer) = draft.answers.last_mut() else {
+            if fence {
+                in_code_block = !in_code_block;
+            }
+            if !in_code_block && !fence && [SINGLE, MULTIPLE].contains(&line.trim()) {
+                draft.single = Some(line.trim() == SINGLE);
+            } else if let Some((correct, text)) = task_item(line).filter(|_| !in_code_block) {
+                let (weight, text) = weight(text).map_err(|message| error(line_number, message))?;
+                draft.answers.push(DraftAnswer {
+                    correct,
+                    weight,
+                    text: text.to_string(),
+                    feedback: Vec::new(),
+                });
+            } else {
+                draft.body.push(line.to_string());
+            }
+            continue;
+        };
+        let trimmed = line.trim();
+        if let Some((correct, text)) = task_item(line) {
+            let (weight, text) = weight(text).map_err(|message| error(line_number, message))?;
+            draft.answers.push(DraftAnswer {
+                correct,
+                weight,
+                text: text.to_string(),
+                feedback: Vec::new(),
+            });
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            answer.feedback.push(quote.trim().to_string());
+        } else if trimmed.is_empty() {
+            continue;
+        } else if !fence && line.starts_with(char::is_whitespace) && answer.feedback.is_empty() {
+            // Lazy continuation of the answer text
+            answer.text.push(' ');
+            answer.text.push_str(trimmed);
+        } else {
+            return Err(error(
+                line_number,
+                "unexpected text after the answers, a new question must start with a heading",
+            ));
+        }
+    }
+    let mut questions = Vec::new();
+    for draft in drafts {
+        let line = draft.line;
+        let mut question =
+            build_question(draft, options).map_err(|message| error(line, message))?;
+        question
+            .as_question_mut()
+            .add_tags(front_matter.tags.clone());
+        questions.push(question);
+    }
+    let mut quiz = Quiz::new(questions);
+    if let Some(category) = front_matter.category {
+        quiz.set_categories(vec![category.into()]);
+    }
+    Ok(quiz)
+}
+
+/// Renders Markdown to sanitized HTML like [`convert::render_markdown`]. A single paragraph is unwrapped, as
+/// answers and feedback are short inline texts.
+fn render_html(markdown: &str, inline: bool) -> Result<String, String> {
+    let html = convert::render_markdown(markdown).map_err(|e| e.to_string())?;
+    if inline {
+        if let Some(inner) = html
+            .strip_prefix("<p>")
+            .and_then(|h| h.strip_suffix("</p>"))
+            .filter(|inner| !inner.contains("<p>"))
+        {
+            return Ok(inner.to_string());
+        }
+    }
+    Ok(html)
+}
+
+fn build_question(draft: Draft, options: MarkdownOptions) -> Result<QuestionType, String> {
+    let body = draft.body.join("\n").trim().to_string();
+    if body.is_empty() {
+        return Err(format!("question `{}` has no text", draft.name));
+    }
+    let (format, text) = if options.render_html {
+        (TextFormat::HTML, render_html(&body, false)?)
+    } else {
+        (TextFormat::Markdown, body)
+    };
+    if draft.answers.is_empty() {
+        if draft.single.is_some() {
+            return Err(format!(
+                "question `{}` has a `{}` or `{}` marker but no answers",
+                draft.name, SINGLE, MULTIPLE
+            ));
+        }
+        let mut question = EssayQuestion::new(draft.name, text);
+        question.set_text_format(format);
+        return Ok(question.into());
+    }
+    let correct = draft.answers.iter().filter(|a| a.correct).count();
+    if correct == 0 {
+        return Err(format!(
+            "question `{}` has no right answer marked with `- [x]`",
+            draft.name
+        ));
+    }
+    let single = draft.single.unwrap_or(correct == 1);
+    let weighted = draft.answers.iter().any(|a| a.weight.is_some());
+    let marked: Vec<bool> = draft.answers.iter().map(|a| a.correct).collect();
+    let divided = if weighted {
+        vec![0.0; marked.len()]
+    } else {
+        divided_fractions(&marked, single)
+    };
+    let mut question = MultiChoiceQuestion::new(
+        draft.name,
+        text,
+        Some(single),
+        None,
+        None,
+        None,
+        None,
+        Some("abc".to_string()),
+    );
+    question.set_text_format(format);
+    let answers = draft
+        .answers
+        .into_iter()
+        .zip(divided)
+        .map(|(draft_answer, divided)| -> Result<Answer, String> {
+            let fraction = match draft_answer.weight {
+                Some(weight) => weight,
+                None if weighted && draft_answer.correct => 100.0,
+                None if weighted => 0.0,
+                None => divided,
+            };
+            let feedback = Some(draft_answer.feedback.join("\n")).filter(|f| !f.is_empty());
+            let (text, feedback) = if options.render_html {
+                (
+                    render_html(&draft_answer.text, true)?,
+                    feedback.map(|f| render_html(&f, true)).transpose()?,
+                )
+            } else {
+                (draft_answer.text, feedback)
+            };
+            let mut answer = Answer::new(fraction, text, feedback);
+            answer.set_text_format(format);
+            Ok(answer)
+        })
+        .collect::<Result<_, _>>()?;
+    question.add_answers(answers).map_err(|e| e.to_string())?;
+    Ok(question.into())
+}
+
+/// Renders a quiz as a Markdown question bank, in the format read by [`parse`].
+///
+/// # Errors
+///
+/// Returns a [`QuizError`] if the quiz is empty, has other than multiple choice or essay questions,
+/// or its questions do not share the same category and tags.
+pub fn to_markdown(quiz: &Quiz) -> Result<String, QuizError> {
+    let mut questions = quiz.questions();
+    let Some((category, first)) = questions.next() else {
+        return Err(EmptyError.into());
+    };
+    let tags = first.as_question().get_tags();
+    for (other_category, question) in quiz.questions() {
+        if other_category != category {
+            return Err(QuizError::ValueError(
+                "All questions of a Markdown bank must be in the same category".to_string(),
+            ));
+        }
+        if question.as_question().get_tags() != tags {
+            return Err(QuizError::ValueError(
+                "All questions of a Markdown bank must have the same tags".to_string(),
+            ));
+        }
+    }
+    let mut output = String::new();
+    if category.is_some() || !tags.is_empty() {
+        output.push_str("---\n");
+        if let Some(category) = category {
+            output.push_str(&format!("category: {}\n", &**category));
+        }
+        if !tags.is_empty() {
+            output.push_str(&format!("tags: [{}]\n", tags.join(", ")));
+        }
+        output.push_str("---\n\n");
+    }
+    for (_, question) in quiz.questions() {
+        let common = question.as_question();
+        output.push_str(&format!(
+            "# {}\n\n{}\n",
+            common.get_name(),
+            common.get_description().trim()
+        ));
+        match question {
+            QuestionType::Multichoice(q) => {
+                let mode = if q.single { SINGLE } else { MULTIPLE };
+                output.push_str(&format!("\n{}\n", mode));
+                let answers = q.answers();
+                // Only the best answers of a single choice question are marked
+                let best = answers.iter().map(|a| a.fraction).fold(0.0, f64::max);
+                let marked: Vec<bool> = answers
+                    .iter()
+                    .map(|a| a.fraction > 0.0 && (!q.single || a.fraction == best))
+                    .collect();
+                let weighted = answers
+                    .iter()
+                    .zip(divided_fractions(&marked, q.single))
+                    .any(|(a, divided)| (a.fraction - divided).abs() > FRACTION_TOLERANCE);
+                for (answer, marked) in answers.iter().zip(marked) {
+                    let mark = if marked { "x" } else { " " };
+                    let weight = if weighted && answer.fraction != 0.0 && answer.fraction != 100.0 {
+                        format!("%{}% ", format_fraction(answer.fraction))
+                    } else {
+                        String::new()
+                    };
+                    output.push_str(&format!("- [{}] {}{}\n", mark, weight, answer.text));
+                    if let Some(feedback) = answer.feedback.as_ref().filter(|f| !f.is_empty()) {
+                        for line in feedback.lines() {
+                            output.push_str(&format!("  > {}\n", line));
+                        }
+                    }
+                }
+            }
+            QuestionType::Essay(_) => {}
+            _ => {
+                return Err(QuizError::ValueError(format!(
+                    "Question `{}` cannot be written in Markdown, only multiple choice and essay questions are supported",
+                    common.get_name()
+                )))
+            }
+        }
+        output.push('\n');
+    }
+    output.pop();
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "---
+category: Geography/Capitals
+tags: [geography, europe]
+---
+
+# Capital of France
+
+What is the **capital** of France?
+
+## Hint
+
+It is not Lyon.
+
+<!-- single -->
+- [x] Paris
+  > Correct!
+- [ ] Lyon
+  > No, Lyon is the third largest city.
+- [ ] Marseille
+
+# Nordic capitals
+
+Which are Nordic capitals?
+
+<!-- multiple -->
+- [x] Oslo
+- [x] Helsinki
+- [x] Stockholm
+- [x] Copenhagen
+- [ ] Berlin
+
+# Capitals essay
+
+Describe the history of your capital.
+";
+
+    #[test]
+    fn test_parse_markdown() {
+        let quiz = parse(SAMPLE, MarkdownOptions::default()).unwrap();
+        let questions: Vec<_> = quiz.questions().collect();
+        assert_eq!(questions.len(), 3);
+        assert_eq!(**questions[0].0.unwrap(), "Geography/Capitals");
+        match questions[0].1 {
+            QuestionType::Multichoice(q) => {
+                assert!(q.single);
+                assert!(q.get_description().contains("## Hint"));
+                assert!(matches!(q.get_text_format(), TextFormat::Markdown));
+                assert_eq!(q.get_tags(), ["geography", "europe"]);
+                assert_eq!(q.answers()[0].fraction, 100.0);
+                assert_eq!(q.answers()[0].feedback.as_deref(), Some("Correct!"));
+            }
+            _ => panic!("expected multichoice"),
+        }
+        match questions[1].1 {
+            QuestionType::Multichoice(q) => {
+                assert!(!q.single);
+                let fractions: Vec<f64> = q.answers().iter().map(|a| a.fraction).collect();
+                assert_eq!(fractions, vec![25.0, 25.0, 25.0, 25.0, 0.0]);
+            }
+            _ => panic!("expected multichoice"),
+        }
+        assert!(matches!(questions[2].1, QuestionType::Essay(_)));
+        assert_eq!(to_markdown(&quiz).unwrap(), SAMPLE);
+    }
+
+    #[test]
+    fn test_partial_credit() {
+        let source = "# Capital of Finland
+
+Which is the capital of Finland?
+
+<!-- single -->
+- [x] Helsinki
+- [ ] %50% Helsingfors
+  > Right, but in Swedish.
+- [ ] Turku
+
+# Nordic capitals
+
+Which are Nordic capitals?
+
+<!-- multiple -->
+- [x] %50% Oslo
+- [x] %50% Helsinki
+- [ ] %-25% Berlin
+";
+        let quiz = parse(source, MarkdownOptions::default()).unwrap();
+        let fractions: Vec<(bool, Vec<f64>)> = quiz
+            .questions()
+            .map(|(_, question)| match question {
+                QuestionType::Multichoice(q) => {
+                    (q.single, q.answers().iter().map(|a| a.fraction).collect())
+                }
+                _ => panic!("expected multichoice"),
+            })
+            .collect();
+        assert_eq!(
+            fractions,
+            vec![
+                (true, vec![100.0, 50.0, 0.0]),
+                (false, vec![50.0, 50.0, -25.0])
+            ]
+        );
+        assert_eq!(to_markdown(&quiz).unwrap(), source);
+        let Err(e) = parse(
+            "# Q\n\nText\n\n- [x] %half% One\n",
+            MarkdownOptions::default(),
+        ) else {
+            panic!("expected an error");
+        };
+        assert_eq!(e.line, 5);
+    }
+
+    #[test]
+    fn test_three_right_answers() {
+        let source = "# Nordic capitals
+
+Which are Nordic capitals?
+
+<!-- multiple -->
+- [x] Oslo
+- [x] Helsinki
+- [x] Stockholm
+- [ ] Berlin
+";
+        let quiz = parse(source, MarkdownOptions::default()).unwrap();
+        let (_, question) = quiz.questions().next().unwrap();
+        let QuestionType::Multichoice(q) = question else {
+            panic!("expected multichoice");
+        };
+        let fractions: Vec<f64> = q.answers().iter().map(|a| a.fraction).collect();
+        assert_eq!(fractions, vec![100.0 / 3.0, 100.0 / 3.0, 100.0 / 3.0, 0.0]);
+        assert_eq!(to_markdown(&quiz).unwrap(), source);
+    }
+
+    #[test]
+    fn test_mode_marker() {
+        let source = "# Capital of Finland
+
+Which of these is a Nordic capital?
+
+<!-- multiple -->
+- [x] Helsinki
+- [ ] Berlin
+
+# Helsinki
+
+What is Helsinki called?
+
+<!-- single -->
+- [x] Helsinki
+- [x] Helsingfors
+- [ ] Turku
+";
+        let quiz = parse(source, MarkdownOptions::default()).unwrap();
+        let modes: Vec<(bool, Vec<f64>)> = quiz
+            .questions()
+            .map(|(_, question)| match question {
+                QuestionType::Multichoice(q) => {
+                    (q.single, q.answers().iter().map(|a| a.fraction).collect())
+                }
+                _ => panic!("expected multichoice"),
+            })
+            .collect();
+        assert_eq!(
+            modes,
+            vec![(false, vec![100.0, 0.0]), (true, vec![100.0, 100.0, 0.0])]
+        );
+        assert_eq!(to_markdown(&quiz).unwrap(), source);
+    }
+
+    #[test]
+    fn test_render_html() {
+        let options = MarkdownOptions { render_html: true };
+        let quiz = parse(SAMPLE, options).unwrap();
+        let (_, question) = quiz.questions().next().unwrap();
+        let QuestionType::Multichoice(q) = question else {
+            panic!("expected multichoice");
+        };
+        assert!(matches!(q.get_text_format(), TextFormat::HTML));
+        assert!(q
+            .get_description()
+            .starts_with("<p>What is the <strong>capital</strong> of France?</p>"));
+        assert_eq!(q.answers()[0].text, "Paris");
+
+        let source = "# Table\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n<script>alert(1)</script>\n\n- [x] ~~Old~~ New\n- [ ] Other\n";
+        let quiz = parse(source, options).unwrap();
+        let (_, question) = quiz.questions().next().unwrap();
+        let QuestionType::Multichoice(q) = question else {
+            panic!("expected multichoice");
+        };
+        assert!(q.get_description().starts_with("<table>"));
+        assert!(!q.get_description().contains("script"));
+        assert_eq!(q.answers()[0].text, "<del>Old</del> New");
+    }
+
+    #[test]
+    fn test_errors() {
+        let Err(e) = parse("Intro text\n# Question\n", MarkdownOptions::default()) else {
+            panic!("expected an error");
+        };
+        assert_eq!(e.line, 1);
+        let source = "# Question\n\nText\n\n- [ ] One\n- [ ] Two\n";
+        let Err(e) = parse(source, MarkdownOptions::default()) else {
+            panic!("expected an error");
+        };
+        assert_eq!(e.line, 1);
+        let source = "# Question\n\nText\n\n- [x] One\n\nMore text\n";
+        let Err(e) = parse(source, MarkdownOptions::default()) else {
+            panic!("expected an error");
+        };
+        assert_eq!(e.line, 7);
+        // A code block after the answers is not moved into the question text
+        let source = "# Question\n\nText\n\n- [x] One\n- [ ] Two\n\n```\ncode\n```\n";
+        let Err(e) = parse(source, MarkdownOptions::default()) else {
+            panic!("expected an error");
+        };
+        assert_eq!(e.line, 8);
+        let Err(e) = parse(
+            "# Essay\n\nText\n\n<!-- single -->\n",
+            MarkdownOptions::default(),
+        ) else {
+            panic!("expected an error");
+        };
+        assert_eq!(e.line, 1);
+    }
+}
@@ -0,0 +1,462 @@
+//! TeX math in question texts.
+//!
+//! Moodle renders TeX with the MathJax filter, which finds math between `$$ … $$`, `\( … \)` and `\[ … \]`.
+//! [`check`] finds the math of a text and reports delimiters which are not closed and TeX whose braces are not
+//! balanced, which MathJax shows as raw TeX or an error. [`check_question`] checks the question text, answers and
+//! feedback of a question.
+//!
+//! Single dollars, `$ … $`, are not math for MathJax in Moodle by default. They are recognized like in Pandoc:
+//! the opening dollar is followed by a non-space character and the closing one preceded by a non-space character
+//! and not followed by a digit, so prices such as `$5 and $10` are not math. [`to_mathjax`] converts them to
+//! `\( … \)`, and so does [`Quiz::set_mathjax_delimiters`](crate::quiz::Quiz::set_mathjax_delimiters) for every
+//! text with a format when the quiz is written.
+//!
+//! ```
+//! use moodle_xml::{math, question::TextFormat};
+//!
+//! assert_eq!(math::to_mathjax("Solve $x^2 = 4$."), r"Solve \(x^2 = 4\).");
+//! let issues = math::check(r"\(\frac{1}{2\)", TextFormat::HTML);
+//! assert_eq!(issues[0].to_string(), r"braces are not balanced in `\frac{1}{2`");
+//! ```
+pub use crate::question::TextLocation;
+use crate::question::{QuestionType, TextFormat};
+use std::{borrow::Cow, fmt, ops::Range};
+
+/// Delimiters of TeX math.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MathDelimiter {
+    /// `$ … $`
+    Dollar,
+    /// `$$ … $$`
+    DoubleDollar,
+    /// `\( … \)`
+    Paren,
+    /// `\[ … \]`
+    Bracket,
+}
+
+impl MathDelimiter {
+    pub fn open(self) -> &'static str {
+        match self {
+            MathDelimiter::Dollar => "$",
+            MathDelimiter::DoubleDollar => "$$",
+            MathDelimiter::Paren => r"\(",
+            MathDelimiter::Bracket => r"\[",
+        }
+    }
+    pub fn close(self) -> &'static str {
+        match self {
+            MathDelimiter::Dollar => "$",
+            MathDelimiter::DoubleDollar => "$$",
+            MathDelimiter::Paren => r"\)",
+            MathDelimiter::Bracket => r"\]",
+        }
+    }
+}
+
+/// TeX math in a text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MathSegment<'a> {
+    pub delimiter: MathDelimiter,
+    /// The TeX between the delimiters.
+    pub tex: &'a str,
+    /// Byte range of the math in the text, including the delimiters.
+    pub range: Range<usize>,
+}
+
+/// A problem with the math of a text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MathIssue {
+    /// The delimiter opening math is never closed. `excerpt` is the text from the delimiter on.
+    Unclosed {
+        delimiter: MathDelimiter,
+        excerpt: String,
+    },
+    /// The delimiter closing math was not opened. `excerpt` is the text before the delimiter.
+    Unopened {
+        delimiter: MathDelimiter,
+        excerpt: String,
+    },
+    /// The TeX has a `}` without a `{` or a `{` without a `}`.
+    UnbalancedBraces { tex: String },
+    /// The TeX has a `\left` without a `\right` or the other way around.
+    UnbalancedLeftRight { tex: String },
+    /// The text has math but is in plain text, which Moodle does not filter.
+    PlainText,
+}
+
+impl fmt::Display for MathIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathIssue::Unclosed { delimiter, excerpt } => {
+                write!(f, "`{}` is not closed in `{}`", delimiter.open(), excerpt)
+            }
+            MathIssue::Unopened { delimiter, excerpt } => write!(
+                f,
+                "`{}` has no `{}` before it in `{}`",
+                delimiter.close(),
+                delimiter.open(),
+                excerpt
+            ),
+            MathIssue::UnbalancedBraces { tex } => {
+                write!(f, "braces are not balanced in `{}`", tex)
+            }
+            MathIssue::UnbalancedLeftRight { tex } => {
+                write!(f, r"`\left` and `\right` are not balanced in `{}`", tex)
+            }
+            MathIssue::PlainText => f.write_str("TeX is not rendered in plain text"),
+        }
+    }
+}
+
+/// Length of the excerpts of issues, in characters.
+const EXCERPT_LENGTH: usize = 30;
+
+fn excerpt_after(text: &str, start: usize) -> String {
+    text[start..].chars().take(EXCERPT_LENGTH).collect()
+}
+
+fn excerpt_before(text: &str, end: usize) -> String {
+    let start = text[..end]
+        .char_indices()
+        .rev()
+        .nth(EXCERPT_LENGTH - 1)
+        .map_or(0, |(index, _)| index);
+    text[start..end].to_string()
+}
+
+/// Finds the math of a text and the delimiters which are not closed or opened.
+fn scan(text: &str) -> (Vec<MathSegment<'_>>, Vec<MathIssue>) {
+    let bytes = text.as_bytes();
+    let mut segments = Vec::new();
+    let mut issues = Vec::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        let delimiter = match (bytes[position], bytes.get(position + 1)) {
+            (b'\\', Some(b'(')) => MathDelimiter::Paren,
+            (b'\\', Some(b'[')) => MathDelimiter::Bracket,
+            (b'\\', Some(&next @ (b')' | b']'))) => {
+                let delimiter = match next {
+                    b')' => MathDelimiter::Paren,
+                    _ => MathDelimiter::Bracket,
+                };
+                issues.push(MathIssue::Unopened {
+                    delimiter,
+                    excerpt: excerpt_before(text, position + 2),
+                });
+                position += 2;
+                continue;
+            }
+            // An escaped character, such as `\$`
+            (b'\\', Some(next)) if next.is_ascii() => {
+                position += 2;
+                continue;
+            }
+            (b'$', Some(b'$')) => MathDelimiter::DoubleDollar,
+            (b'$', _) => {
+                match closing_dollar(text, position) {
+                    Some(end) => {
+                        segments.push(MathSegment {
+                            delimiter: MathDelimiter::Dollar,
+                            tex: &text[position + 1..end],
+                            range: position..end + 1,
+                        });
+                        position = end + 1;
+                    }
+                    None => position += 1,
+                }
+                continue;
+            }
+            _ => {
+                position += 1;
+                continue;
+            }
+        };
+        let start = position + delimiter.open().len();
+        match text[start..].find(delimiter.close()) {
+            Some(length) => {
+                let end = start + length + delimiter.close().len();
+                segments.push(MathSegment {
+                    delimiter,
+                    tex: &text[start..start + length],
+                    range: position..end,
+                });
+                position = end;
+            }
+            None => {
+                issues.push(MathIssue::Unclosed {
+                    delimiter,
+                    excerpt: excerpt_after(text, position),
+                });
+                position = start;
+            }
+        }
+    }
+    (segments, issues)
+}
+
+/// Returns the position of the dollar closing single dollar math opened at `start`, if the dollar opens math.
+fn closing_dollar(text: &str, start: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    if text[start + 1..]
+        .chars()
+        .next()
+        .is_none_or(char::is_whitespace)
+    {
+        return None;
+    }
+    let mut position = start + 1;
+    while position < bytes.len() {
+        match bytes[position] {
+            b'\\' => position += 2,
+            b'$' => {
+                let after_space = text[..position]
+                    .chars()
+                    .next_back()
+                    .is_some_and(char::is_whitespace);
+                let before_digit = bytes.get(position + 1).is_some_and(u8::is_ascii_digit);
+                if after_space || before_digit || bytes.get(position + 1) == Some(&b'$') {
+                    return None;
+                }
+                return Some(position);
+            }
+            _ => position += 1,
+        }
+    }
+    None
+}
+
+/// Checks that the braces and `\left` and `\right` of TeX are balanced.
+fn check_tex(tex: &str, issues: &mut Vec<MathIssue>) {
+    let mut depth = 0i32;
+    let mut balanced = true;
+    let mut rest = tex;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\\' => {
+                // Skips escaped braces and other control symbols
+                let mut chars = rest.chars();
+                chars.next();
+                chars.next();
+                rest = chars.as_str();
+                continue;
+            }
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                balanced &= depth >= 0;
+            }
+            _ => {}
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    if !balanced || depth != 0 {
+        issues.push(MathIssue::UnbalancedBraces {
+            tex: tex.to_string(),
+        });
+    }
+    let count = |command: &str| {
+        tex.match_indices(command)
+            .filter(|(index, _)| {
+                !tex[index + command.len()..].starts_with(|c: char| c.is_ascii_alphabetic())
+            })
+            .count()
+    };
+    if count(r"\left") != count(r"\right") {
+        issues.push(MathIssue::UnbalancedLeftRight {
+            tex: tex.to_string(),
+        });
+    }
+}
+
+/// Returns the math of a text, in the order it appears.
+pub fn segments(text: &str) -> Vec<MathSegment<'_>> {
+    scan(text).0
+}
+
+/// Checks the math of a text in `format`.
+pub fn check(text: &str, format: TextFormat) -> Vec<MathIssue> {
+    let (segments, mut issues) = scan(text);
+    for segment in &segments {
+        check_tex(segment.tex, &mut issues);
+    }
+    if format == TextFormat::PlainText && !segments.is_empty() {
+        issues.push(MathIssue::PlainText);
+    }
+    issues
+}
+
+/// Converts `$ … $` math to `\( … \)`, which MathJax in Moodle renders by default.
+pub fn to_mathjax(text: &str) -> Cow<'_, str> {
+    let segments = segments(text);
+    if !segments
+        .iter()
+        .any(|segment| segment.delimiter == MathDelimiter::Dollar)
+    {
+        return Cow::Borrowed(text);
+    }
+    let mut output = String::with_capacity(text.len() + 2 * segments.len());
+    let mut position = 0;
+    for segment in segments
+        .iter()
+        .filter(|segment| segment.delimiter == MathDelimiter::Dollar)
+    {
+        output.push_str(&text[position..segment.range.start]);
+        output.push_str(r"\(");
+        output.push_str(segment.tex);
+        output.push_str(r"\)");
+        position = segment.range.end;
+    }
+    output.push_str(&text[position..]);
+    Cow::Owned(output)
+}
+
+/// A problem with the math of a text of a question.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MathWarning {
+    pub location: TextLocation,
+    pub issue: MathIssue,
+}
+
+impl fmt::Display for MathWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.issue)
+    }
+}
+
+/// Checks the math in every text of a question, such as its question text, answers, choices and feedback. The
+/// code of CodeRunner questions is not checked.
+pub fn check_question(question: &QuestionType) -> Vec<MathWarning> {
+    question
+        .texts()
+        .into_iter()
+        // Dollars in code are not math
+        .filter(|(location, _, _)| !location.is_code())
+        .flat_map(|(location, text, format)| {
+            check(text, format)
+                .into_iter()
+                .map(move |issue| MathWarning { location, issue })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn test_segments() {
+        let text = r"Prices $5 and $10. $x_1$ and $$\sum_i i$$ and \(a\) \[b\] \$c\$ $ d$";
+        let segments: Vec<(MathDelimiter, &str)> = segments(text)
+            .iter()
+            .map(|segment| (segment.delimiter, segment.tex))
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                (MathDelimiter::Dollar, "x_1"),
+                (MathDelimiter::DoubleDollar, r"\sum_i i"),
+                (MathDelimiter::Paren, "a"),
+                (MathDelimiter::Bracket, "b"),
+            ]
+        );
+        assert_eq!(
+            to_mathjax(text),
+            r"Prices $5 and $10. \(x_1\) and $$\sum_i i$$ and \(a\) \[b\] \$c\$ $ d$"
+        );
+        assert!(matches!(to_mathjax("$5"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check(r"\(\{x\} \left( y \right)\)", TextFormat::HTML), []);
+        assert_eq!(
+            check(r"$$x^{2$$ and \(y", TextFormat::HTML),
+            [
+                MathIssue::Unclosed {
+                    delimiter: MathDelimiter::Paren,
+                    excerpt: r"\(y".to_string()
+                },
+                MathIssue::UnbalancedBraces {
+                    tex: "x^{2".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            check(r"x\) \(\left(x\)", TextFormat::PlainText)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                r"`\)` has no `\(` before it in `x\)`",
+                r"`\left` and `\right` are not balanced in `\left(x`",
+                "TeX is not rendered in plain text",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_question() {
+        let mut question = MultiChoiceQuestion::new(
+            "Roots".to_string(),
+            r"Solve \(x^2 = 4".to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let mut plain = Answer::new(
+            100.0,
+            "$x = 2$".to_string(),
+            Some(r"\(\frac{4}{2\)".to_string()),
+        );
+        plain.set_text_format(TextFormat::PlainText);
+        question
+            .add_answers(vec![plain, Answer::new(0.0, "$x = 3$".to_string(), None)])
+            .unwrap();
+        let warnings: Vec<String> = check_question(&question.into())
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                r"question text: `\(` is not closed in `\(x^2 = 4`",
+                "answer 1: TeX is not rendered in plain text",
+                r"feedback of answer 1: braces are not balanced in `\frac{4}{2`",
+                "feedback of answer 1: TeX is not rendered in plain text",
+            ]
+        );
+
+        let mut ordering =
+            OrderingQuestion::new("Steps".to_string(), "Order the steps".to_string());
+        ordering.add_items(vec![
+            r"Expand \(a(b + c)".to_string(),
+            "Simplify".to_string(),
+        ]);
+        ordering.feedback.incorrectfeedback = r"Check \(\frac{1}{2\)".to_string();
+        let warnings: Vec<String> = check_question(&ordering.into())
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                r"item 1: `\(` is not closed in `\(a(b + c)`",
+                r"incorrect feedback: braces are not balanced in `\frac{1}{2`",
+            ]
+        );
+
+        // Dollars in code are not math
+        let mut coderunner = CodeRunnerQuestion::new(
+            "Shell".to_string(),
+            "Print the $HOME variable".to_string(),
+            "bash".to_string(),
+        );
+        coderunner.answer = "echo $HOME $".to_string();
+        assert!(check_question(&coderunner.into()).is_empty());
+    }
+}
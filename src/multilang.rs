@@ -0,0 +1,291 @@
+//! Texts in several languages for the Moodle multilang filters.
+//!
+//! A [`MultiLang`] maps languages to translations of the same text. Converted into a string it is written with
+//! the syntax of the core multilang filter, `<span lang="fi" class="multilang">…</span>`, so it can be used
+//! wherever questions and answers take text. [`Quiz::set_multilang`](crate::quiz::Quiz::set_multilang) chooses
+//! the syntax written to the file, which can also be the `{mlang fi}…{mlang}` syntax of the multilang2 filter,
+//! and the languages every translated text must have.
+//!
+//! ```
+//! use moodle_xml::{multilang::MultiLang, prelude::*};
+//!
+//! let text = MultiLang::from([("fi", "Mikä on Suomen pääkaupunki?"), ("en", "What is the capital of Finland?")]);
+//! let question = EssayQuestion::new("Capital".to_string(), text.into());
+//! assert!(question.get_description().starts_with(r#"<span lang="fi" class="multilang">"#));
+//! ```
+use crate::quiz::QuizError;
+use std::{borrow::Cow, fmt};
+
+/// Syntax of the multilang blocks written to the file.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum MultiLangSyntax {
+    /// `<span lang="fi" class="multilang">…</span>` of the core multilang filter.
+    #[default]
+    Span,
+    /// `{mlang fi}…{mlang}` of the multilang2 filter.
+    Mlang,
+}
+
+/// Multilang options of a quiz.
+#[derive(Debug, Clone, Default)]
+pub struct MultiLangOptions {
+    pub syntax: MultiLangSyntax,
+    /// Languages every text with multilang blocks must have. Empty allows any languages.
+    pub languages: Vec<String>,
+}
+
+impl MultiLangOptions {
+    pub fn new(syntax: MultiLangSyntax, languages: Vec<String>) -> Self {
+        Self { syntax, languages }
+    }
+}
+
+/// Translations of a text, in the order they are written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MultiLang {
+    texts: Vec<(String, String)>,
+}
+
+impl MultiLang {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Sets the translation to `language`, replacing an earlier one.
+    pub fn insert(&mut self, language: impl Into<String>, text: impl Into<String>) {
+        let (language, text) = (language.into(), text.into());
+        match self.texts.iter_mut().find(|(lang, _)| *lang == language) {
+            Some((_, existing)) => *existing = text,
+            None => self.texts.push((language, text)),
+        }
+    }
+    /// Returns the translation to `language`.
+    pub fn get(&self, language: &str) -> Option<&str> {
+        self.texts
+            .iter()
+            .find(|(lang, _)| lang == language)
+            .map(|(_, text)| text.as_str())
+    }
+    /// Returns the languages of the translations.
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.texts.iter().map(|(language, _)| language.as_str())
+    }
+    /// Renders the translations with the given syntax.
+    pub fn render(&self, syntax: MultiLangSyntax) -> String {
+        self.texts
+            .iter()
+            .map(|(language, text)| block(syntax, language, text))
+            .collect()
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for MultiLang {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut multilang = MultiLang::new();
+        for (language, text) in iter {
+            multilang.insert(language, text);
+        }
+        multilang
+    }
+}
+
+impl<K: Into<String>, V: Into<String>, const N: usize> From<[(K, V); N]> for MultiLang {
+    fn from(texts: [(K, V); N]) -> Self {
+        texts.into_iter().collect()
+    }
+}
+
+impl fmt::Display for MultiLang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(MultiLangSyntax::Span))
+    }
+}
+
+impl From<MultiLang> for String {
+    fn from(multilang: MultiLang) -> Self {
+        multilang.to_string()
+    }
+}
+
+fn block(syntax: MultiLangSyntax, language: &str, text: &str) -> String {
+    match syntax {
+        MultiLangSyntax::Span => {
+            format!(
+                r#"<span lang="{}" class="multilang">{}</span>"#,
+                language, text
+            )
+        }
+        MultiLangSyntax::Mlang => format!("{{mlang {}}}{}{{mlang}}", language, text),
+    }
+}
+
+/// A multilang block in a text, in either syntax.
+struct Block<'a> {
+    start: usize,
+    end: usize,
+    language: &'a str,
+    content_start: usize,
+    content: &'a str,
+}
+
+/// Finds the multilang blocks of a text. Like the filters, a block ends at the first closing tag, so blocks
+/// can not contain other spans.
+fn blocks(text: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut position = 0;
+    while position < text.len() {
+        let rest = &text[position..];
+        let next = [rest.find("<span"), rest.find("{mlang ")]
+            .into_iter()
+            .flatten()
+            .min();
+        let Some(offset) = next else {
+            break;
+        };
+        let start = position + offset;
+        match parse_block(text, start) {
+            Some(block) => {
+                position = block.end;
+                blocks.push(block);
+            }
+            None => position = start + 1,
+        }
+    }
+    blocks
+}
+
+fn parse_block(text: &str, start: usize) -> Option<Block<'_>> {
+    let rest = &text[start..];
+    let (language, open, close) = if rest.starts_with("{mlang ") {
+        let open = rest.find('}')? + 1;
+        (rest["{mlang ".len()..open - 1].trim(), open, "{mlang}")
+    } else {
+        let open = rest.find('>')? + 1;
+        let attributes = &rest["<span".len()..open - 1];
+        if attribute(attributes, "class")? != "multilang" {
+            return None;
+        }
+        (attribute(attributes, "lang")?, open, "</span>")
+    };
+    let length = rest[open..].find(close)?;
+    Some(Block {
+        start,
+        end: start + open + length + close.len(),
+        language,
+        content_start: start + open,
+        content: &rest[open..open + length],
+    })
+}
+
+/// Returns the value of a double quoted attribute.
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!(" {}=\"", name);
+    let start = attributes.find(&pattern)? + pattern.len();
+    let length = attributes[start..].find('"')?;
+    Some(&attributes[start..start + length])
+}
+
+/// Changes the text between the multilang blocks and the content of every block with `change`, keeping the
+/// markup of the blocks as it is. Returns `None` if the text has no multilang blocks.
+pub(crate) fn change_contents(
+    text: &str,
+    mut change: impl FnMut(&str) -> Result<String, QuizError>,
+) -> Result<Option<String>, QuizError> {
+    let blocks = blocks(text);
+    if blocks.is_empty() {
+        return Ok(None);
+    }
+    let mut output = String::with_capacity(text.len());
+    let mut position = 0;
+    for block in &blocks {
+        output.push_str(&change(&text[position..block.start])?);
+        output.push_str(&text[block.start..block.content_start]);
+        output.push_str(&change(block.content)?);
+        output.push_str(&text[block.content_start + block.content.len()..block.end]);
+        position = block.end;
+    }
+    output.push_str(&change(&text[position..])?);
+    Ok(Some(output))
+}
+
+/// Checks that a text has every language of the options and writes its blocks with the syntax of the options.
+/// Texts without multilang blocks are not translated and are returned as they are.
+pub fn apply<'a>(text: &'a str, options: &MultiLangOptions) -> Result<Cow<'a, str>, QuizError> {
+    let blocks = blocks(text);
+    let Some(first) = blocks.first() else {
+        return Ok(Cow::Borrowed(text));
+    };
+    let excerpt: String = first.content.chars().take(40).collect();
+    if !options.languages.is_empty() {
+        if let Some(block) = blocks
+            .iter()
+            .find(|block| !options.languages.iter().any(|l| l == block.language))
+        {
+            return Err(QuizError::ValueError(format!(
+                "Text `{}` has the language `{}`, which is not a language of the quiz",
+                excerpt, block.language
+            )));
+        }
+        let missing: Vec<&str> = options
+            .languages
+            .iter()
+            .filter(|language| !blocks.iter().any(|block| block.language == *language))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(QuizError::ValueError(format!(
+                "Text `{}` has no translation to {}",
+                excerpt,
+                missing.join(", ")
+            )));
+        }
+    }
+    let mut output = String::with_capacity(text.len());
+    let mut position = 0;
+    for block in &blocks {
+        output.push_str(&text[position..block.start]);
+        output.push_str(&self::block(options.syntax, block.language, block.content));
+        position = block.end;
+    }
+    output.push_str(&text[position..]);
+    Ok(Cow::Owned(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let text: String = MultiLang::from([("fi", "Kissa"), ("sv", "Katt")]).into();
+        let text = format!("<p>{}</p> <span>other</span>", text);
+        let mut options = MultiLangOptions::new(
+            MultiLangSyntax::Mlang,
+            vec!["fi".to_string(), "sv".to_string()],
+        );
+        assert_eq!(
+            apply(&text, &options).unwrap(),
+            "<p>{mlang fi}Kissa{mlang}{mlang sv}Katt{mlang}</p> <span>other</span>"
+        );
+        let mlang = apply(&text, &options).unwrap().into_owned();
+        options.syntax = MultiLangSyntax::Span;
+        assert_eq!(
+            apply(&mlang, &options).unwrap(),
+            r#"<p><span lang="fi" class="multilang">Kissa</span><span lang="sv" class="multilang">Katt</span></p> <span>other</span>"#
+        );
+        assert!(matches!(
+            apply("42", &options).unwrap(),
+            Cow::Borrowed("42")
+        ));
+
+        options.languages.push("en".to_string());
+        assert_eq!(
+            apply(&text, &options).unwrap_err().to_string(),
+            "Text `Kissa` has no translation to en"
+        );
+        options.languages = vec!["fi".to_string()];
+        assert_eq!(
+            apply(&text, &options).unwrap_err().to_string(),
+            "Text `Kissa` has the language `sv`, which is not a language of the quiz"
+        );
+    }
+}
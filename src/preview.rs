@@ -0,0 +1,648 @@
+//! Static HTML preview of a quiz, for proofreading it without importing it into Moodle.
+//!
+//! The preview is a single self-contained HTML file. Every question shows its text rendered according to its
+//! [`TextFormat`], the answers with their fractions and feedback, the right answers highlighted, and the
+//! combined feedback, general feedback and hints. Embedded files are inlined as `data:` URLs. HTML is sanitized,
+//! and Markdown is rendered like [`convert::render_markdown`] when the `markdown` feature is enabled.
+//!
+//! ```
+//! use moodle_xml::{preview, prelude::*};
+//!
+//! let question = EssayQuestion::new("Essay".to_string(), "Describe <b>Helsinki</b>.".to_string());
+//! let html = preview::to_html(&Quiz::new(vec![question.into()]));
+//! assert!(html.contains("Describe <b>Helsinki</b>."));
+//! ```
+use crate::{
+    answer::{format_fraction, Answer, CalculatedAnswer, FRACTION_TOLERANCE},
+    convert,
+    dataset::DatasetDefinition,
+    file::EmbeddedFile,
+    html::{self, escape},
+    question::{CombinedFeedback, QuestionType, TextFormat},
+    quiz::{Category, Quiz, QuizError},
+};
+use std::{fmt::Write, path::Path};
+
+const STYLE: &str = "
+body { font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; color: #222; }
+h2.category { border-bottom: 2px solid #888; padding-bottom: 0.2em; }
+section.question { border: 1px solid #ccc; border-radius: 6px; padding: 0.5em 1em; margin: 1em 0; }
+section.question h3 { margin: 0.3em 0; }
+.type { font-size: 0.8em; color: #555; text-transform: uppercase; }
+.tag { display: inline-block; background: #eee; border-radius: 3px; padding: 0 0.4em; margin-right: 0.3em; font-size: 0.8em; }
+.plain-text { white-space: pre-wrap; }
+.answers li { margin: 0.3em 0; padding: 0.2em 0.4em; border-radius: 4px; }
+.answers li.correct { background: #dff0d8; }
+.answers li.partial { background: #fcf8e3; }
+.fraction { float: right; font-size: 0.8em; color: #555; }
+.feedback { font-size: 0.9em; color: #31708f; font-style: italic; }
+.note { font-size: 0.9em; color: #555; }
+table.matching td, table.datasets td { border-bottom: 1px solid #ddd; padding: 0.2em 0.6em; }
+img { max-width: 100%; }
+";
+
+/// Renders a quiz as a self-contained HTML page.
+pub fn to_html(quiz: &Quiz) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Quiz preview</title>\n<style>");
+    html.push_str(STYLE);
+    html.push_str("</style>\n</head>\n<body>\n<h1>Quiz preview</h1>\n");
+    let _ = writeln!(html, "<p class=\"note\">{} questions</p>", quiz.len());
+    let mut current_category: Option<&Category> = None;
+    for (category, question) in quiz.questions() {
+        if category.is_some() && category != current_category {
+            let _ = writeln!(
+                html,
+                "<h2 class=\"category\">{}</h2>",
+                escape(category.map(|c| c.as_str()).unwrap_or_default())
+            );
+        }
+        current_category = category;
+        write_question(&mut html, question);
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Writes the HTML preview of a quiz to `path`.
+pub fn write_file(quiz: &Quiz, path: impl AsRef<Path>) -> Result<(), QuizError> {
+    std::fs::write(path, to_html(quiz))?;
+    Ok(())
+}
+
+/// Renders text according to its format and inlines the embedded files it refers to.
+fn format_text(text: &str, format: TextFormat, files: &[EmbeddedFile]) -> String {
+    let mut html = match format {
+        TextFormat::HTML => html::sanitize(text),
+        // Without the `markdown` feature Markdown is shown as it is
+        TextFormat::Markdown => convert::render_markdown(text)
+            .unwrap_or_else(|_| format!("<div class=\"plain-text\">{}</div>", escape(text))),
+        TextFormat::Moodle | TextFormat::PlainText => {
+            format!("<div class=\"plain-text\">{}</div>", escape(text))
+        }
+    };
+    for file in files {
+        let data_url = file.data_url();
+        html = html
+            .replace(&file.url(), &data_url)
+            .replace(&file.url().replace(' ', "%20"), &data_url);
+    }
+    html
+}
+
+/// Returns the class of an answer by its fraction. Every positive answer of a `multiple` response question is
+/// right, as they share the grade, and otherwise the answers with the `best` fraction are.
+fn answer_class(fraction: f64, best: f64, multiple: bool) -> &'static str {
+    if fraction <= 0.0 {
+        "wrong"
+    } else if multiple || fraction >= best - FRACTION_TOLERANCE {
+        "correct"
+    } else {
+        "partial"
+    }
+}
+
+/// Returns the best fraction of the answers.
+fn best_fraction<'a>(answers: impl Iterator<Item = &'a Answer>) -> f64 {
+    answers.map(|answer| answer.fraction).fold(0.0, f64::max)
+}
+
+fn write_answers(html: &mut String, answers: &[Answer], multiple: bool, files: &[EmbeddedFile]) {
+    let best = best_fraction(answers.iter());
+    html.push_str("<ol class=\"answers\">\n");
+    for answer in answers {
+        write_answer(
+            html,
+            answer,
+            answer_class(answer.fraction, best, multiple),
+            None,
+            files,
+        );
+    }
+    html.push_str("</ol>\n");
+}
+
+fn write_answer(
+    html: &mut String,
+    answer: &Answer,
+    class: &str,
+    extra: Option<&str>,
+    files: &[EmbeddedFile],
+) {
+    let _ = write!(
+        html,
+        "<li class=\"answer {}\"><span class=\"fraction\">{}%</span>{}",
+        class,
+        format_fraction(answer.fraction),
+        format_text(&answer.text, answer.text_format, files)
+    );
+    if let Some(extra) = extra {
+        let _ = write!(html, " <span class=\"note\">{}</span>", escape(extra));
+    }
+    if let Some(feedback) = answer.feedback.as_ref().filter(|f| !f.is_empty()) {
+        let _ = write!(
+            html,
+            "<div class=\"feedback\">{}</div>",
+            format_text(feedback, answer.text_format, files)
+        );
+    }
+    html.push_str("</li>\n");
+}
+
+fn write_calculated_answers(
+    html: &mut String,
+    answers: &[CalculatedAnswer],
+    multiple: bool,
+    files: &[EmbeddedFile],
+) {
+    let best = best_fraction(answers.iter().map(|answer| &answer.answer));
+    html.push_str("<ol class=\"answers\">\n");
+    for answer in answers {
+        let tolerance = format!("± {} ({:?})", answer.tolerance, answer.tolerance_type);
+        let class = answer_class(answer.answer.fraction, best, multiple);
+        write_answer(html, &answer.answer, class, Some(&tolerance), files);
+    }
+    html.push_str("</ol>\n");
+}
+
+/// Writes the feedback shown for right, partially right and wrong responses, if it is set.
+fn write_combined_feedback(
+    html: &mut String,
+    correct: &str,
+    partially_correct: &str,
+    incorrect: &str,
+    files: &[EmbeddedFile],
+) {
+    for (title, feedback) in [
+        ("Correct", correct),
+        ("Partially correct", partially_correct),
+        ("Incorrect", incorrect),
+    ] {
+        if !feedback.is_empty() {
+            let _ = writeln!(
+                html,
+                "<div class=\"feedback\">{}: {}</div>",
+                title,
+                format_text(feedback, TextFormat::default(), files)
+            );
+        }
+    }
+}
+
+fn write_feedback(html: &mut String, feedback: &CombinedFeedback, files: &[EmbeddedFile]) {
+    write_combined_feedback(
+        html,
+        &feedback.correctfeedback,
+        &feedback.partiallycorrectfeedback,
+        &feedback.incorrectfeedback,
+        files,
+    );
+}
+
+fn write_datasets(html: &mut String, datasets: &[DatasetDefinition]) {
+    html.push_str("<table class=\"datasets\">\n");
+    for dataset in datasets {
+        let items: Vec<String> = dataset.items().iter().map(f64::to_string).collect();
+        let _ = writeln!(
+            html,
+            "<tr><td>{{{}}}</td><td>{} – {}</td><td class=\"note\">{}</td></tr>",
+            escape(&dataset.name),
+            dataset.minimum,
+            dataset.maximum,
+            items.join(", ")
+        );
+    }
+    html.push_str("</table>\n");
+}
+
+/// Writes the right choice of every gap and the choices with their groups.
+fn write_gaps(html: &mut String, gaps: &[usize], choices: &[(&str, u8, bool)]) {
+    html.push_str("<table class=\"matching\">\n");
+    for gap in gaps {
+        let right = gap
+            .checked_sub(1)
+            .and_then(|index| choices.get(index))
+            .map_or("?", |choice| choice.0);
+        let _ = writeln!(
+            html,
+            "<tr><td>[[{}]]</td><td>→ {}</td></tr>",
+            gap,
+            escape(right)
+        );
+    }
+    html.push_str("</table>\n<ol class=\"answers\">\n");
+    for (text, group, infinite) in choices {
+        let _ = writeln!(
+            html,
+            "<li>{} <span class=\"note\">group {}{}</span></li>",
+            escape(text),
+            group,
+            if *infinite { ", unlimited" } else { "" }
+        );
+    }
+    html.push_str("</ol>\n");
+}
+
+/// Writes the background image and the right item of every drop zone, `zones` has the zone and the item.
+fn write_zones(html: &mut String, background: Option<&EmbeddedFile>, zones: &[(String, &str)]) {
+    if let Some(background) = background {
+        let _ = writeln!(
+            html,
+            "<img src=\"{}\" alt=\"{}\">",
+            background.data_url(),
+            escape(&background.name)
+        );
+    }
+    html.push_str("<table class=\"matching\">\n");
+    for (zone, item) in zones {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>→ {}</td></tr>",
+            escape(zone),
+            escape(item)
+        );
+    }
+    html.push_str("</table>\n");
+}
+
+fn write_note(html: &mut String, note: &str) {
+    let _ = writeln!(html, "<p class=\"note\">{}</p>", escape(note));
+}
+
+fn write_question(html: &mut String, question: &QuestionType) {
+    let common = question.as_question();
+    let files = common.get_files();
+    html.push_str("<section class=\"question\">\n");
+    let _ = writeln!(
+        html,
+        "<span class=\"type\">{}</span>\n<h3>{}</h3>",
+        question.type_name(),
+        escape(common.get_name())
+    );
+    if !common.get_tags().is_empty() {
+        html.push_str("<div class=\"tags\">");
+        for tag in common.get_tags() {
+            let _ = write!(html, "<span class=\"tag\">{}</span>", escape(tag));
+        }
+        html.push_str("</div>\n");
+    }
+    let _ = writeln!(
+        html,
+        "<div class=\"text\">{}</div>",
+        format_text(common.get_description(), common.get_text_format(), files)
+    );
+    match question {
+        QuestionType::Multichoice(q) => {
+            write_note(
+                html,
+                if q.single {
+                    "One answer can be chosen."
+                } else {
+                    "Several answers can be chosen."
+                },
+            );
+            write_answers(html, q.answers(), !q.single, files);
+            write_combined_feedback(
+                html,
+                &q.correctfeedback,
+                &q.partiallycorrectfeedback,
+                &q.incorrectfeedback,
+                files,
+            );
+        }
+        QuestionType::TrueFalse(q) => write_answers(html, q.answers(), false, files),
+        QuestionType::ShortAnswer(q) => {
+            if q.usecase {
+                write_note(html, "Answers are case sensitive.");
+            }
+            write_answers(html, q.answers(), false, files);
+        }
+        QuestionType::Numerical(q) => {
+            let best = best_fraction(q.answers().iter().map(|answer| &answer.answer));
+            html.push_str("<ol class=\"answers\">\n");
+            for answer in q.answers() {
+                let tolerance = format!("± {}", answer.tolerance);
+                let extra = (answer.tolerance != 0.0).then_some(tolerance.as_str());
+                let class = answer_class(answer.answer.fraction, best, false);
+                write_answer(html, &answer.answer, class, extra, files);
+            }
+            html.push_str("</ol>\n");
+        }
+        QuestionType::Matching(q) => {
+            html.push_str("<table class=\"matching\">\n");
+            for subquestion in q.subquestions() {
+                let text = if subquestion.text.is_empty() {
+                    "<span class=\"note\">(extra answer)</span>".to_string()
+                } else {
+                    format_text(&subquestion.text, common.get_text_format(), files)
+                };
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}</td><td>→ {}</td></tr>",
+                    text,
+                    escape(&subquestion.answer)
+                );
+            }
+            html.push_str("</table>\n");
+            write_feedback(html, &q.feedback, files);
+        }
+        QuestionType::Essay(_) => write_note(html, "Answered with free text."),
+        QuestionType::Description(_) => {}
+        QuestionType::Calculated(q) => {
+            write_calculated_answers(html, q.answers(), false, files);
+            write_datasets(html, q.datasets());
+        }
+        QuestionType::CalculatedSimple(q) => {
+            write_calculated_answers(html, q.answers(), false, files);
+            write_datasets(html, q.datasets());
+        }
+        QuestionType::CalculatedMulti(q) => {
+            write_calculated_answers(html, q.answers(), !q.single, files);
+            write_datasets(html, q.datasets());
+            write_combined_feedback(
+                html,
+                &q.correctfeedback,
+                &q.partiallycorrectfeedback,
+                &q.incorrectfeedback,
+                files,
+            );
+        }
+        QuestionType::DragDropText(q) => {
+            let choices: Vec<(&str, u8, bool)> = q
+                .choices()
+                .iter()
+                .map(|choice| (choice.text.as_str(), choice.group, choice.infinite))
+                .collect();
+            write_gaps(html, &q.gaps(), &choices);
+            write_feedback(html, &q.feedback, files);
+        }
+        QuestionType::GapSelect(q) => {
+            let choices: Vec<(&str, u8, bool)> = q
+                .choices()
+                .iter()
+                .map(|choice| (choice.text.as_str(), choice.group, false))
+                .collect();
+            write_gaps(html, &q.gaps(), &choices);
+            write_feedback(html, &q.feedback, files);
+        }
+        QuestionType::DragDropImage(q) => {
+            let zones: Vec<(String, &str)> = q
+                .drops()
+                .iter()
+                .map(|drop| {
+                    let item = drop
+                        .choice
+                        .checked_sub(1)
+                        .and_then(|index| q.drags().get(index))
+                        .map_or("?", |drag| drag.text.as_str());
+                    (format!("({}, {})", drop.x, drop.y), item)
+                })
+                .collect();
+            write_zones(html, q.background(), &zones);
+            write_feedback(html, &q.feedback, files);
+        }
+        QuestionType::DragMarker(q) => {
+            let zones: Vec<(String, &str)> = q
+                .zones()
+                .iter()
+                .map(|zone| {
+                    let marker = zone
+                        .choice
+                        .checked_sub(1)
+                        .and_then(|index| q.markers().get(index))
+                        .map_or("?", |marker| marker.text.as_str());
+                    (zone.shape.to_string(), marker)
+                })
+                .collect();
+            write_zones(html, q.background(), &zones);
+            write_feedback(html, &q.feedback, files);
+        }
+        QuestionType::RandomShortAnswerMatch(q) => {
+            write_note(
+                html,
+                &format!(
+                    "Matches {} short answer questions drawn from the category{}.",
+                    q.choose,
+                    if q.subcats {
+                        " and its subcategories"
+                    } else {
+                        ""
+                    }
+                ),
+            );
+            write_feedback(html, &q.feedback, files);
+        }
+        QuestionType::Ordering(q) => {
+            write_note(
+                html,
+                &format!(
+                    "Items are shown {} and graded by {}.",
+                    q.layout.name().to_lowercase(),
+                    q.grading.name().to_lowercase().replace('_', " ")
+                ),
+            );
+            html.push_str("<ol class=\"answers\">\n");
+            for item in q.items() {
+                let _ = writeln!(
+                    html,
+                    "<li>{}</li>",
+                    format_text(item, common.get_text_format(), files)
+                );
+            }
+            html.push_str("</ol>\n");
+            write_feedback(html, &q.feedback, files);
+        }
+        QuestionType::CodeRunner(q) => {
+            write_note(
+                html,
+                &format!("Answered with a {} program.", q.coderunnertype),
+            );
+            if !q.answer.is_empty() {
+                let _ = writeln!(html, "<pre class=\"answer\">{}</pre>", escape(&q.answer));
+            }
+            html.push_str("<table class=\"matching\">\n");
+            for test in q.testcases() {
+                let _ = writeln!(
+                    html,
+                    "<tr><td><pre>{}</pre></td><td>→ <pre>{}</pre></td><td class=\"note\">{}</td></tr>",
+                    escape(&test.testcode),
+                    escape(&test.expected),
+                    test.display.name().to_lowercase().replace('_', " ")
+                );
+            }
+            html.push_str("</table>\n");
+        }
+        QuestionType::Custom(_) => write_note(
+            html,
+            "The preview shows only the text of this question type.",
+        ),
+        QuestionType::Random(q) => write_note(
+            html,
+            if q.include_subcategories {
+                "A question drawn from the category and its subcategories."
+            } else {
+                "A question drawn from the category."
+            },
+        ),
+    }
+    // Moodle shows the general feedback after the attempt, whatever the response, and the hints one at a time
+    // when a question is tried again
+    if let Some(feedback) = common.get_general_feedback().filter(|f| !f.is_empty()) {
+        let _ = writeln!(
+            html,
+            "<div class=\"feedback\">General feedback: {}</div>",
+            format_text(feedback, common.get_text_format(), files)
+        );
+    }
+    if !common.get_hints().is_empty() {
+        html.push_str("<ol class=\"hints\">\n");
+        for hint in common.get_hints() {
+            let _ = writeln!(
+                html,
+                "<li class=\"note\">Hint: {}</li>",
+                format_text(&hint.text, hint.text_format, files)
+            );
+        }
+        html.push_str("</ol>\n");
+    }
+    html.push_str("</section>\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn test_preview() {
+        let mut multichoice = MultiChoiceQuestion::new(
+            "Capital".to_string(),
+            "What is the capital of <i>France</i>?".to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        multichoice
+            .add_answers(vec![
+                Answer::new(100.0, "Paris".to_string(), Some("Correct!".to_string())),
+                Answer::new(0.0, "Lyon".to_string(), None),
+            ])
+            .unwrap();
+        let image = EmbeddedFile::new("map.png".to_string(), b"Mo".to_vec());
+        let mut essay = EssayQuestion::new(
+            "Map & <essay>".to_string(),
+            format!("Describe the map.\n<img src=\"{}\">", image.url()),
+        );
+        essay.add_files(vec![image]);
+        let mut quiz = Quiz::new(vec![multichoice.into()]);
+        quiz.add_category("Capitals".into(), vec![essay.into()]);
+
+        let html = to_html(&quiz);
+        assert!(html.contains("What is the capital of <i>France</i>?"));
+        assert!(html.contains(
+            "<li class=\"answer correct\"><span class=\"fraction\">100%</span>Paris<div class=\"feedback\">Correct!</div></li>"
+        ));
+        assert!(html.contains("<h2 class=\"category\">Capitals</h2>"));
+        assert!(html.contains("<h3>Map &amp; &lt;essay&gt;</h3>"));
+        assert!(html.contains("<img src=\"data:image/png;base64,TW8=\">"));
+
+        let mut plain = DescriptionQuestion::new("Plain".to_string(), "1 < 2\n2 < 3".to_string());
+        plain.set_text_format(TextFormat::PlainText);
+        let html = to_html(&Quiz::new(vec![plain.into()]));
+        assert!(html.contains("<div class=\"plain-text\">1 &lt; 2\n2 &lt; 3</div>"));
+
+        let unsafe_html = DescriptionQuestion::new(
+            "Unsafe".to_string(),
+            "<p onclick=\"steal()\">Hi</p><script>steal()</script>".to_string(),
+        );
+        let html = to_html(&Quiz::new(vec![unsafe_html.into()]));
+        assert!(html.contains("<p>Hi</p>"));
+        assert!(!html.contains("steal()"));
+    }
+
+    #[test]
+    fn test_preview_right_answers_and_feedback() {
+        let mut multiple = MultiChoiceQuestion::new(
+            "Nordic".to_string(),
+            "Which are Nordic capitals?".to_string(),
+            Some(false),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        multiple
+            .add_answers(vec![
+                Answer::new(100.0 / 3.0, "Oslo".to_string(), None),
+                Answer::new(100.0 / 3.0, "Helsinki".to_string(), None),
+                Answer::new(100.0 / 3.0, "Stockholm".to_string(), None),
+                Answer::new(-100.0, "Berlin".to_string(), None),
+            ])
+            .unwrap();
+        multiple.set_general_feedback("All three are capitals.".to_string());
+        let mut hint = Hint::new("Think of Scandinavia.".to_string());
+        hint.text_format = TextFormat::PlainText;
+        multiple.add_hints(vec![hint]);
+        let mut short = ShortAnswerQuestion::new(
+            "Capital".to_string(),
+            "Capital of Finland?".to_string(),
+            None,
+        );
+        short
+            .add_answers(vec![
+                Answer::new(100.0, "Helsinki".to_string(), None),
+                Answer::new(50.0, "Helsingfors".to_string(), None),
+            ])
+            .unwrap();
+        let mut matching =
+            MatchingQuestion::new("Pairs".to_string(), "Match the capitals".to_string(), None);
+        matching.add_subquestions(vec![
+            SubQuestion::new("Finland".to_string(), "Helsinki".to_string()),
+            SubQuestion::new("Sweden".to_string(), "Stockholm".to_string()),
+        ]);
+        matching.feedback.correctfeedback = "All right".to_string();
+
+        let html = to_html(&Quiz::new(vec![
+            multiple.into(),
+            short.into(),
+            matching.into(),
+        ]));
+        // The right answers of a multiple response question share the grade
+        assert_eq!(
+            html.matches("<li class=\"answer correct\"><span class=\"fraction\">33.33333%")
+                .count(),
+            3
+        );
+        assert!(
+            html.contains("<li class=\"answer wrong\"><span class=\"fraction\">-100%</span>Berlin")
+        );
+        assert!(html.contains(
+            "<li class=\"answer partial\"><span class=\"fraction\">50%</span>Helsingfors"
+        ));
+        assert!(html
+            .contains("<div class=\"feedback\">General feedback: All three are capitals.</div>"));
+        assert!(html.contains(
+            "<li class=\"note\">Hint: <div class=\"plain-text\">Think of Scandinavia.</div></li>"
+        ));
+        assert!(html.contains("<div class=\"feedback\">Correct: All right</div>"));
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn test_preview_markdown() {
+        let mut markdown = DescriptionQuestion::new(
+            "Markdown".to_string(),
+            "~~Old~~ new\n\n| a |\n|---|\n| 1 |\n\n<script>steal()</script>".to_string(),
+        );
+        markdown.set_text_format(TextFormat::Markdown);
+        let html = to_html(&Quiz::new(vec![markdown.into()]));
+        assert!(html.contains("<p><del>Old</del> new</p>"));
+        assert!(html.contains("<table>"));
+        assert!(!html.contains("steal()"));
+    }
+}
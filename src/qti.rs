@@ -0,0 +1,765 @@
+//! Export to [IMS QTI 2.1](https://www.imsglobal.org/question/qtiv2p1/imsqti_infov2p1.html) content packages.
+//!
+//! Every question becomes an `assessmentItem` file under `items/`, listed in `imsmanifest.xml`, and its embedded
+//! files are copied under `media/`. Multiple choice, true/false, short answer and essay questions are supported.
+//! QTI has no place for some Moodle features, such as categories, answer feedback and hints; they are left out
+//! and reported as warnings.
+//!
+//! ```no_run
+//! use moodle_xml::{prelude::*, qti};
+//!
+//! let mut question = EssayQuestion::new("Essay".to_string(), "Describe Finland.".to_string());
+//! let quiz = Quiz::new(vec![question.into()]);
+//! let package = qti::to_qti(&quiz).unwrap();
+//! for warning in package.warnings() {
+//!     eprintln!("{}", warning);
+//! }
+//! package.to_file("quiz.zip").unwrap();
+//! ```
+use crate::{
+    answer::Answer,
+    html::{self, HtmlIssue, Tag, Token, VOID_ELEMENTS},
+    question::{Question, QuestionType, TextFormat},
+    quiz::{EmptyError, Quiz, QuizError},
+};
+use std::{
+    fmt,
+    fs::File,
+    io::{Seek, Write},
+    path::Path,
+};
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
+
+const QTI_NAMESPACE: &str = "http://www.imsglobal.org/xsd/imsqti_v2p1";
+const QTI_SCHEMA: &str = "http://www.imsglobal.org/xsd/imsqti_v2p1 http://www.imsglobal.org/xsd/qti/qtiv2p1/imsqti_v2p1.xsd";
+const CP_NAMESPACE: &str = "http://www.imsglobal.org/xsd/imscp_v1p1";
+const CP_SCHEMA: &str =
+    "http://www.imsglobal.org/xsd/imscp_v1p1 http://www.imsglobal.org/xsd/imscp_v1p1.xsd";
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+const MAP_RESPONSE: &str = "http://www.imsglobal.org/question/qti_v2p1/rptemplates/map_response";
+
+type Writer<'a> = EventWriter<&'a mut Vec<u8>>;
+
+/// A Moodle feature which was left out of the QTI package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QtiWarning {
+    /// Name of the question, or `None` if the warning is about the whole quiz.
+    pub question: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for QtiWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.question {
+            Some(question) => write!(f, "question `{}`: {}", question, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// A QTI content package, which is written as a zip file.
+#[derive(Debug, Clone)]
+pub struct QtiPackage {
+    files: Vec<(String, String)>,
+    media: Vec<(String, Vec<u8>)>,
+    warnings: Vec<QtiWarning>,
+}
+
+impl QtiPackage {
+    /// Returns the paths and the contents of the XML files in the package, starting with `imsmanifest.xml`.
+    pub fn files(&self) -> &[(String, String)] {
+        &self.files
+    }
+    /// Returns the paths and the contents of the embedded files of the questions, such as images, which the items
+    /// refer to.
+    pub fn media(&self) -> &[(String, Vec<u8>)] {
+        &self.media
+    }
+    /// Returns the Moodle features which could not be exported.
+    pub fn warnings(&self) -> &[QtiWarning] {
+        &self.warnings
+    }
+    /// Writes the package as a zip archive.
+    pub fn write_zip<W: Write + Seek>(&self, writer: W) -> Result<(), QuizError> {
+        let mut zip = zip::ZipWriter::new(writer);
+        let options = zip::write::SimpleFileOptions::default();
+        for (path, contents) in &self.files {
+            zip.start_file(path.as_str(), options)
+                .map_err(std::io::Error::from)?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        for (path, contents) in &self.media {
+            zip.start_file(path.as_str(), options)
+                .map_err(std::io::Error::from)?;
+            zip.write_all(contents)?;
+        }
+        zip.finish().map_err(std::io::Error::from)?;
+        Ok(())
+    }
+    /// Writes the package as a zip file to `path`.
+    pub fn to_file(&self, path: impl AsRef<Path>) -> Result<(), QuizError> {
+        self.write_zip(File::create(path)?)
+    }
+}
+
+/// Converts a quiz to a QTI 2.1 content package.
+///
+/// Questions of unsupported types are skipped with a warning.
+///
+/// # Errors
+///
+/// Returns a [`QuizError`] if the quiz is empty or none of its questions can be exported.
+pub fn to_qti(quiz: &Quiz) -> Result<QtiPackage, QuizError> {
+    if quiz.is_empty() {
+        return Err(EmptyError.into());
+    }
+    let mut files = Vec::new();
+    let mut media = Vec::new();
+    let mut warnings = Vec::new();
+    if quiz.questions().any(|(category, _)| category.is_some()) {
+        warnings.push(QtiWarning {
+            question: None,
+            message: "categories are not exported".to_string(),
+        });
+    }
+    for (_, question) in quiz.questions() {
+        let identifier = format!("item{}", files.len() + 1);
+        let mut messages = Vec::new();
+        if let Some(mut item) = item_xml(&identifier, question, &mut messages)? {
+            // The items refer to the files relative to `items/`
+            for file in question.as_question().get_files() {
+                let path = format!("media/{}/{}", identifier, file.name);
+                let url = format!("../{}", path.replace(' ', "%20"));
+                item = item
+                    .replace(&file.url(), &url)
+                    .replace(&file.url().replace(' ', "%20"), &url);
+                media.push((path, file.data.clone()));
+            }
+            files.push((format!("items/{}.xml", identifier), item));
+        }
+        warnings.extend(messages.into_iter().map(|message| QtiWarning {
+            question: Some(question.as_question().get_name().to_string()),
+            message,
+        }));
+    }
+    if files.is_empty() {
+        return Err(QuizError::ValueError(
+            "None of the questions can be exported to QTI".to_string(),
+        ));
+    }
+    files.insert(
+        0,
+        ("imsmanifest.xml".to_string(), manifest_xml(&files, &media)?),
+    );
+    Ok(QtiPackage {
+        files,
+        media,
+        warnings,
+    })
+}
+
+fn new_writer(output: &mut Vec<u8>) -> Writer<'_> {
+    EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(output)
+}
+
+fn manifest_xml(
+    items: &[(String, String)],
+    media: &[(String, Vec<u8>)],
+) -> Result<String, QuizError> {
+    let mut output = Vec::new();
+    let mut writer = new_writer(&mut output);
+    writer.write(
+        XmlEvent::start_element("manifest")
+            .default_ns(CP_NAMESPACE)
+            .ns("xsi", XSI_NAMESPACE)
+            .attr("xsi:schemaLocation", CP_SCHEMA)
+            .attr("identifier", "MANIFEST-1"),
+    )?;
+    writer.write(XmlEvent::start_element("metadata"))?;
+    write_text_element(&mut writer, "schema", "QTIv2.1 Package")?;
+    write_text_element(&mut writer, "schemaversion", "1.0.0")?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::start_element("organizations"))?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::start_element("resources"))?;
+    for (path, _) in items {
+        let identifier = path.trim_start_matches("items/").trim_end_matches(".xml");
+        writer.write(
+            XmlEvent::start_element("resource")
+                .attr("identifier", identifier)
+                .attr("type", "imsqti_item_xmlv2p1")
+                .attr("href", path),
+        )?;
+        writer.write(XmlEvent::start_element("file").attr("href", path))?;
+        writer.write(XmlEvent::end_element())?;
+        let prefix = format!("media/{}/", identifier);
+        for (file, _) in media.iter().filter(|(file, _)| file.starts_with(&prefix)) {
+            writer.write(XmlEvent::start_element("file").attr("href", file))?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        writer.write(XmlEvent::end_element())?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    drop(writer);
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Writes the assessment item of a question, or returns `None` if the question type is not supported.
+fn item_xml(
+    identifier: &str,
+    question: &QuestionType,
+    warnings: &mut Vec<String>,
+) -> Result<Option<String>, QuizError> {
+    let common = question.as_question();
+    let mut output = Vec::new();
+    let mut writer = new_writer(&mut output);
+    writer.write(
+        XmlEvent::start_element("assessmentItem")
+            .default_ns(QTI_NAMESPACE)
+            .ns("xsi", XSI_NAMESPACE)
+            .attr("xsi:schemaLocation", QTI_SCHEMA)
+            .attr("identifier", identifier)
+            .attr("title", common.get_name())
+            .attr("adaptive", "false")
+            .attr("timeDependent", "false"),
+    )?;
+    match question {
+        QuestionType::Multichoice(q) => {
+            if !(q.correctfeedback.is_empty()
+                && q.partiallycorrectfeedback.is_empty()
+                && q.incorrectfeedback.is_empty())
+            {
+                warnings.push(
+                    "correct, partially correct and incorrect feedback are not exported"
+                        .to_string(),
+                );
+            }
+            write_choice(
+                &mut writer,
+                common,
+                q.answers(),
+                q.single,
+                q.shuffleanswers,
+                warnings,
+            )?;
+        }
+        QuestionType::TrueFalse(q) => {
+            write_choice(&mut writer, common, q.answers(), true, false, warnings)?;
+        }
+        QuestionType::ShortAnswer(q) => {
+            write_short_answer(&mut writer, common, q.answers(), q.usecase, warnings)?;
+        }
+        QuestionType::Essay(_) => {
+            write_response_declaration(&mut writer, "single", "string", &[], &[], None)?;
+            write_score_declaration(&mut writer)?;
+            writer.write(XmlEvent::start_element("itemBody"))?;
+            write_flow(
+                &mut writer,
+                common.get_description(),
+                common.get_text_format(),
+                warnings,
+            )?;
+            writer.write(
+                XmlEvent::start_element("extendedTextInteraction")
+                    .attr("responseIdentifier", "RESPONSE"),
+            )?;
+            writer.write(XmlEvent::end_element())?;
+            writer.write(XmlEvent::end_element())?;
+            // Essays are graded manually, so there is no response processing
+        }
+        _ => {
+            warnings
+                .push("question type is not supported in QTI, the question is skipped".to_string());
+            return Ok(None);
+        }
+    }
+    if common.get_general_feedback().is_some_and(|f| !f.is_empty()) {
+        warnings.push("general feedback is not exported".to_string());
+    }
+    if common.get_default_grade().is_some() {
+        warnings.push("default grade is not exported".to_string());
+    }
+    if common.get_penalty().is_some() {
+        warnings.push("penalty is not exported".to_string());
+    }
+    if common.is_hidden() {
+        warnings.push("hidden flag is not exported".to_string());
+    }
+    if !common.get_hints().is_empty() {
+        warnings.push("hints are not exported".to_string());
+    }
+    if !common.get_tags().is_empty() {
+        warnings.push("tags are not exported".to_string());
+    }
+    writer.write(XmlEvent::end_element())?;
+    drop(writer);
+    Ok(Some(String::from_utf8_lossy(&output).into_owned()))
+}
+
+fn write_text_element(writer: &mut Writer, name: &str, text: &str) -> Result<(), QuizError> {
+    writer.write(XmlEvent::start_element(name))?;
+    writer.write(XmlEvent::characters(text))?;
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+/// Writes the `responseDeclaration` of the question with its correct values and score mapping.
+fn write_response_declaration(
+    writer: &mut Writer,
+    cardinality: &str,
+    base_type: &str,
+    correct: &[&str],
+    mapping: &[(&str, f64)],
+    case_sensitive: Option<bool>,
+) -> Result<(), QuizError> {
+    writer.write(
+        XmlEvent::start_element("responseDeclaration")
+            .attr("identifier", "RESPONSE")
+            .attr("cardinality", cardinality)
+            .attr("baseType", base_type),
+    )?;
+    if !correct.is_empty() {
+        writer.write(XmlEvent::start_element("correctResponse"))?;
+        for value in correct {
+            write_text_element(writer, "value", value)?;
+        }
+        writer.write(XmlEvent::end_element())?;
+    }
+    if !mapping.is_empty() {
+        writer.write(
+            XmlEvent::start_element("mapping")
+                .attr("lowerBound", "0")
+                .attr("defaultValue", "0"),
+        )?;
+        let case_sensitive = case_sensitive.map(|c| c.to_string());
+        for (key, fraction) in mapping {
+            // The fraction in percent has Moodle's 5 decimals, which are 7 decimals of the mapped value
+            let value = ((*fraction * 1e5).round() / 1e7).to_string();
+            let mut entry = XmlEvent::start_element("mapEntry")
+                .attr("mapKey", key)
+                .attr("mappedValue", &value);
+            if let Some(case_sensitive) = &case_sensitive {
+                entry = entry.attr("caseSensitive", case_sensitive);
+            }
+            writer.write(entry)?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        writer.write(XmlEvent::end_element())?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+fn write_score_declaration(writer: &mut Writer) -> Result<(), QuizError> {
+    writer.write(
+        XmlEvent::start_element("outcomeDeclaration")
+            .attr("identifier", "SCORE")
+            .attr("cardinality", "single")
+            .attr("baseType", "float"),
+    )?;
+    writer.write(XmlEvent::start_element("defaultValue"))?;
+    write_text_element(writer, "value", "0")?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+fn write_map_response(writer: &mut Writer) -> Result<(), QuizError> {
+    writer.write(XmlEvent::start_element("responseProcessing").attr("template", MAP_RESPONSE))?;
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+fn warn_answer_feedback(answers: &[Answer], warnings: &mut Vec<String>) {
+    if answers
+        .iter()
+        .any(|answer| answer.feedback.as_ref().is_some_and(|f| !f.is_empty()))
+    {
+        warnings.push("answer feedback is not exported".to_string());
+    }
+}
+
+/// Writes a `choiceInteraction` item, used for multiple choice and true/false questions.
+fn write_choice(
+    writer: &mut Writer,
+    question: &dyn Question,
+    answers: &[Answer],
+    single: bool,
+    shuffle: bool,
+    warnings: &mut Vec<String>,
+) -> Result<(), QuizError> {
+    let identifiers: Vec<String> = (1..=answers.len())
+        .map(|n| format!("choice{}", n))
+        .collect();
+    let scored: Vec<(&str, f64)> = identifiers
+        .iter()
+        .zip(answers)
+        .filter(|(_, answer)| answer.fraction != 0.0)
+        .map(|(identifier, answer)| (identifier.as_str(), answer.fraction))
+        .collect();
+    let correct: Vec<&str> = scored
+        .iter()
+        .filter(|(_, fraction)| *fraction > 0.0 && (!single || *fraction == 100.0))
+        .map(|(identifier, _)| *identifier)
+        .collect();
+    let cardinality = if single { "single" } else { "multiple" };
+    write_response_declaration(writer, cardinality, "identifier", &correct, &scored, None)?;
+    write_score_declaration(writer)?;
+    writer.write(XmlEvent::start_element("itemBody"))?;
+    write_flow(
+        writer,
+        question.get_description(),
+        question.get_text_format(),
+        warnings,
+    )?;
+    writer.write(
+        XmlEvent::start_element("choiceInteraction")
+            .attr("responseIdentifier", "RESPONSE")
+            .attr("shuffle", &shuffle.to_string())
+            .attr("maxChoices", if single { "1" } else { "0" }),
+    )?;
+    for (identifier, answer) in identifiers.iter().zip(answers) {
+        writer.write(XmlEvent::start_element("simpleChoice").attr("identifier", identifier))?;
+        write_flow(writer, &answer.text, answer.text_format, warnings)?;
+        writer.write(XmlEvent::end_element())?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    write_map_response(writer)?;
+    warn_answer_feedback(answers, warnings);
+    Ok(())
+}
+
+/// Writes a `textEntryInteraction` item for a short answer question.
+fn write_short_answer(
+    writer: &mut Writer,
+    question: &dyn Question,
+    answers: &[Answer],
+    case_sensitive: bool,
+    warnings: &mut Vec<String>,
+) -> Result<(), QuizError> {
+    let mapping: Vec<(&str, f64)> = answers
+        .iter()
+        .filter(|answer| answer.fraction > 0.0)
+        .map(|answer| (answer.text.as_str(), answer.fraction))
+        .collect();
+    let correct: Vec<&str> = mapping
+        .iter()
+        .filter(|(_, fraction)| *fraction == 100.0)
+        .map(|(text, _)| *text)
+        .take(1)
+        .collect();
+    if answers.iter().any(|answer| answer.text.contains('*')) {
+        warnings.push("the wildcard `*` is matched literally in QTI".to_string());
+    }
+    write_response_declaration(
+        writer,
+        "single",
+        "string",
+        &correct,
+        &mapping,
+        Some(case_sensitive),
+    )?;
+    write_score_declaration(writer)?;
+    writer.write(XmlEvent::start_element("itemBody"))?;
+    write_flow(
+        writer,
+        question.get_description(),
+        question.get_text_format(),
+        warnings,
+    )?;
+    writer.write(XmlEvent::start_element("p"))?;
+    writer.write(
+        XmlEvent::start_element("textEntryInteraction").attr("responseIdentifier", "RESPONSE"),
+    )?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    write_map_response(writer)?;
+    warn_answer_feedback(answers, warnings);
+    Ok(())
+}
+
+/// Writes question or answer text as QTI flow content. HTML is sanitized and written as XHTML inside a `div`,
+/// unless its tags are not balanced, and other text is written as a plain paragraph.
+fn write_flow(
+    writer: &mut Writer,
+    text: &str,
+    format: TextFormat,
+    warnings: &mut Vec<String>,
+) -> Result<(), QuizError> {
+    match format {
+        TextFormat::HTML => {
+            for issue in html::check(text) {
+                if matches!(
+                    issue,
+                    HtmlIssue::Disallowed { .. }
+                        | HtmlIssue::EventHandler { .. }
+                        | HtmlIssue::UnsafeUrl { .. }
+                ) {
+                    warnings.push(format!("{}, it is removed", issue));
+                }
+            }
+            let sanitized = html::sanitize(text);
+            let balanced = html::check(&sanitized).iter().all(|issue| {
+                !matches!(
+                    issue,
+                    HtmlIssue::Unclosed { .. } | HtmlIssue::Unopened { .. }
+                )
+            });
+            if balanced {
+                return write_xhtml(writer, &sanitized);
+            }
+            warnings
+                .push("HTML with unclosed or unmatched tags is exported as plain text".to_string());
+            return write_text_element(writer, "p", &html::to_text(text));
+        }
+        TextFormat::Markdown => {
+            warnings.push("Markdown is exported as plain text".to_string());
+        }
+        _ => {}
+    }
+    write_text_element(writer, "p", text)
+}
+
+/// Writes sanitized HTML with balanced tags as XHTML inside a `div`. Void elements such as `<br>` are closed, and
+/// the character references are decoded so that the writer escapes the text and attributes for XML.
+fn write_xhtml(writer: &mut Writer, sanitized: &str) -> Result<(), QuizError> {
+    writer.write(XmlEvent::start_element("div"))?;
+    for token in html::tokens(sanitized) {
+        match token {
+            Token::Text(text) => {
+                writer.write(XmlEvent::characters(&html::decode_entities(text)))?
+            }
+            Token::Comment(_) => {}
+            Token::Tag(Tag { name, closing, .. }) if closing => {
+                if !VOID_ELEMENTS.contains(&name.as_str()) {
+                    writer.write(XmlEvent::end_element())?;
+                }
+            }
+            Token::Tag(tag) => {
+                let attributes: Vec<(&str, String)> = tag
+                    .attributes
+                    .iter()
+                    .map(|(name, value)| {
+                        (
+                            name.as_str(),
+                            html::decode_entities(value.unwrap_or_default()),
+                        )
+                    })
+                    .collect();
+                let mut start = XmlEvent::start_element(tag.name.as_str());
+                for (name, value) in &attributes {
+                    start = start.attr(*name, value);
+                }
+                writer.write(start)?;
+                if tag.self_closing || VOID_ELEMENTS.contains(&tag.name.as_str()) {
+                    writer.write(XmlEvent::end_element())?;
+                }
+            }
+        }
+    }
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{file::EmbeddedFile, prelude::*};
+
+    fn sample_quiz() -> Quiz {
+        let mut multichoice = MultiChoiceQuestion::new(
+            "Capital".to_string(),
+            "What is the <b>capital</b> of France?".to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        multichoice
+            .add_answers(vec![
+                Answer::new(100.0, "Paris".to_string(), Some("Correct!".to_string())),
+                Answer::new(0.0, "Lyon".to_string(), None),
+            ])
+            .unwrap();
+        let mut shortanswer = ShortAnswerQuestion::new(
+            "Finland".to_string(),
+            "Capital of Finland?".to_string(),
+            None,
+        );
+        shortanswer
+            .add_answers(vec![Answer::new(100.0, "Helsinki".to_string(), None)])
+            .unwrap();
+        let mut numerical = NumericalQuestion::new("Number".to_string(), "1 + 1?".to_string());
+        numerical
+            .add_answers(vec![Answer::new(100.0, "2".to_string(), None)])
+            .unwrap();
+        let essay = EssayQuestion::new("Essay".to_string(), "Describe Finland.<br>".to_string());
+        let mut quiz = Quiz::new(vec![
+            multichoice.into(),
+            shortanswer.into(),
+            numerical.into(),
+        ]);
+        quiz.add_category("Capitals".into(), vec![essay.into()]);
+        quiz
+    }
+
+    #[test]
+    fn test_qti_package() {
+        let package = to_qti(&sample_quiz()).unwrap();
+        let paths: Vec<&str> = package
+            .files()
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "imsmanifest.xml",
+                "items/item1.xml",
+                "items/item2.xml",
+                "items/item3.xml"
+            ]
+        );
+        let manifest = &package.files()[0].1;
+        assert!(manifest.contains(
+            r#"<resource identifier="item3" type="imsqti_item_xmlv2p1" href="items/item3.xml">"#
+        ));
+        let multichoice = &package.files()[1].1;
+        assert!(multichoice.contains(
+            r#"<choiceInteraction responseIdentifier="RESPONSE" shuffle="true" maxChoices="1">"#
+        ));
+        assert!(multichoice.contains("<b>capital</b>"));
+        assert!(multichoice.contains(r#"<mapEntry mapKey="choice1" mappedValue="1" />"#));
+        let shortanswer = &package.files()[2].1;
+        assert!(shortanswer
+            .contains(r#"<mapEntry mapKey="Helsinki" mappedValue="1" caseSensitive="false" />"#));
+        // Void elements are closed, so that the HTML is XHTML
+        let essay = &package.files()[3].1;
+        assert!(essay.contains("Describe Finland.<br />"));
+
+        let warnings: Vec<String> = package.warnings().iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "categories are not exported",
+                "question `Capital`: answer feedback is not exported",
+                "question `Number`: question type is not supported in QTI, the question is skipped",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        let essay = EssayQuestion::new(
+            "Essay".to_string(),
+            r#"<p onclick="steal()">Describe <b>Finland</b>.</p><script>steal()</script>"#
+                .to_string(),
+        );
+        let package = to_qti(&Quiz::new(vec![essay.into()])).unwrap();
+        let item = &package.files()[1].1;
+        assert!(item.contains("<p>Describe <b>Finland</b>.</p>"));
+        assert!(!item.contains("steal"));
+        let warnings: Vec<String> = package.warnings().iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "question `Essay`: `<p>` has the event handler `onclick`, it is removed",
+                "question `Essay`: `<script>` is not allowed, it is removed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_xhtml() {
+        let essay = EssayQuestion::new(
+            "Essay".to_string(),
+            r#"<p>Line<br>break&nbsp;&amp; <img src=a.png alt='A "map"'><hr></p>"#.to_string(),
+        );
+        let broken = EssayQuestion::new(
+            "Broken".to_string(),
+            "<p>Describe <b>Finland</p>".to_string(),
+        );
+        let package = to_qti(&Quiz::new(vec![essay.into(), broken.into()])).unwrap();
+        let item = &package.files()[1].1;
+        assert!(item.contains(
+            "<p>Line<br />break\u{a0}&amp; <img src=\"a.png\" alt=\"A &quot;map&quot;\" />"
+        ));
+        assert!(item.contains("<hr />"));
+        // HTML with unbalanced tags is written as the text learners would read, not as markup
+        let item = &package.files()[2].1;
+        assert!(item.contains("<p>Describe Finland</p>"));
+        let warnings: Vec<String> = package.warnings().iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            ["question `Broken`: HTML with unclosed or unmatched tags is exported as plain text"]
+        );
+    }
+
+    #[test]
+    fn test_files_and_common_elements() {
+        let image = EmbeddedFile::new("map of Finland.png".to_string(), b"PNG".to_vec());
+        let mut essay = EssayQuestion::new(
+            "Essay".to_string(),
+            format!(r#"<p>Describe the map.</p><img src="{}">"#, image.url()),
+        );
+        essay.add_files(vec![image]);
+        essay.set_general_feedback("Mention the lakes.".to_string());
+        essay.set_default_grade(2.0);
+        essay.set_penalty(0.1);
+        essay.set_hidden(true);
+        essay.add_hints(vec![Hint::new("Look at the east.".to_string())]);
+        let package = to_qti(&Quiz::new(vec![essay.into()])).unwrap();
+        assert!(package.files()[0]
+            .1
+            .contains(r#"<file href="media/item1/map of Finland.png" />"#));
+        assert!(package.files()[1]
+            .1
+            .contains(r#"<img src="../media/item1/map%20of%20Finland.png" />"#));
+        assert_eq!(
+            package.media(),
+            [(
+                "media/item1/map of Finland.png".to_string(),
+                b"PNG".to_vec()
+            )]
+        );
+        let warnings: Vec<String> = package.warnings().iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            [
+                "question `Essay`: general feedback is not exported",
+                "question `Essay`: default grade is not exported",
+                "question `Essay`: penalty is not exported",
+                "question `Essay`: hidden flag is not exported",
+                "question `Essay`: hints are not exported",
+            ]
+        );
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        package.write_zip(&mut buffer).unwrap();
+        let mut archive = zip::ZipArchive::new(buffer).unwrap();
+        assert_eq!(archive.len(), 3);
+        assert_eq!(
+            archive
+                .by_name("media/item1/map of Finland.png")
+                .unwrap()
+                .size(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_write_zip() {
+        let package = to_qti(&sample_quiz()).unwrap();
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        package.write_zip(&mut buffer).unwrap();
+        let archive = zip::ZipArchive::new(buffer).unwrap();
+        assert_eq!(archive.len(), 4);
+        assert!(archive.file_names().any(|name| name == "imsmanifest.xml"));
+    }
+}
@@ -1,28 +1,120 @@
 use crate::{
-    answer::Answer,
+    answer::{Answer, CalculatedAnswer, NumericalAnswer, FRACTION_TOLERANCE},
+    dataset::{DatasetDefinition, Random},
+    file::EmbeddedFile,
+    formula::{self, Formula},
     quiz::{EmptyError, QuizError},
-    xml_util::{write_named_formatted_scope, write_text_tag},
+    xml_util::{
+        end_formatted_element, start_formatted_element, write_named_formatted_scope,
+        write_text_tag, XmlWriter,
+    },
 };
-use std::fs::File;
-use xml::writer::{EventWriter, XmlEvent};
+use std::{collections::HashMap, fmt};
+use xml::writer::XmlEvent;
 
 /// Common trait for all question types
+///
+/// Every question type keeps the parts common to all questions in a [`QuestionBase`], and the getters and
+/// setters of those parts are provided through [`Question::base`] and [`Question::base_mut`].
 pub trait Question {
+    /// Returns the parts common to all question types.
+    fn base(&self) -> &QuestionBase;
+    /// Returns the parts common to all question types for modifying them.
+    fn base_mut(&mut self) -> &mut QuestionBase;
     /// Returns the name of the question>
-    fn get_name(&self) -> &str;
+    fn get_name(&self) -> &str {
+        &self.base().name
+    }
     /// Returns the description of the question.
-    fn get_description(&self) -> &str;
+    fn get_description(&self) -> &str {
+        &self.base().description
+    }
+    /// Returns the text rendering format of the question.
+    fn get_text_format(&self) -> TextFormat {
+        self.base().question_text_format
+    }
     /// Set the text rendering format `TextFormat` for the question.
-    fn set_text_format(&mut self, format: TextFormat);
+    fn set_text_format(&mut self, format: TextFormat) {
+        self.base_mut().question_text_format = format;
+    }
+    /// Adds tags to the question. Moodle uses them for filtering the question bank.
+    fn add_tags(&mut self, tags: Vec<String>) {
+        self.base_mut().tags.extend(tags);
+    }
+    /// Returns the tags of the question.
+    fn get_tags(&self) -> &[String] {
+        &self.base().tags
+    }
+    /// Adds files, such as images, which the question text refers to with `@@PLUGINFILE@@/name`.
+    fn add_files(&mut self, files: Vec<EmbeddedFile>) {
+        self.base_mut().files.extend(files);
+    }
+    /// Returns the files embedded in the question text.
+    fn get_files(&self) -> &[EmbeddedFile] {
+        &self.base().files
+    }
     /// Adds all answers from type `Vec<Answer>` to the Question variant type.
     /// May return an error if there is a problem with the fractions or count of answers.
     fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError>;
-    /// Writes the question in XML format to the provided file descriptor.
-    fn to_xml(&self, writer: &mut EventWriter<&File>) -> Result<(), QuizError>;
+    /// Writes the question in XML format with the provided writer, applying the options of its [`WriteContext`].
+    ///
+    /// [`WriteContext`]: crate::xml_util::WriteContext
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError>;
+    /// Returns the Moodle name of the question type, as in `<question type="multichoice">`.
+    /// Question types implemented outside the crate return the name of their plugin.
+    fn type_name(&self) -> &'static str;
+    /// Returns the ID number of the question, which identifies it within its category in Moodle.
+    fn get_idnumber(&self) -> Option<&str> {
+        self.base().idnumber.as_deref()
+    }
+    /// Sets the ID number of the question.
+    fn set_idnumber(&mut self, idnumber: String) {
+        self.base_mut().idnumber = Some(idnumber);
+    }
+    /// Returns the general feedback, which is shown after the question has been answered.
+    fn get_general_feedback(&self) -> Option<&str> {
+        self.base().general_feedback.as_deref()
+    }
+    /// Sets the general feedback of the question. It has the format of the question text.
+    fn set_general_feedback(&mut self, feedback: String) {
+        self.base_mut().general_feedback = Some(feedback);
+    }
+    /// Returns the default mark of the question, if it is set. Moodle uses 1 otherwise.
+    fn get_default_grade(&self) -> Option<f64> {
+        self.base().default_grade
+    }
+    /// Sets the default mark of the question.
+    fn set_default_grade(&mut self, grade: f64) {
+        self.base_mut().default_grade = Some(grade);
+    }
+    /// Returns the penalty for each wrong try, 0-1, if it is set.
+    fn get_penalty(&self) -> Option<f64> {
+        self.base().penalty
+    }
+    /// Sets the penalty for each wrong try, as a fraction of the mark between 0 and 1.
+    fn set_penalty(&mut self, penalty: f64) {
+        self.base_mut().penalty = Some(penalty);
+    }
+    /// Returns whether the question is hidden in the question bank.
+    fn is_hidden(&self) -> bool {
+        self.base().hidden
+    }
+    /// Sets whether the question is hidden in the question bank.
+    fn set_hidden(&mut self, hidden: bool) {
+        self.base_mut().hidden = hidden;
+    }
+    /// Adds hints, which are shown one after another after wrong tries.
+    fn add_hints(&mut self, hints: Vec<Hint>) {
+        self.base_mut().hints.extend(hints);
+    }
+    /// Returns the hints of the question.
+    fn get_hints(&self) -> &[Hint] {
+        &self.base().hints
+    }
 }
 
 /// Represents the formatting options for the question text, feedback text and in other situations where Moodle could render it differently.
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub enum TextFormat {
     #[default]
     HTML,
@@ -39,6 +131,17 @@ impl TextFormat {
             TextFormat::PlainText => "plain_text",
         }
     }
+    /// Parses a format from its Moodle name, e.g. `html` or `plain_text`.
+    /// The shorthands `moodle` and `plain` are accepted as well.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "html" => Some(TextFormat::HTML),
+            "moodle_auto_format" | "moodle" => Some(TextFormat::Moodle),
+            "markdown" => Some(TextFormat::Markdown),
+            "plain_text" | "plain" => Some(TextFormat::PlainText),
+            _ => None,
+        }
+    }
 }
 
 /// Represents a base for question in Moodle XML format.
@@ -49,31 +152,53 @@ impl TextFormat {
 /// - `description`: A description of the question.
 /// - `question_text_format`: The format that Moodle uses to render the question.
 /// - `answers`: A vector of answer objects associated with the question.
+/// - `tags`: Tags of the question.
+/// - `files`: Files embedded in the question text.
+/// - `idnumber`: The ID number of the question, if it has one.
+/// - `general_feedback`: Feedback shown after the question has been answered, in the format of the question text.
+/// - `default_grade`: The default mark of the question, Moodle uses 1 if it is not set.
+/// - `penalty`: The penalty for each wrong try, 0-1. Moodle uses the default of the question type if it is not set.
+/// - `hidden`: Whether the question is hidden in the question bank.
+/// - `hints`: Hints shown after wrong tries.
 ///
+/// The general feedback, default grade and penalty are written only when they are set, and `<hidden>` only for
+/// hidden questions, so that Moodle uses its defaults for the others.
 #[derive(Debug, Clone)]
-struct QuestionBase {
+pub struct QuestionBase {
     pub name: String,
     pub description: String,
     pub question_text_format: TextFormat,
     pub answers: Vec<Answer>,
+    pub tags: Vec<String>,
+    pub files: Vec<EmbeddedFile>,
+    pub idnumber: Option<String>,
+    pub general_feedback: Option<String>,
+    pub default_grade: Option<f64>,
+    pub penalty: Option<f64>,
+    pub hidden: bool,
+    pub hints: Vec<Hint>,
 }
 impl QuestionBase {
-    fn new(name: String, description: String) -> Self {
+    pub fn new(name: String, description: String) -> Self {
         Self {
             name,
             description,
             question_text_format: TextFormat::default(),
             answers: Vec::new(),
+            tags: Vec::new(),
+            files: Vec::new(),
+            idnumber: None,
+            general_feedback: None,
+            default_grade: None,
+            penalty: None,
+            hidden: false,
+            hints: Vec::new(),
         }
     }
     /// Checks if the answers create the total fraction of 100% at least
     /// There can be also cases where the total fraction is more than 100% because of multiple correct answers
     fn check_answer_fraction(&mut self) -> Result<(), QuizError> {
-        let mut total_fraction = 0usize;
-        for answer in &self.answers {
-            total_fraction += answer.fraction as usize;
-        }
-        if total_fraction < 100 {
+        if !reaches_full_grade(self.answers.iter().map(|answer| answer.fraction)) {
             self.answers.clear();
             return Err(QuizError::AnswerFractionError(
                 "The total fraction of answers must be at least 100".to_string(),
@@ -81,35 +206,15 @@ impl QuestionBase {
         }
         Ok(())
     }
-}
-
-impl Question for QuestionBase {
-    fn get_name(&self) -> &str {
-        self.name.as_str()
-    }
-    fn get_description(&self) -> &str {
-        self.description.as_str()
-    }
-    fn set_text_format(&mut self, format: TextFormat) {
-        self.question_text_format = format;
-    }
+    /// Adds answers and checks that their fractions total at least 100.
     fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
         self.answers.extend(answers);
         self.check_answer_fraction()?;
         Ok(())
     }
-    /// Writes the common part between all types of the question for provided XML EventWriter<File>
-    fn to_xml(&self, writer: &mut EventWriter<&File>) -> Result<(), QuizError> {
-        writer.write(XmlEvent::start_element("name"))?;
-        write_text_tag(writer, self.name.as_str(), false)?;
-        writer.write(XmlEvent::end_element())?;
-        writer.write(
-            XmlEvent::start_element("questiontext")
-                .attr("format", self.question_text_format.name()),
-        )?;
-        // By default, the text format should be specified on the parent of the <text> element.
-        write_text_tag(writer, self.description.as_str(), true)?;
-        writer.write(XmlEvent::end_element())?;
+    /// Writes the common part and the answers of the question types which have no other answer elements.
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.write_header(writer)?;
         if self.answers.is_empty() {
             return Err(EmptyError.into());
         }
@@ -118,6 +223,141 @@ impl Question for QuestionBase {
         }
         Ok(())
     }
+    /// Writes the name, the question text, the general feedback, default grade, penalty, hidden flag and the ID
+    /// number, which every question type has. Question types implemented outside the crate write them with this
+    /// after starting the `<question>` element.
+    pub fn write_header(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.write_header_with_text(writer, |writer| {
+            write_text_tag(writer, self.description.as_str(), true)?;
+            for file in &self.files {
+                write_file(writer, file)?;
+            }
+            Ok(())
+        })
+    }
+    /// Writes the common elements like [`QuestionBase::write_header`], with the contents of the question text
+    /// written by `text`.
+    pub(crate) fn write_header_with_text(
+        &self,
+        writer: &mut XmlWriter,
+        text: impl FnOnce(&mut XmlWriter) -> Result<(), QuizError>,
+    ) -> Result<(), QuizError> {
+        writer.write(XmlEvent::start_element("name"))?;
+        write_text_tag(writer, self.name.as_str(), false)?;
+        writer.write(XmlEvent::end_element())?;
+        // By default, the text format should be specified on the parent of the <text> element.
+        start_formatted_element(
+            writer,
+            XmlEvent::start_element("questiontext"),
+            Some(self.question_text_format),
+        )?;
+        text(writer)?;
+        end_formatted_element(writer)?;
+        if let Some(feedback) = &self.general_feedback {
+            start_formatted_element(
+                writer,
+                XmlEvent::start_element("generalfeedback"),
+                Some(self.question_text_format),
+            )?;
+            write_text_tag(writer, feedback, true)?;
+            end_formatted_element(writer)?;
+        }
+        if let Some(grade) = self.default_grade {
+            if !grade.is_finite() || grade < 0.0 {
+                return Err(QuizError::ValueError(format!(
+                    "Default grade {} of question `{}` is not a positive number",
+                    grade, self.name
+                )));
+            }
+            write_value(writer, "defaultgrade", &grade.to_string())?;
+        }
+        if let Some(penalty) = self.penalty {
+            if !(0.0..=1.0).contains(&penalty) {
+                return Err(QuizError::ValueError(format!(
+                    "Penalty {} of question `{}` is not between 0 and 1",
+                    penalty, self.name
+                )));
+            }
+            write_value(writer, "penalty", &penalty.to_string())?;
+        }
+        if self.hidden {
+            write_value(writer, "hidden", "1")?;
+        }
+        if let Some(idnumber) = &self.idnumber {
+            write_value(writer, "idnumber", idnumber)?;
+        }
+        Ok(())
+    }
+    /// Writes the hints of the question, which come after the elements of the question type and before the tags.
+    pub fn write_hints(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        for hint in &self.hints {
+            hint.to_xml(writer)?;
+        }
+        Ok(())
+    }
+    /// Writes the tags of the question, if there are any, as the last element of the question.
+    pub fn write_tags(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        if self.tags.is_empty() {
+            return Ok(());
+        }
+        writer.write(XmlEvent::start_element("tags"))?;
+        for tag in &self.tags {
+            write_named_formatted_scope(writer, "tag", None, |writer| {
+                write_text_tag(writer, tag, false)
+            })?;
+        }
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Returns whether the positive fractions total at least 100. Fractions rounded like Moodle's, such as three times
+/// 33.33333, are allowed to miss it by [`FRACTION_TOLERANCE`].
+fn reaches_full_grade(fractions: impl Iterator<Item = f64>) -> bool {
+    let total: f64 = fractions.filter(|fraction| *fraction > 0.0).sum();
+    total >= 100.0 - FRACTION_TOLERANCE
+}
+
+/// A hint shown after a wrong try, when the question is attempted with the interactive behaviour.
+#[derive(Debug, Clone, Default)]
+pub struct Hint {
+    pub text: String,
+    pub text_format: TextFormat,
+    /// Whether the number of right responses is shown with the hint.
+    pub shownumcorrect: bool,
+    /// Whether the wrong responses are cleared when the hint is shown.
+    pub clearwrong: bool,
+    /// Options specific to the question type, such as `1` in ordering questions to highlight the responses.
+    pub options: Option<String>,
+}
+
+impl Hint {
+    pub fn new(text: String) -> Self {
+        Self {
+            text,
+            ..Self::default()
+        }
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        start_formatted_element(
+            writer,
+            XmlEvent::start_element("hint"),
+            Some(self.text_format),
+        )?;
+        write_text_tag(writer, &self.text, true)?;
+        for (name, flag) in [
+            ("shownumcorrect", self.shownumcorrect),
+            ("clearwrong", self.clearwrong),
+        ] {
+            if flag {
+                write_value(writer, name, "")?;
+            }
+        }
+        if let Some(options) = &self.options {
+            write_value(writer, "options", options)?;
+        }
+        end_formatted_element(writer)
+    }
 }
 
 /// Multiple choice question type.
@@ -156,59 +396,54 @@ impl MultiChoiceQuestion {
             answernumbering: answernumbering.unwrap_or_default(),
         }
     }
+    /// Returns the answers of the question.
+    pub fn answers(&self) -> &[Answer] {
+        &self.base.answers
+    }
 }
 
 impl Question for MultiChoiceQuestion {
-    fn get_name(&self) -> &str {
-        self.base.get_name()
+    fn type_name(&self) -> &'static str {
+        "multichoice"
     }
-    fn get_description(&self) -> &str {
-        self.base.get_description()
+    fn base(&self) -> &QuestionBase {
+        &self.base
     }
-    fn set_text_format(&mut self, format: TextFormat) {
-        self.base.question_text_format = format;
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
     }
     fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
         self.base.add_answers(answers)
     }
-    fn to_xml(&self, writer: &mut EventWriter<&File>) -> Result<(), QuizError> {
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
         // Start question tag
         writer.write(XmlEvent::start_element("question").attr("type", "multichoice"))?;
         // Write the common part of the question
-        self.base.to_xml(writer)?;
-
-        write_named_formatted_scope(writer, "single", None, |writer| {
-            writer.write(XmlEvent::characters(&self.single.to_string()))?;
-            Ok(())
-        })?;
-        write_named_formatted_scope(writer, "shuffleanswers", None, |writer| {
-            writer.write(XmlEvent::characters(
-                &(self.shuffleanswers as u8).to_string(),
-            ))?;
-            Ok(())
-        })?;
-        write_named_formatted_scope(
-            writer,
-            "correctfeedback",
-            TextFormat::default().into(),
-            |writer| write_text_tag(writer, &self.correctfeedback, true),
-        )?;
-        write_named_formatted_scope(
-            writer,
-            "partiallycorrectfeedback",
-            TextFormat::default().into(),
-            |writer| write_text_tag(writer, &self.partiallycorrectfeedback, true),
-        )?;
-        write_named_formatted_scope(
+        self.base.write_header(writer)?;
+        if self.base.answers.is_empty() {
+            return Err(EmptyError.into());
+        }
+        write_value(writer, "single", &self.single.to_string())?;
+        write_value(
             writer,
-            "incorrectfeedback",
-            TextFormat::default().into(),
-            |writer| write_text_tag(writer, &self.incorrectfeedback, true),
+            "shuffleanswers",
+            &(self.shuffleanswers as u8).to_string(),
         )?;
-        write_named_formatted_scope(writer, "answernumbering", None, |writer| {
-            writer.write(XmlEvent::characters(&self.answernumbering.to_string()))?;
-            Ok(())
-        })?;
+        write_value(writer, "answernumbering", &self.answernumbering.to_string())?;
+        for (name, feedback) in [
+            ("correctfeedback", &self.correctfeedback),
+            ("partiallycorrectfeedback", &self.partiallycorrectfeedback),
+            ("incorrectfeedback", &self.incorrectfeedback),
+        ] {
+            write_named_formatted_scope(writer, name, TextFormat::default().into(), |writer| {
+                write_text_tag(writer, feedback, true)
+            })?;
+        }
+        for answer in &self.base.answers {
+            answer.to_xml(writer)?;
+        }
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
         // End question tag
         writer.write(XmlEvent::end_element())?;
         Ok(())
@@ -226,17 +461,21 @@ impl TrueFalseQuestion {
             base: QuestionBase::new(name, description),
         }
     }
+    /// Returns the answers of the question.
+    pub fn answers(&self) -> &[Answer] {
+        &self.base.answers
+    }
 }
 
 impl Question for TrueFalseQuestion {
-    fn get_name(&self) -> &str {
-        self.base.get_name()
+    fn type_name(&self) -> &'static str {
+        "truefalse"
     }
-    fn get_description(&self) -> &str {
-        self.base.get_description()
+    fn base(&self) -> &QuestionBase {
+        &self.base
     }
-    fn set_text_format(&mut self, format: TextFormat) {
-        self.base.question_text_format = format;
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
     }
     fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
         if answers.len() != 2 {
@@ -244,16 +483,16 @@ impl Question for TrueFalseQuestion {
                 "True/False questions must have exactly 2 answers".to_string(),
             ));
         }
-        if answers[0].fraction == 100 {
-            if answers[1].fraction == 0 {
+        if answers[0].fraction == 100.0 {
+            if answers[1].fraction == 0.0 {
                 // good
             } else {
                 return Err(QuizError::AnswerFractionError(
                     "Only fractions 100 and 0 are allowed in True/False questions".to_string(),
                 ));
             }
-        } else if answers[1].fraction == 100 {
-            if answers[0].fraction == 0 {
+        } else if answers[1].fraction == 100.0 {
+            if answers[0].fraction == 0.0 {
                 // good
             } else {
                 return Err(QuizError::AnswerFractionError(
@@ -267,11 +506,13 @@ impl Question for TrueFalseQuestion {
         }
         self.base.add_answers(answers)
     }
-    fn to_xml(&self, writer: &mut EventWriter<&File>) -> Result<(), QuizError> {
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
         // Start question tag
         writer.write(XmlEvent::start_element("question").attr("type", "truefalse"))?;
         // Write the common part of the question
         self.base.to_xml(writer)?;
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
         // End question tag
         writer.write(XmlEvent::end_element())?;
         Ok(())
@@ -293,30 +534,182 @@ impl ShortAnswerQuestion {
             usecase: usecase.unwrap_or_default(),
         }
     }
+    /// Returns the answers of the question.
+    pub fn answers(&self) -> &[Answer] {
+        &self.base.answers
+    }
+    /// Returns the first answer which matches the response, as Moodle grades it.
+    /// In answers `*` matches any text and `\*` a literal asterisk.
+    pub fn matches(&self, response: &str) -> Option<&Answer> {
+        let response = response.trim();
+        self.base
+            .answers
+            .iter()
+            .find(|answer| Wildcard::new(&answer.text).matches(response, self.usecase))
+    }
+    /// Checks the answers for mistakes which Moodle accepts silently: answers which can never match
+    /// because an earlier answer matches everything they would, and answers which are the same
+    /// once the case is normalised according to `usecase`.
+    pub fn answer_warnings(&self) -> Vec<AnswerWarning> {
+        let answers = &self.base.answers;
+        let patterns: Vec<Wildcard> = answers
+            .iter()
+            .map(|answer| Wildcard::new(&answer.text))
+            .collect();
+        let normalized: Vec<String> = answers
+            .iter()
+            .map(|answer| {
+                let text = answer.text.trim();
+                if self.usecase {
+                    text.to_string()
+                } else {
+                    text.to_lowercase()
+                }
+            })
+            .collect();
+        let mut warnings = Vec::new();
+        for (index, pattern) in patterns.iter().enumerate() {
+            if let Some(first) = normalized[..index]
+                .iter()
+                .position(|text| *text == normalized[index])
+            {
+                warnings.push(AnswerWarning::Duplicate {
+                    answer: index,
+                    first,
+                });
+                continue;
+            }
+            // A catch-all shadows everything, other patterns only the answers without wildcards they match
+            let shadowing = patterns[..index].iter().position(|earlier| {
+                earlier.is_catch_all()
+                    || pattern
+                        .literal()
+                        .is_some_and(|literal| earlier.matches(&literal, self.usecase))
+            });
+            if let Some(by) = shadowing {
+                warnings.push(AnswerWarning::Shadowed { answer: index, by });
+            }
+        }
+        warnings
+    }
+}
+
+/// A mistake in the answers of a short answer question. The answers are referred to by their index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnswerWarning {
+    /// The answer can never match, because the earlier answer `by` matches every response it would.
+    Shadowed { answer: usize, by: usize },
+    /// The answer is the same as the earlier answer `first`.
+    Duplicate { answer: usize, first: usize },
+}
+
+impl fmt::Display for AnswerWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnswerWarning::Shadowed { answer, by } => write!(
+                f,
+                "answer {} is shadowed by answer {} and never matches",
+                answer + 1,
+                by + 1
+            ),
+            AnswerWarning::Duplicate { answer, first } => {
+                write!(
+                    f,
+                    "answer {} is a duplicate of answer {}",
+                    answer + 1,
+                    first + 1
+                )
+            }
+        }
+    }
+}
+
+/// Short answer pattern split into the literal parts between the `*` wildcards.
+struct Wildcard {
+    parts: Vec<String>,
+}
+
+impl Wildcard {
+    fn new(pattern: &str) -> Self {
+        let mut parts = vec![String::new()];
+        let mut chars = pattern.trim().chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    parts.last_mut().unwrap().push('*');
+                }
+                '*' => parts.push(String::new()),
+                c => parts.last_mut().unwrap().push(c),
+            }
+        }
+        Self { parts }
+    }
+    /// Returns true if the pattern matches any text.
+    fn is_catch_all(&self) -> bool {
+        self.parts.len() > 1 && self.parts.iter().all(String::is_empty)
+    }
+    /// Returns the text the pattern matches if it has no wildcards.
+    fn literal(&self) -> Option<String> {
+        match self.parts.as_slice() {
+            [literal] => Some(literal.clone()),
+            _ => None,
+        }
+    }
+    fn matches(&self, text: &str, case_sensitive: bool) -> bool {
+        let normalize = |text: &str| {
+            if case_sensitive {
+                text.to_string()
+            } else {
+                text.to_lowercase()
+            }
+        };
+        let text = normalize(text);
+        let parts: Vec<String> = self.parts.iter().map(|part| normalize(part)).collect();
+        let (first, rest) = parts.split_first().unwrap();
+        let Some(mut remaining) = text.strip_prefix(first.as_str()) else {
+            return false;
+        };
+        let Some((last, middle)) = rest.split_last() else {
+            return remaining.is_empty();
+        };
+        for part in middle {
+            match remaining.find(part.as_str()) {
+                Some(position) => remaining = &remaining[position + part.len()..],
+                None => return false,
+            }
+        }
+        remaining.ends_with(last.as_str())
+    }
 }
 
 impl Question for ShortAnswerQuestion {
-    fn get_name(&self) -> &str {
-        self.base.get_name()
+    fn type_name(&self) -> &'static str {
+        "shortanswer"
     }
-    fn get_description(&self) -> &str {
-        self.base.get_description()
+    fn base(&self) -> &QuestionBase {
+        &self.base
     }
-    fn set_text_format(&mut self, format: TextFormat) {
-        self.base.question_text_format = format;
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
     }
     fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
         self.base.add_answers(answers)
     }
-    fn to_xml(&self, writer: &mut EventWriter<&File>) -> Result<(), QuizError> {
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
         // Start question tag
         writer.write(XmlEvent::start_element("question").attr("type", "shortanswer"))?;
         // Write the common part of the question
-        self.base.to_xml(writer)?;
-        write_named_formatted_scope(writer, "usecase", None, |writer| {
-            writer.write(XmlEvent::characters(&(self.usecase as u8).to_string()))?;
-            Ok(())
-        })?;
+        self.base.write_header(writer)?;
+        if self.base.answers.is_empty() {
+            return Err(EmptyError.into());
+        }
+        write_value(writer, "usecase", &(self.usecase as u8).to_string())?;
+        for answer in &self.base.answers {
+            answer.to_xml(writer)?;
+        }
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
         // End question tag
         writer.write(XmlEvent::end_element())?;
         Ok(())
@@ -338,14 +731,14 @@ impl EssayQuestion {
 }
 
 impl Question for EssayQuestion {
-    fn get_name(&self) -> &str {
-        self.base.get_name()
+    fn type_name(&self) -> &'static str {
+        "essay"
     }
-    fn get_description(&self) -> &str {
-        self.base.get_description()
+    fn base(&self) -> &QuestionBase {
+        &self.base
     }
-    fn set_text_format(&mut self, format: TextFormat) {
-        self.base.question_text_format = format;
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
     }
     fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
         if !answers.is_empty() {
@@ -355,105 +748,2769 @@ impl Question for EssayQuestion {
         }
         Ok(())
     }
-    fn to_xml(&self, writer: &mut EventWriter<&File>) -> Result<(), QuizError> {
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
         // Start question tag
         writer.write(XmlEvent::start_element("question").attr("type", "essay"))?;
-        // Write the common part of the question
-        self.base.to_xml(writer)?;
+        // Essay questions have no answers, only the common header is written
+        self.base.write_header(writer)?;
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
         // End question tag
         writer.write(XmlEvent::end_element())?;
         Ok(())
     }
 }
 
-/// Represents the different types of questions that can be included in a quiz.
-///
-/// - `Multichoice`: A multiple-choice question with several answer options.
-/// - `TrueFalse`: A true/false question.
-/// - `ShortAnswer`: A short-answer question.
-/// - TODO - `Matching`: A matching question where items need to be paired.
-/// - TODO - `Cloze`: A cloze (fill-in-the-blank) question.
-/// - `Essay`: An essay question.
-/// - TODO `Numerical`: A numerical answer question.
-/// - TODO - `Description`: A descriptive question.
-pub enum QuestionType {
-    Multichoice(MultiChoiceQuestion),
-    TrueFalse(TrueFalseQuestion),
-    ShortAnswer(ShortAnswerQuestion),
-    // Matching,
-    // Cloze,
-    Essay(EssayQuestion),
-    // Numerical,
-    // Description,
+/// A pair of a matching question: the question text and the answer it must be matched with.
+/// A pair with empty text adds the answer as an extra distractor.
+#[derive(Debug, Clone)]
+pub struct SubQuestion {
+    pub text: String,
+    pub answer: String,
 }
-impl QuestionType {
-    pub fn to_xml(&self, writer: &mut EventWriter<&File>) -> Result<(), QuizError> {
-        match self {
-            QuestionType::Multichoice(q) => q.to_xml(writer),
-            QuestionType::TrueFalse(q) => q.to_xml(writer),
-            QuestionType::ShortAnswer(q) => q.to_xml(writer),
-            QuestionType::Essay(q) => q.to_xml(writer),
-        }
+
+impl SubQuestion {
+    pub fn new(text: String, answer: String) -> Self {
+        Self { text, answer }
     }
 }
 
-// Make conversion from a single question to into a vector of questions easier with `.into()`
-macro_rules! impl_from_question {
-    ($(($question_type:ty, $variant:ident)),+) => {
-        $(
-            impl<Q> From<$question_type> for Vec<Q>
-            where
-                Q: Question,
-                $question_type: Into<Q>,
-            {
-                fn from(question: $question_type) -> Self {
-                    vec![question.into()]
-                }
-            }
+/// Matching question type. Answers are given as subquestions, at least two of them are required.
+#[derive(Debug, Clone)]
+pub struct MatchingQuestion {
+    base: QuestionBase,
+    pub shuffleanswers: bool,
+    subquestions: Vec<SubQuestion>,
+    pub feedback: CombinedFeedback,
+}
 
-            impl From<$question_type> for Vec<Box<dyn Question>>
-            where
-                $question_type: Question + 'static,
-            {
-                fn from(question: $question_type) -> Self {
-                    vec![Box::new(question)]
-                }
-            }
+impl MatchingQuestion {
+    pub fn new(name: String, description: String, shuffleanswers: Option<bool>) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            shuffleanswers: shuffleanswers.unwrap_or(true),
+            subquestions: Vec::new(),
+            feedback: CombinedFeedback::default(),
+        }
+    }
+    /// Adds subquestions to the question.
+    pub fn add_subquestions(&mut self, subquestions: Vec<SubQuestion>) {
+        self.subquestions.extend(subquestions);
+    }
+    /// Returns the subquestions of the question.
+    pub fn subquestions(&self) -> &[SubQuestion] {
+        &self.subquestions
+    }
+}
 
-            impl From<$question_type> for QuestionType {
-                fn from(question: $question_type) -> Self {
-                    QuestionType::$variant(question)
-                }
-            }
+impl Question for MatchingQuestion {
+    fn type_name(&self) -> &'static str {
+        "matching"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    fn add_answers(&mut self, _answers: Vec<Answer>) -> Result<(), QuizError> {
+        Err(QuizError::AnswerCountError(
+            "Matching questions use subquestions instead of answers".to_string(),
+        ))
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        let pairs = self
+            .subquestions
+            .iter()
+            .filter(|subquestion| !subquestion.text.is_empty())
+            .count();
+        if pairs < 2 {
+            return Err(QuizError::AnswerCountError(
+                "Matching questions must have at least 2 subquestions".to_string(),
+            ));
+        }
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "matching"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_named_formatted_scope(writer, "shuffleanswers", None, |writer| {
+            writer.write(XmlEvent::characters(
+                &(self.shuffleanswers as u8).to_string(),
+            ))?;
+            Ok(())
+        })?;
+        self.feedback.to_xml(writer)?;
+        for subquestion in &self.subquestions {
+            write_named_formatted_scope(
+                writer,
+                "subquestion",
+                self.base.question_text_format.into(),
+                |writer| {
+                    write_text_tag(writer, &subquestion.text, true)?;
+                    write_named_formatted_scope(writer, "answer", None, |writer| {
+                        write_text_tag(writer, &subquestion.answer, false)
+                    })
+                },
+            )?;
+        }
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
 
-            impl From<$question_type> for Vec<QuestionType> {
-                fn from(question: $question_type) -> Self {
-                    vec![QuestionType::$variant(question)]
-                }
-            }
-        )+
-    };
+/// A unit accepted by a numerical question. The value of a response given in the unit is divided by
+/// `multiplier` before comparing it to the answers, so the base unit has the multiplier 1.
+#[derive(Debug, Clone)]
+pub struct NumericalUnit {
+    pub name: String,
+    pub multiplier: f64,
 }
 
-impl_from_question!(
-    (MultiChoiceQuestion, Multichoice),
-    (TrueFalseQuestion, TrueFalse),
-    (ShortAnswerQuestion, ShortAnswer),
-    (EssayQuestion, Essay)
-);
+impl NumericalUnit {
+    pub fn new(name: String, multiplier: f64) -> Self {
+        Self { name, multiplier }
+    }
+}
+
+/// Numerical question type. Answers are numbers with an optional tolerance.
+#[derive(Debug, Clone)]
+pub struct NumericalQuestion {
+    base: QuestionBase,
+    answers: Vec<NumericalAnswer>,
+    units: Vec<NumericalUnit>,
+    /// Fraction of the grade lost when the unit of the response is missing or wrong, 0-1.
+    /// Only used when the question has units.
+    pub unit_penalty: f64,
+}
+
+impl NumericalQuestion {
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            answers: Vec::new(),
+            units: Vec::new(),
+            unit_penalty: 0.1,
+        }
+    }
+    /// Adds answers with tolerances to the question.
+    /// Returns an error if the total fraction of the answers is less than 100.
+    pub fn add_numerical_answers(
+        &mut self,
+        answers: Vec<NumericalAnswer>,
+    ) -> Result<(), QuizError> {
+        self.answers.extend(answers);
+        if !reaches_full_grade(self.answers.iter().map(|answer| answer.answer.fraction)) {
+            self.answers.clear();
+            return Err(QuizError::AnswerFractionError(
+                "The total fraction of answers must be at least 100".to_string(),
+            ));
+        }
+        Ok(())
+    }
+    /// Returns the answers of the question.
+    pub fn answers(&self) -> &[NumericalAnswer] {
+        &self.answers
+    }
+}
+
+impl Question for NumericalQuestion {
+    fn type_name(&self) -> &'static str {
+        "numerical"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// Adds answers with zero tolerance.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        self.add_numerical_answers(answers.into_iter().map(Into::into).collect())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        if self.answers.is_empty() {
+            return Err(EmptyError.into());
+        }
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "numerical"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        for answer in &self.answers {
+            answer.to_xml(writer)?;
+        }
+        write_units(writer, &self.units, self.unit_penalty, false)?;
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Answers and datasets of the calculated question types.
+#[derive(Debug, Clone, Default)]
+struct CalculatedData {
+    answers: Vec<CalculatedAnswer>,
+    datasets: Vec<DatasetDefinition>,
+}
+
+impl CalculatedData {
+    fn add_answers(&mut self, answers: Vec<CalculatedAnswer>) -> Result<(), QuizError> {
+        self.answers.extend(answers);
+        if !reaches_full_grade(self.answers.iter().map(|answer| answer.answer.fraction)) {
+            self.answers.clear();
+            return Err(QuizError::AnswerFractionError(
+                "The total fraction of answers must be at least 100".to_string(),
+            ));
+        }
+        Ok(())
+    }
+    fn generate_items(&mut self, count: usize, random: &mut Random) -> Result<(), QuizError> {
+        for dataset in &mut self.datasets {
+            dataset.generate_items(count, random)?;
+        }
+        Ok(())
+    }
+    /// Returns the item sets, the `n`th items of every dataset. Every dataset must have as many items.
+    fn item_sets(&self) -> Result<Vec<HashMap<String, f64>>, QuizError> {
+        let Some(count) = self.datasets.first().map(|dataset| dataset.items().len()) else {
+            return Ok(vec![HashMap::new()]);
+        };
+        if let Some(dataset) = self
+            .datasets
+            .iter()
+            .find(|dataset| dataset.items().len() != count)
+        {
+            return Err(QuizError::ValueError(format!(
+                "Dataset `{}` has {} items, but the other datasets have {}",
+                dataset.name,
+                dataset.items().len(),
+                count
+            )));
+        }
+        Ok((0..count)
+            .map(|index| {
+                self.datasets
+                    .iter()
+                    .map(|dataset| (dataset.name.clone(), dataset.items()[index]))
+                    .collect()
+            })
+            .collect())
+    }
+    /// Checks that every wildcard has a dataset and every formula can be evaluated with every item set.
+    /// The answers of calculated multichoice questions are text with `{=formula}` expressions,
+    /// the answers of the other types are formulas.
+    fn check(&self, text: &str, multichoice: bool) -> Result<(), QuizError> {
+        if self.answers.is_empty() {
+            return Err(EmptyError.into());
+        }
+        let mut formulas = Vec::new();
+        let mut wildcard_names = formula::wildcards(text);
+        formulas.extend(formula::embedded_formulas(text));
+        for answer in &self.answers {
+            if multichoice {
+                wildcard_names.extend(formula::wildcards(&answer.answer.text));
+                formulas.extend(formula::embedded_formulas(&answer.answer.text));
+            } else {
+                formulas.push(&answer.answer.text);
+            }
+        }
+        let formulas = formulas
+            .into_iter()
+            .map(|text| {
+                Formula::parse(text).map_err(|e| {
+                    QuizError::ValueError(format!("Formula `{}` is invalid: {}", text, e))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for name in formulas
+            .iter()
+            .flat_map(Formula::variables)
+            .chain(wildcard_names)
+        {
+            if !self.datasets.iter().any(|dataset| dataset.name == name) {
+                return Err(QuizError::ValueError(format!(
+                    "Wildcard `{{{}}}` has no dataset definition",
+                    name
+                )));
+            }
+        }
+        for (index, values) in self.item_sets()?.iter().enumerate() {
+            for formula in &formulas {
+                formula.evaluate(values).map_err(|e| {
+                    QuizError::ValueError(format!(
+                        "Formula `{}` fails with item set {}: {}",
+                        formula.text(),
+                        index + 1,
+                        e
+                    ))
+                })?;
+            }
+        }
+        Ok(())
+    }
+    fn write_datasets(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        if self.datasets.is_empty() {
+            return Ok(());
+        }
+        writer.write(XmlEvent::start_element("dataset_definitions"))?;
+        for dataset in &self.datasets {
+            dataset.to_xml(writer)?;
+        }
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Implements the methods of the answers and datasets which the calculated question types keep in their
+/// `calculated` field.
+macro_rules! impl_calculated {
+    ($($question_type:ty),+) => {
+        $(
+            impl $question_type {
+                /// Adds answers with formulas to the question.
+                /// Returns an error if the total fraction of the answers is less than 100.
+                pub fn add_calculated_answers(
+                    &mut self,
+                    answers: Vec<CalculatedAnswer>,
+                ) -> Result<(), QuizError> {
+                    self.calculated.add_answers(answers)
+                }
+                /// Returns the answers of the question.
+                pub fn answers(&self) -> &[CalculatedAnswer] {
+                    &self.calculated.answers
+                }
+                /// Adds dataset definitions for the wildcards of the question.
+                pub fn add_datasets(&mut self, datasets: Vec<DatasetDefinition>) {
+                    self.calculated.datasets.extend(datasets);
+                }
+                /// Returns the dataset definitions of the question.
+                pub fn datasets(&self) -> &[DatasetDefinition] {
+                    &self.calculated.datasets
+                }
+                /// Generates `count` items for every dataset of the question.
+                pub fn generate_items(
+                    &mut self,
+                    count: usize,
+                    random: &mut Random,
+                ) -> Result<(), QuizError> {
+                    self.calculated.generate_items(count, random)
+                }
+                /// Returns the values of the wildcards in every item set.
+                pub fn item_sets(&self) -> Result<Vec<HashMap<String, f64>>, QuizError> {
+                    self.calculated.item_sets()
+                }
+            }
+        )+
+    };
+}
+
+/// Implements the methods of the units which the question types with units keep in their `units` field.
+macro_rules! impl_units {
+    ($($question_type:ty),+) => {
+        $(
+            impl $question_type {
+                /// Adds units to the question. The first unit is the base unit of the answers.
+                pub fn add_units(&mut self, units: Vec<NumericalUnit>) {
+                    self.units.extend(units);
+                }
+                /// Returns the units of the question.
+                pub fn units(&self) -> &[NumericalUnit] {
+                    &self.units
+                }
+            }
+        )+
+    };
+}
+
+impl_calculated!(
+    CalculatedQuestion,
+    CalculatedSimpleQuestion,
+    CalculatedMultiQuestion
+);
+impl_units!(
+    NumericalQuestion,
+    CalculatedQuestion,
+    CalculatedSimpleQuestion
+);
+
+/// Calculated question type. The answers are formulas of wildcards such as `{a} * {b}`, which are replaced
+/// with values from the datasets of the question.
+#[derive(Debug, Clone)]
+pub struct CalculatedQuestion {
+    base: QuestionBase,
+    calculated: CalculatedData,
+    units: Vec<NumericalUnit>,
+    /// Fraction of the grade lost when the unit of the response is missing or wrong, 0-1.
+    pub unit_penalty: f64,
+    /// Whether shared datasets use the same item set in every question of the category.
+    pub synchronize: bool,
+}
+
+impl CalculatedQuestion {
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            calculated: CalculatedData::default(),
+            units: Vec::new(),
+            unit_penalty: 0.1,
+            synchronize: false,
+        }
+    }
+}
+
+impl Question for CalculatedQuestion {
+    fn type_name(&self) -> &'static str {
+        "calculated"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// Adds answers whose text is the formula, with the default tolerance.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        self.add_calculated_answers(answers.into_iter().map(Into::into).collect())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.calculated.check(&self.base.description, false)?;
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "calculated"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_calculated_options(writer, self.synchronize, false, "abc", false, ["", "", ""])?;
+        for answer in &self.calculated.answers {
+            answer.to_xml(writer)?;
+        }
+        write_units(writer, &self.units, self.unit_penalty, true)?;
+        self.calculated.write_datasets(writer)?;
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Simple calculated question type. Like [`CalculatedQuestion`], but every dataset is private to the question.
+#[derive(Debug, Clone)]
+pub struct CalculatedSimpleQuestion {
+    base: QuestionBase,
+    calculated: CalculatedData,
+    units: Vec<NumericalUnit>,
+    /// Fraction of the grade lost when the unit of the response is missing or wrong, 0-1.
+    pub unit_penalty: f64,
+}
+
+impl CalculatedSimpleQuestion {
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            calculated: CalculatedData::default(),
+            units: Vec::new(),
+            unit_penalty: 0.1,
+        }
+    }
+}
+
+impl Question for CalculatedSimpleQuestion {
+    fn type_name(&self) -> &'static str {
+        "calculatedsimple"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// Adds answers whose text is the formula, with the default tolerance.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        self.add_calculated_answers(answers.into_iter().map(Into::into).collect())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.calculated.check(&self.base.description, false)?;
+        if let Some(dataset) = self.calculated.datasets.iter().find(|d| d.shared) {
+            return Err(QuizError::ValueError(format!(
+                "Dataset `{}` of a simple calculated question can not be shared",
+                dataset.name
+            )));
+        }
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "calculatedsimple"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_calculated_options(writer, false, false, "abc", false, ["", "", ""])?;
+        for answer in &self.calculated.answers {
+            answer.to_xml(writer)?;
+        }
+        write_units(writer, &self.units, self.unit_penalty, true)?;
+        self.calculated.write_datasets(writer)?;
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Calculated multiple choice question type. The answers are text with `{=formula}` expressions,
+/// such as `{={a} + {b}} m`, which are evaluated with values from the datasets of the question.
+#[derive(Debug, Clone)]
+pub struct CalculatedMultiQuestion {
+    base: QuestionBase,
+    calculated: CalculatedData,
+    pub single: bool,
+    pub shuffleanswers: bool,
+    pub correctfeedback: String,
+    pub partiallycorrectfeedback: String,
+    pub incorrectfeedback: String,
+    pub answernumbering: String,
+    /// Whether shared datasets use the same item set in every question of the category.
+    pub synchronize: bool,
+}
+
+impl CalculatedMultiQuestion {
+    pub fn new(name: String, description: String, single: Option<bool>) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            calculated: CalculatedData::default(),
+            single: single.unwrap_or(true),
+            shuffleanswers: true,
+            correctfeedback: String::new(),
+            partiallycorrectfeedback: String::new(),
+            incorrectfeedback: String::new(),
+            answernumbering: "abc".to_string(),
+            synchronize: false,
+        }
+    }
+}
+
+impl Question for CalculatedMultiQuestion {
+    fn type_name(&self) -> &'static str {
+        "calculatedmulti"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// Adds answers whose text is the formula, with the default tolerance.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        self.add_calculated_answers(answers.into_iter().map(Into::into).collect())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.calculated.check(&self.base.description, true)?;
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "calculatedmulti"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_calculated_options(
+            writer,
+            self.synchronize,
+            self.single,
+            &self.answernumbering,
+            self.shuffleanswers,
+            [
+                &self.correctfeedback,
+                &self.partiallycorrectfeedback,
+                &self.incorrectfeedback,
+            ],
+        )?;
+        for answer in &self.calculated.answers {
+            answer.to_xml(writer)?;
+        }
+        self.calculated.write_datasets(writer)?;
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Writes the options Moodle writes for every calculated type before the answers: how the datasets are
+/// synchronized, the multiple choice options and the combined feedback. The options which only calculated
+/// multichoice questions have are written with their defaults by the other types.
+fn write_calculated_options(
+    writer: &mut XmlWriter,
+    synchronize: bool,
+    single: bool,
+    answernumbering: &str,
+    shuffleanswers: bool,
+    feedback: [&str; 3],
+) -> Result<(), QuizError> {
+    write_value(writer, "synchronize", &(synchronize as u8).to_string())?;
+    write_value(writer, "single", &(single as u8).to_string())?;
+    write_value(writer, "answernumbering", answernumbering)?;
+    write_value(
+        writer,
+        "shuffleanswers",
+        &(shuffleanswers as u8).to_string(),
+    )?;
+    for (name, feedback) in [
+        "correctfeedback",
+        "partiallycorrectfeedback",
+        "incorrectfeedback",
+    ]
+    .into_iter()
+    .zip(feedback)
+    {
+        write_named_formatted_scope(writer, name, TextFormat::default().into(), |writer| {
+            write_text_tag(writer, feedback, true)
+        })?;
+    }
+    Ok(())
+}
+
+/// Writes the units and how they are graded, if there are any. Responses are typed with the unit on the right.
+/// Moodle writes the units of numerical questions before the grading options and the units of calculated
+/// questions after them, which `grading_first` chooses.
+fn write_units(
+    writer: &mut XmlWriter,
+    units: &[NumericalUnit],
+    unit_penalty: f64,
+    grading_first: bool,
+) -> Result<(), QuizError> {
+    if units.is_empty() {
+        return Ok(());
+    }
+    if !(0.0..=1.0).contains(&unit_penalty) {
+        return Err(QuizError::ValueError(format!(
+            "Unit penalty {} is not between 0 and 1",
+            unit_penalty
+        )));
+    }
+    if let Some(unit) = units
+        .iter()
+        .find(|unit| !unit.multiplier.is_finite() || unit.multiplier <= 0.0)
+    {
+        return Err(QuizError::ValueError(format!(
+            "Multiplier of unit `{}` must be a positive number",
+            unit.name
+        )));
+    }
+    let write_grading = |writer: &mut XmlWriter| {
+        let grading_type = if unit_penalty > 0.0 { "1" } else { "0" };
+        write_value(writer, "unitgradingtype", grading_type)?;
+        write_value(writer, "unitpenalty", &unit_penalty.to_string())?;
+        write_value(writer, "showunits", "0")?;
+        write_value(writer, "unitsleft", "0")
+    };
+    if grading_first {
+        write_grading(writer)?;
+    }
+    write_named_formatted_scope(writer, "units", None, |writer| {
+        for unit in units {
+            write_named_formatted_scope(writer, "unit", None, |writer| {
+                write_value(writer, "multiplier", &unit.multiplier.to_string())?;
+                write_value(writer, "unit_name", &unit.name)
+            })?;
+        }
+        Ok(())
+    })?;
+    if !grading_first {
+        write_grading(writer)?;
+    }
+    Ok(())
+}
+
+/// Writes a base64 encoded `<file>` element.
+fn write_file(writer: &mut XmlWriter, file: &EmbeddedFile) -> Result<(), QuizError> {
+    writer.write(
+        XmlEvent::start_element("file")
+            .attr("name", &file.name)
+            .attr("path", "/")
+            .attr("encoding", "base64"),
+    )?;
+    writer.write(XmlEvent::characters(&file.to_base64()))?;
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+/// Writes an element which contains only text. An empty element is written as `<name />`.
+fn write_value(writer: &mut XmlWriter, name: &str, value: &str) -> Result<(), QuizError> {
+    writer.write(XmlEvent::start_element(name))?;
+    if !value.is_empty() {
+        writer.write(XmlEvent::characters(value))?;
+    }
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+/// Moodle allows at most 8 groups of choices in gap questions.
+pub const MAX_GAP_GROUPS: u8 = 8;
+
+/// Returns the choice numbers of the `[[n]]` placeholders in a text, in order.
+fn gap_placeholders(text: &str) -> Vec<usize> {
+    let mut placeholders = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        if let Some((number, after)) = rest.split_once("]]") {
+            if let Ok(number) = number.trim().parse() {
+                placeholders.push(number);
+                rest = after;
+            }
+        }
+    }
+    placeholders
+}
+
+/// Checks that every placeholder refers to a choice, every group is in use and no group has the same
+/// choice twice. `choices` are the texts and groups of the choices.
+fn check_gaps(text: &str, choices: &[(&str, u8)]) -> Result<Vec<usize>, QuizError> {
+    let placeholders = gap_placeholders(text);
+    if placeholders.is_empty() {
+        return Err(QuizError::ValueError(
+            "Question text has no [[n]] placeholders".to_string(),
+        ));
+    }
+    if let Some(number) = placeholders
+        .iter()
+        .find(|number| **number == 0 || **number > choices.len())
+    {
+        return Err(QuizError::ValueError(format!(
+            "Placeholder [[{}]] does not refer to a choice, there are {} choices",
+            number,
+            choices.len()
+        )));
+    }
+    for (index, (choice, group)) in choices.iter().enumerate() {
+        if choice.trim().is_empty() {
+            return Err(QuizError::ValueError(format!(
+                "Choice {} has no text",
+                index + 1
+            )));
+        }
+        if *group == 0 || *group > MAX_GAP_GROUPS {
+            return Err(QuizError::ValueError(format!(
+                "Group {} of choice `{}` is not between 1 and {}",
+                group, choice, MAX_GAP_GROUPS
+            )));
+        }
+        if choices[..index].contains(&(choice, *group)) {
+            return Err(QuizError::ValueError(format!(
+                "Choice `{}` is in group {} twice",
+                choice, group
+            )));
+        }
+        // A choice is only offered in the gaps of its group
+        if !placeholders
+            .iter()
+            .any(|number| choices[number - 1].1 == *group)
+        {
+            return Err(QuizError::ValueError(format!(
+                "Choice `{}` is in group {}, which no placeholder uses",
+                choice, group
+            )));
+        }
+    }
+    Ok(placeholders)
+}
+
+/// Combined feedback of the matching, gap and drag and drop question types.
+#[derive(Debug, Clone, Default)]
+pub struct CombinedFeedback {
+    pub correctfeedback: String,
+    pub partiallycorrectfeedback: String,
+    pub incorrectfeedback: String,
+    /// Whether the number of right gaps is shown with the partially correct feedback.
+    pub shownumcorrect: bool,
+}
+
+impl CombinedFeedback {
+    /// Returns the texts of the feedback.
+    fn texts(&self) -> [(TextLocation, &str, TextFormat); 3] {
+        combined_feedback(
+            &self.correctfeedback,
+            &self.partiallycorrectfeedback,
+            &self.incorrectfeedback,
+        )
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        for (name, feedback) in [
+            ("correctfeedback", &self.correctfeedback),
+            ("partiallycorrectfeedback", &self.partiallycorrectfeedback),
+            ("incorrectfeedback", &self.incorrectfeedback),
+        ] {
+            write_named_formatted_scope(writer, name, TextFormat::default().into(), |writer| {
+                write_text_tag(writer, feedback, true)
+            })?;
+        }
+        if self.shownumcorrect {
+            writer.write(XmlEvent::start_element("shownumcorrect"))?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        Ok(())
+    }
+}
+
+/// A choice of a drag and drop into text question.
+#[derive(Debug, Clone)]
+pub struct DragBox {
+    pub text: String,
+    /// Boxes can only be dropped into the gaps whose right box is in the same group, 1-8.
+    pub group: u8,
+    /// An infinite box can be dropped into several gaps.
+    pub infinite: bool,
+}
+
+impl DragBox {
+    pub fn new(text: String, group: u8) -> Self {
+        Self {
+            text,
+            group,
+            infinite: false,
+        }
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        write_named_formatted_scope(writer, "dragbox", None, |writer| {
+            write_text_tag(writer, &self.text, false)?;
+            write_value(writer, "group", &self.group.to_string())?;
+            if self.infinite {
+                writer.write(XmlEvent::start_element("infinite"))?;
+                writer.write(XmlEvent::end_element())?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Drag and drop into text question type. The question text has `[[n]]` placeholders, where `n` is the
+/// number of the right choice, counting from 1.
+#[derive(Debug, Clone)]
+pub struct DragDropTextQuestion {
+    base: QuestionBase,
+    choices: Vec<DragBox>,
+    pub shuffleanswers: bool,
+    pub feedback: CombinedFeedback,
+}
+
+impl DragDropTextQuestion {
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            choices: Vec::new(),
+            shuffleanswers: true,
+            feedback: CombinedFeedback::default(),
+        }
+    }
+    /// Adds choices to the question. The first choice is referred to with `[[1]]`.
+    pub fn add_choices(&mut self, choices: Vec<DragBox>) {
+        self.choices.extend(choices);
+    }
+    /// Returns the choices of the question.
+    pub fn choices(&self) -> &[DragBox] {
+        &self.choices
+    }
+    /// Returns the number of the right choice of every gap, in the order of the placeholders.
+    pub fn gaps(&self) -> Vec<usize> {
+        gap_placeholders(&self.base.description)
+    }
+    fn check(&self) -> Result<(), QuizError> {
+        let choices: Vec<(&str, u8)> = self
+            .choices
+            .iter()
+            .map(|choice| (choice.text.as_str(), choice.group))
+            .collect();
+        let placeholders = check_gaps(&self.base.description, &choices)?;
+        for (index, number) in placeholders.iter().enumerate() {
+            let choice = &self.choices[number - 1];
+            if !choice.infinite && placeholders[..index].contains(number) {
+                return Err(QuizError::ValueError(format!(
+                    "Choice `{}` is used in several gaps, but it is not infinite",
+                    choice.text
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Question for DragDropTextQuestion {
+    fn type_name(&self) -> &'static str {
+        "ddwtos"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// Drag and drop into text questions have choices instead of answers, see `add_choices`.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        if !answers.is_empty() {
+            return Err(QuizError::AnswerCountError(
+                "Drag and drop into text questions use choices instead of answers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.check()?;
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "ddwtos"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_value(
+            writer,
+            "shuffleanswers",
+            &(self.shuffleanswers as u8).to_string(),
+        )?;
+        self.feedback.to_xml(writer)?;
+        for choice in &self.choices {
+            choice.to_xml(writer)?;
+        }
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// A choice of a select missing words question.
+#[derive(Debug, Clone)]
+pub struct SelectOption {
+    pub text: String,
+    /// The drop-down menu of a gap lists the options in the group of its right option, 1-8.
+    pub group: u8,
+}
+
+impl SelectOption {
+    pub fn new(text: String, group: u8) -> Self {
+        Self { text, group }
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        write_named_formatted_scope(writer, "selectoption", None, |writer| {
+            write_text_tag(writer, &self.text, false)?;
+            write_value(writer, "group", &self.group.to_string())
+        })
+    }
+}
+
+/// Select missing words question type. The question text has `[[n]]` placeholders, which are shown as
+/// drop-down menus, where `n` is the number of the right option, counting from 1.
+#[derive(Debug, Clone)]
+pub struct GapSelectQuestion {
+    base: QuestionBase,
+    choices: Vec<SelectOption>,
+    pub shuffleanswers: bool,
+    pub feedback: CombinedFeedback,
+}
+
+impl GapSelectQuestion {
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            choices: Vec::new(),
+            shuffleanswers: true,
+            feedback: CombinedFeedback::default(),
+        }
+    }
+    /// Adds options to the question. The first option is referred to with `[[1]]`.
+    pub fn add_choices(&mut self, choices: Vec<SelectOption>) {
+        self.choices.extend(choices);
+    }
+    /// Returns the options of the question.
+    pub fn choices(&self) -> &[SelectOption] {
+        &self.choices
+    }
+    /// Returns the number of the right option of every gap, in the order of the placeholders.
+    pub fn gaps(&self) -> Vec<usize> {
+        gap_placeholders(&self.base.description)
+    }
+}
+
+impl Question for GapSelectQuestion {
+    fn type_name(&self) -> &'static str {
+        "gapselect"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// Select missing words questions have choices instead of answers, see `add_choices`.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        if !answers.is_empty() {
+            return Err(QuizError::AnswerCountError(
+                "Select missing words questions use choices instead of answers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        let choices: Vec<(&str, u8)> = self
+            .choices
+            .iter()
+            .map(|choice| (choice.text.as_str(), choice.group))
+            .collect();
+        check_gaps(&self.base.description, &choices)?;
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "gapselect"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_value(
+            writer,
+            "shuffleanswers",
+            &(self.shuffleanswers as u8).to_string(),
+        )?;
+        self.feedback.to_xml(writer)?;
+        for choice in &self.choices {
+            choice.to_xml(writer)?;
+        }
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Returns the size of the background image of a drag and drop onto image or drag markers question.
+fn background_size(background: Option<&EmbeddedFile>) -> Result<(u32, u32), QuizError> {
+    let background = background.ok_or_else(|| {
+        QuizError::EmptyError("Question has no background image, see `set_background`".to_string())
+    })?;
+    background.image_size().ok_or_else(|| {
+        QuizError::ValueError(format!(
+            "Background `{}` is not a PNG or JPEG image",
+            background.name
+        ))
+    })
+}
+
+/// Writes an empty element, such as `<infinite/>`, if `value` is true.
+fn write_flag(writer: &mut XmlWriter, name: &str, value: bool) -> Result<(), QuizError> {
+    if value {
+        writer.write(XmlEvent::start_element(name))?;
+        writer.write(XmlEvent::end_element())?;
+    }
+    Ok(())
+}
+
+/// A draggable item of a drag and drop onto image question, which is a text or an image.
+#[derive(Debug, Clone)]
+pub struct DragItem {
+    /// Text of the item, shown when there is no image.
+    pub text: String,
+    /// Items can only be dropped into the zones whose right item is in the same group, 1-8.
+    pub group: u8,
+    /// An infinite item can be dropped into several zones.
+    pub infinite: bool,
+    pub image: Option<EmbeddedFile>,
+}
+
+impl DragItem {
+    pub fn new(text: String, group: u8) -> Self {
+        Self {
+            text,
+            group,
+            infinite: false,
+            image: None,
+        }
+    }
+    fn to_xml(&self, writer: &mut XmlWriter, number: usize) -> Result<(), QuizError> {
+        writer.write(XmlEvent::start_element("drag"))?;
+        write_value(writer, "no", &number.to_string())?;
+        write_value(writer, "text", &self.text)?;
+        write_value(writer, "draggroup", &self.group.to_string())?;
+        write_flag(writer, "infinite", self.infinite)?;
+        if let Some(image) = &self.image {
+            write_file(writer, image)?;
+        }
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// A drop zone of a drag and drop onto image question, placed with its top left corner on the background.
+#[derive(Debug, Clone)]
+pub struct DropZone {
+    /// Number of the right item, counting from 1.
+    pub choice: usize,
+    /// Distance from the left edge of the background in pixels.
+    pub x: u32,
+    /// Distance from the top edge of the background in pixels.
+    pub y: u32,
+    /// Label of the zone for screen readers.
+    pub text: String,
+}
+
+impl DropZone {
+    pub fn new(choice: usize, x: u32, y: u32) -> Self {
+        Self {
+            choice,
+            x,
+            y,
+            text: String::new(),
+        }
+    }
+    fn to_xml(&self, writer: &mut XmlWriter, number: usize) -> Result<(), QuizError> {
+        writer.write(XmlEvent::start_element("drop"))?;
+        write_value(writer, "text", &self.text)?;
+        write_value(writer, "no", &number.to_string())?;
+        write_value(writer, "choice", &self.choice.to_string())?;
+        write_value(writer, "xleft", &self.x.to_string())?;
+        write_value(writer, "ytop", &self.y.to_string())?;
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Drag and drop onto image question type. Texts or images are dragged into the drop zones of a background
+/// image, which must be a PNG or JPEG image.
+#[derive(Debug, Clone)]
+pub struct DragDropImageQuestion {
+    base: QuestionBase,
+    background: Option<EmbeddedFile>,
+    drags: Vec<DragItem>,
+    drops: Vec<DropZone>,
+    pub shuffleanswers: bool,
+    pub feedback: CombinedFeedback,
+}
+
+impl DragDropImageQuestion {
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            background: None,
+            drags: Vec::new(),
+            drops: Vec::new(),
+            shuffleanswers: false,
+            feedback: CombinedFeedback::default(),
+        }
+    }
+    /// Sets the background image, which the drop zones are placed on.
+    pub fn set_background(&mut self, background: EmbeddedFile) {
+        self.background = Some(background);
+    }
+    pub fn background(&self) -> Option<&EmbeddedFile> {
+        self.background.as_ref()
+    }
+    /// Adds draggable items to the question. Drop zones refer to the first item with 1.
+    pub fn add_drags(&mut self, drags: Vec<DragItem>) {
+        self.drags.extend(drags);
+    }
+    pub fn drags(&self) -> &[DragItem] {
+        &self.drags
+    }
+    pub fn add_drops(&mut self, drops: Vec<DropZone>) {
+        self.drops.extend(drops);
+    }
+    pub fn drops(&self) -> &[DropZone] {
+        &self.drops
+    }
+    fn check(&self) -> Result<(), QuizError> {
+        let (width, height) = background_size(self.background.as_ref())?;
+        if self.drops.is_empty() {
+            return Err(QuizError::EmptyError(
+                "Question has no drop zones".to_string(),
+            ));
+        }
+        for (index, drag) in self.drags.iter().enumerate() {
+            if drag.text.trim().is_empty() && drag.image.is_none() {
+                return Err(QuizError::ValueError(format!(
+                    "Drag item {} has neither text nor image",
+                    index + 1
+                )));
+            }
+            if drag.group == 0 || drag.group > MAX_GAP_GROUPS {
+                return Err(QuizError::ValueError(format!(
+                    "Group {} of drag item {} is not between 1 and {}",
+                    drag.group,
+                    index + 1,
+                    MAX_GAP_GROUPS
+                )));
+            }
+        }
+        for (index, drop) in self.drops.iter().enumerate() {
+            if drop.choice == 0 || drop.choice > self.drags.len() {
+                return Err(QuizError::ValueError(format!(
+                    "Drop zone {} refers to drag item {}, there are {} items",
+                    index + 1,
+                    drop.choice,
+                    self.drags.len()
+                )));
+            }
+            if drop.x >= width || drop.y >= height {
+                return Err(QuizError::ValueError(format!(
+                    "Drop zone {} at ({}, {}) is outside the {}x{} background image",
+                    index + 1,
+                    drop.x,
+                    drop.y,
+                    width,
+                    height
+                )));
+            }
+            let drag = &self.drags[drop.choice - 1];
+            if !drag.infinite
+                && self.drops[..index]
+                    .iter()
+                    .any(|other| other.choice == drop.choice)
+            {
+                return Err(QuizError::ValueError(format!(
+                    "Drag item {} is the right item of several drop zones, but it is not infinite",
+                    drop.choice
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Question for DragDropImageQuestion {
+    fn type_name(&self) -> &'static str {
+        "ddimageortext"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// Drag and drop onto image questions have drag items and drop zones instead of answers.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        if !answers.is_empty() {
+            return Err(QuizError::AnswerCountError(
+                "Drag and drop onto image questions use drag items instead of answers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.check()?;
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "ddimageortext"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_flag(writer, "shuffleanswers", self.shuffleanswers)?;
+        self.feedback.to_xml(writer)?;
+        if let Some(background) = &self.background {
+            write_file(writer, background)?;
+        }
+        for (index, drag) in self.drags.iter().enumerate() {
+            drag.to_xml(writer, index + 1)?;
+        }
+        for (index, drop) in self.drops.iter().enumerate() {
+            drop.to_xml(writer, index + 1)?;
+        }
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Shape of a drop zone of a drag markers question, in pixels of the background image.
+///
+/// The shapes are written as strings such as `circle;x,y;r`, `rectangle;x,y;width,height` and
+/// `polygon;x1,y1;x2,y2;x3,y3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarkerShape {
+    Circle {
+        x: u32,
+        y: u32,
+        radius: u32,
+    },
+    Rectangle {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    Polygon(Vec<(u32, u32)>),
+}
+
+impl MarkerShape {
+    /// Returns the Moodle name of the shape.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MarkerShape::Circle { .. } => "circle",
+            MarkerShape::Rectangle { .. } => "rectangle",
+            MarkerShape::Polygon(_) => "polygon",
+        }
+    }
+    /// Returns the coordinates as Moodle writes them, such as `x,y;r` for a circle.
+    pub fn coords(&self) -> String {
+        match self {
+            MarkerShape::Circle { x, y, radius } => format!("{},{};{}", x, y, radius),
+            MarkerShape::Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } => format!("{},{};{},{}", x, y, width, height),
+            MarkerShape::Polygon(points) => points
+                .iter()
+                .map(|(x, y)| format!("{},{}", x, y))
+                .collect::<Vec<_>>()
+                .join(";"),
+        }
+    }
+    /// Parses a shape string such as `circle;10,20;5`. Returns `None` if the shape is not valid.
+    pub fn parse(shape: &str) -> Option<Self> {
+        let (name, coords) = shape.split_once(';')?;
+        Self::from_parts(name, coords)
+    }
+    /// Parses a shape from its name and coordinates, which Moodle XML has in separate elements.
+    pub fn from_parts(name: &str, coords: &str) -> Option<Self> {
+        let numbers: Vec<Vec<u32>> = coords
+            .split(';')
+            .map(|part| {
+                part.split(',')
+                    .map(|number| number.trim().parse().ok())
+                    .collect()
+            })
+            .collect::<Option<_>>()?;
+        let shape = match (name.trim(), numbers.as_slice()) {
+            ("circle", [point, radius]) => match (point.as_slice(), radius.as_slice()) {
+                ([x, y], [radius]) => MarkerShape::Circle {
+                    x: *x,
+                    y: *y,
+                    radius: *radius,
+                },
+                _ => return None,
+            },
+            ("rectangle", [point, size]) => match (point.as_slice(), size.as_slice()) {
+                ([x, y], [width, height]) => MarkerShape::Rectangle {
+                    x: *x,
+                    y: *y,
+                    width: *width,
+                    height: *height,
+                },
+                _ => return None,
+            },
+            ("polygon", points) => MarkerShape::Polygon(
+                points
+                    .iter()
+                    .map(|point| match point.as_slice() {
+                        [x, y] => Some((*x, *y)),
+                        _ => None,
+                    })
+                    .collect::<Option<_>>()?,
+            ),
+            _ => return None,
+        };
+        Some(shape)
+    }
+    /// Checks that the shape is not empty and fits on an image of the given size.
+    fn check(&self, width: u32, height: u32) -> Result<(), String> {
+        let (left, top, right, bottom) = match self {
+            MarkerShape::Circle { x, y, radius } => {
+                if *radius == 0 {
+                    return Err("has no radius".to_string());
+                }
+                (
+                    x.checked_sub(*radius),
+                    y.checked_sub(*radius),
+                    x.saturating_add(*radius),
+                    y.saturating_add(*radius),
+                )
+            }
+            MarkerShape::Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                if *width == 0 || *height == 0 {
+                    return Err("has no area".to_string());
+                }
+                (
+                    Some(*x),
+                    Some(*y),
+                    x.saturating_add(*width),
+                    y.saturating_add(*height),
+                )
+            }
+            MarkerShape::Polygon(points) => {
+                if points.len() < 3 {
+                    return Err("needs at least 3 points".to_string());
+                }
+                (
+                    Some(0),
+                    Some(0),
+                    points.iter().map(|(x, _)| *x).max().unwrap_or_default(),
+                    points.iter().map(|(_, y)| *y).max().unwrap_or_default(),
+                )
+            }
+        };
+        if left.is_none() || top.is_none() || right > width || bottom > height {
+            return Err(format!(
+                "does not fit on the {}x{} background image",
+                width, height
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MarkerShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{};{}", self.name(), self.coords())
+    }
+}
+
+/// A marker of a drag markers question.
+#[derive(Debug, Clone)]
+pub struct Marker {
+    pub text: String,
+    /// An infinite marker can be placed any number of times.
+    pub infinite: bool,
+    /// How many times the marker can be placed, if it is not infinite.
+    pub count: u32,
+}
+
+impl Marker {
+    pub fn new(text: String) -> Self {
+        Self {
+            text,
+            infinite: false,
+            count: 1,
+        }
+    }
+    fn to_xml(&self, writer: &mut XmlWriter, number: usize) -> Result<(), QuizError> {
+        writer.write(XmlEvent::start_element("drag"))?;
+        write_value(writer, "no", &number.to_string())?;
+        write_value(writer, "text", &self.text)?;
+        write_flag(writer, "infinite", self.infinite)?;
+        write_value(writer, "noofdrags", &self.count.to_string())?;
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// A drop zone of a drag markers question, where the marker `choice` must be placed.
+#[derive(Debug, Clone)]
+pub struct MarkerZone {
+    pub shape: MarkerShape,
+    /// Number of the right marker, counting from 1.
+    pub choice: usize,
+}
+
+impl MarkerZone {
+    pub fn new(shape: MarkerShape, choice: usize) -> Self {
+        Self { shape, choice }
+    }
+    fn to_xml(&self, writer: &mut XmlWriter, number: usize) -> Result<(), QuizError> {
+        writer.write(XmlEvent::start_element("drop"))?;
+        write_value(writer, "no", &number.to_string())?;
+        write_value(writer, "shape", self.shape.name())?;
+        write_value(writer, "coords", &self.shape.coords())?;
+        write_value(writer, "choice", &self.choice.to_string())?;
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Drag markers question type. Markers are placed on a background image, which must be a PNG or JPEG
+/// image, and they are right when they are inside the shape of their zone.
+#[derive(Debug, Clone)]
+pub struct DragMarkerQuestion {
+    base: QuestionBase,
+    background: Option<EmbeddedFile>,
+    markers: Vec<Marker>,
+    zones: Vec<MarkerZone>,
+    pub shuffleanswers: bool,
+    /// Whether the zones of misplaced markers are highlighted after the attempt.
+    pub showmisplaced: bool,
+    pub feedback: CombinedFeedback,
+}
+
+impl DragMarkerQuestion {
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            background: None,
+            markers: Vec::new(),
+            zones: Vec::new(),
+            shuffleanswers: false,
+            showmisplaced: false,
+            feedback: CombinedFeedback::default(),
+        }
+    }
+    /// Sets the background image, which the markers are placed on.
+    pub fn set_background(&mut self, background: EmbeddedFile) {
+        self.background = Some(background);
+    }
+    pub fn background(&self) -> Option<&EmbeddedFile> {
+        self.background.as_ref()
+    }
+    /// Adds markers to the question. Zones refer to the first marker with 1.
+    pub fn add_markers(&mut self, markers: Vec<Marker>) {
+        self.markers.extend(markers);
+    }
+    pub fn markers(&self) -> &[Marker] {
+        &self.markers
+    }
+    pub fn add_zones(&mut self, zones: Vec<MarkerZone>) {
+        self.zones.extend(zones);
+    }
+    pub fn zones(&self) -> &[MarkerZone] {
+        &self.zones
+    }
+    fn check(&self) -> Result<(), QuizError> {
+        let (width, height) = background_size(self.background.as_ref())?;
+        if self.zones.is_empty() {
+            return Err(QuizError::EmptyError(
+                "Question has no drop zones".to_string(),
+            ));
+        }
+        for (index, marker) in self.markers.iter().enumerate() {
+            if marker.text.trim().is_empty() {
+                return Err(QuizError::ValueError(format!(
+                    "Marker {} has no text",
+                    index + 1
+                )));
+            }
+            let zones = self
+                .zones
+                .iter()
+                .filter(|zone| zone.choice == index + 1)
+                .count();
+            if !marker.infinite && zones > marker.count as usize {
+                return Err(QuizError::ValueError(format!(
+                    "Marker `{}` is the right marker of {} zones, but it can be placed {} times",
+                    marker.text, zones, marker.count
+                )));
+            }
+        }
+        for (index, zone) in self.zones.iter().enumerate() {
+            if zone.choice == 0 || zone.choice > self.markers.len() {
+                return Err(QuizError::ValueError(format!(
+                    "Drop zone {} refers to marker {}, there are {} markers",
+                    index + 1,
+                    zone.choice,
+                    self.markers.len()
+                )));
+            }
+            zone.shape.check(width, height).map_err(|message| {
+                QuizError::ValueError(format!(
+                    "Drop zone {} `{}` {}",
+                    index + 1,
+                    zone.shape,
+                    message
+                ))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl Question for DragMarkerQuestion {
+    fn type_name(&self) -> &'static str {
+        "ddmarker"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// Drag markers questions have markers and zones instead of answers.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        if !answers.is_empty() {
+            return Err(QuizError::AnswerCountError(
+                "Drag markers questions use markers instead of answers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.check()?;
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "ddmarker"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_flag(writer, "shuffleanswers", self.shuffleanswers)?;
+        write_flag(writer, "showmisplaced", self.showmisplaced)?;
+        self.feedback.to_xml(writer)?;
+        if let Some(background) = &self.background {
+            write_file(writer, background)?;
+        }
+        for (index, marker) in self.markers.iter().enumerate() {
+            marker.to_xml(writer, index + 1)?;
+        }
+        for (index, zone) in self.zones.iter().enumerate() {
+            zone.to_xml(writer, index + 1)?;
+        }
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// How the items of an ordering question are laid out.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OrderingLayout {
+    #[default]
+    Vertical,
+    Horizontal,
+}
+
+impl OrderingLayout {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrderingLayout::Vertical => "VERTICAL",
+            OrderingLayout::Horizontal => "HORIZONTAL",
+        }
+    }
+    /// Parses a layout from its Moodle name, e.g. `VERTICAL`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "VERTICAL" => Some(OrderingLayout::Vertical),
+            "HORIZONTAL" => Some(OrderingLayout::Horizontal),
+            _ => None,
+        }
+    }
+}
+
+/// Which items of an ordering question are shown.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OrderingSelect {
+    /// All items are shown.
+    #[default]
+    All,
+    /// `select_count` random items are shown.
+    Random,
+    /// `select_count` items in a row of the right order are shown.
+    Contiguous,
+}
+
+impl OrderingSelect {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrderingSelect::All => "ALL",
+            OrderingSelect::Random => "RANDOM",
+            OrderingSelect::Contiguous => "CONTIGUOUS",
+        }
+    }
+    /// Parses a select type from its Moodle name, e.g. `ALL`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ALL" => Some(OrderingSelect::All),
+            "RANDOM" => Some(OrderingSelect::Random),
+            "CONTIGUOUS" => Some(OrderingSelect::Contiguous),
+            _ => None,
+        }
+    }
+}
+
+/// How the order of an ordering question is graded.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OrderingGrading {
+    /// Full marks only for the right order.
+    AllOrNothing,
+    /// A mark for every item in its right position.
+    #[default]
+    AbsolutePosition,
+    /// A mark for every item, except the last one, followed by its right next item.
+    RelativeNextExcludeLast,
+    /// A mark for every item followed by its right next item, the last one included.
+    RelativeNextIncludeLast,
+    /// A mark for the right previous and a mark for the right next item of every item.
+    RelativeOnePreviousAndNext,
+    /// A mark for every item before or after an item in the right order.
+    RelativeAllPreviousAndNext,
+    /// Marks for the longest subset of items in the right order.
+    LongestOrderedSubset,
+    /// Marks for the longest subset of items in the right order next to each other.
+    LongestContiguousSubset,
+    /// Marks by the distance of every item from its right position.
+    RelativeToCorrect,
+}
+
+impl OrderingGrading {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrderingGrading::AllOrNothing => "ALL_OR_NOTHING",
+            OrderingGrading::AbsolutePosition => "ABSOLUTE_POSITION",
+            OrderingGrading::RelativeNextExcludeLast => "RELATIVE_NEXT_EXCLUDE_LAST",
+            OrderingGrading::RelativeNextIncludeLast => "RELATIVE_NEXT_INCLUDE_LAST",
+            OrderingGrading::RelativeOnePreviousAndNext => "RELATIVE_ONE_PREVIOUS_AND_NEXT",
+            OrderingGrading::RelativeAllPreviousAndNext => "RELATIVE_ALL_PREVIOUS_AND_NEXT",
+            OrderingGrading::LongestOrderedSubset => "LONGEST_ORDERED_SUBSET",
+            OrderingGrading::LongestContiguousSubset => "LONGEST_CONTIGUOUS_SUBSET",
+            OrderingGrading::RelativeToCorrect => "RELATIVE_TO_CORRECT",
+        }
+    }
+    /// Parses a grading type from its Moodle name, e.g. `ABSOLUTE_POSITION`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            OrderingGrading::AllOrNothing,
+            OrderingGrading::AbsolutePosition,
+            OrderingGrading::RelativeNextExcludeLast,
+            OrderingGrading::RelativeNextIncludeLast,
+            OrderingGrading::RelativeOnePreviousAndNext,
+            OrderingGrading::RelativeAllPreviousAndNext,
+            OrderingGrading::LongestOrderedSubset,
+            OrderingGrading::LongestContiguousSubset,
+            OrderingGrading::RelativeToCorrect,
+        ]
+        .into_iter()
+        .find(|grading| grading.name() == name)
+    }
+}
+
+/// Ordering question type of the `qtype_ordering` plugin. The items are added in their right order and
+/// shown shuffled.
+#[derive(Debug, Clone)]
+pub struct OrderingQuestion {
+    base: QuestionBase,
+    items: Vec<String>,
+    pub layout: OrderingLayout,
+    pub select: OrderingSelect,
+    /// Number of items shown when `select` is not `All`, at least 2.
+    pub select_count: usize,
+    pub grading: OrderingGrading,
+    pub feedback: CombinedFeedback,
+}
+
+impl OrderingQuestion {
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            items: Vec::new(),
+            layout: OrderingLayout::default(),
+            select: OrderingSelect::default(),
+            select_count: 0,
+            grading: OrderingGrading::default(),
+            feedback: CombinedFeedback::default(),
+        }
+    }
+    /// Adds items to the end of the right order.
+    pub fn add_items(&mut self, items: Vec<String>) {
+        self.items.extend(items);
+    }
+    /// Returns the items in their right order.
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+    fn check(&self) -> Result<(), QuizError> {
+        if self.items.len() < 2 {
+            return Err(QuizError::AnswerCountError(
+                "Ordering questions need at least 2 items".to_string(),
+            ));
+        }
+        // Fractions hold the positions of the items
+        if self.items.len() > 100 {
+            return Err(QuizError::AnswerCountError(
+                "Ordering questions can have at most 100 items".to_string(),
+            ));
+        }
+        for (index, item) in self.items.iter().enumerate() {
+            if item.trim().is_empty() {
+                return Err(QuizError::ValueError(format!(
+                    "Item {} has no text",
+                    index + 1
+                )));
+            }
+            if self.items[..index].contains(item) {
+                return Err(QuizError::ValueError(format!(
+                    "Item `{}` is in the question twice",
+                    item
+                )));
+            }
+        }
+        if self.select != OrderingSelect::All
+            && !(2..=self.items.len()).contains(&self.select_count)
+        {
+            return Err(QuizError::ValueError(format!(
+                "Select count {} is not between 2 and the number of items, {}",
+                self.select_count,
+                self.items.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Question for OrderingQuestion {
+    fn type_name(&self) -> &'static str {
+        "ordering"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// Adds the texts of the answers as items, in order. The fractions and feedback are not used.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        self.items
+            .extend(answers.into_iter().map(|answer| answer.text));
+        Ok(())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.check()?;
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "ordering"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_value(writer, "layouttype", self.layout.name())?;
+        write_value(writer, "selecttype", self.select.name())?;
+        write_value(writer, "selectcount", &self.select_count.to_string())?;
+        write_value(writer, "gradingtype", self.grading.name())?;
+        self.feedback.to_xml(writer)?;
+        // The fraction of an answer is its position in the right order
+        for (index, item) in self.items.iter().enumerate() {
+            let mut answer = Answer::new(index as f64 + 1.0, item.clone(), None);
+            answer.set_text_format(self.base.question_text_format);
+            answer.to_xml(writer)?;
+        }
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// When the precheck button of a CodeRunner question is shown and which tests it runs.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Precheck {
+    #[default]
+    Disabled,
+    /// Runs the code without tests, for syntax errors.
+    Empty,
+    /// Runs the tests which are used as examples.
+    Examples,
+    /// Runs the tests whose type is `Precheck` or `Both`.
+    Selected,
+    All,
+}
+
+impl Precheck {
+    /// Returns the code of the precheck option in Moodle XML.
+    pub fn code(&self) -> u8 {
+        match self {
+            Precheck::Disabled => 0,
+            Precheck::Empty => 1,
+            Precheck::Examples => 2,
+            Precheck::Selected => 3,
+            Precheck::All => 4,
+        }
+    }
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Precheck::Disabled),
+            1 => Some(Precheck::Empty),
+            2 => Some(Precheck::Examples),
+            3 => Some(Precheck::Selected),
+            4 => Some(Precheck::All),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a CodeRunner test is run when the answer is checked, prechecked or both.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TestType {
+    #[default]
+    Normal,
+    Precheck,
+    Both,
+}
+
+impl TestType {
+    /// Returns the code of the test type in Moodle XML.
+    pub fn code(&self) -> u8 {
+        match self {
+            TestType::Normal => 0,
+            TestType::Precheck => 1,
+            TestType::Both => 2,
+        }
+    }
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(TestType::Normal),
+            1 => Some(TestType::Precheck),
+            2 => Some(TestType::Both),
+            _ => None,
+        }
+    }
+}
+
+/// When the result of a CodeRunner test is shown to the student.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TestDisplay {
+    #[default]
+    Show,
+    Hide,
+    HideIfFail,
+    HideIfSucceed,
+}
+
+impl TestDisplay {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TestDisplay::Show => "SHOW",
+            TestDisplay::Hide => "HIDE",
+            TestDisplay::HideIfFail => "HIDE_IF_FAIL",
+            TestDisplay::HideIfSucceed => "HIDE_IF_SUCCEED",
+        }
+    }
+    /// Parses a display option from its Moodle name, e.g. `HIDE_IF_FAIL`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "SHOW" => Some(TestDisplay::Show),
+            "HIDE" => Some(TestDisplay::Hide),
+            "HIDE_IF_FAIL" => Some(TestDisplay::HideIfFail),
+            "HIDE_IF_SUCCEED" => Some(TestDisplay::HideIfSucceed),
+            _ => None,
+        }
+    }
+}
+
+/// A test case of a CodeRunner question.
+#[derive(Debug, Clone)]
+pub struct TestCase {
+    /// Code run after the answer, such as `print(square(3))`.
+    pub testcode: String,
+    pub stdin: String,
+    /// Expected output of the test.
+    pub expected: String,
+    /// Extra data for the template.
+    pub extra: String,
+    pub display: TestDisplay,
+    /// Whether the later tests are hidden if this one fails.
+    pub hiderestiffail: bool,
+    /// Mark of the test, relative to the other tests.
+    pub mark: f64,
+    /// Whether the test is shown as an example in the question text.
+    pub useasexample: bool,
+    pub testtype: TestType,
+}
+
+impl TestCase {
+    pub fn new(testcode: String, expected: String) -> Self {
+        Self {
+            testcode,
+            stdin: String::new(),
+            expected,
+            extra: String::new(),
+            display: TestDisplay::default(),
+            hiderestiffail: false,
+            mark: 1.0,
+            useasexample: false,
+            testtype: TestType::default(),
+        }
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        writer.write(
+            XmlEvent::start_element("testcase")
+                .attr("testtype", &self.testtype.code().to_string())
+                .attr("useasexample", if self.useasexample { "1" } else { "0" })
+                .attr(
+                    "hiderestiffail",
+                    if self.hiderestiffail { "1" } else { "0" },
+                )
+                .attr("mark", &self.mark.to_string()),
+        )?;
+        for (name, value) in [
+            ("testcode", self.testcode.as_str()),
+            ("stdin", &self.stdin),
+            ("expected", &self.expected),
+            ("extra", &self.extra),
+            ("display", self.display.name()),
+        ] {
+            write_named_formatted_scope(writer, name, None, |writer| {
+                write_text_tag(writer, value, false)
+            })?;
+        }
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Checks a penalty regime such as `10, 20, ...`, the penalties in percent of the submissions after the first.
+fn check_penalty_regime(regime: &str) -> Result<(), QuizError> {
+    if regime.trim().is_empty() {
+        return Ok(());
+    }
+    let mut penalties: Vec<&str> = regime.split(',').map(str::trim).collect();
+    if penalties.last() == Some(&"...") {
+        penalties.pop();
+    }
+    let valid = penalties.iter().all(|penalty| {
+        penalty
+            .trim_end_matches('%')
+            .parse::<f64>()
+            .is_ok_and(|penalty| (0.0..=100.0).contains(&penalty))
+    });
+    if !valid {
+        return Err(QuizError::ValueError(format!(
+            "Penalty regime `{}` is not a list of percentages such as `10, 20, ...`",
+            regime
+        )));
+    }
+    Ok(())
+}
+
+/// Question type of the CodeRunner plugin. The answer is a program, which is run with the test cases in the
+/// sandbox of the Moodle site and graded by its output.
+#[derive(Debug, Clone)]
+pub struct CodeRunnerQuestion {
+    base: QuestionBase,
+    /// Prototype of the question, such as `python3` or `java_method`.
+    pub coderunnertype: String,
+    /// Sample answer, which Moodle validates against the tests when the question is saved.
+    pub answer: String,
+    /// Code shown in the answer box when the attempt starts.
+    pub answerpreload: String,
+    /// Customised template, the template of the prototype is used if `None`.
+    pub template: Option<String>,
+    testcases: Vec<TestCase>,
+    pub precheck: Precheck,
+    /// Penalties of the submissions after the first, such as `10, 20, ...`. Empty uses the site default.
+    pub penaltyregime: String,
+    /// Whether every test must pass for any marks.
+    pub allornothing: bool,
+    /// Language of the sandbox, the language of the prototype is used if `None`.
+    pub language: Option<String>,
+    /// Language of the syntax highlighting of the answer box.
+    pub acelang: Option<String>,
+    pub sandbox: Option<String>,
+    pub cputimelimitsecs: Option<u32>,
+    pub memlimitmb: Option<u32>,
+}
+
+impl CodeRunnerQuestion {
+    pub fn new(name: String, description: String, coderunnertype: String) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            coderunnertype,
+            answer: String::new(),
+            answerpreload: String::new(),
+            template: None,
+            testcases: Vec::new(),
+            precheck: Precheck::default(),
+            penaltyregime: String::new(),
+            allornothing: true,
+            language: None,
+            acelang: None,
+            sandbox: None,
+            cputimelimitsecs: None,
+            memlimitmb: None,
+        }
+    }
+    pub fn add_testcases(&mut self, testcases: Vec<TestCase>) {
+        self.testcases.extend(testcases);
+    }
+    pub fn testcases(&self) -> &[TestCase] {
+        &self.testcases
+    }
+    fn check(&self) -> Result<(), QuizError> {
+        if self.coderunnertype.trim().is_empty() {
+            return Err(QuizError::ValueError(format!(
+                "CodeRunner question `{}` has no coderunnertype",
+                self.base.name
+            )));
+        }
+        if self.testcases.is_empty() {
+            return Err(QuizError::EmptyError(format!(
+                "CodeRunner question `{}` has no test cases",
+                self.base.name
+            )));
+        }
+        if let Some(index) = self
+            .testcases
+            .iter()
+            .position(|test| !test.mark.is_finite() || test.mark < 0.0)
+        {
+            return Err(QuizError::ValueError(format!(
+                "Test case {} has an invalid mark {}",
+                index + 1,
+                self.testcases[index].mark
+            )));
+        }
+        if self.precheck == Precheck::Selected
+            && self
+                .testcases
+                .iter()
+                .all(|test| test.testtype == TestType::Normal)
+        {
+            return Err(QuizError::ValueError(
+                "Precheck runs the selected tests, but no test case is selected for it".to_string(),
+            ));
+        }
+        check_penalty_regime(&self.penaltyregime)
+    }
+}
+
+impl Question for CodeRunnerQuestion {
+    fn type_name(&self) -> &'static str {
+        "coderunner"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// CodeRunner questions are graded by test cases, see `add_testcases`.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        if !answers.is_empty() {
+            return Err(QuizError::AnswerCountError(
+                "CodeRunner questions use test cases instead of answers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.check()?;
+        let optional =
+            |value: Option<u32>| value.map(|value| value.to_string()).unwrap_or_default();
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "coderunner"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_value(writer, "coderunnertype", &self.coderunnertype)?;
+        write_value(writer, "prototypetype", "0")?;
+        write_value(
+            writer,
+            "allornothing",
+            &(self.allornothing as u8).to_string(),
+        )?;
+        write_value(writer, "penaltyregime", &self.penaltyregime)?;
+        write_value(writer, "precheck", &self.precheck.code().to_string())?;
+        write_value(writer, "answerpreload", &self.answerpreload)?;
+        // Empty options are inherited from the prototype
+        write_value(
+            writer,
+            "template",
+            self.template.as_deref().unwrap_or_default(),
+        )?;
+        write_value(writer, "answer", &self.answer)?;
+        write_value(
+            writer,
+            "language",
+            self.language.as_deref().unwrap_or_default(),
+        )?;
+        write_value(
+            writer,
+            "acelang",
+            self.acelang.as_deref().unwrap_or_default(),
+        )?;
+        write_value(
+            writer,
+            "sandbox",
+            self.sandbox.as_deref().unwrap_or_default(),
+        )?;
+        write_value(writer, "cputimelimitsecs", &optional(self.cputimelimitsecs))?;
+        write_value(writer, "memlimitmb", &optional(self.memlimitmb))?;
+        writer.write(XmlEvent::start_element("testcases"))?;
+        for testcase in &self.testcases {
+            testcase.to_xml(writer)?;
+        }
+        writer.write(XmlEvent::end_element())?;
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Random short-answer matching question type. Moodle builds a matching question from `choose` short
+/// answer questions drawn from the category of this question when the quiz is attempted.
+#[derive(Debug, Clone)]
+pub struct RandomShortAnswerMatchQuestion {
+    base: QuestionBase,
+    /// Number of short answer questions drawn, at least 2.
+    pub choose: u32,
+    /// Whether the questions are drawn from the subcategories as well.
+    pub subcats: bool,
+    pub feedback: CombinedFeedback,
+}
+
+impl RandomShortAnswerMatchQuestion {
+    pub fn new(name: String, description: String, choose: u32) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+            choose,
+            subcats: true,
+            feedback: CombinedFeedback::default(),
+        }
+    }
+}
+
+impl Question for RandomShortAnswerMatchQuestion {
+    fn type_name(&self) -> &'static str {
+        "randomsamatch"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// The answers come from the short answer questions drawn, so none can be added.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        if !answers.is_empty() {
+            return Err(QuizError::AnswerCountError(
+                "Random short-answer matching questions draw their answers from the category"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        if self.choose < 2 {
+            return Err(QuizError::ValueError(format!(
+                "Random short-answer matching question `{}` must choose at least 2 questions",
+                self.base.name
+            )));
+        }
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "randomsamatch"))?;
+        // Write the common part of the question
+        self.base.write_header(writer)?;
+        write_value(writer, "choose", &self.choose.to_string())?;
+        write_value(writer, "subcats", &(self.subcats as u8).to_string())?;
+        self.feedback.to_xml(writer)?;
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Random question placeholder. A quiz shows a question drawn from the category of the placeholder in its
+/// place, so the placeholder has only a name.
+#[derive(Debug, Clone)]
+pub struct RandomQuestion {
+    base: QuestionBase,
+    /// Whether the question is drawn from the subcategories as well.
+    pub include_subcategories: bool,
+}
+
+impl RandomQuestion {
+    pub fn new(name: String, include_subcategories: bool) -> Self {
+        Self {
+            base: QuestionBase::new(name, String::new()),
+            include_subcategories,
+        }
+    }
+}
+
+impl Question for RandomQuestion {
+    fn type_name(&self) -> &'static str {
+        "random"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    /// Random questions have no answers.
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        if !answers.is_empty() {
+            return Err(QuizError::AnswerCountError(
+                "Random questions do not have answers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "random"))?;
+        // Moodle stores the subcategory flag in the question text
+        self.base.write_header_with_text(writer, |writer| {
+            write_text_tag(
+                writer,
+                if self.include_subcategories { "1" } else { "0" },
+                false,
+            )
+        })?;
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Description is not a real question, only the text is shown. There are no answers for this question type.
+#[derive(Debug, Clone)]
+pub struct DescriptionQuestion {
+    base: QuestionBase,
+}
+
+impl DescriptionQuestion {
+    pub fn new(name: String, description: String) -> Self {
+        Self {
+            base: QuestionBase::new(name, description),
+        }
+    }
+}
+
+impl Question for DescriptionQuestion {
+    fn type_name(&self) -> &'static str {
+        "description"
+    }
+    fn base(&self) -> &QuestionBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut QuestionBase {
+        &mut self.base
+    }
+    fn add_answers(&mut self, answers: Vec<Answer>) -> Result<(), QuizError> {
+        if !answers.is_empty() {
+            return Err(QuizError::AnswerCountError(
+                "Description questions must not have any answers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+    fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        // Start question tag
+        writer.write(XmlEvent::start_element("question").attr("type", "description"))?;
+        self.base.write_header(writer)?;
+        self.base.write_hints(writer)?;
+        self.base.write_tags(writer)?;
+        // End question tag
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Represents the different types of questions that can be included in a quiz.
+///
+/// - `Multichoice`: A multiple-choice question with several answer options.
+/// - `TrueFalse`: A true/false question.
+/// - `ShortAnswer`: A short-answer question.
+/// - `Matching`: A matching question where items need to be paired.
+/// - TODO - `Cloze`: A cloze (fill-in-the-blank) question.
+/// - `Essay`: An essay question.
+/// - `Numerical`: A numerical answer question.
+/// - `Description`: A descriptive question.
+/// - `Calculated`, `CalculatedSimple` and `CalculatedMulti`: Questions with formula answers.
+/// - `DragDropText` and `GapSelect`: Questions where words are chosen for the gaps of a text.
+/// - `DragDropImage` and `DragMarker`: Questions where items are placed on a background image.
+/// - `RandomShortAnswerMatch` and `Random`: Questions drawn from the category when the quiz is attempted.
+/// - `Ordering`: A question where items are put in order.
+/// - `CodeRunner`: A programming question graded by running test cases.
+/// - `Custom`: A question type implemented outside the crate, such as the question type of a Moodle plugin.
+///   Exporters other than Moodle XML do not support it.
+pub enum QuestionType {
+    Multichoice(MultiChoiceQuestion),
+    TrueFalse(TrueFalseQuestion),
+    ShortAnswer(ShortAnswerQuestion),
+    Matching(MatchingQuestion),
+    // Cloze,
+    Essay(EssayQuestion),
+    Numerical(NumericalQuestion),
+    Description(DescriptionQuestion),
+    Calculated(CalculatedQuestion),
+    CalculatedSimple(CalculatedSimpleQuestion),
+    CalculatedMulti(CalculatedMultiQuestion),
+    DragDropText(DragDropTextQuestion),
+    GapSelect(GapSelectQuestion),
+    DragDropImage(DragDropImageQuestion),
+    DragMarker(DragMarkerQuestion),
+    RandomShortAnswerMatch(RandomShortAnswerMatchQuestion),
+    Random(RandomQuestion),
+    Ordering(OrderingQuestion),
+    CodeRunner(CodeRunnerQuestion),
+    Custom(Box<dyn Question>),
+}
+impl QuestionType {
+    /// Returns the question as a trait object, for accessing the common properties.
+    pub fn as_question(&self) -> &dyn Question {
+        match self {
+            QuestionType::Multichoice(q) => q,
+            QuestionType::TrueFalse(q) => q,
+            QuestionType::ShortAnswer(q) => q,
+            QuestionType::Matching(q) => q,
+            QuestionType::Essay(q) => q,
+            QuestionType::Numerical(q) => q,
+            QuestionType::Description(q) => q,
+            QuestionType::Calculated(q) => q,
+            QuestionType::CalculatedSimple(q) => q,
+            QuestionType::CalculatedMulti(q) => q,
+            QuestionType::DragDropText(q) => q,
+            QuestionType::GapSelect(q) => q,
+            QuestionType::DragDropImage(q) => q,
+            QuestionType::DragMarker(q) => q,
+            QuestionType::RandomShortAnswerMatch(q) => q,
+            QuestionType::Random(q) => q,
+            QuestionType::Ordering(q) => q,
+            QuestionType::CodeRunner(q) => q,
+            QuestionType::Custom(q) => q.as_ref(),
+        }
+    }
+    /// Returns the question as a mutable trait object.
+    pub fn as_question_mut(&mut self) -> &mut dyn Question {
+        match self {
+            QuestionType::Multichoice(q) => q,
+            QuestionType::TrueFalse(q) => q,
+            QuestionType::ShortAnswer(q) => q,
+            QuestionType::Matching(q) => q,
+            QuestionType::Essay(q) => q,
+            QuestionType::Numerical(q) => q,
+            QuestionType::Description(q) => q,
+            QuestionType::Calculated(q) => q,
+            QuestionType::CalculatedSimple(q) => q,
+            QuestionType::CalculatedMulti(q) => q,
+            QuestionType::DragDropText(q) => q,
+            QuestionType::GapSelect(q) => q,
+            QuestionType::DragDropImage(q) => q,
+            QuestionType::DragMarker(q) => q,
+            QuestionType::RandomShortAnswerMatch(q) => q,
+            QuestionType::Random(q) => q,
+            QuestionType::Ordering(q) => q,
+            QuestionType::CodeRunner(q) => q,
+            QuestionType::Custom(q) => q.as_mut(),
+        }
+    }
+    /// Returns the Moodle name of the question type, as in `<question type="multichoice">`.
+    pub fn type_name(&self) -> &'static str {
+        self.as_question().type_name()
+    }
+    pub fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        self.as_question().to_xml(writer)
+    }
+    /// Returns every text of the question, such as the question text, answers, choices, feedback and hints,
+    /// with their formats. The code of CodeRunner questions is plain text. Custom question types only have the
+    /// texts of their [`QuestionBase`] here.
+    pub fn texts(&self) -> Vec<(TextLocation, &str, TextFormat)> {
+        let common = self.as_question();
+        let format = common.get_text_format();
+        let mut texts = vec![(TextLocation::QuestionText, common.get_description(), format)];
+        if let Some(feedback) = common.get_general_feedback() {
+            texts.push((TextLocation::GeneralFeedback, feedback, format));
+        }
+        texts.extend(common.get_hints().iter().enumerate().map(|(index, hint)| {
+            (
+                TextLocation::Hint(index),
+                hint.text.as_str(),
+                hint.text_format,
+            )
+        }));
+        let answers: Vec<&Answer> = match self {
+            QuestionType::Multichoice(q) => {
+                texts.extend(combined_feedback(
+                    &q.correctfeedback,
+                    &q.partiallycorrectfeedback,
+                    &q.incorrectfeedback,
+                ));
+                q.answers().iter().collect()
+            }
+            QuestionType::TrueFalse(q) => q.answers().iter().collect(),
+            QuestionType::ShortAnswer(q) => q.answers().iter().collect(),
+            QuestionType::Essay(_) | QuestionType::Description(_) | QuestionType::Random(_) => {
+                Vec::new()
+            }
+            QuestionType::Numerical(q) => q.answers().iter().map(|a| &a.answer).collect(),
+            QuestionType::Calculated(q) => q.answers().iter().map(|a| &a.answer).collect(),
+            QuestionType::CalculatedSimple(q) => q.answers().iter().map(|a| &a.answer).collect(),
+            QuestionType::CalculatedMulti(q) => {
+                texts.extend(combined_feedback(
+                    &q.correctfeedback,
+                    &q.partiallycorrectfeedback,
+                    &q.incorrectfeedback,
+                ));
+                q.answers().iter().map(|a| &a.answer).collect()
+            }
+            QuestionType::Matching(q) => {
+                for (index, subquestion) in q.subquestions().iter().enumerate() {
+                    texts.push((TextLocation::SubQuestion(index), &subquestion.text, format));
+                    texts.push((
+                        TextLocation::SubQuestionAnswer(index),
+                        &subquestion.answer,
+                        TextFormat::PlainText,
+                    ));
+                }
+                texts.extend(q.feedback.texts());
+                Vec::new()
+            }
+            QuestionType::DragDropText(q) => {
+                texts.extend(q.choices().iter().enumerate().map(|(index, choice)| {
+                    (
+                        TextLocation::Choice(index),
+                        choice.text.as_str(),
+                        TextFormat::HTML,
+                    )
+                }));
+                texts.extend(q.feedback.texts());
+                Vec::new()
+            }
+            QuestionType::GapSelect(q) => {
+                texts.extend(q.choices().iter().enumerate().map(|(index, choice)| {
+                    (
+                        TextLocation::Choice(index),
+                        choice.text.as_str(),
+                        TextFormat::PlainText,
+                    )
+                }));
+                texts.extend(q.feedback.texts());
+                Vec::new()
+            }
+            QuestionType::DragDropImage(q) => {
+                texts.extend(q.drags().iter().enumerate().map(|(index, drag)| {
+                    (
+                        TextLocation::Choice(index),
+                        drag.text.as_str(),
+                        TextFormat::PlainText,
+                    )
+                }));
+                texts.extend(q.drops().iter().enumerate().map(|(index, drop)| {
+                    (
+                        TextLocation::DropZone(index),
+                        drop.text.as_str(),
+                        TextFormat::PlainText,
+                    )
+                }));
+                texts.extend(q.feedback.texts());
+                Vec::new()
+            }
+            QuestionType::DragMarker(q) => {
+                texts.extend(q.markers().iter().enumerate().map(|(index, marker)| {
+                    (
+                        TextLocation::Choice(index),
+                        marker.text.as_str(),
+                        TextFormat::PlainText,
+                    )
+                }));
+                texts.extend(q.feedback.texts());
+                Vec::new()
+            }
+            QuestionType::RandomShortAnswerMatch(q) => {
+                texts.extend(q.feedback.texts());
+                Vec::new()
+            }
+            QuestionType::Ordering(q) => {
+                texts.extend(q.items().iter().enumerate().map(|(index, item)| {
+                    (TextLocation::Item(index), item.as_str(), TextFormat::HTML)
+                }));
+                texts.extend(q.feedback.texts());
+                Vec::new()
+            }
+            QuestionType::CodeRunner(q) => {
+                let code = [
+                    (TextLocation::CodeRunnerAnswer, q.answer.as_str()),
+                    (TextLocation::AnswerPreload, q.answerpreload.as_str()),
+                    (
+                        TextLocation::Template,
+                        q.template.as_deref().unwrap_or_default(),
+                    ),
+                ];
+                let tests = q.testcases().iter().enumerate().flat_map(|(index, test)| {
+                    [&test.testcode, &test.stdin, &test.expected, &test.extra]
+                        .map(|text| (TextLocation::TestCase(index), text.as_str()))
+                });
+                texts.extend(
+                    code.into_iter()
+                        .chain(tests)
+                        .filter(|(_, text)| !text.is_empty())
+                        .map(|(location, text)| (location, text, TextFormat::PlainText)),
+                );
+                Vec::new()
+            }
+            QuestionType::Custom(_) => Vec::new(),
+        };
+        for (index, answer) in answers.into_iter().enumerate() {
+            texts.push((
+                TextLocation::Answer(index),
+                answer.text.as_str(),
+                answer.text_format,
+            ));
+            if let Some(feedback) = &answer.feedback {
+                texts.push((
+                    TextLocation::AnswerFeedback(index),
+                    feedback.as_str(),
+                    answer.text_format,
+                ));
+            }
+        }
+        texts
+    }
+}
+
+/// Returns the texts of the combined feedback of a question.
+fn combined_feedback<'a>(
+    correct: &'a str,
+    partially_correct: &'a str,
+    incorrect: &'a str,
+) -> [(TextLocation, &'a str, TextFormat); 3] {
+    [
+        (TextLocation::CorrectFeedback, correct, TextFormat::HTML),
+        (
+            TextLocation::PartiallyCorrectFeedback,
+            partially_correct,
+            TextFormat::HTML,
+        ),
+        (TextLocation::IncorrectFeedback, incorrect, TextFormat::HTML),
+    ]
+}
+
+/// A text of a question.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextLocation {
+    QuestionText,
+    /// An answer, by its index.
+    Answer(usize),
+    /// The feedback of an answer, by the index of the answer.
+    AnswerFeedback(usize),
+    /// A subquestion of a matching question, by its index.
+    SubQuestion(usize),
+    /// The answer of a subquestion of a matching question, by the index of the subquestion.
+    SubQuestionAnswer(usize),
+    /// A choice, drag item or marker of a drag and drop or select missing words question, by its index.
+    Choice(usize),
+    /// The label of a drop zone, by its index.
+    DropZone(usize),
+    /// An item of an ordering question, by its index.
+    Item(usize),
+    /// The answer of a CodeRunner question.
+    CodeRunnerAnswer,
+    /// The answer preload of a CodeRunner question.
+    AnswerPreload,
+    /// The template of a CodeRunner question.
+    Template,
+    /// The code, input, expected output or extra data of a test case, by its index.
+    TestCase(usize),
+    CorrectFeedback,
+    PartiallyCorrectFeedback,
+    IncorrectFeedback,
+    GeneralFeedback,
+    /// A hint, by its index.
+    Hint(usize),
+}
+
+impl TextLocation {
+    /// Returns true for the code of a CodeRunner question, which is not rendered.
+    pub fn is_code(&self) -> bool {
+        matches!(
+            self,
+            TextLocation::CodeRunnerAnswer
+                | TextLocation::AnswerPreload
+                | TextLocation::Template
+                | TextLocation::TestCase(_)
+        )
+    }
+}
+
+impl fmt::Display for TextLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextLocation::QuestionText => f.write_str("question text"),
+            TextLocation::Answer(index) => write!(f, "answer {}", index + 1),
+            TextLocation::AnswerFeedback(index) => write!(f, "feedback of answer {}", index + 1),
+            TextLocation::SubQuestion(index) => write!(f, "subquestion {}", index + 1),
+            TextLocation::SubQuestionAnswer(index) => {
+                write!(f, "answer of subquestion {}", index + 1)
+            }
+            TextLocation::Choice(index) => write!(f, "choice {}", index + 1),
+            TextLocation::DropZone(index) => write!(f, "drop zone {}", index + 1),
+            TextLocation::Item(index) => write!(f, "item {}", index + 1),
+            TextLocation::CodeRunnerAnswer => f.write_str("answer code"),
+            TextLocation::AnswerPreload => f.write_str("answer preload"),
+            TextLocation::Template => f.write_str("template"),
+            TextLocation::TestCase(index) => write!(f, "test case {}", index + 1),
+            TextLocation::CorrectFeedback => f.write_str("correct feedback"),
+            TextLocation::PartiallyCorrectFeedback => f.write_str("partially correct feedback"),
+            TextLocation::IncorrectFeedback => f.write_str("incorrect feedback"),
+            TextLocation::GeneralFeedback => f.write_str("general feedback"),
+            TextLocation::Hint(index) => write!(f, "hint {}", index + 1),
+        }
+    }
+}
+
+impl From<Box<dyn Question>> for QuestionType {
+    fn from(question: Box<dyn Question>) -> Self {
+        QuestionType::Custom(question)
+    }
+}
+
+// Make conversion from a single question to into a vector of questions easier with `.into()`
+macro_rules! impl_from_question {
+    ($(($question_type:ty, $variant:ident)),+) => {
+        $(
+            impl<Q> From<$question_type> for Vec<Q>
+            where
+                Q: Question,
+                $question_type: Into<Q>,
+            {
+                fn from(question: $question_type) -> Self {
+                    vec![question.into()]
+                }
+            }
+
+            impl From<$question_type> for Vec<Box<dyn Question>>
+            where
+                $question_type: Question + 'static,
+            {
+                fn from(question: $question_type) -> Self {
+                    vec![Box::new(question)]
+                }
+            }
+
+            impl From<$question_type> for QuestionType {
+                fn from(question: $question_type) -> Self {
+                    QuestionType::$variant(question)
+                }
+            }
+
+            impl From<$question_type> for Vec<QuestionType> {
+                fn from(question: $question_type) -> Self {
+                    vec![QuestionType::$variant(question)]
+                }
+            }
+        )+
+    };
+}
+
+impl_from_question!(
+    (MultiChoiceQuestion, Multichoice),
+    (TrueFalseQuestion, TrueFalse),
+    (ShortAnswerQuestion, ShortAnswer),
+    (MatchingQuestion, Matching),
+    (EssayQuestion, Essay),
+    (NumericalQuestion, Numerical),
+    (DescriptionQuestion, Description),
+    (CalculatedQuestion, Calculated),
+    (CalculatedSimpleQuestion, CalculatedSimple),
+    (CalculatedMultiQuestion, CalculatedMulti),
+    (DragDropTextQuestion, DragDropText),
+    (GapSelectQuestion, GapSelect),
+    (DragDropImageQuestion, DragDropImage),
+    (DragMarkerQuestion, DragMarker),
+    (RandomShortAnswerMatchQuestion, RandomShortAnswerMatch),
+    (RandomQuestion, Random),
+    (OrderingQuestion, Ordering),
+    (CodeRunnerQuestion, CodeRunner)
+);
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::xml_util::WriteContext;
     use std::io::{Read, Seek};
     use xml::writer::EmitterConfig;
 
     #[test]
     fn test_multichoice_question_xml() {
         let mut tmp_file = tempfile::tempfile().unwrap();
-        let mut writer = EmitterConfig::new()
-            .perform_indent(true)
-            .create_writer(&tmp_file);
+        let mut writer = XmlWriter::new(
+            &tmp_file,
+            EmitterConfig::new().perform_indent(true),
+            WriteContext::default(),
+        );
         let multichoice_question = MultiChoiceQuestion {
             base: QuestionBase {
                 name: "Name of question".to_string(),
@@ -461,24 +3518,32 @@ mod tests {
                 question_text_format: TextFormat::HTML,
                 answers: vec![
                     Answer {
-                        fraction: 100,
+                        fraction: 100.0,
                         text: "The correct answer".to_string(),
                         feedback: "Correct!".to_string().into(),
                         text_format: TextFormat::HTML,
                     },
                     Answer {
-                        fraction: 0,
+                        fraction: 0.0,
                         text: "A distractor".to_string(),
                         feedback: "Ooops!".to_string().into(),
                         text_format: TextFormat::HTML,
                     },
                     Answer {
-                        fraction: 0,
+                        fraction: 0.0,
                         text: "Another distractor".to_string(),
                         feedback: "Ooops!".to_string().into(),
                         text_format: TextFormat::HTML,
                     },
                 ],
+                tags: Vec::new(),
+                files: Vec::new(),
+                idnumber: None,
+                general_feedback: None,
+                default_grade: None,
+                penalty: None,
+                hidden: false,
+                hints: Vec::new(),
             },
             single: true,
             shuffleanswers: true,
@@ -490,6 +3555,7 @@ mod tests {
         multichoice_question.to_xml(&mut writer).unwrap();
 
         let mut buf = String::new();
+        drop(writer);
         tmp_file.seek(std::io::SeekFrom::Start(0)).unwrap();
         tmp_file.read_to_string(&mut buf).unwrap();
         print!("{buf}");
@@ -501,6 +3567,18 @@ mod tests {
   <questiontext format="html">
     <text><![CDATA[What is the answer to this question?]]></text>
   </questiontext>
+  <single>true</single>
+  <shuffleanswers>1</shuffleanswers>
+  <answernumbering>abc</answernumbering>
+  <correctfeedback format="html">
+    <text><![CDATA[Correct!]]></text>
+  </correctfeedback>
+  <partiallycorrectfeedback format="html">
+    <text><![CDATA[Partially correct!]]></text>
+  </partiallycorrectfeedback>
+  <incorrectfeedback format="html">
+    <text><![CDATA[Incorrect!]]></text>
+  </incorrectfeedback>
   <answer fraction="100" format="html">
     <text>The correct answer</text>
     <feedback format="html">
@@ -519,27 +3597,45 @@ mod tests {
       <text>Ooops!</text>
     </feedback>
   </answer>
-  <single>true</single>
-  <shuffleanswers>1</shuffleanswers>
-  <correctfeedback format="html">
-    <text><![CDATA[Correct!]]></text>
-  </correctfeedback>
-  <partiallycorrectfeedback format="html">
-    <text><![CDATA[Partially correct!]]></text>
-  </partiallycorrectfeedback>
-  <incorrectfeedback format="html">
-    <text><![CDATA[Incorrect!]]></text>
-  </incorrectfeedback>
-  <answernumbering>abc</answernumbering>
 </question>"#;
         assert_eq!(expected, buf);
     }
     #[test]
+    fn test_shared_fractions() {
+        let question = |fraction: f64| {
+            let mut question = MultiChoiceQuestion::new(
+                "Thirds".into(),
+                "Which are right?".into(),
+                Some(false),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            let answers = ["a", "b", "c"]
+                .iter()
+                .map(|text| Answer::new(fraction, text.to_string(), None))
+                .collect();
+            question.add_answers(answers).map(|_| question)
+        };
+        assert!(question(33.33333).is_ok());
+        assert!(question(33.3).is_err());
+        let mut buf = Vec::new();
+        let mut writer = XmlWriter::new(&mut buf, EmitterConfig::new(), WriteContext::default());
+        question(100.0 / 3.0).unwrap().to_xml(&mut writer).unwrap();
+        drop(writer);
+        let xml = String::from_utf8(buf).unwrap();
+        assert_eq!(xml.matches(r#"<answer fraction="33.33333""#).count(), 3);
+    }
+    #[test]
     fn test_truefalse_question_xml() {
         let mut tmp_file = tempfile::tempfile().unwrap();
-        let mut writer = EmitterConfig::new()
-            .perform_indent(true)
-            .create_writer(&tmp_file);
+        let mut writer = XmlWriter::new(
+            &tmp_file,
+            EmitterConfig::new().perform_indent(true),
+            WriteContext::default(),
+        );
         let truefalse_question = TrueFalseQuestion {
             base: QuestionBase {
                 name: "Name of question".to_string(),
@@ -547,23 +3643,32 @@ mod tests {
                 question_text_format: TextFormat::HTML,
                 answers: vec![
                     Answer {
-                        fraction: 100,
+                        fraction: 100.0,
                         text: "True".to_string(),
                         feedback: "Correct!".to_string().into(),
                         text_format: TextFormat::HTML,
                     },
                     Answer {
-                        fraction: 0,
+                        fraction: 0.0,
                         text: "False".to_string(),
                         feedback: "Ooops!".to_string().into(),
                         text_format: TextFormat::HTML,
                     },
                 ],
+                tags: Vec::new(),
+                files: Vec::new(),
+                idnumber: None,
+                general_feedback: None,
+                default_grade: None,
+                penalty: None,
+                hidden: false,
+                hints: Vec::new(),
             },
         };
         truefalse_question.to_xml(&mut writer).unwrap();
 
         let mut buf = String::new();
+        drop(writer);
         tmp_file.seek(std::io::SeekFrom::Start(0)).unwrap();
         tmp_file.read_to_string(&mut buf).unwrap();
         let expected = r#"<?xml version="1.0" encoding="utf-8"?>
@@ -589,4 +3694,734 @@ mod tests {
 </question>"#;
         assert_eq!(expected, buf);
     }
+    #[test]
+    fn test_embedded_file_xml() {
+        let mut tmp_file = tempfile::tempfile().unwrap();
+        let mut writer = XmlWriter::new(
+            &tmp_file,
+            EmitterConfig::new().perform_indent(true),
+            WriteContext::default(),
+        );
+        let mut essay_question = EssayQuestion::new(
+            "Map".to_string(),
+            r#"<img src="@@PLUGINFILE@@/map.png">"#.to_string(),
+        );
+        essay_question.add_files(vec![EmbeddedFile::new(
+            "map.png".to_string(),
+            b"Moodle".to_vec(),
+        )]);
+        essay_question.to_xml(&mut writer).unwrap();
+
+        let mut buf = String::new();
+        drop(writer);
+        tmp_file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        tmp_file.read_to_string(&mut buf).unwrap();
+        let expected = r#"<?xml version="1.0" encoding="utf-8"?>
+<question type="essay">
+  <name>
+    <text>Map</text>
+  </name>
+  <questiontext format="html">
+    <text><![CDATA[<img src="@@PLUGINFILE@@/map.png">]]></text>
+    <file name="map.png" path="/" encoding="base64">TW9vZGxl</file>
+  </questiontext>
+</question>"#;
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        let matches =
+            |pattern, text, case_sensitive| Wildcard::new(pattern).matches(text, case_sensitive);
+        assert!(matches("Paris", "paris", false));
+        assert!(!matches("Paris", "paris", true));
+        assert!(matches("*", "", false));
+        assert!(matches("Par*s", "Paris", true));
+        assert!(matches("*ar*", "Paris", true));
+        assert!(!matches("Par*s", "Parist", true));
+        assert!(matches("5 \\* 3", "5 * 3", true));
+        assert!(!matches("5 \\* 3", "5 x 3", true));
+        assert!(!matches("a*a", "a", true));
+    }
+
+    #[test]
+    fn test_shortanswer_matches() {
+        let mut question = ShortAnswerQuestion::new(
+            "Product".to_string(),
+            "What is 5 * 3 written with an asterisk?".to_string(),
+            None,
+        );
+        question
+            .add_answers(vec![
+                Answer::new(100.0, "5 \\* 3".to_string(), None),
+                Answer::new(50.0, "5*3".to_string(), None),
+                Answer::new(0.0, "*".to_string(), None),
+            ])
+            .unwrap();
+        assert_eq!(question.matches(" 5 * 3 ").unwrap().fraction, 100.0);
+        assert_eq!(question.matches("5 x 3").unwrap().fraction, 50.0);
+        assert_eq!(question.matches("53").unwrap().fraction, 50.0);
+        assert_eq!(question.matches("15").unwrap().fraction, 0.0);
+        assert!(question.answer_warnings().is_empty());
+
+        let mut question = ShortAnswerQuestion::new(
+            "Capital".to_string(),
+            "What is the capital of France?".to_string(),
+            Some(false),
+        );
+        question
+            .add_answers(vec![
+                Answer::new(100.0, "Paris".to_string(), None),
+                Answer::new(50.0, "Par*".to_string(), None),
+                Answer::new(0.0, "*".to_string(), None),
+                Answer::new(50.0, "Pariisi".to_string(), None),
+                Answer::new(50.0, "paris".to_string(), None),
+            ])
+            .unwrap();
+        assert!(question.matches("PARIS").is_some());
+        assert_eq!(
+            question.answer_warnings(),
+            [
+                AnswerWarning::Shadowed { answer: 3, by: 1 },
+                AnswerWarning::Duplicate {
+                    answer: 4,
+                    first: 0
+                },
+            ]
+        );
+        question.usecase = true;
+        assert!(question.matches("PARIS").unwrap().text == "*");
+        assert_eq!(
+            question.answer_warnings(),
+            [
+                AnswerWarning::Shadowed { answer: 3, by: 1 },
+                AnswerWarning::Shadowed { answer: 4, by: 2 },
+            ]
+        );
+        assert_eq!(
+            question.answer_warnings()[0].to_string(),
+            "answer 4 is shadowed by answer 2 and never matches"
+        );
+    }
+
+    #[test]
+    fn test_calculated_question_xml() {
+        let mut tmp_file = tempfile::tempfile().unwrap();
+        let mut writer = XmlWriter::new(
+            &tmp_file,
+            EmitterConfig::new().perform_indent(true),
+            WriteContext::default(),
+        );
+        let mut question =
+            CalculatedSimpleQuestion::new("Sum".to_string(), "What is {a} + {b}?".to_string());
+        question
+            .add_calculated_answers(vec![CalculatedAnswer::new(
+                100.0,
+                "{a} + {b}".to_string(),
+                0.01,
+                None,
+            )])
+            .unwrap();
+        let mut a = DatasetDefinition::new("a".to_string(), 1.0, 5.0, 0);
+        a.add_items(vec![2.0]);
+        let mut b = DatasetDefinition::new("b".to_string(), 0.5, 1.5, 1);
+        b.add_items(vec![0.7]);
+        question.add_datasets(vec![a, b]);
+        question.to_xml(&mut writer).unwrap();
+
+        let mut buf = String::new();
+        drop(writer);
+        tmp_file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        tmp_file.read_to_string(&mut buf).unwrap();
+        let expected = r#"<?xml version="1.0" encoding="utf-8"?>
+<question type="calculatedsimple">
+  <name>
+    <text>Sum</text>
+  </name>
+  <questiontext format="html">
+    <text><![CDATA[What is {a} + {b}?]]></text>
+  </questiontext>
+  <synchronize>0</synchronize>
+  <single>0</single>
+  <answernumbering>abc</answernumbering>
+  <shuffleanswers>0</shuffleanswers>
+  <correctfeedback format="html">
+    <text><![CDATA[]]></text>
+  </correctfeedback>
+  <partiallycorrectfeedback format="html">
+    <text><![CDATA[]]></text>
+  </partiallycorrectfeedback>
+  <incorrectfeedback format="html">
+    <text><![CDATA[]]></text>
+  </incorrectfeedback>
+  <answer fraction="100" format="html">
+    <text>{a} + {b}</text>
+    <tolerance>0.01</tolerance>
+    <tolerancetype>1</tolerancetype>
+    <correctanswerformat>1</correctanswerformat>
+    <correctanswerlength>2</correctanswerlength>
+  </answer>
+  <dataset_definitions>
+    <dataset_definition>
+      <status>
+        <text>private</text>
+      </status>
+      <name>
+        <text>a</text>
+      </name>
+      <type>calculated</type>
+      <distribution>
+        <text>uniform</text>
+      </distribution>
+      <minimum>
+        <text>1</text>
+      </minimum>
+      <maximum>
+        <text>5</text>
+      </maximum>
+      <decimals>
+        <text>0</text>
+      </decimals>
+      <itemcount>1</itemcount>
+      <dataset_items>
+        <dataset_item>
+          <number>1</number>
+          <value>2</value>
+        </dataset_item>
+      </dataset_items>
+      <number_of_items>1</number_of_items>
+    </dataset_definition>
+    <dataset_definition>
+      <status>
+        <text>private</text>
+      </status>
+      <name>
+        <text>b</text>
+      </name>
+      <type>calculated</type>
+      <distribution>
+        <text>uniform</text>
+      </distribution>
+      <minimum>
+        <text>0.5</text>
+      </minimum>
+      <maximum>
+        <text>1.5</text>
+      </maximum>
+      <decimals>
+        <text>1</text>
+      </decimals>
+      <itemcount>1</itemcount>
+      <dataset_items>
+        <dataset_item>
+          <number>1</number>
+          <value>0.7</value>
+        </dataset_item>
+      </dataset_items>
+      <number_of_items>1</number_of_items>
+    </dataset_definition>
+  </dataset_definitions>
+</question>"#;
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_calculated_formula_errors() {
+        let tmp_file = tempfile::tempfile().unwrap();
+        let error = |question: &dyn Question| {
+            let mut writer =
+                XmlWriter::new(&tmp_file, EmitterConfig::new(), WriteContext::default());
+            question.to_xml(&mut writer).unwrap_err().to_string()
+        };
+        let mut question = CalculatedQuestion::new("Area".to_string(), "Radius {r}".to_string());
+        question
+            .add_calculated_answers(vec![CalculatedAnswer::new(
+                100.0,
+                "pi() * {r} ^ 2".to_string(),
+                0.01,
+                None,
+            )])
+            .unwrap();
+        let mut r = DatasetDefinition::new("r".to_string(), 0.0, 2.0, 1);
+        r.generate_items(10, &mut Random::new(3)).unwrap();
+        question.add_datasets(vec![r.clone()]);
+        assert!(error(&question).contains("bitwise"));
+
+        let mut question = CalculatedQuestion::new("Area".to_string(), "Radius {r}".to_string());
+        question
+            .add_answers(vec![Answer::new(100.0, "1 / {r} + {h}".to_string(), None)])
+            .unwrap();
+        assert_eq!(error(&question), "Wildcard `{r}` has no dataset definition");
+        question.add_datasets(vec![r]);
+        let mut h = DatasetDefinition::new("h".to_string(), 1.0, 2.0, 1);
+        h.add_items(vec![1.0]);
+        question.add_datasets(vec![h]);
+        assert_eq!(
+            error(&question),
+            "Dataset `h` has 1 items, but the other datasets have 10"
+        );
+        question.generate_items(10, &mut Random::new(3)).unwrap();
+        question
+            .to_xml(&mut XmlWriter::new(
+                &tmp_file,
+                EmitterConfig::new(),
+                WriteContext::default(),
+            ))
+            .unwrap();
+        question.calculated.datasets[0].add_items(vec![0.0]);
+        question.calculated.datasets[1].add_items(vec![1.0]);
+        assert_eq!(
+            error(&question),
+            "Formula `1 / {r} + {h}` fails with item set 11: character 3: division by zero"
+        );
+
+        let mut question =
+            CalculatedMultiQuestion::new("Sum".to_string(), "What is {a} + 1?".to_string(), None);
+        question
+            .add_answers(vec![
+                Answer::new(100.0, "{={a} + 1}".to_string(), None),
+                Answer::new(0.0, "{={a} - }".to_string(), None),
+            ])
+            .unwrap();
+        let mut a = DatasetDefinition::new("a".to_string(), 1.0, 2.0, 0);
+        a.add_items(vec![1.0]);
+        question.add_datasets(vec![a]);
+        assert!(error(&question).starts_with("Formula `{a} - ` is invalid"));
+    }
+
+    #[test]
+    fn test_drag_drop_text_question_xml() {
+        let mut tmp_file = tempfile::tempfile().unwrap();
+        let mut writer = XmlWriter::new(
+            &tmp_file,
+            EmitterConfig::new().perform_indent(true),
+            WriteContext::default(),
+        );
+        let mut question = DragDropTextQuestion::new(
+            "Animals".to_string(),
+            "The [[1]] chases the [[2]].".to_string(),
+        );
+        let mut mouse = DragBox::new("mouse".to_string(), 1);
+        mouse.infinite = true;
+        question.add_choices(vec![DragBox::new("cat".to_string(), 1), mouse]);
+        question.feedback.shownumcorrect = true;
+        question.to_xml(&mut writer).unwrap();
+
+        let mut buf = String::new();
+        drop(writer);
+        tmp_file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        tmp_file.read_to_string(&mut buf).unwrap();
+        let expected = r#"<?xml version="1.0" encoding="utf-8"?>
+<question type="ddwtos">
+  <name>
+    <text>Animals</text>
+  </name>
+  <questiontext format="html">
+    <text><![CDATA[The [[1]] chases the [[2]].]]></text>
+  </questiontext>
+  <shuffleanswers>1</shuffleanswers>
+  <correctfeedback format="html">
+    <text><![CDATA[]]></text>
+  </correctfeedback>
+  <partiallycorrectfeedback format="html">
+    <text><![CDATA[]]></text>
+  </partiallycorrectfeedback>
+  <incorrectfeedback format="html">
+    <text><![CDATA[]]></text>
+  </incorrectfeedback>
+  <shownumcorrect />
+  <dragbox>
+    <text>cat</text>
+    <group>1</group>
+  </dragbox>
+  <dragbox>
+    <text>mouse</text>
+    <group>1</group>
+    <infinite />
+  </dragbox>
+</question>"#;
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_gap_errors() {
+        let tmp_file = tempfile::tempfile().unwrap();
+        let error = |question: &dyn Question| {
+            let mut writer =
+                XmlWriter::new(&tmp_file, EmitterConfig::new(), WriteContext::default());
+            question.to_xml(&mut writer).unwrap_err().to_string()
+        };
+        let mut question =
+            GapSelectQuestion::new("Gaps".to_string(), "Pick [[1]] or [[3]]".to_string());
+        question.add_choices(vec![
+            SelectOption::new("one".to_string(), 1),
+            SelectOption::new("two".to_string(), 2),
+        ]);
+        let message = error(&question);
+        assert!(message.contains("[[3]]"), "{}", message);
+
+        let mut question =
+            DragDropTextQuestion::new("Gaps".to_string(), "[[1]] and [[1]]".to_string());
+        question.add_choices(vec![DragBox::new("one".to_string(), 1)]);
+        let message = error(&question);
+        assert!(message.contains("one"), "{}", message);
+        question.choices[0].infinite = true;
+        question
+            .to_xml(&mut XmlWriter::new(
+                &tmp_file,
+                EmitterConfig::new(),
+                WriteContext::default(),
+            ))
+            .unwrap();
+        question.add_choices(vec![DragBox::new("nine".to_string(), 9)]);
+        assert!(error(&question).contains('9'));
+    }
+
+    /// Returns the signature and header of a PNG image, which is enough for reading its size.
+    fn png(name: &str, width: u32, height: u32) -> EmbeddedFile {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        EmbeddedFile::new(name.to_string(), data)
+    }
+
+    #[test]
+    fn test_drag_marker_question_xml() {
+        let mut tmp_file = tempfile::tempfile().unwrap();
+        let mut writer = XmlWriter::new(
+            &tmp_file,
+            EmitterConfig::new().perform_indent(true),
+            WriteContext::default(),
+        );
+        let mut question =
+            DragMarkerQuestion::new("Heart".to_string(), "Mark the aorta.".to_string());
+        question.set_background(png("heart.png", 2, 1));
+        question.add_markers(vec![Marker::new("Aorta".to_string())]);
+        question.add_zones(vec![MarkerZone::new(
+            MarkerShape::parse("rectangle;0,0;2,1").unwrap(),
+            1,
+        )]);
+        question.showmisplaced = true;
+        question.to_xml(&mut writer).unwrap();
+
+        let mut buf = String::new();
+        drop(writer);
+        tmp_file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        tmp_file.read_to_string(&mut buf).unwrap();
+        let expected = r#"<?xml version="1.0" encoding="utf-8"?>
+<question type="ddmarker">
+  <name>
+    <text>Heart</text>
+  </name>
+  <questiontext format="html">
+    <text><![CDATA[Mark the aorta.]]></text>
+  </questiontext>
+  <showmisplaced />
+  <correctfeedback format="html">
+    <text><![CDATA[]]></text>
+  </correctfeedback>
+  <partiallycorrectfeedback format="html">
+    <text><![CDATA[]]></text>
+  </partiallycorrectfeedback>
+  <incorrectfeedback format="html">
+    <text><![CDATA[]]></text>
+  </incorrectfeedback>
+  <file name="heart.png" path="/" encoding="base64">iVBORw0KGgoAAAANSUhEUgAAAAIAAAAB</file>
+  <drag>
+    <no>1</no>
+    <text>Aorta</text>
+    <noofdrags>1</noofdrags>
+  </drag>
+  <drop>
+    <no>1</no>
+    <shape>rectangle</shape>
+    <coords>0,0;2,1</coords>
+    <choice>1</choice>
+  </drop>
+</question>"#;
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_image_zone_errors() {
+        let tmp_file = tempfile::tempfile().unwrap();
+        let error = |question: &dyn Question| {
+            let mut writer =
+                XmlWriter::new(&tmp_file, EmitterConfig::new(), WriteContext::default());
+            question.to_xml(&mut writer).unwrap_err().to_string()
+        };
+        let mut question = DragDropImageQuestion::new("Bones".to_string(), String::new());
+        question.add_drags(vec![DragItem::new("Femur".to_string(), 1)]);
+        question.add_drops(vec![DropZone::new(1, 100, 50)]);
+        assert!(error(&question).contains("no background image"));
+        question.set_background(EmbeddedFile::new("bones.gif".to_string(), Vec::new()));
+        assert_eq!(
+            error(&question),
+            "Background `bones.gif` is not a PNG or JPEG image"
+        );
+        question.set_background(png("bones.png", 100, 200));
+        assert_eq!(
+            error(&question),
+            "Drop zone 1 at (100, 50) is outside the 100x200 background image"
+        );
+        question.drops[0].x = 99;
+        question
+            .to_xml(&mut XmlWriter::new(
+                &tmp_file,
+                EmitterConfig::new(),
+                WriteContext::default(),
+            ))
+            .unwrap();
+        question.add_drops(vec![DropZone::new(1, 0, 0)]);
+        assert!(error(&question).contains("not infinite"));
+
+        assert_eq!(
+            MarkerShape::parse("polygon;1,2;3,4;5,6")
+                .unwrap()
+                .to_string(),
+            "polygon;1,2;3,4;5,6"
+        );
+        assert!(MarkerShape::parse("circle;1,2").is_none());
+        assert!(MarkerShape::parse("square;1,2;3").is_none());
+        let mut question = DragMarkerQuestion::new("Heart".to_string(), String::new());
+        question.set_background(png("heart.png", 100, 100));
+        question.add_markers(vec![Marker::new("Aorta".to_string())]);
+        question.add_zones(vec![MarkerZone::new(
+            MarkerShape::parse("circle;10,50;20").unwrap(),
+            1,
+        )]);
+        assert_eq!(
+            error(&question),
+            "Drop zone 1 `circle;10,50;20` does not fit on the 100x100 background image"
+        );
+        question.zones[0].shape = MarkerShape::Circle {
+            x: 50,
+            y: 50,
+            radius: 20,
+        };
+        question.add_zones(vec![MarkerZone::new(
+            MarkerShape::parse("polygon;0,0;10,0;10,10").unwrap(),
+            1,
+        )]);
+        assert!(error(&question).contains("placed 1 times"));
+        question.markers[0].count = 2;
+        question
+            .to_xml(&mut XmlWriter::new(
+                &tmp_file,
+                EmitterConfig::new(),
+                WriteContext::default(),
+            ))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_random_question_xml() {
+        let mut tmp_file = tempfile::tempfile().unwrap();
+        let mut writer = XmlWriter::new(
+            &tmp_file,
+            EmitterConfig::new().perform_indent(true),
+            WriteContext::default(),
+        );
+        let mut drill = RandomShortAnswerMatchQuestion::new(
+            "Drill".to_string(),
+            "Match the words.".to_string(),
+            1,
+        );
+        assert!(drill.to_xml(&mut writer).is_err());
+        drill.choose = 3;
+        drill.subcats = false;
+        drill.to_xml(&mut writer).unwrap();
+        let mut random = RandomQuestion::new("Random".to_string(), true);
+        random.set_general_feedback("Drawn from the category.".to_string());
+        random.set_default_grade(2.0);
+        random.set_penalty(0.5);
+        random.set_hidden(true);
+        random.set_idnumber("random-1".to_string());
+        random.to_xml(&mut writer).unwrap();
+
+        let mut buf = String::new();
+        drop(writer);
+        tmp_file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        tmp_file.read_to_string(&mut buf).unwrap();
+        let expected = r#"<?xml version="1.0" encoding="utf-8"?>
+<question type="randomsamatch">
+  <name>
+    <text>Drill</text>
+  </name>
+  <questiontext format="html">
+    <text><![CDATA[Match the words.]]></text>
+  </questiontext>
+  <choose>3</choose>
+  <subcats>0</subcats>
+  <correctfeedback format="html">
+    <text><![CDATA[]]></text>
+  </correctfeedback>
+  <partiallycorrectfeedback format="html">
+    <text><![CDATA[]]></text>
+  </partiallycorrectfeedback>
+  <incorrectfeedback format="html">
+    <text><![CDATA[]]></text>
+  </incorrectfeedback>
+</question>
+<question type="random">
+  <name>
+    <text>Random</text>
+  </name>
+  <questiontext format="html">
+    <text>1</text>
+  </questiontext>
+  <generalfeedback format="html">
+    <text><![CDATA[Drawn from the category.]]></text>
+  </generalfeedback>
+  <defaultgrade>2</defaultgrade>
+  <penalty>0.5</penalty>
+  <hidden>1</hidden>
+  <idnumber>random-1</idnumber>
+</question>"#;
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_ordering_question_xml() {
+        let mut tmp_file = tempfile::tempfile().unwrap();
+        let mut writer = XmlWriter::new(
+            &tmp_file,
+            EmitterConfig::new().perform_indent(true),
+            WriteContext::default(),
+        );
+        let mut question =
+            OrderingQuestion::new("Sort".to_string(), "Order the steps.".to_string());
+        question.add_items(vec!["Split".to_string()]);
+        assert!(question.to_xml(&mut writer).is_err());
+        question.add_items(vec!["Merge".to_string()]);
+        question.select = OrderingSelect::Random;
+        assert_eq!(
+            question.to_xml(&mut writer).unwrap_err().to_string(),
+            "Select count 0 is not between 2 and the number of items, 2"
+        );
+        question.select = OrderingSelect::All;
+        question.layout = OrderingLayout::Horizontal;
+        question.grading = OrderingGrading::AllOrNothing;
+        question.to_xml(&mut writer).unwrap();
+
+        let mut buf = String::new();
+        drop(writer);
+        tmp_file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        tmp_file.read_to_string(&mut buf).unwrap();
+        let expected = r#"<?xml version="1.0" encoding="utf-8"?>
+<question type="ordering">
+  <name>
+    <text>Sort</text>
+  </name>
+  <questiontext format="html">
+    <text><![CDATA[Order the steps.]]></text>
+  </questiontext>
+  <layouttype>HORIZONTAL</layouttype>
+  <selecttype>ALL</selecttype>
+  <selectcount>0</selectcount>
+  <gradingtype>ALL_OR_NOTHING</gradingtype>
+  <correctfeedback format="html">
+    <text><![CDATA[]]></text>
+  </correctfeedback>
+  <partiallycorrectfeedback format="html">
+    <text><![CDATA[]]></text>
+  </partiallycorrectfeedback>
+  <incorrectfeedback format="html">
+    <text><![CDATA[]]></text>
+  </incorrectfeedback>
+  <answer fraction="1" format="html">
+    <text>Split</text>
+  </answer>
+  <answer fraction="2" format="html">
+    <text>Merge</text>
+  </answer>
+</question>"#;
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_coderunner_question_xml() {
+        let mut tmp_file = tempfile::tempfile().unwrap();
+        let mut writer = XmlWriter::new(
+            &tmp_file,
+            EmitterConfig::new().perform_indent(true),
+            WriteContext::default(),
+        );
+        let mut question = CodeRunnerQuestion::new(
+            "Square".to_string(),
+            "Write `sqr(x)`.".to_string(),
+            "python3".to_string(),
+        );
+        assert!(question.to_xml(&mut writer).is_err());
+        let mut test = TestCase::new("print(sqr(-7))".to_string(), "49".to_string());
+        test.useasexample = true;
+        question.add_testcases(vec![test]);
+        question.answer = "def sqr(x): return x * x".to_string();
+        question.penaltyregime = "10, 20".to_string();
+        question.cputimelimitsecs = Some(5);
+        question.precheck = Precheck::Selected;
+        assert_eq!(
+            question.to_xml(&mut writer).unwrap_err().to_string(),
+            "Precheck runs the selected tests, but no test case is selected for it"
+        );
+        question.precheck = Precheck::Examples;
+        question.to_xml(&mut writer).unwrap();
+
+        let mut buf = String::new();
+        drop(writer);
+        tmp_file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        tmp_file.read_to_string(&mut buf).unwrap();
+        let expected = r#"<?xml version="1.0" encoding="utf-8"?>
+<question type="coderunner">
+  <name>
+    <text>Square</text>
+  </name>
+  <questiontext format="html">
+    <text><![CDATA[Write `sqr(x)`.]]></text>
+  </questiontext>
+  <coderunnertype>python3</coderunnertype>
+  <prototypetype>0</prototypetype>
+  <allornothing>1</allornothing>
+  <penaltyregime>10, 20</penaltyregime>
+  <precheck>2</precheck>
+  <answerpreload />
+  <template />
+  <answer>def sqr(x): return x * x</answer>
+  <language />
+  <acelang />
+  <sandbox />
+  <cputimelimitsecs>5</cputimelimitsecs>
+  <memlimitmb />
+  <testcases>
+    <testcase testtype="0" useasexample="1" hiderestiffail="0" mark="1">
+      <testcode>
+        <text>print(sqr(-7))</text>
+      </testcode>
+      <stdin>
+        <text />
+      </stdin>
+      <expected>
+        <text>49</text>
+      </expected>
+      <extra>
+        <text />
+      </extra>
+      <display>
+        <text>SHOW</text>
+      </display>
+    </testcase>
+  </testcases>
+</question>"#;
+        assert_eq!(expected, buf);
+
+        for (regime, valid) in [
+            ("", true),
+            ("10, 20, ...", true),
+            ("33.3%", true),
+            ("ten", false),
+            ("150", false),
+        ] {
+            assert_eq!(check_penalty_regime(regime).is_ok(), valid, "{}", regime);
+        }
+    }
 }
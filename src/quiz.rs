@@ -1,7 +1,14 @@
-use crate::question::QuestionType;
+use crate::{
+    convert::TextConversion,
+    export::{ElementOrder, ExportOptions, QuestionOrder},
+    multilang::MultiLangOptions,
+    question::QuestionType,
+    xml_util::{WriteContext, XmlWriter},
+};
 use std::fs::File;
+use std::io::Write;
 use std::{fmt, ops::Deref};
-use xml::writer::{EmitterConfig, XmlEvent};
+use xml::writer::XmlEvent;
 
 /// Error type for Quiz, Question and Answer struct
 ///
@@ -14,19 +21,40 @@ use xml::writer::{EmitterConfig, XmlEvent};
 /// ```ValueError``` - Error when generating answer with too much points
 /// AnswerFractionError - Error when answer fraction is larger than 100
 /// AnswerCountError - Error when answer count is different than required
+///
+/// ```IoError``` - Error when writing an exported file
 #[derive(Debug)]
 pub enum QuizError {
     XMLWriterError(xml::writer::Error),
+    IoError(std::io::Error),
     EmptyError(String),
     ValueError(String),
     AnswerFractionError(String),
     AnswerCountError(String),
 }
+impl fmt::Display for QuizError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuizError::XMLWriterError(e) => write!(f, "XML writer error: {}", e),
+            QuizError::IoError(e) => write!(f, "IO error: {}", e),
+            QuizError::EmptyError(s)
+            | QuizError::ValueError(s)
+            | QuizError::AnswerFractionError(s)
+            | QuizError::AnswerCountError(s) => f.write_str(s),
+        }
+    }
+}
+impl std::error::Error for QuizError {}
 impl From<xml::writer::Error> for QuizError {
     fn from(e: xml::writer::Error) -> Self {
         QuizError::XMLWriterError(e)
     }
 }
+impl From<std::io::Error> for QuizError {
+    fn from(e: std::io::Error) -> Self {
+        QuizError::IoError(e)
+    }
+}
 impl From<EmptyError> for QuizError {
     fn from(e: EmptyError) -> Self {
         QuizError::EmptyError(e.to_string())
@@ -58,9 +86,26 @@ impl fmt::Display for ValueError {
 
 /// A category for the quiz, can be used to categorize questions.
 /// A string type is used internally to represent the category.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Category(String);
 
+impl Category {
+    /// Returns the full category path written to the XML.
+    /// The category is placed under `$course$/` unless it already starts with a context such as `$system$/`.
+    pub fn path(&self) -> String {
+        if self.0.starts_with('$') {
+            [self.0.as_str(), "/"].concat()
+        } else {
+            ["$course$/", self.0.as_str(), "/"].concat()
+        }
+    }
+    /// Parses a category from its full path, removing the `$course$/` context and the trailing slash.
+    pub fn from_path(path: &str) -> Self {
+        let path = path.trim().trim_end_matches('/');
+        Category(path.strip_prefix("$course$/").unwrap_or(path).to_string())
+    }
+}
+
 impl Deref for Category {
     type Target = String;
 
@@ -84,11 +129,27 @@ impl From<Category> for Vec<Category> {
     }
 }
 
+/// When the texts of a quiz are written as CDATA sections instead of escaped characters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CdataPolicy {
+    /// Every text is a CDATA section.
+    Always,
+    /// No text is a CDATA section.
+    Never,
+    /// Texts with markup, `<`, `>` or `&`, are CDATA sections.
+    Markup,
+}
+
 /// A quiz struct that contains a vector of questions and optional categories.
 pub struct Quiz {
     /// A vector of questions, can be any type of a question
     questions: Vec<QuestionType>,
     categories: Option<Vec<Category>>,
+    /// Questions imported into a specific category, written after `questions`.
+    sections: Vec<(Category, Vec<QuestionType>)>,
+    /// Options applied to the texts when the quiz is written.
+    context: WriteContext,
+    export: ExportOptions,
 }
 impl Quiz {
     /// Creates a new quiz instance with the specified moodle categories and questions.
@@ -99,12 +160,126 @@ impl Quiz {
         Self {
             questions,
             categories: None,
+            sections: Vec::new(),
+            context: WriteContext::default(),
+            export: ExportOptions::default(),
         }
     }
     /// Adds categories to the quiz.
     pub fn set_categories(&mut self, categories: Vec<Category>) {
         self.categories = Some(categories);
     }
+    /// Sets the syntax of the multilang blocks written and the languages every translated text must have.
+    /// See [`multilang`](crate::multilang).
+    pub fn set_multilang(&mut self, options: MultiLangOptions) {
+        self.context.multilang = Some(options);
+    }
+    /// Converts the texts of the questions to one format when the quiz is written.
+    /// See [`convert`](crate::convert).
+    pub fn set_text_conversion(&mut self, conversion: TextConversion) {
+        self.context.text_conversion = Some(conversion);
+    }
+    /// Converts `$ … $` math to `\( … \)` when the quiz is written, if `enabled`. See [`math`](crate::math).
+    pub fn set_mathjax_delimiters(&mut self, enabled: bool) {
+        self.context.mathjax_delimiters = enabled;
+    }
+    /// Removes scripts and event handlers from the HTML texts when the quiz is written, if `enabled`.
+    /// See [`html::sanitize`](crate::html::sanitize).
+    pub fn set_sanitize_html(&mut self, enabled: bool) {
+        self.context.sanitize_html = enabled;
+    }
+    /// Writes the texts of every field as CDATA sections according to `policy`. By default the question text and
+    /// the combined feedback are CDATA sections and other texts are escaped.
+    pub fn set_cdata(&mut self, policy: CdataPolicy) {
+        self.context.cdata = Some(policy);
+    }
+    /// Sets the indentation, declaration, line endings, element order and question order of the XML written.
+    /// See [`export`](crate::export).
+    pub fn set_export_options(&mut self, options: ExportOptions) {
+        self.export = options;
+    }
+    /// Adds questions which are imported into `category`, in addition to the questions given in [`Quiz::new`].
+    pub fn add_category(&mut self, category: Category, questions: Vec<QuestionType>) {
+        self.sections.push((category, questions));
+    }
+    /// Moves all questions and category markers of `other` to the end of the quiz, keeping their categories and
+    /// order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ValueError` if `other` has questions without a category and the quiz already has a category
+    /// marker, as every question after a marker is imported into its category. `self` is not changed then.
+    pub fn append(&mut self, other: Quiz) -> Result<(), QuizError> {
+        let Quiz {
+            questions,
+            categories,
+            sections,
+            ..
+        } = other;
+        // The questions of `other` belong to its last category, the markers of the others are kept as well
+        match categories.and_then(|mut categories| categories.pop().map(|last| (categories, last)))
+        {
+            Some((earlier, last)) => {
+                for category in earlier {
+                    self.push_section(category, Vec::new());
+                }
+                self.push_section(last, questions);
+            }
+            None if questions.is_empty() => {}
+            None => {
+                let current = self
+                    .sections
+                    .last()
+                    .map(|(category, _)| category)
+                    .or_else(|| self.categories.as_ref().and_then(|c| c.last()));
+                if let Some(category) = current {
+                    return Err(QuizError::ValueError(format!(
+                        "Questions without a category cannot follow the questions of category `{}`, they would \
+                         be imported into it",
+                        **category
+                    )));
+                }
+                self.questions.extend(questions);
+            }
+        }
+        for (category, questions) in sections {
+            self.push_section(category, questions);
+        }
+        Ok(())
+    }
+    /// Adds questions into `category`, continuing the last section if it has the same category.
+    fn push_section(&mut self, category: Category, questions: Vec<QuestionType>) {
+        match self.sections.last_mut() {
+            Some((last, existing)) if *last == category => existing.extend(questions),
+            _ => self.sections.push((category, questions)),
+        }
+    }
+    /// Returns all questions of the quiz in the order they are written, with the category they are imported into.
+    /// The questions given in [`Quiz::new`] belong to the last category set with [`Quiz::set_categories`], if any.
+    pub fn questions(&self) -> impl Iterator<Item = (Option<&Category>, &QuestionType)> {
+        let default_category = self.categories.as_ref().and_then(|c| c.last());
+        self.questions
+            .iter()
+            .map(move |question| (default_category, question))
+            .chain(self.sections.iter().flat_map(|(category, questions)| {
+                questions
+                    .iter()
+                    .map(move |question| (Some(category), question))
+            }))
+    }
+    /// Returns the number of questions in the quiz.
+    pub fn len(&self) -> usize {
+        self.questions.len()
+            + self
+                .sections
+                .iter()
+                .map(|(_, questions)| questions.len())
+                .sum::<usize>()
+    }
+    /// Returns true if the quiz has no questions.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     /// Creates an XML file from quiz object, containing question and answer objects.
     ///
     /// # Arguments
@@ -114,38 +289,173 @@ impl Quiz {
     /// # Errors
     ///
     /// Returns an QuizError if the problem occurs during writing the XML file or requirements are not met.
-
     pub fn to_xml(&mut self, filename: &str) -> Result<(), QuizError> {
-        if self.questions.is_empty() {
+        if self.is_empty() {
             return Err(EmptyError.into());
         }
-        let output: File = File::create(filename)
-            .unwrap_or_else(|e| panic!("Bad file path: {} More: {}", filename, e));
-        let mut writer = EmitterConfig::new()
-            .perform_indent(true)
-            .create_writer(&output);
-
+        self.export.check()?;
+        let output: File = File::create(filename)?;
+        let config = self.export.emitter_config();
+        let mut writer = XmlWriter::new(&output, config, self.context.clone());
+        self.export.start_document(&mut writer)?;
+        self.write_questions(&mut writer)?;
+        writer.flush()
+    }
+    /// Starts writing the quiz into `output`, such as a file or the standard output, and returns a [`QuizWriter`], which writes more questions and
+    /// categories one at a time with the options of the quiz. The questions of the quiz are written first, and
+    /// are not kept in memory afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ValueError` if the export options sort the questions, which needs the whole quiz, or an error if
+    /// a question of the quiz cannot be written.
+    pub fn into_writer<'a>(self, output: impl Write + 'a) -> Result<QuizWriter<'a>, QuizError> {
+        self.export.check()?;
+        if self.export.question_order != QuestionOrder::Insertion {
+            return Err(QuizError::ValueError(
+                "Streamed questions are written in the order they are given, sorting needs Quiz::to_xml"
+                    .to_string(),
+            ));
+        }
+        let config = self.export.emitter_config();
+        let mut writer = XmlWriter::new(output, config, self.context.clone());
+        self.export.start_document(&mut writer)?;
         writer.write(XmlEvent::start_element("quiz"))?;
+        self.write_contents(&mut writer)?;
+        Ok(QuizWriter {
+            writer,
+            element_order: self.export.element_order,
+            written: self.len(),
+        })
+    }
+    fn write_questions(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+        writer.write(XmlEvent::start_element("quiz"))?;
+        self.write_contents(writer)?;
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+    /// Writes the categories and questions inside the `<quiz>` element.
+    fn write_contents(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
         if let Some(categories) = self.categories.as_ref() {
             for category in categories {
-                writer.write(XmlEvent::start_element("question").attr("type", "category"))?;
-                writer.write(XmlEvent::start_element("category"))?;
-                writer.write(XmlEvent::start_element("text"))?;
-                let string = ["$course$/", category.as_str(), "/"].concat();
-                writer.write(XmlEvent::characters(string.as_str()))?;
-                writer.write(XmlEvent::end_element())?;
-                writer.write(XmlEvent::end_element())?;
-                writer.write(XmlEvent::end_element())?;
+                write_category(writer, category)?;
+            }
+        }
+        let (elements, order) = (self.export.element_order, self.export.question_order);
+        for question in order.sort(&self.questions) {
+            elements.write(question, writer)?;
+        }
+        for (category, questions) in &self.sections {
+            write_category(writer, category)?;
+            for question in order.sort(questions) {
+                elements.write(question, writer)?;
             }
         }
+        Ok(())
+    }
+}
 
-        if self.questions.is_empty() {
-            return Err(EmptyError.into());
+/// A category marker or a question, in the order they are in a quiz.
+pub enum QuizItem {
+    /// The following questions are imported into this category.
+    Category(Category),
+    Question(Box<QuestionType>),
+}
+impl From<Category> for QuizItem {
+    fn from(category: Category) -> Self {
+        QuizItem::Category(category)
+    }
+}
+impl From<QuestionType> for QuizItem {
+    fn from(question: QuestionType) -> Self {
+        QuizItem::Question(Box::new(question))
+    }
+}
+
+/// Writes a quiz one question at a time, so that quizzes of any size are written with bounded memory.
+///
+/// The writer is created with [`QuizWriter::new`], or with [`Quiz::into_writer`] to use the options of a quiz,
+/// and the `<quiz>` element is closed by [`QuizWriter::finish`], which also flushes the buffered output. A file
+/// whose writer is dropped without finishing it, or in which a question cannot be written, is left incomplete.
+///
+/// ```
+/// use moodle_xml::{prelude::*, quiz::QuizWriter};
+///
+/// let file = tempfile::tempfile().unwrap();
+/// let mut writer = QuizWriter::new(&file).unwrap();
+/// writer.write_category(&"Essays".into()).unwrap();
+/// writer
+///     .write_all((1..=3).map(|n| {
+///         QuestionType::from(EssayQuestion::new(format!("Essay {}", n), format!("Describe city {}.", n)))
+///     }))
+///     .unwrap();
+/// assert_eq!(writer.finish().unwrap(), 3);
+/// ```
+pub struct QuizWriter<'a> {
+    writer: XmlWriter<'a>,
+    element_order: ElementOrder,
+    written: usize,
+}
+impl<'a> QuizWriter<'a> {
+    /// Starts writing a quiz with the default options into `output`.
+    pub fn new(output: impl Write + 'a) -> Result<Self, QuizError> {
+        Quiz::new(Vec::new()).into_writer(output)
+    }
+    /// Writes a category marker, the questions written after it are imported into `category`.
+    pub fn write_category(&mut self, category: &Category) -> Result<(), QuizError> {
+        write_category(&mut self.writer, category)
+    }
+    /// Writes a question.
+    pub fn write_question(&mut self, question: &QuestionType) -> Result<(), QuizError> {
+        self.element_order.write(question, &mut self.writer)?;
+        self.written += 1;
+        Ok(())
+    }
+    /// Writes a category marker or a question.
+    pub fn write_item(&mut self, item: &QuizItem) -> Result<(), QuizError> {
+        match item {
+            QuizItem::Category(category) => self.write_category(category),
+            QuizItem::Question(question) => self.write_question(question),
         }
-        for question in &self.questions {
-            question.to_xml(&mut writer)?;
+    }
+    /// Writes the questions or category markers of an iterator, one at a time.
+    pub fn write_all<I>(&mut self, items: I) -> Result<(), QuizError>
+    where
+        I: IntoIterator,
+        I::Item: Into<QuizItem>,
+    {
+        for item in items {
+            self.write_item(&item.into())?;
         }
-        writer.write(XmlEvent::end_element())?;
         Ok(())
     }
+    /// Returns the number of questions written so far.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+    /// Closes the `<quiz>` element and returns the number of questions written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `EmptyError` if no question was written, like [`Quiz::to_xml`].
+    pub fn finish(mut self) -> Result<usize, QuizError> {
+        if self.written == 0 {
+            return Err(EmptyError.into());
+        }
+        self.writer.write(XmlEvent::end_element())?;
+        self.writer.flush()?;
+        Ok(self.written)
+    }
+}
+
+/// Writes a category marker, all following questions are imported into the category.
+fn write_category(writer: &mut XmlWriter, category: &Category) -> Result<(), QuizError> {
+    writer.write(XmlEvent::start_element("question").attr("type", "category"))?;
+    writer.write(XmlEvent::start_element("category"))?;
+    writer.write(XmlEvent::start_element("text"))?;
+    writer.write(XmlEvent::characters(category.path().as_str()))?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
 }
@@ -0,0 +1,1098 @@
+//! Reading Moodle XML back into a [`Quiz`].
+//!
+//! All question types of the crate are supported. Category markers become the categories of the questions
+//! following them. Answer fractions are read exactly, such as the `33.33333` Moodle writes for a third. Large
+//! files can be read one question at a time with [`QuestionReader`].
+//!
+//! ```
+//! use moodle_xml::reader;
+//!
+//! let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+//! <quiz>
+//!   <question type="category">
+//!     <category><text>$course$/Capitals/</text></category>
+//!   </question>
+//!   <question type="essay">
+//!     <name><text>Essay</text></name>
+//!     <questiontext format="html"><text><![CDATA[Describe <b>Helsinki</b>.]]></text></questiontext>
+//!   </question>
+//! </quiz>"#;
+//! let quiz = reader::parse(source).unwrap();
+//! let (category, question) = quiz.questions().next().unwrap();
+//! assert_eq!(category.unwrap().as_str(), "Capitals");
+//! assert_eq!(question.as_question().get_description(), "Describe <b>Helsinki</b>.");
+//! ```
+use crate::{
+    answer::{Answer, CalculatedAnswer, CorrectAnswerFormat, NumericalAnswer, ToleranceType},
+    dataset::{DatasetDefinition, Distribution},
+    file::EmbeddedFile,
+    question::{
+        CalculatedMultiQuestion, CalculatedQuestion, CalculatedSimpleQuestion, CodeRunnerQuestion,
+        CombinedFeedback, DescriptionQuestion, DragBox, DragDropImageQuestion,
+        DragDropTextQuestion, DragItem, DragMarkerQuestion, DropZone, EssayQuestion,
+        GapSelectQuestion, Hint, Marker, MarkerShape, MarkerZone, MatchingQuestion,
+        MultiChoiceQuestion, NumericalQuestion, NumericalUnit, OrderingGrading, OrderingLayout,
+        OrderingQuestion, OrderingSelect, Precheck, Question, QuestionType, RandomQuestion,
+        RandomShortAnswerMatchQuestion, SelectOption, ShortAnswerQuestion, SubQuestion, TestCase,
+        TestDisplay, TestType, TextFormat, TrueFalseQuestion,
+    },
+    quiz::{Category, Quiz, QuizItem},
+};
+use std::{fmt, io::Read};
+use xml::{
+    common::Position,
+    reader::{EventReader, ParserConfig, XmlEvent},
+};
+
+/// Error when reading Moodle XML, `line` is the 1-based line of the problem.
+#[derive(Debug)]
+pub struct ReadError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl std::error::Error for ReadError {}
+
+/// An XML element with its text and child elements.
+struct Element {
+    name: String,
+    line: usize,
+    attributes: Vec<(String, String)>,
+    text: String,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+    /// Returns the `<text>` of the child element `name`, such as `<name><text>...</text></name>`.
+    fn text_of(&self, name: &str) -> Option<&str> {
+        self.child(name)?
+            .child("text")
+            .map(|text| text.text.as_str())
+    }
+    /// Returns the trimmed text of the child element `name` without a `<text>` wrapper, such as
+    /// `<single>true</single>`.
+    fn value_of(&self, name: &str) -> Option<&str> {
+        self.raw_value_of(name).map(str::trim)
+    }
+    /// Returns the text of the child element `name` without a `<text>` wrapper as it is, such as the code in
+    /// `<answer>` of a CodeRunner question.
+    fn raw_value_of(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.as_str())
+    }
+    fn flag(&self, name: &str) -> Option<bool> {
+        self.value_of(name)
+            .map(|value| matches!(value, "1" | "true"))
+    }
+    fn error(&self, message: impl Into<String>) -> ReadError {
+        ReadError {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+}
+
+/// Parses Moodle XML into a quiz.
+///
+/// # Errors
+///
+/// Returns a [`ReadError`] if the XML is malformed or a question cannot be created.
+pub fn parse(source: &str) -> Result<Quiz, ReadError> {
+    parse_reader(source.as_bytes())
+}
+
+/// Parses Moodle XML from a reader, such as a file, into a quiz.
+///
+/// # Errors
+///
+/// Returns a [`ReadError`] if the XML is malformed or a question cannot be created.
+pub fn parse_reader<R: Read>(reader: R) -> Result<Quiz, ReadError> {
+    let mut leading = Vec::new();
+    let mut sections: Vec<(Category, Vec<QuestionType>)> = Vec::new();
+    for item in QuestionReader::new(reader) {
+        match item? {
+            QuizItem::Category(category) => sections.push((category, Vec::new())),
+            QuizItem::Question(question) => match sections.last_mut() {
+                Some((_, questions)) => questions.push(*question),
+                None => leading.push(*question),
+            },
+        }
+    }
+    let mut quiz = Quiz::new(leading);
+    for (category, questions) in sections {
+        quiz.add_category(category, questions);
+    }
+    Ok(quiz)
+}
+
+/// Reads the category markers and questions of Moodle XML lazily, one at a time, so that files of any size are
+/// read with bounded memory. Reading stops at the first error.
+///
+/// ```
+/// use moodle_xml::{quiz::QuizItem, reader::QuestionReader};
+///
+/// let source = r#"<quiz>
+///   <question type="category"><category><text>$course$/Cities/</text></category></question>
+///   <question type="essay"><name><text>Oulu</text></name><questiontext><text>Describe Oulu.</text></questiontext></question>
+/// </quiz>"#;
+/// let mut reader = QuestionReader::new(source.as_bytes());
+/// assert!(matches!(reader.next(), Some(Ok(QuizItem::Category(_)))));
+/// match reader.next() {
+///     Some(Ok(QuizItem::Question(question))) => assert_eq!(question.as_question().get_name(), "Oulu"),
+///     _ => panic!("expected a question"),
+/// }
+/// assert!(reader.next().is_none());
+/// ```
+pub struct QuestionReader<R: Read> {
+    events: EventReader<R>,
+    /// The elements being read, from `<quiz>` to the innermost.
+    stack: Vec<Element>,
+    found_root: bool,
+    done: bool,
+}
+
+impl<R: Read> QuestionReader<R> {
+    /// Reads Moodle XML from `reader`, which should be buffered, such as a `BufReader` of a file.
+    pub fn new(reader: R) -> Self {
+        // Whitespace is kept, as it matters in texts such as the code of CodeRunner questions. Names, keywords
+        // and numbers are trimmed where they are read.
+        let config = ParserConfig::new()
+            .whitespace_to_characters(true)
+            .cdata_to_characters(true);
+        Self {
+            events: EventReader::new_with_config(reader, config),
+            stack: Vec::new(),
+            found_root: false,
+            done: false,
+        }
+    }
+    /// Reads events until the next category marker or question is complete, or the document ends.
+    fn read_item(&mut self) -> Result<Option<QuizItem>, ReadError> {
+        loop {
+            let event = self.events.next().map_err(|e| ReadError {
+                line: e.position().row as usize + 1,
+                message: e.msg().to_string(),
+            })?;
+            let line = self.events.position().row as usize + 1;
+            match event {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    if self.stack.is_empty() && !self.found_root {
+                        if name.local_name != "quiz" {
+                            return Err(ReadError {
+                                line,
+                                message: format!("expected <quiz>, found <{}>", name.local_name),
+                            });
+                        }
+                        self.found_root = true;
+                    }
+                    self.stack.push(Element {
+                        name: name.local_name,
+                        line,
+                        attributes: attributes
+                            .into_iter()
+                            .map(|a| (a.name.local_name, a.value))
+                            .collect(),
+                        text: String::new(),
+                        children: Vec::new(),
+                    });
+                }
+                // The text of <quiz> is the whitespace between the questions, which is not kept so that the
+                // memory used does not grow with the file
+                XmlEvent::Characters(text) if self.stack.len() > 1 => {
+                    self.stack.last_mut().unwrap().text.push_str(&text);
+                }
+                XmlEvent::EndElement { .. } => {
+                    // The parser only reports end tags which match a start tag
+                    let Some(element) = self.stack.pop() else {
+                        continue;
+                    };
+                    match self.stack.len() {
+                        // Every direct child of <quiz> is a question
+                        1 if element.name == "question" => {
+                            return read_question(&element).map(Some)
+                        }
+                        1 => {
+                            return Err(element
+                                .error(format!("expected <question>, found <{}>", element.name)))
+                        }
+                        0 => {}
+                        _ => self.stack.last_mut().unwrap().children.push(element),
+                    }
+                }
+                XmlEvent::EndDocument => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for QuestionReader<R> {
+    type Item = Result<QuizItem, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.read_item().transpose();
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+fn read_format(element: &Element) -> Result<TextFormat, ReadError> {
+    match element.attr("format") {
+        None => Ok(TextFormat::default()),
+        Some(name) => TextFormat::from_name(name)
+            .ok_or_else(|| element.error(format!("unknown text format `{}`", name))),
+    }
+}
+
+fn read_answer(element: &Element) -> Result<Answer, ReadError> {
+    let fraction: f64 = element
+        .attr("fraction")
+        .unwrap_or("0")
+        .trim()
+        .parse()
+        .ok()
+        .filter(|fraction: &f64| fraction.is_finite())
+        .ok_or_else(|| element.error("answer fraction is not a number"))?;
+    if !(-100.0..=100.0).contains(&fraction) {
+        return Err(element.error(format!(
+            "answer fraction {} is not between -100 and 100",
+            fraction
+        )));
+    }
+    let text = element
+        .child("text")
+        .map(|text| text.text.clone())
+        .unwrap_or_default();
+    let feedback = element.text_of("feedback").map(str::to_string);
+    let mut answer = Answer::new(fraction, text, feedback);
+    answer.set_text_format(read_format(element)?);
+    Ok(answer)
+}
+
+fn read_file(element: &Element) -> Result<EmbeddedFile, ReadError> {
+    let name = element
+        .attr("name")
+        .ok_or_else(|| element.error("file has no name"))?;
+    if element.attr("encoding").is_some_and(|e| e != "base64") {
+        return Err(element.error("only base64 encoded files are supported"));
+    }
+    EmbeddedFile::from_base64(name.to_string(), element.text.trim())
+        .ok_or_else(|| element.error(format!("file `{}` is not valid base64", name)))
+}
+
+/// Reads the units of a numerical or calculated question and the unit penalty, `default_penalty` if not given.
+fn read_units(
+    element: &Element,
+    default_penalty: f64,
+) -> Result<(Vec<NumericalUnit>, f64), ReadError> {
+    let units = match element.child("units") {
+        Some(units) => units
+            .children("unit")
+            .map(|unit| {
+                let multiplier = unit
+                    .value_of("multiplier")
+                    .unwrap_or("1")
+                    .parse()
+                    .map_err(|_| unit.error("unit multiplier is not a number"))?;
+                let name = unit.value_of("unit_name").unwrap_or_default();
+                Ok(NumericalUnit::new(name.to_string(), multiplier))
+            })
+            .collect::<Result<Vec<_>, ReadError>>()?,
+        None => Vec::new(),
+    };
+    // Unit grading type 0 means that the units are not graded
+    let unit_penalty = match element.value_of("unitgradingtype") {
+        Some("0") => 0.0,
+        _ => element
+            .value_of("unitpenalty")
+            .map(|penalty| penalty.parse())
+            .transpose()
+            .map_err(|_| element.error("unit penalty is not a number"))?
+            .unwrap_or(default_penalty),
+    };
+    Ok((units, unit_penalty))
+}
+
+/// Parses the text of the child element `name` as a number, `default` if there is no such element.
+fn number_of<T: std::str::FromStr>(
+    element: &Element,
+    name: &str,
+    default: T,
+) -> Result<T, ReadError> {
+    // Dataset definitions wrap their values in <text>, answers do not
+    match element.text_of(name).or_else(|| element.value_of(name)) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| element.error(format!("<{}> is not a number", name))),
+        None => Ok(default),
+    }
+}
+
+fn read_calculated_answers(element: &Element) -> Result<Vec<CalculatedAnswer>, ReadError> {
+    element
+        .children("answer")
+        .map(|answer| {
+            let mut calculated = CalculatedAnswer::from(read_answer(answer)?);
+            calculated.tolerance = number_of(answer, "tolerance", calculated.tolerance)?;
+            let code = number_of(answer, "tolerancetype", 1)?;
+            calculated.tolerance_type = ToleranceType::from_code(code)
+                .ok_or_else(|| answer.error(format!("unknown tolerance type {}", code)))?;
+            let code = number_of(answer, "correctanswerformat", 1)?;
+            calculated.correct_answer_format = CorrectAnswerFormat::from_code(code)
+                .ok_or_else(|| answer.error(format!("unknown correct answer format {}", code)))?;
+            calculated.correct_answer_length = number_of(answer, "correctanswerlength", 2)?;
+            Ok(calculated)
+        })
+        .collect()
+}
+
+fn read_datasets(element: &Element) -> Result<Vec<DatasetDefinition>, ReadError> {
+    let Some(definitions) = element.child("dataset_definitions") else {
+        return Ok(Vec::new());
+    };
+    definitions
+        .children("dataset_definition")
+        .map(|definition| {
+            let name = definition
+                .text_of("name")
+                .map(str::trim)
+                .ok_or_else(|| definition.error("dataset definition has no name"))?;
+            let mut dataset = DatasetDefinition::new(
+                name.to_string(),
+                number_of(definition, "minimum", 0.0)?,
+                number_of(definition, "maximum", 0.0)?,
+                number_of(definition, "decimals", 0)?,
+            );
+            dataset.shared = definition.text_of("status").map(str::trim) == Some("shared");
+            if let Some(distribution) = definition.text_of("distribution").map(str::trim) {
+                dataset.distribution = Distribution::from_name(distribution).ok_or_else(|| {
+                    definition.error(format!("unknown distribution `{}`", distribution))
+                })?;
+            }
+            if let Some(items) = definition.child("dataset_items") {
+                let mut items = items
+                    .children("dataset_item")
+                    .map(|item| {
+                        Ok((
+                            number_of(item, "number", 0usize)?,
+                            number_of(item, "value", 0.0)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, ReadError>>()?;
+                items.sort_by_key(|(number, _)| *number);
+                dataset.add_items(items.into_iter().map(|(_, value)| value).collect());
+            }
+            Ok(dataset)
+        })
+        .collect()
+}
+
+fn read_combined_feedback(element: &Element) -> CombinedFeedback {
+    let feedback = |name| element.text_of(name).unwrap_or_default().to_string();
+    CombinedFeedback {
+        correctfeedback: feedback("correctfeedback"),
+        partiallycorrectfeedback: feedback("partiallycorrectfeedback"),
+        incorrectfeedback: feedback("incorrectfeedback"),
+        shownumcorrect: element.child("shownumcorrect").is_some(),
+    }
+}
+
+/// Reads the choices of a gap question, `<dragbox>` or `<selectoption>` elements, as text, group and
+/// whether `<infinite/>` is present.
+fn read_gap_choices(element: &Element, name: &str) -> Result<Vec<(String, u8, bool)>, ReadError> {
+    element
+        .children(name)
+        .map(|choice| {
+            Ok((
+                choice
+                    .child("text")
+                    .map(|text| text.text.clone())
+                    .unwrap_or_default(),
+                number_of(choice, "group", 1)?,
+                choice.child("infinite").is_some(),
+            ))
+        })
+        .collect()
+}
+
+fn read_testcase(element: &Element) -> Result<TestCase, ReadError> {
+    let text = |name: &str| element.text_of(name).unwrap_or_default().to_string();
+    let mut testcase = TestCase::new(text("testcode"), text("expected"));
+    testcase.stdin = text("stdin");
+    testcase.extra = text("extra");
+    if let Some(display) = element.text_of("display").map(str::trim) {
+        testcase.display = TestDisplay::from_name(display)
+            .ok_or_else(|| element.error(format!("unknown test display `{}`", display)))?;
+    }
+    let attribute = |name: &str| element.attr(name).map(str::trim);
+    testcase.useasexample = attribute("useasexample") == Some("1");
+    testcase.hiderestiffail = attribute("hiderestiffail") == Some("1");
+    if let Some(mark) = attribute("mark") {
+        testcase.mark = mark
+            .parse()
+            .map_err(|_| element.error("test case mark is not a number"))?;
+    }
+    if let Some(code) = attribute("testtype") {
+        testcase.testtype = code
+            .parse()
+            .ok()
+            .and_then(TestType::from_code)
+            .ok_or_else(|| element.error(format!("unknown test type `{}`", code)))?;
+    }
+    Ok(testcase)
+}
+
+fn read_hint(element: &Element) -> Result<Hint, ReadError> {
+    let text = element
+        .child("text")
+        .map(|text| text.text.clone())
+        .unwrap_or_default();
+    let mut hint = Hint::new(text);
+    hint.text_format = read_format(element)?;
+    hint.shownumcorrect = element.child("shownumcorrect").is_some();
+    hint.clearwrong = element.child("clearwrong").is_some();
+    hint.options = element.value_of("options").map(str::to_string);
+    Ok(hint)
+}
+
+/// Reads a flag which is either an empty element, such as `<shuffleanswers/>`, or has a value.
+fn empty_flag(element: &Element, name: &str) -> bool {
+    element
+        .value_of(name)
+        .is_some_and(|value| value.is_empty() || matches!(value, "1" | "true"))
+}
+
+/// Returns the `<drag>` or `<drop>` children of a question in the order of their `<no>`.
+fn numbered<'a>(element: &'a Element, name: &'a str) -> Result<Vec<&'a Element>, ReadError> {
+    let mut children = element
+        .children(name)
+        .map(|child| Ok((number_of(child, "no", 0usize)?, child)))
+        .collect::<Result<Vec<_>, ReadError>>()?;
+    children.sort_by_key(|(number, _)| *number);
+    Ok(children.into_iter().map(|(_, child)| child).collect())
+}
+
+/// A `<question>` element is either a category marker or a question.
+fn read_question(element: &Element) -> Result<QuizItem, ReadError> {
+    let question_type = element
+        .attr("type")
+        .ok_or_else(|| element.error("question has no type"))?;
+    if question_type == "category" {
+        let path = element
+            .text_of("category")
+            .ok_or_else(|| element.error("category has no <category><text>"))?;
+        return Ok(QuizItem::Category(Category::from_path(path)));
+    }
+    let name = element
+        .text_of("name")
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let text = element
+        .text_of("questiontext")
+        .unwrap_or_default()
+        .to_string();
+    let answers = || {
+        element
+            .children("answer")
+            .map(read_answer)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let mut question: QuestionType = match question_type {
+        "multichoice" => {
+            let mut question = MultiChoiceQuestion::new(
+                name,
+                text,
+                element.flag("single"),
+                element.flag("shuffleanswers"),
+                element.text_of("correctfeedback").map(str::to_string),
+                element
+                    .text_of("partiallycorrectfeedback")
+                    .map(str::to_string),
+                element.text_of("incorrectfeedback").map(str::to_string),
+                element.value_of("answernumbering").map(str::to_string),
+            );
+            question
+                .add_answers(answers()?)
+                .map_err(|e| element.error(e.to_string()))?;
+            question.into()
+        }
+        "truefalse" => {
+            let mut question = TrueFalseQuestion::new(name, text);
+            question
+                .add_answers(answers()?)
+                .map_err(|e| element.error(e.to_string()))?;
+            question.into()
+        }
+        "shortanswer" => {
+            let mut question = ShortAnswerQuestion::new(name, text, element.flag("usecase"));
+            question
+                .add_answers(answers()?)
+                .map_err(|e| element.error(e.to_string()))?;
+            question.into()
+        }
+        "matching" => {
+            let mut question = MatchingQuestion::new(name, text, element.flag("shuffleanswers"));
+            question.add_subquestions(
+                element
+                    .children("subquestion")
+                    .map(|subquestion| {
+                        SubQuestion::new(
+                            subquestion
+                                .child("text")
+                                .map(|text| text.text.clone())
+                                .unwrap_or_default(),
+                            subquestion
+                                .text_of("answer")
+                                .unwrap_or_default()
+                                .to_string(),
+                        )
+                    })
+                    .collect(),
+            );
+            question.feedback = read_combined_feedback(element);
+            question.into()
+        }
+        "numerical" => {
+            let mut question = NumericalQuestion::new(name, text);
+            let answers = element
+                .children("answer")
+                .map(|answer| {
+                    let tolerance = match answer.value_of("tolerance") {
+                        Some(tolerance) => tolerance
+                            .parse()
+                            .map_err(|_| answer.error("tolerance is not a number"))?,
+                        None => 0.0,
+                    };
+                    Ok(NumericalAnswer {
+                        answer: read_answer(answer)?,
+                        tolerance,
+                    })
+                })
+                .collect::<Result<Vec<_>, ReadError>>()?;
+            question
+                .add_numerical_answers(answers)
+                .map_err(|e| element.error(e.to_string()))?;
+            let (units, unit_penalty) = read_units(element, question.unit_penalty)?;
+            question.add_units(units);
+            question.unit_penalty = unit_penalty;
+            question.into()
+        }
+        "calculated" => {
+            let mut question = CalculatedQuestion::new(name, text);
+            question.synchronize = element.flag("synchronize").unwrap_or_default();
+            question
+                .add_calculated_answers(read_calculated_answers(element)?)
+                .map_err(|e| element.error(e.to_string()))?;
+            question.add_datasets(read_datasets(element)?);
+            let (units, unit_penalty) = read_units(element, question.unit_penalty)?;
+            question.add_units(units);
+            question.unit_penalty = unit_penalty;
+            question.into()
+        }
+        "calculatedsimple" => {
+            let mut question = CalculatedSimpleQuestion::new(name, text);
+            question
+                .add_calculated_answers(read_calculated_answers(element)?)
+                .map_err(|e| element.error(e.to_string()))?;
+            question.add_datasets(read_datasets(element)?);
+            let (units, unit_penalty) = read_units(element, question.unit_penalty)?;
+            question.add_units(units);
+            question.unit_penalty = unit_penalty;
+            question.into()
+        }
+        "calculatedmulti" => {
+            let mut question = CalculatedMultiQuestion::new(name, text, element.flag("single"));
+            question.synchronize = element.flag("synchronize").unwrap_or_default();
+            if let Some(shuffle) = element.flag("shuffleanswers") {
+                question.shuffleanswers = shuffle;
+            }
+            for (name, feedback) in [
+                ("correctfeedback", &mut question.correctfeedback),
+                (
+                    "partiallycorrectfeedback",
+                    &mut question.partiallycorrectfeedback,
+                ),
+                ("incorrectfeedback", &mut question.incorrectfeedback),
+            ] {
+                *feedback = element.text_of(name).unwrap_or_default().to_string();
+            }
+            if let Some(numbering) = element.value_of("answernumbering") {
+                question.answernumbering = numbering.to_string();
+            }
+            question
+                .add_calculated_answers(read_calculated_answers(element)?)
+                .map_err(|e| element.error(e.to_string()))?;
+            question.add_datasets(read_datasets(element)?);
+            question.into()
+        }
+        "ddwtos" => {
+            let mut question = DragDropTextQuestion::new(name, text);
+            if let Some(shuffle) = element.flag("shuffleanswers") {
+                question.shuffleanswers = shuffle;
+            }
+            question.feedback = read_combined_feedback(element);
+            question.add_choices(
+                read_gap_choices(element, "dragbox")?
+                    .into_iter()
+                    .map(|(text, group, infinite)| {
+                        let mut dragbox = DragBox::new(text, group);
+                        dragbox.infinite = infinite;
+                        dragbox
+                    })
+                    .collect(),
+            );
+            question.into()
+        }
+        "gapselect" => {
+            let mut question = GapSelectQuestion::new(name, text);
+            if let Some(shuffle) = element.flag("shuffleanswers") {
+                question.shuffleanswers = shuffle;
+            }
+            question.feedback = read_combined_feedback(element);
+            question.add_choices(
+                read_gap_choices(element, "selectoption")?
+                    .into_iter()
+                    .map(|(text, group, _)| SelectOption::new(text, group))
+                    .collect(),
+            );
+            question.into()
+        }
+        "ddimageortext" => {
+            let mut question = DragDropImageQuestion::new(name, text);
+            question.shuffleanswers = empty_flag(element, "shuffleanswers");
+            question.feedback = read_combined_feedback(element);
+            if let Some(file) = element.child("file") {
+                question.set_background(read_file(file)?);
+            }
+            for drag in numbered(element, "drag")? {
+                let mut item = DragItem::new(
+                    drag.value_of("text").unwrap_or_default().to_string(),
+                    number_of(drag, "draggroup", 1)?,
+                );
+                item.infinite = drag.child("infinite").is_some();
+                item.image = drag.child("file").map(read_file).transpose()?;
+                question.add_drags(vec![item]);
+            }
+            for drop in numbered(element, "drop")? {
+                let mut zone = DropZone::new(
+                    number_of(drop, "choice", 0)?,
+                    number_of(drop, "xleft", 0)?,
+                    number_of(drop, "ytop", 0)?,
+                );
+                zone.text = drop.value_of("text").unwrap_or_default().to_string();
+                question.add_drops(vec![zone]);
+            }
+            question.into()
+        }
+        "ddmarker" => {
+            let mut question = DragMarkerQuestion::new(name, text);
+            question.shuffleanswers = empty_flag(element, "shuffleanswers");
+            question.showmisplaced = empty_flag(element, "showmisplaced");
+            question.feedback = read_combined_feedback(element);
+            if let Some(file) = element.child("file") {
+                question.set_background(read_file(file)?);
+            }
+            for drag in numbered(element, "drag")? {
+                let mut marker = Marker::new(drag.value_of("text").unwrap_or_default().to_string());
+                marker.infinite = drag.child("infinite").is_some();
+                marker.count = number_of(drag, "noofdrags", 1)?;
+                question.add_markers(vec![marker]);
+            }
+            for drop in numbered(element, "drop")? {
+                let (shape, coords) = (
+                    drop.value_of("shape").unwrap_or_default(),
+                    drop.value_of("coords").unwrap_or_default(),
+                );
+                let shape = MarkerShape::from_parts(shape, coords).ok_or_else(|| {
+                    drop.error(format!("invalid {} coordinates `{}`", shape, coords))
+                })?;
+                question.add_zones(vec![MarkerZone::new(shape, number_of(drop, "choice", 0)?)]);
+            }
+            question.into()
+        }
+        "coderunner" => {
+            let value = |name: &str| element.value_of(name).unwrap_or_default().to_string();
+            let optional = |name: &str| element.value_of(name).filter(|value| !value.is_empty());
+            let raw = |name: &str| element.raw_value_of(name).unwrap_or_default().to_string();
+            let mut question = CodeRunnerQuestion::new(name, text, value("coderunnertype"));
+            question.answer = raw("answer");
+            question.answerpreload = raw("answerpreload");
+            question.template = element
+                .raw_value_of("template")
+                .filter(|template| !template.trim().is_empty())
+                .map(str::to_string);
+            question.penaltyregime = value("penaltyregime");
+            question.allornothing = element.flag("allornothing").unwrap_or(true);
+            let code = number_of(element, "precheck", 0)?;
+            question.precheck = Precheck::from_code(code)
+                .ok_or_else(|| element.error(format!("unknown precheck option {}", code)))?;
+            question.language = optional("language").map(str::to_string);
+            question.acelang = optional("acelang").map(str::to_string);
+            question.sandbox = optional("sandbox").map(str::to_string);
+            question.cputimelimitsecs = optional("cputimelimitsecs")
+                .map(str::parse)
+                .transpose()
+                .map_err(|_| element.error("<cputimelimitsecs> is not a number"))?;
+            question.memlimitmb = optional("memlimitmb")
+                .map(str::parse)
+                .transpose()
+                .map_err(|_| element.error("<memlimitmb> is not a number"))?;
+            if let Some(testcases) = element.child("testcases") {
+                for testcase in testcases.children("testcase") {
+                    question.add_testcases(vec![read_testcase(testcase)?]);
+                }
+            }
+            question.into()
+        }
+        "ordering" => {
+            let mut question = OrderingQuestion::new(name, text);
+            if let Some(layout) = element.value_of("layouttype") {
+                question.layout = OrderingLayout::from_name(layout)
+                    .ok_or_else(|| element.error(format!("unknown layout type `{}`", layout)))?;
+            }
+            if let Some(select) = element.value_of("selecttype") {
+                question.select = OrderingSelect::from_name(select)
+                    .ok_or_else(|| element.error(format!("unknown select type `{}`", select)))?;
+            }
+            if let Some(grading) = element.value_of("gradingtype") {
+                question.grading = OrderingGrading::from_name(grading)
+                    .ok_or_else(|| element.error(format!("unknown grading type `{}`", grading)))?;
+            }
+            question.select_count = number_of(element, "selectcount", 0)?;
+            question.feedback = read_combined_feedback(element);
+            // The fractions are the positions of the items
+            let mut items = answers()?;
+            items.sort_by(|a, b| a.fraction.total_cmp(&b.fraction));
+            question
+                .add_answers(items)
+                .map_err(|e| element.error(e.to_string()))?;
+            question.into()
+        }
+        "randomsamatch" => {
+            let mut question =
+                RandomShortAnswerMatchQuestion::new(name, text, number_of(element, "choose", 2)?);
+            question.subcats = element.flag("subcats").unwrap_or(true);
+            question.feedback = read_combined_feedback(element);
+            question.into()
+        }
+        // The question text of a random question is the subcategory flag
+        "random" => RandomQuestion::new(name, matches!(text.trim(), "1" | "true")).into(),
+        "essay" => EssayQuestion::new(name, text).into(),
+        "description" => DescriptionQuestion::new(name, text).into(),
+        other => {
+            return Err(element.error(format!("unsupported question type `{}`", other)));
+        }
+    };
+    if let Some(questiontext) = element.child("questiontext") {
+        question
+            .as_question_mut()
+            .set_text_format(read_format(questiontext)?);
+        let files = questiontext
+            .children("file")
+            .map(read_file)
+            .collect::<Result<Vec<_>, _>>()?;
+        question.as_question_mut().add_files(files);
+    }
+    if let Some(idnumber) = element
+        .value_of("idnumber")
+        .filter(|idnumber| !idnumber.is_empty())
+    {
+        question
+            .as_question_mut()
+            .set_idnumber(idnumber.to_string());
+    }
+    let common = question.as_question_mut();
+    if let Some(feedback) = element.text_of("generalfeedback") {
+        common.set_general_feedback(feedback.to_string());
+    }
+    if element.child("defaultgrade").is_some() {
+        common.set_default_grade(number_of(element, "defaultgrade", 1.0)?);
+    }
+    if element.child("penalty").is_some() {
+        common.set_penalty(number_of(element, "penalty", 0.0)?);
+    }
+    common.set_hidden(element.flag("hidden").unwrap_or_default());
+    let hints = element
+        .children("hint")
+        .map(read_hint)
+        .collect::<Result<Vec<_>, _>>()?;
+    common.add_hints(hints);
+    let tags = element
+        .child("tags")
+        .map(|tags| {
+            tags.children("tag")
+                .filter_map(|tag| tag.child("text"))
+                .map(|text| text.text.trim().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    question.as_question_mut().add_tags(tags);
+    Ok(QuizItem::Question(Box::new(question)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_questions() {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<quiz>
+  <question type="multichoice">
+    <name><text>Capital</text></name>
+    <questiontext format="moodle_auto_format"><text>What is the capital of France?</text></questiontext>
+    <answer fraction="100" format="html">
+      <text>Paris</text>
+      <feedback format="html"><text>Correct!</text></feedback>
+    </answer>
+    <answer fraction="-33.33333" format="html"><text>Lyon</text></answer>
+    <single>true</single>
+    <shuffleanswers>0</shuffleanswers>
+    <answernumbering>ABCD</answernumbering>
+    <tags><tag><text>geography</text></tag></tags>
+  </question>
+  <question type="essay">
+    <name><text>Map</text></name>
+    <questiontext format="html">
+      <text><![CDATA[<img src="@@PLUGINFILE@@/map.png">]]></text>
+      <file name="map.png" path="/" encoding="base64">TW9v
+ZGxl</file>
+    </questiontext>
+  </question>
+  <question type="category">
+    <category><text>$course$/Numbers/</text></category>
+  </question>
+  <question type="numerical">
+    <name><text>Pi</text></name>
+    <questiontext format="html"><text>Value of pi?</text></questiontext>
+    <answer fraction="100"><text>3.14</text><tolerance>0.01</tolerance></answer>
+    <answer fraction="-50"><text>3</text><tolerance>0</tolerance></answer>
+    <units>
+      <unit><multiplier>1</multiplier><unit_name>rad</unit_name></unit>
+      <unit><multiplier>57.29578</multiplier><unit_name>deg</unit_name></unit>
+    </units>
+    <unitgradingtype>1</unitgradingtype>
+    <unitpenalty>0.25</unitpenalty>
+  </question>
+</quiz>"#;
+        let quiz = parse(source).unwrap();
+        let questions: Vec<_> = quiz.questions().collect();
+        assert_eq!(questions.len(), 3);
+        assert!(questions[0].0.is_none());
+        match questions[0].1 {
+            QuestionType::Multichoice(q) => {
+                assert!(!q.shuffleanswers);
+                assert_eq!(q.answernumbering, "ABCD");
+                assert!(matches!(q.get_text_format(), TextFormat::Moodle));
+                assert_eq!(q.get_tags(), ["geography"]);
+                assert_eq!(q.answers()[0].feedback.as_deref(), Some("Correct!"));
+                assert_eq!(q.answers()[1].fraction, -33.33333);
+            }
+            _ => panic!("expected multichoice"),
+        }
+        let files = questions[1].1.as_question().get_files();
+        assert_eq!(
+            files,
+            [EmbeddedFile::new("map.png".to_string(), b"Moodle".to_vec())]
+        );
+        assert_eq!(questions[2].0.unwrap().as_str(), "Numbers");
+        match questions[2].1 {
+            QuestionType::Numerical(q) => {
+                assert_eq!(q.answers()[0].tolerance, 0.01);
+                assert_eq!(q.answers()[1].answer.fraction, -50.0);
+                assert_eq!(q.units()[1].name, "deg");
+                assert_eq!(q.units()[1].multiplier, 57.29578);
+                assert_eq!(q.unit_penalty, 0.25);
+            }
+            _ => panic!("expected numerical"),
+        }
+    }
+
+    #[test]
+    fn test_read_split_fractions() {
+        // As exported by Moodle, with the grade split between three right answers
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<quiz>
+  <question type="multichoice">
+    <name>
+      <text>Nordic capitals</text>
+    </name>
+    <questiontext format="html">
+      <text><![CDATA[<p>Which are Nordic capitals?</p>]]></text>
+    </questiontext>
+    <idnumber></idnumber>
+    <single>false</single>
+    <shuffleanswers>true</shuffleanswers>
+    <answernumbering>abc</answernumbering>
+    <answer fraction="33.33333" format="html">
+      <text><![CDATA[<p>Oslo</p>]]></text>
+    </answer>
+    <answer fraction="33.33333" format="html">
+      <text><![CDATA[<p>Helsinki</p>]]></text>
+    </answer>
+    <answer fraction="33.33333" format="html">
+      <text><![CDATA[<p>Stockholm</p>]]></text>
+    </answer>
+    <answer fraction="-100" format="html">
+      <text><![CDATA[<p>Berlin</p>]]></text>
+    </answer>
+  </question>
+</quiz>"#;
+        let mut quiz = parse(source).unwrap();
+        let Some((_, QuestionType::Multichoice(q))) = quiz.questions().next() else {
+            panic!("expected multichoice");
+        };
+        let fractions: Vec<f64> = q.answers().iter().map(|a| a.fraction).collect();
+        assert_eq!(fractions, vec![33.33333, 33.33333, 33.33333, -100.0]);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let written = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert_eq!(written.matches(r#"<answer fraction="33.33333""#).count(), 3);
+        let Err(error) = parse(&source.replace("-100", "-150")) else {
+            panic!("expected an error");
+        };
+        assert_eq!(error.line, 23);
+    }
+
+    #[test]
+    fn test_round_trip_common_elements() {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<quiz>
+  <question type="shortanswer">
+    <name><text>Capital</text></name>
+    <questiontext format="html"><text>What is the capital of France?</text></questiontext>
+    <generalfeedback format="html"><text><![CDATA[<p>Paris has been the capital since 987.</p>]]></text></generalfeedback>
+    <defaultgrade>2.0000000</defaultgrade>
+    <penalty>0.3333333</penalty>
+    <hidden>1</hidden>
+    <idnumber>capital-fr</idnumber>
+    <usecase>0</usecase>
+    <answer fraction="100" format="moodle_auto_format"><text>Paris</text></answer>
+    <hint format="html"><text>It is on the Seine.</text></hint>
+    <hint format="html"><text>It has the Eiffel Tower.</text><shownumcorrect/><clearwrong/></hint>
+  </question>
+</quiz>"#;
+        let check = |quiz: &Quiz| {
+            let (_, question) = quiz.questions().next().unwrap();
+            let question = question.as_question();
+            assert_eq!(
+                question.get_general_feedback(),
+                Some("<p>Paris has been the capital since 987.</p>")
+            );
+            assert_eq!(question.get_default_grade(), Some(2.0));
+            assert_eq!(question.get_penalty(), Some(0.3333333));
+            assert!(question.is_hidden());
+            let hints = question.get_hints();
+            assert_eq!(hints.len(), 2);
+            assert_eq!(hints[0].text, "It is on the Seine.");
+            assert!(!hints[0].shownumcorrect);
+            assert!(hints[1].shownumcorrect && hints[1].clearwrong);
+        };
+        let mut quiz = parse(source).unwrap();
+        check(&quiz);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let written = std::fs::read_to_string(tmp_file.path()).unwrap();
+        check(&parse(&written).unwrap());
+        // The common elements are written in Moodle's order
+        let positions: Vec<usize> = [
+            "<questiontext",
+            "<generalfeedback",
+            "<defaultgrade>2</defaultgrade>",
+            "<penalty>0.3333333</penalty>",
+            "<hidden>1</hidden>",
+            "<idnumber>",
+            "<answer",
+            "<hint",
+        ]
+        .iter()
+        .map(|element| written.find(element).expect(element))
+        .collect();
+        assert!(
+            positions.windows(2).all(|pair| pair[0] < pair[1]),
+            "{}",
+            written
+        );
+    }
+
+    #[test]
+    fn test_keep_whitespace() {
+        let mut question = CodeRunnerQuestion::new(
+            "Square".to_string(),
+            "  Write <code>square(x)</code>.  ".to_string(),
+            "python3".to_string(),
+        );
+        question.answer = "def square(x):\n    return x * x\n".to_string();
+        question.template = Some("{{ STUDENT_ANSWER }}\n\n{{ TEST.testcode }}\n".to_string());
+        question.add_testcases(vec![TestCase::new(
+            "print(square(3))".to_string(),
+            "  9\n".to_string(),
+        )]);
+        question.add_tags(vec!["python".to_string()]);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        let mut quiz = Quiz::new(vec![question.into()]);
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let source = std::fs::read_to_string(tmp_file.path()).unwrap();
+        let quiz = parse(&source).unwrap();
+        let Some((_, QuestionType::CodeRunner(q))) = quiz.questions().next() else {
+            panic!("expected coderunner");
+        };
+        assert_eq!(q.get_name(), "Square");
+        assert_eq!(q.get_description(), "  Write <code>square(x)</code>.  ");
+        assert_eq!(q.answer, "def square(x):\n    return x * x\n");
+        assert_eq!(
+            q.template.as_deref(),
+            Some("{{ STUDENT_ANSWER }}\n\n{{ TEST.testcode }}\n")
+        );
+        assert_eq!(q.testcases()[0].expected, "  9\n");
+        assert_eq!(q.get_tags(), ["python"]);
+
+        // The whitespace between the questions is not kept
+        let question = r#"<question type="description"><name><text>Intro</text></name><questiontext><text>Hi</text></questiontext></question>"#;
+        let source = format!("<quiz>\n  {}\n  {}\n</quiz>", question, question);
+        let mut reader = QuestionReader::new(source.as_bytes());
+        assert!(matches!(reader.next(), Some(Ok(QuizItem::Question(_)))));
+        assert!(matches!(reader.next(), Some(Ok(QuizItem::Question(_)))));
+        assert_eq!(reader.stack.len(), 1);
+        assert!(reader.stack[0].text.is_empty());
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let cases = [
+            ("<quiz>\n<question type=\"cloze\">\n</question>\n</quiz>", 2),
+            ("<quiz>\n<item/>\n</quiz>", 2),
+            ("<quiz>\n<question type=\"essay\">\n</quiz>", 3),
+            ("<questions/>", 1),
+        ];
+        for (source, line) in cases {
+            match parse(source) {
+                Err(error) => assert_eq!(error.line, line, "{}", error),
+                Ok(_) => panic!("expected an error for {:?}", source),
+            }
+        }
+    }
+}
@@ -2,22 +2,139 @@
 //! The main purpose is to reduce code duplication
 //! and make sure that every element has a single start and end event.
 //! The functions are specific to Moodle XML structure.
+//!
+//! Question types implemented outside the crate write their texts with these functions, so that the options
+//! of the quiz in the [`WriteContext`] of the [`XmlWriter`] apply to them as well.
 
+use crate::convert::{self, TextConversion};
+use crate::multilang::{self, MultiLangOptions};
 use crate::question::TextFormat;
-use crate::quiz::QuizError;
-use std::fs::File;
-use xml::writer::{EventWriter, XmlEvent};
+use crate::quiz::{CdataPolicy, QuizError};
+use crate::{html, math};
+use std::borrow::Cow;
+use std::io::{BufWriter, Write};
+use xml::writer::{events::StartElementBuilder, EmitterConfig, EventWriter, XmlEvent};
+
+/// Options of a quiz which apply to every text written into it.
+#[derive(Debug, Clone, Default)]
+pub struct WriteContext {
+    /// Syntax of the multilang blocks and the languages every translated text must have.
+    pub multilang: Option<MultiLangOptions>,
+    /// Format the texts are converted to.
+    pub text_conversion: Option<TextConversion>,
+    /// Whether `$ … $` math is converted to `\( … \)`.
+    pub mathjax_delimiters: bool,
+    /// Whether scripts and event handlers are removed from HTML texts.
+    pub sanitize_html: bool,
+    /// Which texts are CDATA sections, overriding the default of each field.
+    pub cdata: Option<CdataPolicy>,
+}
+
+/// Writes the XML of a quiz with the options of its [`WriteContext`].
+pub struct XmlWriter<'a> {
+    events: EventWriter<BufWriter<Box<dyn Write + 'a>>>,
+    context: WriteContext,
+    /// Formats of the elements being written, the innermost last. `None` is an element without a format.
+    formats: Vec<Option<TextFormat>>,
+}
+
+impl<'a> XmlWriter<'a> {
+    /// Creates a writer into `output`, such as a file or the standard output. The output is buffered, and
+    /// [`XmlWriter::flush`] writes what is left in the buffer.
+    pub fn new(output: impl Write + 'a, config: EmitterConfig, context: WriteContext) -> Self {
+        let output: Box<dyn Write + 'a> = Box::new(output);
+        Self {
+            events: config.create_writer(BufWriter::new(output)),
+            context,
+            formats: Vec::new(),
+        }
+    }
+    /// Writes an XML event.
+    pub fn write<'e, E>(&mut self, event: E) -> xml::writer::Result<()>
+    where
+        E: Into<XmlEvent<'e>>,
+    {
+        self.events.write(event)
+    }
+    /// Returns the options the texts are written with.
+    pub fn context(&self) -> &WriteContext {
+        &self.context
+    }
+    /// Writes the rest of the buffer into the output and flushes it.
+    pub fn flush(&mut self) -> Result<(), QuizError> {
+        self.events.inner_mut().flush()?;
+        Ok(())
+    }
+    /// Returns the format written into the current element, if it has one.
+    fn written_format(&self) -> Option<TextFormat> {
+        let format = self.formats.last().copied().flatten();
+        match (self.context.text_conversion, format) {
+            (Some(conversion), Some(format)) => Some(conversion.format(format)),
+            _ => format,
+        }
+    }
+    /// Applies the options of the context to a text in the current element.
+    fn apply<'t>(&self, text: &'t str) -> Result<Cow<'t, str>, QuizError> {
+        let context = &self.context;
+        let mut text = Cow::Borrowed(text);
+        if let Some(options) = &context.multilang {
+            text = replace(text, |text| multilang::apply(text, options))?;
+        }
+        let format = self.formats.last().copied().flatten();
+        if let (Some(conversion), Some(format)) = (context.text_conversion, format) {
+            text = replace(text, |text| {
+                Ok(convert::convert(text, format, conversion)?.0)
+            })?;
+        }
+        // Math and HTML are only changed in the texts written in a format other than plain text
+        match self.written_format() {
+            Some(format) if context.mathjax_delimiters && format != TextFormat::PlainText => {
+                text = replace(text, |text| Ok(math::to_mathjax(text)))?;
+            }
+            _ => {}
+        }
+        if context.sanitize_html && self.written_format() == Some(TextFormat::HTML) {
+            text = Cow::Owned(html::sanitize(&text));
+        }
+        Ok(text)
+    }
+}
+
+/// Replaces a text with the result of `change`, if it changed the text.
+fn replace<'t>(
+    text: Cow<'t, str>,
+    change: impl FnOnce(&str) -> Result<Cow<'_, str>, QuizError>,
+) -> Result<Cow<'t, str>, QuizError> {
+    let changed = match change(&text)? {
+        Cow::Owned(changed) => Some(changed),
+        Cow::Borrowed(_) => None,
+    };
+    Ok(changed.map_or(text, Cow::Owned))
+}
 
 /// Writes a text named tag and a add text inside it, wheter plain or wrapped with CDATA
-pub fn write_text_tag(
-    writer: &mut EventWriter<&File>,
-    data: &str,
-    cdata: bool,
-) -> Result<(), QuizError> {
+/// Multilang blocks are written with the syntax of the quiz being written, and the text is converted to the
+/// text format of the quiz from the format of the element it is in. `$ … $` math is converted to `\( … \)` if
+/// the quiz does so, and HTML is sanitized if the quiz does so. The CDATA policy of the quiz overrides `cdata`.
+pub fn write_text_tag(writer: &mut XmlWriter, data: &str, cdata: bool) -> Result<(), QuizError> {
+    let data = writer.apply(data)?;
+    let data = data.as_ref();
+    let cdata = match writer.context.cdata {
+        Some(CdataPolicy::Always) => true,
+        Some(CdataPolicy::Never) => false,
+        Some(CdataPolicy::Markup) => data.contains(['<', '>', '&']),
+        None => cdata,
+    };
     writer.write(XmlEvent::start_element("text"))?;
     if cdata {
-        writer.write(XmlEvent::cdata(data))?;
-    } else {
+        // `]]>` would end the section, so it is split between two sections
+        let mut rest = data;
+        while let Some(index) = rest.find("]]>") {
+            writer.write(XmlEvent::cdata(&rest[..index + 2]))?;
+            rest = &rest[index + 2..];
+        }
+        writer.write(XmlEvent::cdata(rest))?;
+    } else if !data.is_empty() {
         writer.write(XmlEvent::characters(data))?;
     }
     writer.write(XmlEvent::end_element())?;
@@ -31,20 +148,36 @@ pub fn write_text_tag(
 /// ... scope end...
 /// </correctfeedback>
 pub fn write_named_formatted_scope<F>(
-    writer: &mut EventWriter<&File>,
+    writer: &mut XmlWriter,
     name: &str,
     format: Option<TextFormat>,
     scope: F,
 ) -> Result<(), QuizError>
 where
-    F: FnOnce(&mut EventWriter<&File>) -> Result<(), QuizError>,
+    F: FnOnce(&mut XmlWriter) -> Result<(), QuizError>,
 {
-    if let Some(format) = format {
-        writer.write(XmlEvent::start_element(name).attr("format", format.name()))?;
+    start_formatted_element(writer, XmlEvent::start_element(name), format)?;
+    scope(writer)?;
+    end_formatted_element(writer)
+}
+/// Starts an element with an optional format attribute, which applies to the texts written until
+/// [`end_formatted_element`]. The format is written as converted by the text conversion of the quiz.
+pub fn start_formatted_element(
+    writer: &mut XmlWriter,
+    element: StartElementBuilder,
+    format: Option<TextFormat>,
+) -> Result<(), QuizError> {
+    writer.formats.push(format);
+    if let Some(format) = writer.written_format() {
+        writer.write(element.attr("format", format.name()))?;
     } else {
-        writer.write(XmlEvent::start_element(name))?;
+        writer.write(element)?;
     }
-    scope(writer)?;
+    Ok(())
+}
+/// Ends an element started with [`start_formatted_element`].
+pub fn end_formatted_element(writer: &mut XmlWriter) -> Result<(), QuizError> {
+    writer.formats.pop();
     writer.write(XmlEvent::end_element())?;
     Ok(())
 }
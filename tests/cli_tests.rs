@@ -0,0 +1,202 @@
+#![cfg(feature = "cli")]
+
+use std::process::Command;
+
+const GIFT: &str = "$CATEGORY: capitals
+
+::Capital::What is the capital of France? {=Paris ~Lyon ~Marseille}
+
+::Essay::Describe your capital. {}
+";
+
+fn moodle_xml(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_moodle-xml"))
+        .args(args)
+        .output()
+        .expect("Cannot run moodle-xml")
+}
+
+#[test]
+fn convert_validate_and_merge() {
+    let dir = tempfile::tempdir().unwrap();
+    let gift = dir.path().join("bank.gift");
+    std::fs::write(&gift, GIFT).unwrap();
+    let xml = dir.path().join("bank.xml");
+    let merged = dir.path().join("merged.xml");
+
+    let output = moodle_xml(&[
+        "convert",
+        gift.to_str().unwrap(),
+        "--to",
+        "moodle-xml",
+        "-o",
+        xml.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    // Without --output the XML is written to the standard output
+    let output = moodle_xml(&["convert", gift.to_str().unwrap(), "--to", "moodle-xml"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, std::fs::read(&xml).unwrap());
+
+    let output = moodle_xml(&["validate", xml.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let output = moodle_xml(&[
+        "merge",
+        xml.to_str().unwrap(),
+        gift.to_str().unwrap(),
+        "-o",
+        merged.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    let output = moodle_xml(&["stats", merged.to_str().unwrap()]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("4 questions"));
+    assert!(stdout.contains("multichoice  2"));
+    assert!(stdout.contains("capitals  4"));
+
+    let output = moodle_xml(&["preview", merged.to_str().unwrap()]);
+    let html = String::from_utf8(output.stdout).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("What is the capital of France?"));
+
+    let output = moodle_xml(&["convert", gift.to_str().unwrap(), "--to", "json"]);
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["questions"][0]["type"], "multichoice");
+    assert_eq!(json["questions"][0]["category"], "capitals");
+}
+
+#[test]
+fn exit_codes() {
+    let dir = tempfile::tempdir().unwrap();
+    let invalid = dir.path().join("invalid.gift");
+    std::fs::write(&invalid, "::Broken::Question {=Paris ~Lyon").unwrap();
+
+    let output = moodle_xml(&["validate", invalid.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("error: "));
+    assert!(stderr.contains("invalid.gift: line 1"));
+
+    let shadowed = dir.path().join("shadowed.gift");
+    std::fs::write(&shadowed, "::Capital::Capital of France? {=* =Paris}").unwrap();
+    let output = moodle_xml(&["validate", shadowed.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("warning: question `Capital`: answer 2 is shadowed by answer 1"));
+
+    let output = moodle_xml(&["validate", "quiz.unknown"]);
+    assert_eq!(output.status.code(), Some(2));
+
+    let missing = dir.path().join("missing.xml");
+    let output = moodle_xml(&["stats", missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn merge_keeps_common_elements() {
+    let dir = tempfile::tempdir().unwrap();
+    let gift = dir.path().join("bank.gift");
+    std::fs::write(&gift, GIFT).unwrap();
+    let xml = dir.path().join("essay.xml");
+    let merged = dir.path().join("merged.xml");
+    std::fs::write(
+        &xml,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<quiz>
+  <question type="essay">
+    <name><text>Essay</text></name>
+    <questiontext format="html"><text>Describe your capital.</text></questiontext>
+    <generalfeedback format="html"><text>Mention its history.</text></generalfeedback>
+    <defaultgrade>5.0000000</defaultgrade>
+    <penalty>0.1000000</penalty>
+    <hidden>1</hidden>
+    <hint format="html"><text>Start with its name.</text></hint>
+  </question>
+</quiz>"#,
+    )
+    .unwrap();
+    let output = moodle_xml(&[
+        "merge",
+        xml.to_str().unwrap(),
+        gift.to_str().unwrap(),
+        "-o",
+        merged.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let merged = std::fs::read_to_string(&merged).unwrap();
+    for element in [
+        "Mention its history.",
+        "<defaultgrade>5</defaultgrade>",
+        "<penalty>0.1</penalty>",
+        "<hidden>1</hidden>",
+        "Start with its name.",
+    ] {
+        assert!(
+            merged.contains(element),
+            "{} is missing from {}",
+            element,
+            merged
+        );
+    }
+}
+
+#[test]
+fn merge_keeps_input_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let uncategorized = dir.path().join("uncategorized.gift");
+    std::fs::write(&uncategorized, "::First::First question {}\n").unwrap();
+    let categorized = dir.path().join("categorized.xml");
+    std::fs::write(
+        &categorized,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<quiz>
+  <question type="category"><category><text>$course$/empty</text></category></question>
+  <question type="category"><category><text>$course$/capitals</text></category></question>
+  <question type="essay">
+    <name><text>Second</text></name>
+    <questiontext format="html"><text>Second question</text></questiontext>
+  </question>
+</quiz>"#,
+    )
+    .unwrap();
+    let merged = dir.path().join("merged.xml");
+
+    let output = moodle_xml(&[
+        "merge",
+        uncategorized.to_str().unwrap(),
+        categorized.to_str().unwrap(),
+        "-o",
+        merged.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let merged = std::fs::read_to_string(&merged).unwrap();
+    let positions: Vec<usize> = [
+        "First question",
+        "$course$/empty/",
+        "$course$/capitals/",
+        "Second question",
+    ]
+    .iter()
+    .map(|text| merged.find(text).expect(text))
+    .collect();
+    assert!(
+        positions.windows(2).all(|pair| pair[0] < pair[1]),
+        "{}",
+        merged
+    );
+
+    // The questions without a category would be imported into the category of the file before them
+    let output = moodle_xml(&[
+        "merge",
+        categorized.to_str().unwrap(),
+        uncategorized.to_str().unwrap(),
+        "-o",
+        dir.path().join("reversed.xml").to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("uncategorized.gift"), "{}", stderr);
+}
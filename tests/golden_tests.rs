@@ -0,0 +1,248 @@
+//! Golden files of every question type, in `tests/golden`.
+//!
+//! Every question type is written with the default options and compared with its golden file. The elements the
+//! crate writes keep the relative order of Moodle's exporter, `writequestion` of
+//! `question/format/xml/format.php`. Optional elements are written only when set, so the fixtures set them, such
+//! as the general feedback, default grade, penalty, hidden flag and hints of the multiple choice question and the
+//! `infinite`, `shownumcorrect` and `showmisplaced` flags of the drag and drop questions. The calculated types
+//! share one block in `writequestion`, so the calculated and simple calculated files have the multiple choice
+//! options and combined feedback of calculated multichoice questions too. Run the tests with `UPDATE_GOLDEN=1` to write the
+//! golden files again after an intended change, and check that the order of the elements is still Moodle's.
+use moodle_xml::{
+    answer::NumericalAnswer,
+    dataset::Random,
+    export::{ElementOrder, ExportOptions},
+    prelude::*,
+};
+use std::path::PathBuf;
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    png.extend_from_slice(&width.to_be_bytes());
+    png.extend_from_slice(&height.to_be_bytes());
+    png
+}
+
+/// One question of every type.
+fn questions() -> Vec<QuestionType> {
+    let mut multichoice = MultiChoiceQuestion::new(
+        "Capital".into(),
+        "What is the capital of <b>Finland</b>?".into(),
+        None,
+        None,
+        Some("Right".into()),
+        None,
+        Some("Wrong".into()),
+        Some("abc".into()),
+    );
+    multichoice
+        .add_answers(vec![
+            Answer::new(100.0, "Helsinki".into(), Some("Yes".into())),
+            Answer::new(0.0, "Turku".into(), None),
+        ])
+        .unwrap();
+    multichoice.add_tags(vec!["geography".into()]);
+    multichoice.set_idnumber("capital-fi".into());
+    multichoice.set_general_feedback("Helsinki has been the capital since 1812.".into());
+    multichoice.set_default_grade(2.0);
+    multichoice.set_penalty(0.5);
+    multichoice.set_hidden(true);
+    let mut hint = Hint::new("It is on the coast.".into());
+    hint.shownumcorrect = true;
+    multichoice.add_hints(vec![hint]);
+
+    let mut truefalse = TrueFalseQuestion::new("Earth".into(), "The earth is round.".into());
+    truefalse
+        .add_answers(vec![
+            Answer::new(100.0, "true".into(), None),
+            Answer::new(0.0, "false".into(), Some("It is.".into())),
+        ])
+        .unwrap();
+
+    let mut shortanswer = ShortAnswerQuestion::new(
+        "Largest lake".into(),
+        "Largest lake of Finland?".into(),
+        None,
+    );
+    shortanswer
+        .add_answers(vec![Answer::new(100.0, "Saimaa".into(), None)])
+        .unwrap();
+
+    let mut matching = MatchingQuestion::new("Pairs".into(), "Match the capitals".into(), None);
+    matching.add_subquestions(vec![
+        SubQuestion::new("Finland".into(), "Helsinki".into()),
+        SubQuestion::new("Sweden".into(), "Stockholm".into()),
+        SubQuestion::new(String::new(), "Oslo".into()),
+    ]);
+    matching.feedback.correctfeedback = "All right".into();
+    matching.feedback.shownumcorrect = true;
+
+    let essay = EssayQuestion::new("History".into(), "Describe the history of Helsinki.".into());
+
+    let mut numerical = NumericalQuestion::new(
+        "Boiling point".into(),
+        "Boiling point of water in °F?".into(),
+    );
+    numerical
+        .add_numerical_answers(vec![NumericalAnswer::new(100.0, 212.0, 1.0, None)])
+        .unwrap();
+    numerical.add_units(vec![NumericalUnit::new("°F".into(), 1.0)]);
+
+    let description =
+        DescriptionQuestion::new("Intro".into(), "The next questions are hard.".into());
+
+    let mut calculated = CalculatedQuestion::new("Speed".into(), "{d} km in {t} h?".into());
+    calculated
+        .add_calculated_answers(vec![CalculatedAnswer::new(
+            100.0,
+            "{d} / {t}".into(),
+            0.1,
+            None,
+        )])
+        .unwrap();
+    calculated.add_datasets(vec![
+        DatasetDefinition::new("d".into(), 10.0, 100.0, 0),
+        DatasetDefinition::new("t".into(), 1.0, 2.0, 1),
+    ]);
+    calculated.generate_items(2, &mut Random::new(1)).unwrap();
+    calculated.add_units(vec![NumericalUnit::new("km/h".into(), 1.0)]);
+
+    let mut simple = CalculatedSimpleQuestion::new("Area".into(), "Area of a {r} m circle?".into());
+    simple
+        .add_calculated_answers(vec![CalculatedAnswer::new(
+            100.0,
+            "pi() * {r} * {r}".into(),
+            0.1,
+            None,
+        )])
+        .unwrap();
+    simple.add_datasets(vec![DatasetDefinition::new("r".into(), 1.0, 5.0, 0)]);
+    simple.generate_items(2, &mut Random::new(2)).unwrap();
+
+    let mut multi = CalculatedMultiQuestion::new("Double".into(), "Double {x}".into(), None);
+    multi
+        .add_answers(vec![
+            Answer::new(100.0, "{=2 * {x}}".into(), None),
+            Answer::new(0.0, "{={x} + 2}".into(), None),
+        ])
+        .unwrap();
+    multi.add_datasets(vec![DatasetDefinition::new("x".into(), 1.0, 9.0, 0)]);
+    multi.generate_items(2, &mut Random::new(3)).unwrap();
+
+    let mut ddwtos = DragDropTextQuestion::new("Drag".into(), "[[1]] plus [[2]]".into());
+    let mut one = DragBox::new("one".into(), 1);
+    one.infinite = true;
+    ddwtos.add_choices(vec![one, DragBox::new("two".into(), 1)]);
+    ddwtos.feedback.shownumcorrect = true;
+
+    let mut gapselect = GapSelectQuestion::new("Select".into(), "Roses are [[1]]".into());
+    gapselect.add_choices(vec![
+        SelectOption::new("red".into(), 1),
+        SelectOption::new("blue".into(), 1),
+    ]);
+    gapselect.feedback.shownumcorrect = true;
+
+    let mut ddimage = DragDropImageQuestion::new("Skeleton".into(), "Name the bones".into());
+    ddimage.set_background(EmbeddedFile::new("skeleton.png".into(), png(300, 200)));
+    let mut skull = DragItem::new("Skull".into(), 1);
+    skull.infinite = true;
+    skull.image = Some(EmbeddedFile::new("skull.png".into(), png(40, 30)));
+    ddimage.add_drags(vec![skull]);
+    ddimage.shuffleanswers = true;
+    ddimage.feedback.shownumcorrect = true;
+    ddimage.add_drops(vec![DropZone::new(1, 140, 10)]);
+
+    let mut ddmarker = DragMarkerQuestion::new("Heart".into(), "Mark the chambers".into());
+    ddmarker.set_background(EmbeddedFile::new("heart.png".into(), png(300, 200)));
+    let mut chamber = Marker::new("Chamber".into());
+    chamber.infinite = true;
+    ddmarker.add_markers(vec![chamber]);
+    ddmarker.shuffleanswers = true;
+    ddmarker.showmisplaced = true;
+    ddmarker.feedback.shownumcorrect = true;
+    ddmarker.add_zones(vec![MarkerZone::new(
+        MarkerShape::parse("circle;50,50;20").unwrap(),
+        1,
+    )]);
+
+    let mut randomsamatch = RandomShortAnswerMatchQuestion::new("Drill".into(), "Match".into(), 3);
+    randomsamatch.feedback.shownumcorrect = true;
+    let random = RandomQuestion::new("Any".into(), true);
+
+    let mut ordering = OrderingQuestion::new("Sort".into(), "Order the steps".into());
+    ordering.add_items(vec!["First".into(), "Second".into(), "Third".into()]);
+    ordering.feedback.shownumcorrect = true;
+
+    let mut coderunner =
+        CodeRunnerQuestion::new("Hello".into(), "Print a greeting".into(), "python3".into());
+    coderunner.answer = "print('Hello')".into();
+    let mut testcase = TestCase::new(String::new(), "Hello".into());
+    testcase.hiderestiffail = true;
+    testcase.useasexample = true;
+    coderunner.add_testcases(vec![testcase]);
+    coderunner.template = Some("{{ STUDENT_ANSWER }}".into());
+    coderunner.allornothing = true;
+    coderunner.language = Some("python3".into());
+    coderunner.acelang = Some("python".into());
+    coderunner.sandbox = Some("jobesandbox".into());
+    coderunner.cputimelimitsecs = Some(5);
+    coderunner.memlimitmb = Some(200);
+
+    vec![
+        multichoice.into(),
+        truefalse.into(),
+        shortanswer.into(),
+        matching.into(),
+        essay.into(),
+        numerical.into(),
+        description.into(),
+        calculated.into(),
+        simple.into(),
+        multi.into(),
+        ddwtos.into(),
+        gapselect.into(),
+        ddimage.into(),
+        ddmarker.into(),
+        randomsamatch.into(),
+        random.into(),
+        ordering.into(),
+        coderunner.into(),
+    ]
+}
+
+fn write(question: QuestionType, options: ExportOptions) -> String {
+    let mut quiz = Quiz::new(vec![question]);
+    quiz.set_export_options(options);
+    let tmp_file = tempfile::NamedTempFile::new().unwrap();
+    quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+    std::fs::read_to_string(tmp_file.path()).unwrap()
+}
+
+#[test]
+fn golden_files() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut types = Vec::new();
+    let canonical = ExportOptions {
+        element_order: ElementOrder::Canonical,
+        ..ExportOptions::default()
+    };
+    for (question, again) in questions().into_iter().zip(questions()) {
+        let type_name = question.type_name();
+        types.push(type_name);
+        let xml = write(question, ExportOptions::default());
+        // The question types of the crate already write Moodle's order, which the canonical order keeps
+        assert_eq!(xml, write(again, canonical.clone()), "{}", type_name);
+        let path = directory.join(format!("{}.xml", type_name));
+        if update {
+            std::fs::write(&path, &xml).unwrap();
+            continue;
+        }
+        let golden =
+            std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(xml, golden, "{} differs from {}", type_name, path.display());
+    }
+    types.sort_unstable();
+    types.dedup();
+    assert_eq!(types.len(), 18, "every question type has a golden file");
+}
@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
     use moodle_xml::prelude::*;
+    use moodle_xml::quiz::CdataPolicy;
+    use moodle_xml::xml_util::{write_named_formatted_scope, write_text_tag, XmlWriter};
     use std::fs::File;
     use std::io::BufReader;
     use xml::reader::EventReader;
@@ -10,7 +12,7 @@ mod tests {
         let mut question =
             ShortAnswerQuestion::new("Easy question".into(), "Kenella on S rinnassa".into(), None);
 
-        let answer = Answer::new(100, "Superman".into(), Some("Oikein".into()));
+        let answer = Answer::new(100.0, "Superman".into(), Some("Oikein".into()));
         question.add_answers(answer.into()).unwrap();
 
         let mut quiz = Quiz::new(question.into());
@@ -34,7 +36,7 @@ mod tests {
     fn pointlimit_test() {
         let mut question =
             ShortAnswerQuestion::new("Easy question".into(), "Kenella on S rinnassa".into(), None);
-        let answer = Answer::new(200, "Superman".into(), Some("Oikein".into()));
+        let answer = Answer::new(200.0, "Superman".into(), Some("Oikein".into()));
         question.add_answers(answer.into()).unwrap();
 
         let mut quiz = Quiz::new(question.into());
@@ -66,7 +68,7 @@ mod tests {
             None,
         );
 
-        let answer = Answer::new(100, "NaN".into(), Some("1E02".into()));
+        let answer = Answer::new(100.0, "NaN".into(), Some("1E02".into()));
         question.add_answers(vec![answer]).unwrap();
         let mut quiz = Quiz::new(question.into());
 
@@ -78,10 +80,10 @@ mod tests {
         let mut question =
             ShortAnswerQuestion::new("Easy question".into(), "Kenella on S rinnassa".into(), None);
 
-        let answer = Answer::new(100, "Superman".into(), Some("Oikein".into()));
-        let answer2 = Answer::new(0, "Batman".into(), Some("Väärin".into()));
-        let answer3 = Answer::new(0, "Robin".into(), None);
-        let answer4 = Answer::new(0, "Spiderman".into(), Some("Oikein".into()));
+        let answer = Answer::new(100.0, "Superman".into(), Some("Oikein".into()));
+        let answer2 = Answer::new(0.0, "Batman".into(), Some("Väärin".into()));
+        let answer3 = Answer::new(0.0, "Robin".into(), None);
+        let answer4 = Answer::new(0.0, "Spiderman".into(), Some("Oikein".into()));
 
         question
             .add_answers(vec![answer, answer2, answer3, answer4])
@@ -95,9 +97,9 @@ mod tests {
     }
     #[test]
     fn add_quiz_vec_xml() {
-        let answer1 = Answer::new(100, "Superman".into(), Some("Oikein".into()));
-        let answer2 = Answer::new(100, "Spiderman".into(), Some("Oikein".into()));
-        let answer3 = Answer::new(100, "Superman".into(), Some("Oikein".into()));
+        let answer1 = Answer::new(100.0, "Superman".into(), Some("Oikein".into()));
+        let answer2 = Answer::new(100.0, "Spiderman".into(), Some("Oikein".into()));
+        let answer3 = Answer::new(100.0, "Superman".into(), Some("Oikein".into()));
 
         let answers = vec![answer1, answer2, answer3.clone()];
 
@@ -121,4 +123,829 @@ mod tests {
         let tmp_file = tempfile::NamedTempFile::new().unwrap();
         assert!(quiz.to_xml(tmp_file.path().to_str().unwrap()).is_ok());
     }
+
+    #[test]
+    fn gift_to_xml() {
+        let source = r#"
+$CATEGORY: capitals
+
+::Capital::What is the capital of France? {=Paris ~Lyon ~Marseille}
+
+::Match::Match the countries with their capitals. {
+    =Finland -> Helsinki
+    =Sweden -> Stockholm
+}
+
+::Pi::What is pi? {#3.14:0.01}
+
+::Intro::Answer the following questions.
+
+::Essay::Describe your capital. {}
+"#;
+        let mut quiz = moodle_xml::gift::parse(source).unwrap();
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        assert!(quiz.to_xml(tmp_file.path().to_str().unwrap()).is_ok());
+
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(xml.contains("<text>$course$/capitals/</text>"));
+        assert!(xml.contains("<tolerance>0.01</tolerance>"));
+        assert!(xml.contains(r#"<question type="description">"#));
+        assert!(xml.contains(r#"<question type="essay">"#));
+        let parser = EventReader::new(BufReader::new(File::open(tmp_file.path()).unwrap()));
+        for e in parser {
+            assert!(e.is_ok())
+        }
+    }
+
+    #[test]
+    fn xml_round_trip() {
+        let source = r#"
+::Capital::What is the capital of France? {=Paris ~Lyon ~Marseille}
+
+$CATEGORY: numbers
+
+::Pi::What is pi? {#3.14:0.01}
+
+::Match::Match the countries with their capitals. {
+    =Finland -> Helsinki
+    =Sweden -> Stockholm
+}
+"#;
+        let mut quiz = moodle_xml::gift::parse(source).unwrap();
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+
+        let file = File::open(tmp_file.path()).unwrap();
+        let read = moodle_xml::reader::parse_reader(BufReader::new(file)).unwrap();
+        let questions: Vec<_> = read.questions().collect();
+        assert_eq!(questions.len(), 3);
+        assert!(questions[0].0.is_none());
+        assert_eq!(questions[1].0.unwrap().as_str(), "numbers");
+        assert_eq!(questions[2].1.type_name(), "matching");
+        assert_eq!(
+            moodle_xml::gift::to_gift(&read).unwrap(),
+            moodle_xml::gift::to_gift(&quiz).unwrap()
+        );
+    }
+
+    #[test]
+    fn calculated_round_trip() {
+        use moodle_xml::dataset::Random;
+
+        let mut calculated =
+            CalculatedQuestion::new("Speed".into(), "{d} km in {t} h, how fast?".into());
+        let mut answer = CalculatedAnswer::new(100.0, "{d} / {t}".into(), 0.5, None);
+        answer.tolerance_type = moodle_xml::answer::ToleranceType::Nominal;
+        calculated.add_calculated_answers(vec![answer]).unwrap();
+        calculated.add_datasets(vec![
+            DatasetDefinition::new("d".into(), 10.0, 100.0, 0),
+            DatasetDefinition::new("t".into(), 0.5, 2.0, 1),
+        ]);
+        calculated.add_units(vec![NumericalUnit::new("km/h".into(), 1.0)]);
+        calculated
+            .generate_items(20, &mut Random::new(2024))
+            .unwrap();
+
+        let mut multi = CalculatedMultiQuestion::new("Double".into(), "Double {x}".into(), None);
+        multi
+            .add_answers(vec![
+                Answer::new(100.0, "{=2 * {x}}".into(), None),
+                Answer::new(0.0, "{={x} + 2}".into(), None),
+            ])
+            .unwrap();
+        multi.add_datasets(vec![DatasetDefinition::new("x".into(), 1.0, 9.0, 0)]);
+        multi.generate_items(5, &mut Random::new(1)).unwrap();
+
+        let mut quiz = Quiz::new(vec![calculated.into(), multi.into()]);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let read =
+            moodle_xml::reader::parse_reader(BufReader::new(File::open(tmp_file.path()).unwrap()))
+                .unwrap();
+        let questions: Vec<_> = read.questions().map(|(_, q)| q).collect();
+        let (QuestionType::Calculated(read_calculated), QuestionType::CalculatedMulti(read_multi)) =
+            (questions[0], questions[1])
+        else {
+            panic!("expected calculated questions");
+        };
+        let QuestionType::Calculated(calculated) = quiz.questions().next().unwrap().1 else {
+            unreachable!()
+        };
+        assert_eq!(read_calculated.answers()[0].tolerance, 0.5);
+        assert_eq!(
+            read_calculated.answers()[0].tolerance_type,
+            moodle_xml::answer::ToleranceType::Nominal
+        );
+        assert_eq!(read_calculated.units()[0].name, "km/h");
+        assert_eq!(
+            read_calculated.datasets()[1].items(),
+            calculated.datasets()[1].items()
+        );
+        assert_eq!(read_calculated.item_sets().unwrap().len(), 20);
+        assert_eq!(read_multi.answers()[0].answer.text, "{=2 * {x}}");
+        assert_eq!(read_multi.datasets()[0].items().len(), 5);
+    }
+
+    #[test]
+    fn gap_round_trip() {
+        let mut ddwtos =
+            DragDropTextQuestion::new("Drag".into(), "[[1]] plus [[2]] is [[3]]".into());
+        let mut one = DragBox::new("one".into(), 1);
+        one.infinite = true;
+        ddwtos.add_choices(vec![
+            one,
+            DragBox::new("one".into(), 2),
+            DragBox::new("two".into(), 1),
+        ]);
+        ddwtos.feedback.correctfeedback = "Well done".into();
+        let mut gapselect = GapSelectQuestion::new("Select".into(), "Roses are [[1]]".into());
+        gapselect.add_choices(vec![
+            SelectOption::new("red".into(), 1),
+            SelectOption::new("blue".into(), 1),
+        ]);
+        gapselect.shuffleanswers = false;
+
+        let mut quiz = Quiz::new(vec![ddwtos.into(), gapselect.into()]);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let read =
+            moodle_xml::reader::parse_reader(BufReader::new(File::open(tmp_file.path()).unwrap()))
+                .unwrap();
+        let questions: Vec<_> = read.questions().map(|(_, q)| q).collect();
+        let (QuestionType::DragDropText(ddwtos), QuestionType::GapSelect(gapselect)) =
+            (questions[0], questions[1])
+        else {
+            panic!("expected gap questions");
+        };
+        assert_eq!(ddwtos.gaps(), [1, 2, 3]);
+        assert!(ddwtos.choices()[0].infinite);
+        assert_eq!(ddwtos.choices()[1].group, 2);
+        assert_eq!(ddwtos.feedback.correctfeedback, "Well done");
+        assert!(!gapselect.shuffleanswers);
+        assert_eq!(gapselect.choices()[1].text, "blue");
+    }
+
+    #[test]
+    fn image_round_trip() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&300u32.to_be_bytes());
+        png.extend_from_slice(&200u32.to_be_bytes());
+
+        let mut ddimage = DragDropImageQuestion::new("Skeleton".into(), "Name the bones".into());
+        ddimage.set_background(EmbeddedFile::new("skeleton.png".into(), png.clone()));
+        let mut femur = DragItem::new("Femur".into(), 2);
+        femur.image = Some(EmbeddedFile::new("femur.png".into(), vec![1, 2, 3]));
+        ddimage.add_drags(vec![DragItem::new("Skull".into(), 1), femur]);
+        ddimage.add_drops(vec![DropZone::new(2, 120, 150), DropZone::new(1, 140, 10)]);
+        ddimage.shuffleanswers = true;
+
+        let mut ddmarker = DragMarkerQuestion::new("Heart".into(), "Mark the chambers".into());
+        ddmarker.set_background(EmbeddedFile::new("heart.png".into(), png));
+        let mut marker = Marker::new("Chamber".into());
+        marker.infinite = true;
+        ddmarker.add_markers(vec![marker]);
+        ddmarker.add_zones(vec![
+            MarkerZone::new(MarkerShape::parse("circle;50,50;20").unwrap(), 1),
+            MarkerZone::new(
+                MarkerShape::parse("polygon;100,100;150,100;120,180").unwrap(),
+                1,
+            ),
+        ]);
+
+        let mut quiz = Quiz::new(vec![ddimage.into(), ddmarker.into()]);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let read =
+            moodle_xml::reader::parse_reader(BufReader::new(File::open(tmp_file.path()).unwrap()))
+                .unwrap();
+        let questions: Vec<_> = read.questions().map(|(_, q)| q).collect();
+        let (QuestionType::DragDropImage(ddimage), QuestionType::DragMarker(ddmarker)) =
+            (questions[0], questions[1])
+        else {
+            panic!("expected image questions");
+        };
+        assert_eq!(ddimage.background().unwrap().image_size(), Some((300, 200)));
+        assert!(ddimage.shuffleanswers);
+        assert_eq!(ddimage.drags()[1].group, 2);
+        assert_eq!(ddimage.drags()[1].image.as_ref().unwrap().data, [1, 2, 3]);
+        assert_eq!(
+            (
+                ddimage.drops()[1].choice,
+                ddimage.drops()[1].x,
+                ddimage.drops()[1].y
+            ),
+            (1, 140, 10)
+        );
+        assert!(ddmarker.markers()[0].infinite);
+        assert_eq!(
+            ddmarker.zones()[1].shape.to_string(),
+            "polygon;100,100;150,100;120,180"
+        );
+    }
+
+    #[test]
+    fn random_round_trip() {
+        let mut drill = RandomShortAnswerMatchQuestion::new("Drill".into(), "Match".into(), 4);
+        drill.subcats = false;
+        let mut quiz = Quiz::new(Vec::new());
+        quiz.add_category(
+            "Vocabulary".into(),
+            vec![drill.into(), RandomQuestion::new("Any".into(), true).into()],
+        );
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let read =
+            moodle_xml::reader::parse_reader(BufReader::new(File::open(tmp_file.path()).unwrap()))
+                .unwrap();
+        let questions: Vec<_> = read.questions().collect();
+        let (Some(category), QuestionType::RandomShortAnswerMatch(drill)) = questions[0] else {
+            panic!("expected a randomsamatch question in a category");
+        };
+        assert_eq!(category.as_str(), "Vocabulary");
+        assert_eq!((drill.choose, drill.subcats), (4, false));
+        let QuestionType::Random(random) = questions[1].1 else {
+            panic!("expected a random question");
+        };
+        assert!(random.include_subcategories);
+    }
+
+    #[test]
+    fn ordering_round_trip() {
+        let mut question = OrderingQuestion::new("Quicksort".into(), "Order the steps".into());
+        question.add_items(vec![
+            "Pick a pivot".into(),
+            "Partition".into(),
+            "Recurse".into(),
+        ]);
+        question.select = OrderingSelect::Contiguous;
+        question.select_count = 2;
+        question.grading = OrderingGrading::LongestContiguousSubset;
+
+        let mut quiz = Quiz::new(question.into());
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let read =
+            moodle_xml::reader::parse_reader(BufReader::new(File::open(tmp_file.path()).unwrap()))
+                .unwrap();
+        let Some((_, QuestionType::Ordering(read))) = read.questions().next() else {
+            panic!("expected an ordering question");
+        };
+        assert_eq!(read.items(), ["Pick a pivot", "Partition", "Recurse"]);
+        assert_eq!(read.select, OrderingSelect::Contiguous);
+        assert_eq!(read.select_count, 2);
+        assert_eq!(read.grading, OrderingGrading::LongestContiguousSubset);
+        assert_eq!(read.layout, OrderingLayout::Vertical);
+    }
+
+    /// A question type of a Moodle plugin, implemented outside the crate.
+    struct StackQuestion {
+        base: QuestionBase,
+        feedback: String,
+    }
+
+    impl Question for StackQuestion {
+        fn base(&self) -> &QuestionBase {
+            &self.base
+        }
+        fn base_mut(&mut self) -> &mut QuestionBase {
+            &mut self.base
+        }
+        fn add_answers(&mut self, _: Vec<Answer>) -> Result<(), QuizError> {
+            Ok(())
+        }
+        fn to_xml(&self, writer: &mut XmlWriter) -> Result<(), QuizError> {
+            use xml::writer::XmlEvent;
+            writer.write(XmlEvent::start_element("question").attr("type", "stack"))?;
+            // The tags and the elements of the plugin are written before the common elements
+            self.base.write_hints(writer)?;
+            self.base.write_tags(writer)?;
+            write_named_formatted_scope(
+                writer,
+                "specificfeedback",
+                Some(TextFormat::HTML),
+                |writer| write_text_tag(writer, &self.feedback, false),
+            )?;
+            self.base.write_header(writer)?;
+            writer.write(XmlEvent::end_element())?;
+            Ok(())
+        }
+        fn type_name(&self) -> &'static str {
+            "stack"
+        }
+    }
+
+    #[test]
+    fn custom_question_type() {
+        let mut stack: Box<dyn Question> = Box::new(StackQuestion {
+            base: QuestionBase::new("Derivative".into(), "Differentiate x^2".into()),
+            feedback: "<p>Use the $x^n$ rule.</p>".into(),
+        });
+        stack.add_tags(vec!["calculus".into()]);
+        assert_eq!(stack.get_tags(), ["calculus"]);
+        let mut essay = EssayQuestion::new("Essay".into(), "Explain".into());
+        essay.add_tags(vec!["calculus".into()]);
+        let mut quiz = Quiz::new(vec![stack.into(), essay.into()]);
+        let (_, custom) = quiz.questions().next().unwrap();
+        assert_eq!(custom.type_name(), "stack");
+        assert_eq!(custom.as_question().get_description(), "Differentiate x^2");
+
+        // The options of the quiz apply to the texts of custom questions as well
+        quiz.set_cdata(CdataPolicy::Never);
+        quiz.set_mathjax_delimiters(true);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(xml.contains(r#"<question type="stack">"#));
+        assert!(xml.contains(r#"<question type="essay">"#));
+        assert!(xml.contains(r#"<specificfeedback format="html">"#));
+        assert!(xml.contains(r#"<text>&lt;p&gt;Use the \(x^n\) rule.&lt;/p&gt;</text>"#));
+        assert!(xml.contains("<text>Differentiate x^2</text>"));
+        assert_eq!(xml.matches("<text>calculus</text>").count(), 2);
+        let position = |xml: &str, element: &str| xml.find(element).unwrap();
+        assert!(position(&xml, "<tags>") < position(&xml, "<name>"));
+
+        // The canonical order puts the common elements first and the tags last
+        quiz.set_export_options(moodle_xml::export::ExportOptions {
+            element_order: moodle_xml::export::ElementOrder::Canonical,
+            ..Default::default()
+        });
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let canonical = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(position(&canonical, "<name>") < position(&canonical, "<questiontext"));
+        assert!(position(&canonical, "<questiontext") < position(&canonical, "<specificfeedback"));
+        assert!(position(&canonical, "<specificfeedback") < position(&canonical, "<tags>"));
+        assert!(canonical.contains(r#"<text>&lt;p&gt;Use the \(x^n\) rule.&lt;/p&gt;</text>"#));
+        assert_eq!(canonical.len(), xml.len());
+        let error = moodle_xml::gift::to_gift(&quiz).unwrap_err();
+        assert_eq!(error.to_string(), "GIFT does not support stack questions");
+    }
+
+    #[test]
+    fn coderunner_round_trip() {
+        let mut question = CodeRunnerQuestion::new(
+            "Hello".into(),
+            "Print a greeting".into(),
+            "c_program".into(),
+        );
+        question.answer = "#include <stdio.h>\nint main() { puts(\"Hello\"); }".into();
+        question.answerpreload = "#include <stdio.h>".into();
+        question.allornothing = false;
+        question.language = Some("c".into());
+        question.memlimitmb = Some(64);
+        let mut test = TestCase::new(String::new(), "Hello".into());
+        test.stdin = "ignored".into();
+        test.display = moodle_xml::question::TestDisplay::HideIfFail;
+        test.hiderestiffail = true;
+        test.mark = 2.5;
+        test.testtype = moodle_xml::question::TestType::Both;
+        question.add_testcases(vec![test]);
+
+        let mut quiz = Quiz::new(question.into());
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let read =
+            moodle_xml::reader::parse_reader(BufReader::new(File::open(tmp_file.path()).unwrap()))
+                .unwrap();
+        let Some((_, QuestionType::CodeRunner(read))) = read.questions().next() else {
+            panic!("expected a coderunner question");
+        };
+        assert_eq!(read.coderunnertype, "c_program");
+        assert_eq!(
+            read.answer,
+            "#include <stdio.h>\nint main() { puts(\"Hello\"); }"
+        );
+        assert_eq!(read.answerpreload, "#include <stdio.h>");
+        assert!(!read.allornothing);
+        assert_eq!(read.language.as_deref(), Some("c"));
+        assert_eq!(read.template, None);
+        assert_eq!((read.cputimelimitsecs, read.memlimitmb), (None, Some(64)));
+        let test = &read.testcases()[0];
+        assert_eq!(test.stdin, "ignored");
+        assert_eq!(test.display, moodle_xml::question::TestDisplay::HideIfFail);
+        assert!(test.hiderestiffail && !test.useasexample);
+        assert_eq!(test.mark, 2.5);
+        assert_eq!(test.testtype, moodle_xml::question::TestType::Both);
+    }
+
+    #[test]
+    fn multilang_quiz() {
+        use moodle_xml::multilang::{MultiLang, MultiLangOptions, MultiLangSyntax};
+
+        let mut question = ShortAnswerQuestion::new(
+            "Capital".into(),
+            MultiLang::from([("fi", "Suomen pääkaupunki?"), ("sv", "Finlands huvudstad?")]).into(),
+            None,
+        );
+        question
+            .add_answers(vec![Answer::new(
+                100.0,
+                MultiLang::from([("fi", "Helsinki"), ("sv", "Helsingfors")]).into(),
+                Some(MultiLang::from([("fi", "Oikein"), ("sv", "Rätt")]).into()),
+            )])
+            .unwrap();
+        let mut quiz = Quiz::new(question.into());
+        quiz.set_multilang(MultiLangOptions::new(
+            MultiLangSyntax::Mlang,
+            vec!["fi".into(), "sv".into()],
+        ));
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(xml
+            .contains("{mlang fi}Suomen pääkaupunki?{mlang}{mlang sv}Finlands huvudstad?{mlang}"));
+        assert!(xml.contains("<text>{mlang fi}Helsinki{mlang}{mlang sv}Helsingfors{mlang}</text>"));
+        assert!(xml.contains("{mlang sv}Rätt{mlang}"));
+        assert!(!xml.contains("multilang"));
+
+        quiz.set_multilang(MultiLangOptions::new(
+            MultiLangSyntax::Span,
+            vec!["fi".into(), "sv".into(), "en".into()],
+        ));
+        let error = quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Text `Suomen pääkaupunki?` has no translation to en"
+        );
+    }
+
+    #[test]
+    fn text_conversion() {
+        use moodle_xml::convert::TextConversion;
+
+        let mut question = ShortAnswerQuestion::new(
+            "Comparison".into(),
+            "<p>Is <b>1 &lt; 2</b>?</p>".into(),
+            None,
+        );
+        let mut answer = Answer::new(100.0, "yes".into(), Some("1 < 2\nis true".into()));
+        answer.set_text_format(TextFormat::PlainText);
+        question.add_answers(vec![answer]).unwrap();
+        let mut quiz = Quiz::new(question.into());
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+
+        quiz.set_text_conversion(TextConversion::PlainText);
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(xml.contains(
+            "<questiontext format=\"plain_text\">\n      <text><![CDATA[Is 1 < 2?]]></text>"
+        ));
+        assert!(xml.contains("<text>1 &lt; 2\nis true</text>"));
+
+        quiz.set_text_conversion(TextConversion::Html);
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(xml.contains("<text><![CDATA[<p>Is <b>1 &lt; 2</b>?</p>]]></text>"));
+        assert!(xml.contains("<answer fraction=\"100\" format=\"html\">"));
+        assert!(xml.contains("<feedback format=\"html\">"));
+        assert!(xml.contains("<text>1 &amp;lt; 2&lt;br /&gt;\nis true</text>"));
+        assert!(!xml.contains("plain_text"));
+    }
+
+    #[test]
+    fn multilang_text_conversion() {
+        use moodle_xml::convert::TextConversion;
+        use moodle_xml::multilang::{MultiLang, MultiLangOptions, MultiLangSyntax};
+
+        let mut question = ShortAnswerQuestion::new(
+            "Greeting".into(),
+            MultiLang::from([("fi", "<p>Hei</p>"), ("en", "<p>Hello</p>")]).into(),
+            None,
+        );
+        let mut answer = Answer::new(
+            100.0,
+            "hei".into(),
+            Some(MultiLang::from([("fi", "1 < 2"), ("en", "Right")]).into()),
+        );
+        answer.set_text_format(TextFormat::PlainText);
+        question.add_answers(vec![answer]).unwrap();
+        let mut quiz = Quiz::new(question.into());
+        quiz.set_cdata(CdataPolicy::Always);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+
+        // Plain text converted to HTML keeps the markup of the blocks and escapes their contents
+        quiz.set_text_conversion(TextConversion::Html);
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(xml.contains(concat!(
+            r#"<![CDATA[<span lang="fi" class="multilang">1 &lt; 2</span>"#,
+            r#"<span lang="en" class="multilang">Right</span>]]>"#
+        )));
+
+        // HTML converted to plain text keeps the languages apart
+        quiz.set_multilang(MultiLangOptions::new(
+            MultiLangSyntax::Mlang,
+            vec!["fi".into(), "en".into()],
+        ));
+        quiz.set_text_conversion(TextConversion::PlainText);
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(xml.contains("<![CDATA[{mlang fi}Hei{mlang}{mlang en}Hello{mlang}]]>"));
+        assert!(xml.contains("<![CDATA[{mlang fi}1 < 2{mlang}{mlang en}Right{mlang}]]>"));
+    }
+
+    #[test]
+    fn mathjax_delimiters() {
+        let mut question =
+            ShortAnswerQuestion::new("$a$ and $b$".into(), "Solve $x^2 = 4$.".into(), None);
+        question
+            .add_answers(vec![Answer::new(
+                100.0,
+                "2".into(),
+                Some("Yes, $x = 2$ costs $5".into()),
+            )])
+            .unwrap();
+        let mut quiz = Quiz::new(question.into());
+        quiz.set_mathjax_delimiters(true);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(xml.contains("<text>$a$ and $b$</text>"));
+        assert!(xml.contains(r"<text><![CDATA[Solve \(x^2 = 4\).]]></text>"));
+        assert!(xml.contains(r"<text>Yes, \(x = 2\) costs $5</text>"));
+    }
+
+    #[test]
+    fn html_checks_and_cdata_end() {
+        use moodle_xml::html;
+
+        let question = EssayQuestion::new(
+            "Code".into(),
+            r#"<p onclick="steal()">Is <code>a[b[0]]>1</code> true?<script>steal()</script></p>"#
+                .into(),
+        );
+        let question: QuestionType = question.into();
+        let warnings: Vec<String> = html::check_question(&question)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "question text: `<p>` has the event handler `onclick`",
+                "question text: `<script>` is not allowed",
+                "question text: `]]>` ends the CDATA section of the text",
+            ]
+        );
+
+        let mut quiz = Quiz::new(vec![question]);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        let read = moodle_xml::reader::parse(&xml).unwrap();
+        let (_, question) = read.questions().next().unwrap();
+        assert!(question
+            .as_question()
+            .get_description()
+            .contains("<code>a[b[0]]>1</code>"));
+
+        quiz.set_sanitize_html(true);
+        quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(xml.contains("<![CDATA[<p>Is <code>a[b[0]]&gt;1</code> true?</p>]]>"));
+    }
+
+    #[test]
+    fn cdata_policy() {
+        let mut question =
+            ShortAnswerQuestion::new("Compare".into(), "Which is true?".into(), None);
+        question
+            .add_answers(vec![Answer::new(
+                100.0,
+                "a < b".into(),
+                Some("Right".into()),
+            )])
+            .unwrap();
+        let mut quiz = Quiz::new(question.into());
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        let write = |quiz: &mut Quiz| {
+            quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+            std::fs::read_to_string(tmp_file.path()).unwrap()
+        };
+
+        let xml = write(&mut quiz);
+        assert!(xml.contains("<text><![CDATA[Which is true?]]></text>"));
+        assert!(xml.contains("<text>a &lt; b</text>"));
+
+        quiz.set_cdata(CdataPolicy::Always);
+        let xml = write(&mut quiz);
+        assert!(xml.contains("<text><![CDATA[Compare]]></text>"));
+        assert!(xml.contains("<text><![CDATA[a < b]]></text>"));
+        assert!(xml.contains("<text><![CDATA[Right]]></text>"));
+
+        quiz.set_cdata(CdataPolicy::Never);
+        let xml = write(&mut quiz);
+        assert!(!xml.contains("CDATA"));
+
+        quiz.set_cdata(CdataPolicy::Markup);
+        let xml = write(&mut quiz);
+        assert!(xml.contains("<text>Which is true?</text>"));
+        assert!(xml.contains("<text><![CDATA[a < b]]></text>"));
+        assert!(xml.contains("<text>Right</text>"));
+    }
+
+    #[test]
+    fn export_options() {
+        use moodle_xml::export::{ExportOptions, Indent, LineEnding};
+
+        let mut question = MultiChoiceQuestion::new(
+            "Capital".into(),
+            "Capital of Finland?".into(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        question
+            .add_answers(vec![
+                Answer::new(100.0, "Helsinki".into(), None),
+                Answer::new(0.0, "Turku".into(), None),
+            ])
+            .unwrap();
+        let mut quiz = Quiz::new(question.into());
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        let write = |quiz: &mut Quiz| {
+            quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+            std::fs::read_to_string(tmp_file.path()).unwrap()
+        };
+
+        let xml = write(&mut quiz);
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<quiz>\n  <question"));
+
+        quiz.set_export_options(ExportOptions {
+            indent: Indent::Compact,
+            declaration: false,
+            ..ExportOptions::default()
+        });
+        let xml = write(&mut quiz);
+        assert!(xml.starts_with("<quiz><question type=\"multichoice\"><name><text>Capital</text>"));
+        assert!(!xml.contains('\n'));
+
+        quiz.set_export_options(ExportOptions {
+            indent: Indent::Tab,
+            encoding: "UTF-8".into(),
+            line_ending: LineEnding::CrLf,
+            ..ExportOptions::default()
+        });
+        let xml = write(&mut quiz);
+        assert!(
+            xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n<quiz>\r\n\t<question")
+        );
+        assert!(!xml.replace("\r\n", "").contains('\n'));
+        let position = |element: &str| xml.find(element).unwrap();
+        assert!(position("<questiontext") < position("<single>"));
+        assert!(position("<single>") < position("<shuffleanswers>"));
+        assert!(position("<answernumbering") < position("<correctfeedback"));
+        assert!(position("<incorrectfeedback") < position("<answer "));
+        assert!(xml.contains("<![CDATA[Capital of Finland?]]>"));
+        let read = moodle_xml::reader::parse(&xml).unwrap();
+        assert_eq!(read.len(), 1);
+
+        quiz.set_export_options(ExportOptions {
+            encoding: "ISO-8859-1".into(),
+            ..ExportOptions::default()
+        });
+        let error = quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Files are written in UTF-8, not in ISO-8859-1"
+        );
+    }
+
+    #[test]
+    fn question_order() {
+        use moodle_xml::export::{ExportOptions, QuestionOrder};
+
+        let essay = |name: &str, idnumber: Option<&str>| -> QuestionType {
+            let mut question = EssayQuestion::new(name.into(), "Describe it.".into());
+            if let Some(idnumber) = idnumber {
+                question.set_idnumber(idnumber.into());
+            }
+            question.into()
+        };
+        let mut quiz = Quiz::new(vec![essay("Oulu", Some("b")), essay("Espoo", None)]);
+        quiz.add_category(
+            "Cities".into(),
+            vec![
+                essay("Turku", None),
+                essay("Helsinki", Some("c")),
+                essay("Tampere", Some("a")),
+                essay("Helsinki", Some("a")),
+            ],
+        );
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        let mut written = |order| {
+            quiz.set_export_options(ExportOptions {
+                question_order: order,
+                ..ExportOptions::default()
+            });
+            quiz.to_xml(tmp_file.path().to_str().unwrap()).unwrap();
+            let read =
+                moodle_xml::reader::parse(&std::fs::read_to_string(tmp_file.path()).unwrap())
+                    .unwrap();
+            read.questions()
+                .map(|(_, question)| {
+                    let question = question.as_question();
+                    format!(
+                        "{}:{}",
+                        question.get_name(),
+                        question.get_idnumber().unwrap_or("-")
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            written(QuestionOrder::Insertion),
+            [
+                "Oulu:b",
+                "Espoo:-",
+                "Turku:-",
+                "Helsinki:c",
+                "Tampere:a",
+                "Helsinki:a"
+            ]
+        );
+        // Questions are sorted within their category, and equal names keep their order
+        assert_eq!(
+            written(QuestionOrder::Name),
+            [
+                "Espoo:-",
+                "Oulu:b",
+                "Helsinki:c",
+                "Helsinki:a",
+                "Tampere:a",
+                "Turku:-"
+            ]
+        );
+        assert_eq!(
+            written(QuestionOrder::IdNumber),
+            [
+                "Oulu:b",
+                "Espoo:-",
+                "Tampere:a",
+                "Helsinki:a",
+                "Helsinki:c",
+                "Turku:-"
+            ]
+        );
+    }
+
+    #[test]
+    fn streaming() {
+        use moodle_xml::{
+            export::{ExportOptions, QuestionOrder},
+            reader::QuestionReader,
+        };
+
+        let essay = |n: usize| -> QuestionType {
+            EssayQuestion::new(format!("Essay {}", n), format!("Describe city {}.", n)).into()
+        };
+        let mut quiz = Quiz::new(vec![essay(0)]);
+        quiz.set_cdata(CdataPolicy::Never);
+        let tmp_file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = quiz.into_writer(tmp_file.as_file()).unwrap();
+        assert_eq!(writer.written(), 1);
+        writer.write_category(&"Cities".into()).unwrap();
+        writer.write_all((1..=1000).map(essay)).unwrap();
+        writer
+            .write_all([QuizItem::Category("Rest".into()), essay(1001).into()])
+            .unwrap();
+        assert_eq!(writer.finish().unwrap(), 1002);
+
+        let xml = std::fs::read_to_string(tmp_file.path()).unwrap();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<quiz>\n  <question"));
+        assert!(xml.ends_with("</question>\n</quiz>"));
+        assert!(xml.contains("<text>Describe city 1000.</text>"));
+
+        let file = File::open(tmp_file.path()).unwrap();
+        let mut categories = Vec::new();
+        let mut questions = 0;
+        for item in QuestionReader::new(BufReader::new(file)) {
+            match item.unwrap() {
+                QuizItem::Category(category) => categories.push(category.to_string()),
+                QuizItem::Question(_) => questions += 1,
+            }
+        }
+        assert_eq!(categories, ["Cities", "Rest"]);
+        assert_eq!(questions, 1002);
+        let quiz = moodle_xml::reader::parse(&xml).unwrap();
+        assert_eq!(quiz.len(), 1002);
+
+        // Reading stops at the first error
+        let mut reader = QuestionReader::new("<quiz><question type=\"essay\"></quiz>".as_bytes());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+
+        assert!(QuizWriter::new(tmp_file.as_file())
+            .unwrap()
+            .finish()
+            .is_err());
+        let mut quiz = Quiz::new(vec![essay(0)]);
+        quiz.set_export_options(ExportOptions {
+            question_order: QuestionOrder::Name,
+            ..ExportOptions::default()
+        });
+        assert!(quiz.into_writer(tmp_file.as_file()).is_err());
+    }
 }